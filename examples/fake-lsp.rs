@@ -0,0 +1,332 @@
+#![warn(clippy::all, clippy::pedantic, clippy::print_stdout)]
+//! A tiny language server to try the LSP client with, no real language
+//! needed. It treats any text as words: hover counts a word, definitions
+//! are `fn word`, references and renames are every occurrence, and
+//! completions are the words of the document. `TODO`
+//! and `FIXME` are reported as problems and formatting trims trailing
+//! whitespace. Pass `--utf-8` to count positions in bytes.
+
+use r_edit::editor::lsp::protocol::Encoding;
+use r_edit::editor::lsp::transport::{read_message, write_message};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Error};
+
+struct Server {
+    encoding: Encoding,
+    /// Open documents by URI
+    documents: HashMap<String, Vec<String>>,
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(String::from).collect()
+}
+
+impl Server {
+    /// The character column of `position` in `lines`
+    fn column(&self, lines: &[String], position: &Value) -> (usize, usize) {
+        let y = usize::try_from(position["line"].as_u64().unwrap_or(0))
+            .unwrap_or(0);
+        let character =
+            usize::try_from(position["character"].as_u64().unwrap_or(0))
+                .unwrap_or(0);
+        let line = lines.get(y).map_or("", String::as_str);
+        (y, self.encoding.column(line, character))
+    }
+
+    fn range(&self, lines: &[String], y: usize, x: usize, len: usize) -> Value {
+        let line = lines.get(y).map_or("", String::as_str);
+        let start = self.encoding.character(line, x);
+        let end = self.encoding.character(line, x + len);
+        json!({
+            "start": { "line": y, "character": start },
+            "end": { "line": y, "character": end },
+        })
+    }
+
+    /// The word at `position` of the document of `params`
+    fn word(&self, params: &Value) -> Option<String> {
+        let lines = self
+            .documents
+            .get(params["textDocument"]["uri"].as_str()?)?;
+        let (y, x) = self.column(lines, &params["position"]);
+        let chars: Vec<char> = lines.get(y)?.chars().collect();
+        let x = x.min(chars.len());
+        let start =
+            x - chars[..x].iter().rev().take_while(|c| is_word(**c)).count();
+        let end =
+            start + chars[start..].iter().take_while(|c| is_word(**c)).count();
+        (end > start).then(|| chars[start..end].iter().collect())
+    }
+
+    /// Every whole-word occurrence of `word` as (line, character column)
+    fn occurrences(lines: &[String], word: &str) -> Vec<(usize, usize)> {
+        let word: Vec<char> = word.chars().collect();
+        let mut found = Vec::new();
+        for (y, line) in lines.iter().enumerate() {
+            let chars: Vec<char> = line.chars().collect();
+            for x in 0..chars.len() {
+                let end = x + word.len();
+                if chars.get(x..end) == Some(word.as_slice())
+                    && (x == 0 || !is_word(chars[x - 1]))
+                    && chars.get(end).is_none_or(|c| !is_word(*c))
+                {
+                    found.push((y, x));
+                }
+            }
+        }
+        found
+    }
+
+    /// Every word of `lines` as a completion item, functions for the
+    /// words after `fn`
+    fn completions(lines: &[String]) -> Value {
+        let mut words: BTreeMap<&str, (usize, bool)> = BTreeMap::new();
+        for line in lines {
+            let mut previous = "";
+            for word in line.split(|c: char| !is_word(c)) {
+                if word.is_empty() {
+                    continue;
+                }
+                let entry = words.entry(word).or_default();
+                entry.0 += 1;
+                entry.1 |= previous == "fn";
+                previous = word;
+            }
+        }
+        let items: Vec<Value> = words
+            .into_iter()
+            .map(|(word, (count, function))| {
+                json!({
+                    "label": word,
+                    "kind": if function { 3 } else { 6 },
+                    "detail": if function { "function" } else { "word" },
+                    "documentation": {
+                        "kind": "markdown",
+                        "value": format!("{count} occurrences"),
+                    },
+                })
+            })
+            .collect();
+        json!({ "isIncomplete": false, "items": items })
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let lines = &self.documents[uri];
+        let mut diagnostics = Vec::new();
+        for (marker, severity) in [("TODO", 2), ("FIXME", 1)] {
+            for (y, x) in Self::occurrences(lines, marker) {
+                diagnostics.push(json!({
+                    "range": self.range(lines, y, x, marker.len()),
+                    "severity": severity,
+                    "message": format!("{marker} left in the code"),
+                }));
+            }
+        }
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+
+    fn change(&mut self, uri: &str, change: &Value) {
+        let Some(text) = change["text"].as_str() else {
+            return;
+        };
+        if change.get("range").is_none() {
+            self.documents.insert(uri.to_string(), lines(text));
+            return;
+        }
+        let Some(lines) = self.documents.get(uri) else {
+            return;
+        };
+        let (start_y, start_x) = self.column(lines, &change["range"]["start"]);
+        let (end_y, end_x) = self.column(lines, &change["range"]["end"]);
+        let mut whole: String = String::new();
+        for (y, line) in lines.iter().enumerate() {
+            for (x, c) in line.chars().chain(['\n']).enumerate() {
+                if (y, x) == (start_y, start_x) {
+                    whole.push_str(text);
+                }
+                if (y, x) < (start_y, start_x) || (y, x) >= (end_y, end_x) {
+                    whole.push(c);
+                }
+            }
+        }
+        if start_y >= lines.len() {
+            whole.push_str(text);
+        }
+        self.documents.insert(uri.to_string(), self::lines(&whole));
+    }
+
+    /// Handles a request, returning its result
+    fn request(&self, method: &str, params: &Value) -> Result<Value, String> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let lines = self.documents.get(uri).cloned().unwrap_or_default();
+        let location = |y, x, len| json!({ "uri": uri, "range": self.range(&lines, y, x, len) });
+        match method {
+            "initialize" => {
+                let encoding = match self.encoding {
+                    Encoding::Utf8 => "utf-8",
+                    Encoding::Utf16 => "utf-16",
+                };
+                Ok(json!({
+                    "capabilities": {
+                        "positionEncoding": encoding,
+                        "textDocumentSync": { "openClose": true, "change": 2 },
+                        "hoverProvider": true,
+                        "definitionProvider": true,
+                        "referencesProvider": true,
+                        "renameProvider": true,
+                        "documentFormattingProvider": true,
+                        "completionProvider": {},
+                    },
+                    "serverInfo": { "name": "fake-lsp" },
+                }))
+            }
+            "shutdown" => Ok(Value::Null),
+            "textDocument/hover" => Ok(match self.word(params) {
+                Some(word) => {
+                    let count = Self::occurrences(&lines, &word).len();
+                    json!({ "contents": {
+                        "kind": "markdown",
+                        "value": format!("`{word}`\n\n{count} occurrences"),
+                    }})
+                }
+                None => Value::Null,
+            }),
+            "textDocument/definition" => {
+                let Some(word) = self.word(params) else {
+                    return Ok(Value::Null);
+                };
+                let found = Self::occurrences(&lines, &word);
+                let defined = found.iter().find(|(y, x)| {
+                    let before: Vec<char> =
+                        lines[*y].chars().take(*x).collect();
+                    before.ends_with(&['f', 'n', ' '])
+                });
+                Ok(match defined.or(found.first()) {
+                    Some(&(y, x)) => location(y, x, word.chars().count()),
+                    None => Value::Null,
+                })
+            }
+            "textDocument/references" => {
+                let Some(word) = self.word(params) else {
+                    return Ok(json!([]));
+                };
+                let len = word.chars().count();
+                Ok(Self::occurrences(&lines, &word)
+                    .into_iter()
+                    .map(|(y, x)| location(y, x, len))
+                    .collect())
+            }
+            "textDocument/rename" => {
+                let Some(word) = self.word(params) else {
+                    return Err(String::from("Nothing to rename"));
+                };
+                let len = word.chars().count();
+                let edits: Vec<Value> = Self::occurrences(&lines, &word)
+                    .into_iter()
+                    .map(|(y, x)| {
+                        json!({
+                            "range": self.range(&lines, y, x, len),
+                            "newText": params["newName"],
+                        })
+                    })
+                    .collect();
+                Ok(json!({ "changes": { uri: edits } }))
+            }
+            "textDocument/completion" => Ok(Self::completions(&lines)),
+            "textDocument/formatting" => {
+                Ok(lines
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(y, line)| {
+                        let kept = line.trim_end().chars().count();
+                        let len = line.chars().count();
+                        (kept < len).then(|| json!({
+                        "range": self.range(&lines, y, kept, len - kept),
+                        "newText": "",
+                    }))
+                    })
+                    .collect())
+            }
+            _ => Err(format!("Unknown method {method}")),
+        }
+    }
+
+    /// Handles a notification, returning notifications to send back
+    fn notification(&mut self, method: &str, params: &Value) -> Option<Value> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text =
+                    params["textDocument"]["text"].as_str().unwrap_or("");
+                self.documents.insert(uri.clone(), lines(text));
+                Some(self.diagnostics(&uri))
+            }
+            "textDocument/didChange" => {
+                for change in params["contentChanges"].as_array()? {
+                    self.change(&uri, change);
+                }
+                Some(self.diagnostics(&uri))
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                None
+            }
+            "exit" => std::process::exit(0),
+            _ => None,
+        }
+    }
+}
+
+/// Serves on stdin and stdout until the client says `exit`. Public for
+/// the LSP tests, which run it from their own binary.
+pub fn main() -> Result<(), Error> {
+    let encoding = if std::env::args().any(|arg| arg == "--utf-8") {
+        Encoding::Utf8
+    } else {
+        Encoding::Utf16
+    };
+    let mut server = Server {
+        encoding,
+        documents: HashMap::new(),
+    };
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        match message.get("id") {
+            Some(id) => {
+                let response = match server.request(method, params) {
+                    Ok(result) => {
+                        json!({ "jsonrpc": "2.0", "id": id, "result": result })
+                    }
+                    Err(e) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32601, "message": e },
+                    }),
+                };
+                write_message(&mut output, &response)?;
+            }
+            None => {
+                if let Some(notification) = server.notification(method, params)
+                {
+                    write_message(&mut output, &notification)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
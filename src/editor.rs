@@ -1,29 +1,247 @@
 pub mod buffer;
 pub mod caret;
+pub mod command_line;
+pub mod commands;
+pub mod completion;
+pub mod config;
+pub mod diff;
+pub mod event_source;
+pub mod ex;
+pub mod explorer;
+pub mod folds;
+pub mod frame;
+pub mod fuzzy;
+pub mod grep;
+pub mod highlight;
+pub mod ignore;
+pub mod keymap;
+pub mod keys;
+pub mod layout;
 pub mod logger;
+pub mod lsp;
+pub mod macros;
+pub mod modal;
+pub mod palette;
+pub mod paths;
+pub mod picker;
+pub mod popup;
+pub mod recovery;
+pub mod replace;
+pub mod results;
+pub mod session;
+pub mod swap;
+#[cfg(feature = "tree-sitter")]
+pub mod syntax;
 pub mod terminal;
+pub mod theme;
+pub mod timers;
 pub mod view;
+pub mod walk;
+pub mod watcher;
+pub mod window;
 
 use buffer::Buffer;
 use caret::{Caret, Direction};
+use command_line::{CommandLine, MAX_HISTORY};
+use commands::Command;
+use completion::{Completion, CompletionItem, buffer_words, is_word_char};
+use config::{Config, PROJECT_CONFIG};
 use crossterm::event::Event::Resize;
 use crossterm::event::KeyCode::{
-    Backspace, Char, Down, End, Enter, Home, Left, PageDown, PageUp, Right, Up,
+    Backspace, Char, Down, Enter, Esc, Left, Right, Tab, Up,
 };
-use crossterm::event::{Event, Event::Key, KeyEvent, KeyModifiers, read};
+use crossterm::event::{
+    Event, Event::Key, Event::Mouse, KeyEvent, KeyModifiers,
+};
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use event_source::{EventSource, Input, ScriptedEvents, TerminalEvents};
+use ex::{Ex, LineRange, SetOption};
+use explorer::{Action, Change, EXPLORER_WIDTH, Explorer};
+use folds::Folds;
+use frame::Frame;
+use grep::{GrepMatch, GrepQuery, Search};
+use highlight::{Highlighter, Language};
+use keymap::{Keymap, Lookup};
+use keys::format_keys;
+use layout::{Layout, Rect, Split};
 use log::debug;
 use log::info;
-use std::io::Error;
+use lsp::protocol::FileLocation;
+use lsp::{Lsp, Reply, Request};
+use macros::Macros;
+use modal::{Modal, Mode, Outcome, ordered};
+use palette::{MAX_RECENT, Palette};
+use picker::{MAX_PREVIEW, Picker};
+use popup::Popup;
+use regex::Regex;
+use replace::Replace;
+use results::{RESULTS_HEIGHT, Results};
+use session::{Entry, Header, RecordingEvents, ReplayEvents, SessionWriter};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufRead, Error, ErrorKind};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
 use std::thread::sleep;
-use std::time::Duration;
-use terminal::{Size, Terminal};
-use view::View;
+use std::time::{Duration, Instant};
+use swap::SwapFile;
+use terminal::crossterm_backend::CrosstermBackend;
+use terminal::virtual_backend::{VirtualBackend, VirtualScreen};
+use terminal::{Location, Position, Size, Terminal};
+use theme::{Element, Theme};
+use timers::{Timer, Timers};
+use view::{
+    Autosave, Document, Overlay, Selection, Status, View, line_diagnostic,
+    tab_at,
+};
+use walk::FileWalk;
+use watcher::FileWatcher;
+use window::{OpenBuffer, TabPage, Window};
+
+const MESSAGE_DURATION: Duration = Duration::from_secs(5);
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the buffer is synced with language servers and their
+/// messages are handled
+const LSP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How often the file finder picks up the files listed so far
+const WALK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How often the results panel picks up the matches found so far
+const SEARCH_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How often open files are checked for changes other programs made
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How often unsaved changes are journaled to swap files
+const SWAP_INTERVAL: Duration = Duration::from_secs(2);
+/// Lines shown above the place of the selected item in picker previews
+const PREVIEW_CONTEXT: usize = 2;
+/// Deepest a macro may play other macros, guards against a macro
+/// playing itself forever
+const MAX_REPLAY_DEPTH: usize = 16;
+/// Characters of a word typed before completions show up on their own
+const COMPLETION_PREFIX: usize = 2;
+
+#[derive(Debug, Clone)]
+/// Commands that need more keys before they can run
+enum Pending {
+    /// Waiting for the register to record a macro into
+    RecordRegister,
+    /// Waiting for an optional count, then the register to play
+    PlayMacro { count: usize },
+    /// Waiting for what to do with a swap file left behind for the
+    /// current file
+    Swap(SwapFile),
+    /// Waiting for what to do about saving over the current file, which
+    /// changed on disk since it was read
+    Conflict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What saving a buffer does
+enum Saving {
+    /// Writes the file
+    Write,
+    /// Goes through the motions without writing any file, for replaying
+    /// sessions
+    DryRun,
+}
 
 pub struct Editor {
     should_quit: bool,
-    caret: Caret,
-    view: View,
-    // buffers: Vec<&Buffer>,
+    terminal: Terminal,
+    events: Box<dyn EventSource>,
+    timers: Timers,
+    config: Config,
+    keymap: Keymap,
+    /// Keys typed so far of a binding longer than one key
+    key_sequence: Vec<KeyEvent>,
+    /// Watches the configuration files once `load_config` ran
+    config_watcher: Option<FileWatcher>,
+    /// Watches the files of open buffers, by absolute path, as they were
+    /// last read or saved
+    file_watcher: FileWatcher,
+    /// Files that changed on disk that the user was told about, kept
+    /// until the buffer is saved or reloaded
+    disk_warned: HashSet<PathBuf>,
+    macros: Macros,
+    /// Where macros are persisted, `None` keeps them in memory only
+    macros_path: Option<PathBuf>,
+    /// Where unsaved changes are journaled, `None` journals nothing
+    swap_dir: Option<PathBuf>,
+    saving: Saving,
+    /// What kept the last autosave from saving every buffer
+    autosave_error: Option<String>,
+    /// Files this editor wrote a swap file for, by absolute path, with
+    /// the revision of their buffer it holds
+    swapped: HashMap<PathBuf, u64>,
+    /// The command palette, while it is open
+    palette: Option<Palette>,
+    /// Commands run from the palette, most recent first
+    recent_commands: Vec<Command>,
+    /// Where recent commands are persisted, `None` keeps them in memory
+    recent_commands_path: Option<PathBuf>,
+    /// The `:` prompt, while it is open
+    command_line: Option<CommandLine>,
+    /// Command lines run, most recent first
+    command_history: Vec<String>,
+    /// Where the command history is persisted, `None` keeps it in memory
+    command_history_path: Option<PathBuf>,
+    /// A command waiting for more keys, e.g. a macro register
+    pending: Option<Pending>,
+    /// How many macros are being played back, nested
+    replay_depth: usize,
+    /// Vim-style modal editing, when turned on in the configuration
+    modal: Option<Modal>,
+    /// Revision of the buffer when it was last read or saved
+    saved_revision: u64,
+    /// Colors the current buffer, after the language of its file
+    highlighter: Highlighter,
+    /// Language servers of the languages configured for one
+    lsp: Lsp,
+    /// What the language server said about the symbol at the caret, until
+    /// the next key
+    hover: Option<Popup>,
+    /// Places to jump to, like the references of a symbol, while picking
+    /// one
+    picker: Option<Picker<FileLocation>>,
+    /// Lists the files of the project for the file finder while it is
+    /// open
+    file_walk: Option<FileWalk>,
+    /// What the last search in files found, shown under the panes until
+    /// closed
+    results: Option<Results>,
+    /// Whether keys go to the results panel
+    results_focused: bool,
+    /// The search in files while it runs
+    search: Option<Search>,
+    /// Replacing the matches of the search in files, previewed in the
+    /// results panel until applied or cancelled
+    replace: Option<Replace>,
+    /// Completions for the word at the caret, while the menu is open
+    completion: Option<Completion>,
+    /// Where the word starts that completions were asked of the language
+    /// server for, until it answers
+    pending_completion: Option<Location>,
+    /// How the screen is split into panes in the current tab, and which
+    /// of them has the focus
+    layout: Layout,
+    /// Every pane of the current tab, by id
+    windows: HashMap<usize, Window>,
+    /// The other tabs, in order, the current one left out
+    tabs: Vec<TabPage>,
+    /// Index of the current tab among all of them
+    tab: usize,
+    /// Buffers only shown in panes without the focus, by id
+    buffers: HashMap<usize, OpenBuffer>,
+    /// Id of the current buffer
+    buffer_id: usize,
+    next_buffer_id: usize,
+    /// The file explorer on the left, while it is open
+    explorer: Option<Explorer>,
+    /// Whether keys go to the explorer rather than the focused pane
+    explorer_focused: bool,
+    /// Size of the whole screen
+    size: Size,
+    /// Shown in the message bar on the last row
+    message: Option<String>,
     current_buffer: Buffer,
     filename: String,
 }
@@ -31,205 +249,4417 @@ pub struct Editor {
 impl Drop for Editor {
     fn drop(&mut self) {
         debug!("Dropping Editor!");
-        match Terminal::terminate() {
-            Ok(_) => {}
-            Err(e) => {
-                debug!("Error terminating terminal: {}", e);
-            }
+        if let Err(e) = self.terminal.terminate() {
+            debug!("Error terminating terminal: {e}");
         }
     }
 }
 
 impl Editor {
     pub fn default() -> Self {
-        Self {
-            should_quit: false,
-            caret: caret::Caret::default(),
+        Self::build(
+            Terminal::default(),
+            Box::new(TerminalEvents),
             // TODO implement multiple buffers
-            // buffers: Vec::new(),
-            current_buffer: Buffer::default(),
-            view: View::default(),
-            filename: String::from("./test/test.txt"),
-        }
+            Buffer::default(),
+            String::from("./test/test.txt"),
+        )
     }
     pub fn new(filename: String) -> Self {
-        Self::set_up_panic_hook();
+        Self::build(
+            Terminal::default(),
+            Box::new(TerminalEvents),
+            Buffer::default(),
+            filename,
+        )
+    }
+
+    fn build(
+        terminal: Terminal,
+        events: Box<dyn EventSource>,
+        buffer: Buffer,
+        filename: String,
+    ) -> Self {
         Self {
             should_quit: false,
-            caret: caret::Caret::default(),
-            current_buffer: Buffer::default(),
-            view: View::default(),
-            filename: filename,
+            current_buffer: buffer,
+            terminal,
+            events,
+            timers: Timers::default(),
+            config: Config::default(),
+            keymap: Keymap::default(),
+            key_sequence: Vec::new(),
+            config_watcher: None,
+            file_watcher: FileWatcher::new(Vec::new()),
+            disk_warned: HashSet::new(),
+            macros: Macros::default(),
+            macros_path: None,
+            swap_dir: None,
+            saving: Saving::Write,
+            swapped: HashMap::new(),
+            autosave_error: None,
+            palette: None,
+            recent_commands: Vec::new(),
+            recent_commands_path: None,
+            command_line: None,
+            command_history: Vec::new(),
+            command_history_path: None,
+            pending: None,
+            replay_depth: 0,
+            modal: None,
+            saved_revision: 0,
+            highlighter: Highlighter::new(None),
+            lsp: Lsp::default(),
+            hover: None,
+            picker: None,
+            file_walk: None,
+            results: None,
+            results_focused: false,
+            search: None,
+            replace: None,
+            completion: None,
+            pending_completion: None,
+            layout: Layout::default(),
+            windows: HashMap::from([(0, Window::new(0))]),
+            tabs: Vec::new(),
+            tab: 0,
+            buffers: HashMap::new(),
+            buffer_id: 0,
+            next_buffer_id: 1,
+            explorer: None,
+            explorer_focused: false,
+            size: Size {
+                height: 40,
+                width: 80,
+            },
+            message: None,
+            filename,
         }
     }
 
-    pub fn run(&mut self) {
-        info!("--------------------------------------------");
-        info!("Editor is running");
-        match Terminal::initialize() {
-            Ok(_) => {}
+    /// An editor on the given `terminal` editing `buffer`, with no file
+    /// read at startup and no events until `set_event_source` is called.
+    /// Nothing is saved unless `filename` is set.
+    pub fn with_terminal(
+        terminal: Terminal,
+        buffer: Buffer,
+        filename: String,
+    ) -> Self {
+        let mut editor = Self::build(
+            terminal,
+            Box::new(ScriptedEvents::new(Vec::new())),
+            buffer,
+            filename,
+        );
+        editor.saved_revision = editor.current_buffer.revision();
+        editor.fit_to_terminal();
+        editor.detect_language();
+        editor
+    }
+
+    /// A headless editor drawing on an in-memory screen of `size`, for
+    /// driving the editor from tests. The returned `VirtualScreen` shows
+    /// what a user would see.
+    pub fn headless(size: Size, buffer: Buffer) -> (Self, VirtualScreen) {
+        let backend = VirtualBackend::new(size);
+        let screen = backend.screen();
+        let terminal = Terminal::new(Box::new(backend));
+        (Self::with_terminal(terminal, buffer, String::new()), screen)
+    }
+
+    /// A headless editor replaying a session recorded on `filename`,
+    /// read from disk like `run` does but never written back. Events
+    /// come from the session as fast as they are asked for.
+    pub fn replaying(
+        header: &Header,
+        entries: Vec<Entry>,
+        filename: String,
+    ) -> Result<(Self, VirtualScreen), Error> {
+        let buffer = Buffer::read_file(&filename)?;
+        let (mut editor, screen) = Self::headless(header.size(), buffer);
+        editor.saving = Saving::DryRun;
+        editor.filename = filename;
+        editor.detect_language();
+        editor.attach_lsp();
+        let events = ReplayEvents::new(header, entries, false);
+        editor.set_event_source(Box::new(events));
+        Ok((editor, screen))
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.current_buffer
+    }
+
+    pub fn caret(&self) -> &Caret {
+        &self.window().caret
+    }
+
+    pub fn should_quit(&self) -> bool {
+        self.should_quit
+    }
+
+    pub fn view(&self) -> &View {
+        &self.window().view
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Applies `config` to everything that depends on it
+    pub fn set_config(&mut self, config: Config) {
+        for view in self.views_mut() {
+            view.apply_config(&config);
+        }
+        match Theme::load(&config.theme, &config.themes) {
+            Ok(theme) => {
+                for view in self.views_mut() {
+                    view.set_theme(theme.clone());
+                }
+            }
             Err(e) => {
-                debug!("Error initializing terminal: {e}");
-                panic!();
+                info!("Theme error: {e}");
+                self.show_message(format!("Theme error: {e}"));
             }
         }
+        self.keymap = Keymap::default();
+        for error in self.keymap.apply_overrides(&config.keymap) {
+            info!("Keymap error: {error}");
+        }
+        log::set_max_level(config.log.level);
+        if !config.modal {
+            self.modal = None;
+        } else if self.modal.is_none() {
+            self.modal = Some(Modal::default());
+        }
+        let servers_changed = self.lsp.configure(&config.lsp);
+        self.config = config;
+        if servers_changed {
+            self.attach_lsp();
+        }
+    }
 
-        match Buffer::read_file(&self.filename) {
-            Ok(buffer) => self.current_buffer = buffer,
-            Err(e) => {
-                debug!("Error opening file: {e}");
-                // TODO(dan): for now panic is fine, in the future maybe
-                // open some kind of dashboard
-                panic!();
-            }
+    /// Loads the user and project configuration and keeps reloading it
+    /// whenever one of the files changes. Errors end up in the message bar.
+    pub fn load_config(&mut self) {
+        let dir = Path::new(&self.filename)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let paths = Config::paths(&dir);
+        let (config, errors) = Config::load(&paths);
+
+        for error in &errors {
+            info!("Configuration error: {error}");
         }
-        // self.buffers.push(self.current_buffer);
+        if let Some(error) = errors.first() {
+            self.show_message(format!("Config error: {error}"));
+        }
+        self.set_config(config);
 
-        self.view = View::new(Terminal::size().unwrap());
-        let result = self.repl();
-        Terminal::terminate().unwrap();
-        result.unwrap();
-        info!("Editor finished running");
-        info!("--------------------------------------------");
+        // Also watch for a project configuration showing up
+        let mut watched = paths;
+        let project = dir.join(PROJECT_CONFIG);
+        if !watched.contains(&project) {
+            watched.push(project);
+        }
+        self.config_watcher = Some(FileWatcher::new(watched));
+        self.timers
+            .schedule(Timer::ConfigCheck, CONFIG_CHECK_INTERVAL);
     }
 
-    pub fn repl(&mut self) -> Result<(), Error> {
-        info!("Starting read-evaluate-print loop");
+    /// Replaces where `repl` gets its events from
+    pub fn set_event_source(&mut self, events: Box<dyn EventSource>) {
+        self.events = events;
+    }
 
-        loop {
-            match View::render(
-                &mut self.view,
-                &mut self.caret,
-                &mut self.current_buffer,
-            ) {
-                Ok(_) => {}
-                Err(e) => {
-                    debug!("Error rendering: {e}");
-                }
-            }
+    /// Records every event from now on into a session file at `path`,
+    /// which `--replay` can play back later
+    pub fn record_to(&mut self, path: &Path) -> Result<(), Error> {
+        let size = self.terminal.size()?;
+        let header = Header::new(size, &self.filename);
+        let writer = SessionWriter::create(path, &header)?;
+        let inner = std::mem::replace(
+            &mut self.events,
+            Box::new(ScriptedEvents::new(Vec::new())),
+        );
+        self.events = Box::new(RecordingEvents::new(inner, writer));
+        info!("Recording session to {}", path.display());
+        Ok(())
+    }
 
-            if self.should_quit {
-                info!("Quitting editor");
-                match View::goodbye_message(&mut self.caret) {
-                    Ok(_) => {
-                        sleep(Duration::from_millis(1000));
-                    }
-                    Err(e) => {
-                        debug!("Error printing goodbye message: {e}");
-                    }
-                };
-                break;
+    /// Evaluates a single event and renders the result, the same way one
+    /// turn of `repl` does
+    pub fn handle_event(&mut self, event: &Event) {
+        self.evaluate_event(event);
+        self.render();
+    }
+
+    /// Renders the current state of the editor onto the terminal
+    pub fn render(&mut self) {
+        self.arrange_windows();
+        let view = &self.windows[&self.layout.focused()].view;
+        if view.is_new_buffer {
+            if let Err(e) = view.welcome_message(&mut self.terminal, self.size)
+            {
+                debug!("Error rendering: {e}");
             }
+            return;
+        }
 
-            match read() {
-                Ok(event) => self.evaluate_event(&event),
-                Err(e) => {
-                    debug!("Error handling event: {}", e);
-                }
+        let mut frame = Frame::with_style(
+            self.size,
+            self.window().view.theme().element(Element::Text),
+        );
+        let area = self.pane_area();
+        let focused = self.layout.focused();
+        for (id, rect) in self.layout.arrange(area) {
+            let pane = if id == focused {
+                Some(self.draw_focused_window())
+            } else {
+                self.draw_window(id)
+            };
+            if let Some(pane) = pane {
+                frame.blit(
+                    &pane,
+                    Position {
+                        x: rect.x,
+                        y: rect.y,
+                    },
+                );
             }
         }
+        let window = self.window();
+        for border in self.layout.borders(area) {
+            window.view.draw_border(&mut frame, border);
+        }
+        if !self.tabs.is_empty() {
+            let labels = self.tab_labels();
+            window.view.draw_tab_bar(&mut frame, &labels, self.tab);
+        }
+        let explorer_caret = self.draw_explorer(&mut frame, area);
+        let results_caret = self.draw_results(&mut frame, area);
+        let window = self.window();
+        // Without a message, the problem on the caret's line
+        let diagnostics = self.lsp.diagnostics(Path::new(&self.filename));
+        let message = self.message.as_deref().or_else(|| {
+            line_diagnostic(diagnostics, window.caret.location.y)
+                .map(|d| d.message.as_str())
+        });
+        window.view.draw_message_bar(&mut frame, message);
 
-        info!("Exiting REPL loop");
-        Ok(())
+        let caret = window.view.screen_position(
+            &self.current_buffer,
+            &window.folds,
+            window.caret.location,
+        );
+        if let Some(completion) = &self.completion {
+            let start = window.view.screen_position(
+                &self.current_buffer,
+                &window.folds,
+                completion.start(),
+            );
+            window
+                .view
+                .draw_completion(&mut frame, completion, caret.y, start.x);
+        }
+        if let Some(hover) = &mut self.hover {
+            hover.place(caret);
+        }
+        let caret = explorer_caret.or(results_caret).unwrap_or(caret);
+        let prompt = self.explorer.as_ref().and_then(Explorer::prompt);
+        let overlay = if let Some(palette) = &self.palette {
+            Some(palette as &dyn Overlay)
+        } else if let Some(command_line) = &self.command_line {
+            Some(command_line as &dyn Overlay)
+        } else if let Some(picker) = &self.picker {
+            Some(picker as &dyn Overlay)
+        } else if let Some(prompt) = prompt {
+            Some(prompt as &dyn Overlay)
+        } else {
+            self.hover.as_ref().map(|hover| hover as &dyn Overlay)
+        };
+        if let Err(e) = View::present(&mut self.terminal, frame, overlay, caret)
+        {
+            debug!("Error rendering: {e}");
+        }
     }
 
-    fn evaluate_event(&mut self, event: &Event) {
-        info!("Evaluating event: {:?}", event);
-        if let Resize(x, y) = event {
-            self.view.resize(Size {
-                height: *y as usize,
-                width: *x as usize,
-            });
-            self.caret.size = Size {
-                height: *y as usize,
-                width: *x as usize,
+    /// Draws the explorer, if open, left of the panes in `area` with a
+    /// border between them. Returns where the caret goes if the explorer
+    /// has the focus.
+    fn draw_explorer(
+        &mut self,
+        frame: &mut Frame,
+        area: Rect,
+    ) -> Option<Position> {
+        let explorer = self.explorer.as_mut()?;
+        let path = std::path::absolute(&self.filename).unwrap_or_default();
+        explorer.reveal(&path);
+        let sidebar = Rect {
+            x: 0,
+            width: area.x.saturating_sub(1),
+            ..area
+        };
+        let size = Size {
+            width: sidebar.width,
+            height: sidebar.height,
+        };
+        let focused = self.explorer_focused;
+        let view = &self.windows[&self.layout.focused()].view;
+        frame.blit(
+            &explorer.draw(size, view.theme(), focused),
+            Position { x: 0, y: sidebar.y },
+        );
+        view.draw_border(
+            frame,
+            Rect {
+                x: sidebar.width,
+                width: 1,
+                ..sidebar
+            },
+        );
+        let at = explorer.caret();
+        focused.then_some(Position {
+            x: at.x,
+            y: sidebar.y + at.y,
+        })
+    }
+
+    /// Draws the results panel, if open, under the panes in `area`.
+    /// Returns where the caret goes if the panel has the focus.
+    fn draw_results(
+        &mut self,
+        frame: &mut Frame,
+        area: Rect,
+    ) -> Option<Position> {
+        let size = Size {
+            width: self.size.width,
+            height: self.results_height(),
+        };
+        let results = self.results.as_mut()?;
+        let y = area.y + area.height;
+        let focused = self.results_focused;
+        let theme = self.windows[&self.layout.focused()].view.theme();
+        let (panel, at) = match &mut self.replace {
+            Some(replace) => {
+                (replace.draw(size, theme, focused), replace.caret())
+            }
+            None => (results.draw(size, theme, focused), results.caret()),
+        };
+        frame.blit(&panel, Position { x: 0, y });
+        focused.then_some(Position {
+            x: at.x,
+            y: y + at.y,
+        })
+    }
+
+    /// Draws the focused pane, scrolled to the caret
+    fn draw_focused_window(&mut self) -> Frame {
+        let selection = self.selection();
+        let modified = self.is_modified();
+        let window = focused_window(&mut self.windows, &self.layout);
+        let mut text_area = window.view.text_area_size();
+        text_area.width = text_area
+            .width
+            .saturating_sub(window.view.gutter_width(&self.current_buffer));
+        window.caret.size = text_area;
+        window.folds.update(&self.current_buffer);
+        window.view.scroll_to(
+            &self.current_buffer,
+            &window.folds,
+            window.caret.location,
+        );
+        self.highlighter.update(
+            &self.current_buffer,
+            window.view.visible_lines(&window.folds),
+        );
+
+        let status = Status {
+            mode: self.modal.as_ref().map(|modal| modal.mode().name()),
+            file: self.filename.clone(),
+            modified,
+            location: window.caret.location,
+            pending: self
+                .modal
+                .as_ref()
+                .map_or_else(String::new, Modal::pending_keys),
+            autosave: autosave_state(
+                &self.config,
+                self.autosave_error.is_some(),
+                &self.filename,
+            ),
+        };
+        let document = Document {
+            buffer: &self.current_buffer,
+            highlighter: &self.highlighter,
+            folds: &window.folds,
+            selection,
+            diagnostics: self.lsp.diagnostics(Path::new(&self.filename)),
+        };
+        window.view.draw(&document, &status, true)
+    }
+
+    /// Draws pane `id`, one without the focus
+    fn draw_window(&mut self, id: usize) -> Option<Frame> {
+        let window = self.windows.get_mut(&id)?;
+        let (buffer, highlighter, filename, saved_revision) =
+            if window.buffer == self.buffer_id {
+                (
+                    &self.current_buffer,
+                    &mut self.highlighter,
+                    &self.filename,
+                    self.saved_revision,
+                )
+            } else {
+                let open = self.buffers.get_mut(&window.buffer)?;
+                (
+                    &open.buffer,
+                    &mut open.highlighter,
+                    &open.filename,
+                    open.saved_revision,
+                )
             };
+        // The buffer may have changed in another pane
+        window.caret.clamp_to(buffer);
+        window.folds.update(buffer);
+        window
+            .view
+            .scroll_to(buffer, &window.folds, window.caret.location);
+        highlighter.update(buffer, window.view.visible_lines(&window.folds));
+        let path = Path::new(filename);
+        window.view.set_signs(self.lsp.is_attached(path));
+
+        let status = Status {
+            mode: None,
+            file: filename.clone(),
+            modified: buffer.revision() != saved_revision,
+            location: window.caret.location,
+            pending: String::new(),
+            autosave: autosave_state(
+                &self.config,
+                self.autosave_error.is_some(),
+                filename,
+            ),
+        };
+        let document = Document {
+            buffer,
+            highlighter,
+            folds: &window.folds,
+            selection: None,
+            diagnostics: self.lsp.diagnostics(path),
+        };
+        Some(window.view.draw(&document, &status, false))
+    }
+
+    /// The part of the screen the panes share, all but the message bar,
+    /// the tab bar, which is only there with more than one tab, and the
+    /// explorer with its border while it is open
+    fn pane_area(&self) -> Rect {
+        let tab_bar = usize::from(!self.tabs.is_empty());
+        let explorer = if self.explorer.is_some() {
+            EXPLORER_WIDTH.min(self.size.width / 2) + 1
+        } else {
+            0
+        };
+        Rect {
+            x: explorer,
+            y: tab_bar,
+            width: self.size.width.saturating_sub(explorer),
+            height: self
+                .size
+                .height
+                .saturating_sub(1 + tab_bar + self.results_height()),
         }
-        if let Key(KeyEvent {
-            code, modifiers, ..
-        }) = event
-        {
-            match code {
-                Char('q') if *modifiers == KeyModifiers::CONTROL => {
-                    self.should_quit = true;
-                    info!("Ctrl-Q pressed, quitting");
-                }
-                Char('s') if *modifiers == KeyModifiers::CONTROL => {
-                    self.should_quit = true;
-                    info!("Ctrl-S pressed, quitting and saving buffer");
-                    self.current_buffer.write_file(&self.filename);
-                }
-                Char(c) => {
-                    Buffer::insert(
-                        &mut self.current_buffer,
-                        *c,
-                        self.caret.location,
-                    );
+    }
 
-                    self.caret.shift(Direction::Right);
-                    self.view.needs_redraw = true;
-                }
-                Enter => {
-                    Buffer::insert(
-                        &mut self.current_buffer,
-                        '\n',
-                        self.caret.location,
-                    );
+    /// Rows of the results panel, none while it is closed
+    fn results_height(&self) -> usize {
+        if self.results.is_some() {
+            RESULTS_HEIGHT.min(self.size.height / 2)
+        } else {
+            0
+        }
+    }
 
-                    View::draw_buffer(&self.current_buffer, &mut self.caret)
-                        .unwrap();
-                    self.caret.shift(Direction::Down);
-                    self.caret.location.x = 0;
-                    self.view.needs_redraw = true;
-                }
-                Backspace => {
-                    Buffer::backspace(
-                        &mut self.current_buffer,
-                        self.caret.location,
-                    );
+    /// Moves every pane's view to its place in the layout
+    fn arrange_windows(&mut self) {
+        for (id, rect) in self.layout.arrange(self.pane_area()) {
+            if let Some(window) = self.windows.get_mut(&id) {
+                window.view.place(rect);
+            }
+        }
+    }
 
-                    self.caret.shift(Direction::Left);
-                    self.view.needs_redraw = true;
-                }
-                Left => {
-                    self.caret.shift(Direction::Left);
-                }
-                Right => {
-                    self.caret.shift(Direction::Right);
-                }
-                Up => {
-                    self.caret.shift(Direction::Up);
-                }
-                Down => {
-                    self.caret.shift(Direction::Down);
+    /// The views of every pane in every tab
+    fn views_mut(&mut self) -> impl Iterator<Item = &mut View> {
+        let parked = self
+            .tabs
+            .iter_mut()
+            .flat_map(|tab| tab.windows.values_mut());
+        self.windows
+            .values_mut()
+            .chain(parked)
+            .map(|window| &mut window.view)
+    }
+
+    /// The focused pane
+    fn window(&self) -> &Window {
+        &self.windows[&self.layout.focused()]
+    }
+
+    fn window_mut(&mut self) -> &mut Window {
+        focused_window(&mut self.windows, &self.layout)
+    }
+
+    /// Every pane, in every tab
+    fn windows(&self) -> impl Iterator<Item = &Window> {
+        let parked = self.tabs.iter().flat_map(|tab| tab.windows.values());
+        self.windows.values().chain(parked)
+    }
+
+    /// Runs the commands that split, close, resize or move between panes
+    fn window_command(&mut self, command: Command) {
+        let to_pane =
+            matches!(command, Command::FocusPaneRight | Command::FocusNextPane);
+        if self.explorer_focused && to_pane {
+            self.explorer_focused = false;
+            return;
+        }
+        let from_results =
+            matches!(command, Command::FocusPaneUp | Command::FocusNextPane);
+        if self.results_focused && from_results {
+            self.results_focused = false;
+            return;
+        }
+        let area = self.pane_area();
+        let target = match command {
+            Command::SplitHorizontal => {
+                self.split_window(Split::Horizontal, None);
+                return;
+            }
+            Command::SplitVertical => {
+                self.split_window(Split::Vertical, None);
+                return;
+            }
+            Command::ClosePane => {
+                self.close_window(false);
+                return;
+            }
+            Command::GrowPane | Command::ShrinkPane => {
+                let grow = command == Command::GrowPane;
+                if !self.layout.resize(Split::Horizontal, grow) {
+                    self.show_message(String::from("No pane above or below"));
                 }
-                Home => {
-                    self.caret.go_start_of_line();
+                return;
+            }
+            Command::WidenPane | Command::NarrowPane => {
+                let grow = command == Command::WidenPane;
+                if !self.layout.resize(Split::Vertical, grow) {
+                    self.show_message(String::from("No pane beside this one"));
                 }
-                End => {
-                    self.caret.go_end_of_line();
+                return;
+            }
+            Command::FocusPaneLeft => {
+                self.layout.neighbor(area, Direction::Left)
+            }
+            Command::FocusPaneDown => {
+                self.layout.neighbor(area, Direction::Down)
+            }
+            Command::FocusPaneUp => self.layout.neighbor(area, Direction::Up),
+            Command::FocusPaneRight => {
+                self.layout.neighbor(area, Direction::Right)
+            }
+            Command::FocusNextPane => Some(self.layout.next()),
+            _ => return,
+        };
+        match target {
+            Some(id) => self.focus_window(id),
+            // Left of the leftmost pane is the explorer
+            None if command == Command::FocusPaneLeft
+                && self.explorer.is_some() =>
+            {
+                self.explorer_focused = true;
+                self.results_focused = false;
+            }
+            // Below the lowest pane are the results
+            None if command == Command::FocusPaneDown
+                && self.results.is_some() =>
+            {
+                self.results_focused = true;
+                self.explorer_focused = false;
+            }
+            None => self.show_message(String::from("No pane there")),
+        }
+    }
+
+    /// Splits the focused pane, the new pane shows the file at `path` or
+    /// else the same buffer, and gets the focus
+    fn split_window(&mut self, split: Split, path: Option<String>) {
+        let id = self.layout.split(split);
+        let window = self.copy_window();
+        self.windows.insert(id, window);
+        self.focus_window(id);
+        if let Some(path) = path {
+            self.open_file(path, false);
+        }
+    }
+
+    /// A pane like the focused one, showing the same buffer
+    fn copy_window(&self) -> Window {
+        let window = self.window();
+        Window {
+            view: window.view.clone(),
+            caret: window.caret,
+            buffer: self.buffer_id,
+            folds: window.folds.clone(),
+            selection_anchor: None,
+            selection_history: Vec::new(),
+        }
+    }
+
+    /// Closes the focused pane, and the tab with its last pane. Unsaved
+    /// changes to a buffer no other pane shows are kept unless `force` is
+    /// set. Returns whether it closed.
+    fn close_window(&mut self, force: bool) -> bool {
+        if self.layout.pane_count() == 1 {
+            if !self.tabs.is_empty() {
+                return self.close_tab(force);
+            }
+            self.show_message(String::from("Can not close the last pane"));
+            return false;
+        }
+        if self.is_modified() && !force && !self.is_shown_elsewhere() {
+            self.show_message(String::from(
+                "No write since last change (add ! to override)",
+            ));
+            return false;
+        }
+        let closing = self.layout.focused();
+        let Some(next) = self.layout.close(closing) else {
+            return false;
+        };
+        self.focus_window(next);
+        self.windows.remove(&closing);
+        self.drop_unused_buffers();
+        true
+    }
+
+    /// Gives pane `id` the focus
+    fn focus_window(&mut self, id: usize) {
+        if !self.windows.contains_key(&id) {
+            return;
+        }
+        self.layout.focus(id);
+        self.enter_window();
+        self.explorer_focused = false;
+        self.results_focused = false;
+    }
+
+    /// Makes the buffer of the pane that just got the focus the current
+    /// one, and drops what belonged to the pane that had it
+    fn enter_window(&mut self) {
+        let buffer = self.window().buffer;
+        self.switch_buffer(buffer);
+        self.move_caret(Caret::clamp_to);
+        if let Some(modal) = &mut self.modal {
+            modal.escape_visual();
+        }
+        self.hover = None;
+        self.completion = None;
+        self.pending_completion = None;
+    }
+
+    /// Makes buffer `id` the current one, putting the current one away
+    fn switch_buffer(&mut self, id: usize) {
+        if id == self.buffer_id {
+            return;
+        }
+        self.autosave();
+        let Some(open) = self.buffers.remove(&id) else {
+            return;
+        };
+        let previous = OpenBuffer {
+            buffer: std::mem::replace(&mut self.current_buffer, open.buffer),
+            filename: std::mem::replace(&mut self.filename, open.filename),
+            saved_revision: std::mem::replace(
+                &mut self.saved_revision,
+                open.saved_revision,
+            ),
+            highlighter: std::mem::replace(
+                &mut self.highlighter,
+                open.highlighter,
+            ),
+        };
+        self.buffers.insert(self.buffer_id, previous);
+        self.buffer_id = id;
+        let path = PathBuf::from(&self.filename);
+        let signs = self.lsp.is_attached(&path);
+        let window = self.window_mut();
+        window.buffer = id;
+        window.view.set_signs(signs);
+    }
+
+    /// Whether there is a pane besides the focused one, in any tab
+    fn has_other_panes(&self) -> bool {
+        self.layout.pane_count() > 1 || !self.tabs.is_empty()
+    }
+
+    /// Whether a pane besides the focused one, in any tab, shows the
+    /// current buffer too
+    fn is_shown_elsewhere(&self) -> bool {
+        let shown = self.windows().filter(|w| w.buffer == self.buffer_id);
+        shown.count() > 1
+    }
+
+    /// Buffer `id`, the current one or one another pane shows
+    fn buffer_by_id(&self, id: usize) -> &Buffer {
+        if id == self.buffer_id {
+            &self.current_buffer
+        } else {
+            &self.buffers[&id].buffer
+        }
+    }
+
+    /// Whether buffer `id` has changes that are not saved
+    fn is_buffer_modified(&self, id: usize) -> bool {
+        if id == self.buffer_id {
+            return self.is_modified();
+        }
+        self.buffers
+            .get(&id)
+            .is_some_and(|open| open.buffer.revision() != open.saved_revision)
+    }
+
+    /// Forgets the buffers no pane shows anymore
+    fn drop_unused_buffers(&mut self) {
+        let unused: Vec<usize> = self
+            .buffers
+            .keys()
+            .filter(|id| !self.windows().any(|w| w.buffer == **id))
+            .copied()
+            .collect();
+        for id in unused {
+            if let Some(open) = self.buffers.remove(&id) {
+                info!("Closing buffer {}", open.filename);
+                self.lsp.close(Path::new(&open.filename));
+            }
+        }
+    }
+
+    /// Opens the file finder or the command line for a search in files,
+    /// or jumps to the next or previous match of the last search
+    fn search_command(&mut self, command: Command) {
+        match command {
+            Command::FindFile => return self.find_file(),
+            Command::SearchInFiles => {
+                return self.open_command_line(String::from("grep "));
+            }
+            Command::ReplaceInFiles => {
+                return self.open_command_line(String::from("replace "));
+            }
+            Command::UndoReplace => return self.undo_replace(),
+            _ => {}
+        }
+        let Some(results) = &mut self.results else {
+            self.show_message(String::from("No search in files"));
+            return;
+        };
+        let forward = command == Command::NextResult;
+        let Some(found) = results.step(forward).cloned() else {
+            self.show_message(String::from("No matches"));
+            return;
+        };
+        let position = results.position();
+        if self.go_to_match(&found) {
+            self.show_message(format!("({position}) {}", found.text));
+        }
+    }
+
+    /// Starts searching the files below the working directory. The
+    /// results panel opens with the focus and fills as matches are found.
+    fn search_in_files(&mut self, query: GrepQuery) {
+        let root = match std::env::current_dir() {
+            Ok(root) => root,
+            Err(e) => {
+                self.show_message(format!("Could not search files: {e}"));
+                return;
+            }
+        };
+        info!("Searching for {} below {}", query.regex, root.display());
+        self.results = Some(Results::new(root.clone(), query.regex.clone()));
+        self.replace = None;
+        self.search = Some(Search::start(root, query));
+        self.results_focused = true;
+        self.explorer_focused = false;
+        self.timers
+            .schedule(Timer::SearchPoll, SEARCH_POLL_INTERVAL);
+    }
+
+    /// Adds the matches found since the last time to the results panel
+    fn poll_search(&mut self) {
+        let (Some(search), Some(results)) =
+            (&mut self.search, &mut self.results)
+        else {
+            self.search = None;
+            return;
+        };
+        results.extend(search.poll());
+        if search.is_done() {
+            results.finish();
+            self.search = None;
+            if results.is_empty() {
+                self.show_message(String::from("No matches"));
+            }
+        } else {
+            self.timers
+                .schedule(Timer::SearchPoll, SEARCH_POLL_INTERVAL);
+        }
+    }
+
+    /// Moves the caret to `found`, opening its file first if it is
+    /// another one. Returns whether it got there.
+    fn go_to_match(&mut self, found: &GrepMatch) -> bool {
+        if !self.is_current_file(&found.path) {
+            self.open_file(display_path(&found.path), false);
+            if !self.is_current_file(&found.path) {
+                return false;
+            }
+        }
+        self.jump_to(Location {
+            x: found.start,
+            y: found.line,
+        });
+        true
+    }
+
+    /// Keys while the results panel has the focus: moving through the
+    /// matches, jumping to one and closing the panel
+    fn evaluate_results_key(&mut self, key: &KeyEvent) {
+        let Some(results) = &mut self.results else {
+            return;
+        };
+        let modified = key
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if modified || !self.key_sequence.is_empty() {
+            self.evaluate_key(key);
+            return;
+        }
+        if let Some(replace) = &mut self.replace {
+            match key.code {
+                Up | Char('k') => replace.select_previous(),
+                Down | Char('j') => replace.select_next(),
+                Char(' ') => replace.toggle(),
+                Char('f') => replace.toggle_file(),
+                Enter => self.apply_replace(),
+                Esc | Char('q') => self.replace = None,
+                Tab => self.results_focused = false,
+                _ => {}
+            }
+            return;
+        }
+        match key.code {
+            Up | Char('k') => results.select_previous(),
+            Down | Char('j') => results.select_next(),
+            Enter | Char('o') => {
+                if let Some(found) = results.visit().cloned() {
+                    self.results_focused = false;
+                    self.go_to_match(&found);
                 }
-                PageUp => {
-                    self.caret.page_up();
+            }
+            Char('q') => {
+                self.results = None;
+                self.results_focused = false;
+                self.search = None;
+            }
+            Esc | Tab => self.results_focused = false,
+            _ => {}
+        }
+    }
+
+    /// Previews replacing the matches of the search in files with
+    /// `replacement` in the results panel, where they can be left out one
+    /// by one before any file changes
+    fn preview_replace(&mut self, replacement: String) {
+        let Some(results) = &self.results else {
+            self.show_message(String::from("No search in files"));
+            return;
+        };
+        if self.search.is_some() {
+            self.show_message(String::from("The search is still running"));
+            return;
+        }
+        let replace = Replace::new(
+            results.root().clone(),
+            results.regex().clone(),
+            replacement,
+            results.matches(),
+            |path| self.file_lines(path),
+        );
+        if replace.is_empty() {
+            self.show_message(String::from("Nothing to replace"));
+            return;
+        }
+        self.replace = Some(replace);
+        self.results_focused = true;
+        self.explorer_focused = false;
+        self.show_message(String::from(
+            "Space leaves a match out, f a file, Enter replaces, Esc cancels",
+        ));
+    }
+
+    /// Makes the replacements left in the preview. Every file is written
+    /// before any of them replaces its file, and open buffers take the
+    /// changes as one that `undo_replace` takes back.
+    fn apply_replace(&mut self) {
+        let Some(replace) = &self.replace else {
+            return;
+        };
+        let edits = match replace.edits(|path| self.file_lines(path)) {
+            Ok(edits) if edits.is_empty() => {
+                self.show_message(String::from("Every match is left out"));
+                return;
+            }
+            Ok(edits) => edits,
+            Err(e) => {
+                self.show_message(e);
+                return;
+            }
+        };
+        let count = replace.included_count();
+        // Writing them would save their other changes along
+        let unsaved = edits.iter().find(|(path, _)| {
+            self.open_buffer_id(path)
+                .is_some_and(|id| self.is_buffer_modified(id))
+        });
+        if let Some((path, _)) = unsaved {
+            self.show_message(format!(
+                "{} has unsaved changes",
+                display_path(path)
+            ));
+            return;
+        }
+        let files: Result<Vec<(PathBuf, String)>, String> = edits
+            .iter()
+            .map(|(path, changes)| {
+                let text = self.file_text(path).map_err(|e| {
+                    format!("Could not read {}: {e}", display_path(path))
+                })?;
+                Ok((path.clone(), replace::edit_text(&text, changes)))
+            })
+            .collect();
+        let written = files.and_then(|files| {
+            self.write_files(&files)
+                .map_err(|e| format!("Nothing replaced, could not write {e}"))
+        });
+        if let Err(e) = written {
+            self.show_message(e);
+            return;
+        }
+        info!("Replaced {count} matches in {} files", edits.len());
+        for (path, changes) in &edits {
+            match self.open_buffer_id(path) {
+                Some(id) if id == self.buffer_id => {
+                    self.current_buffer.replace_lines(changes);
+                    self.saved_revision = self.current_buffer.revision();
+                    self.move_caret(Caret::clamp_to);
+                    self.lsp.sync(path, &self.current_buffer);
+                    self.lsp.save(path);
+                    self.file_watcher.watch(path.clone());
                 }
-                PageDown => {
-                    self.caret.page_down();
+                Some(id) => {
+                    if let Some(open) = self.buffers.get_mut(&id) {
+                        open.buffer.replace_lines(changes);
+                        open.saved_revision = open.buffer.revision();
+                    }
+                    self.file_watcher.watch(path.clone());
                 }
-                _ => info!("Unhandled key event: {:?}", code),
+                None => {}
             }
         }
+        self.replace = None;
+        self.results = None;
+        self.results_focused = false;
+        self.show_message(format!(
+            "Replaced {count} matches in {} files",
+            edits.len()
+        ));
     }
 
-    fn set_up_panic_hook() {
-        let current_hook = std::panic::take_hook();
-        std::panic::set_hook(Box::new(move |panic_info| {
-            match Terminal::terminate() {
-                Ok(_) => {}
-                Err(e) => {
-                    println!("{}", e);
-                }
+    /// Takes back the last replace in files in the current buffer, if it
+    /// has not changed since. The file is left as it is until saved.
+    fn undo_replace(&mut self) {
+        if self.current_buffer.undo() {
+            self.move_caret(Caret::clamp_to);
+            self.show_message(String::from("Replace in files taken back"));
+        } else {
+            self.show_message(String::from("No replace in files to undo"));
+        }
+    }
+
+    /// The text of the file at `path`, from its buffer if it is open, as
+    /// saving the buffer would write it
+    fn file_text(&self, path: &Path) -> Result<String, Error> {
+        match self.open_buffer_id(path) {
+            Some(id) if id == self.buffer_id => {
+                Ok(self.current_buffer.contents())
             }
-            current_hook(panic_info);
-        }));
+            Some(id) => Ok(self.buffers[&id].buffer.contents()),
+            None => fs::read_to_string(path),
+        }
+    }
+
+    /// The lines of the file at `path`, from its buffer if it is open
+    fn file_lines(&self, path: &Path) -> Vec<String> {
+        match self.open_buffer_id(path) {
+            Some(id) if id == self.buffer_id => {
+                self.current_buffer.lines.clone()
+            }
+            Some(id) => self.buffers[&id].buffer.lines.clone(),
+            None => read_lines(path),
+        }
+    }
+
+    /// The id of the buffer of the file at `path`, if it is open
+    fn open_buffer_id(&self, path: &Path) -> Option<usize> {
+        if self.is_current_file(path) {
+            return Some(self.buffer_id);
+        }
+        self.buffers
+            .iter()
+            .find(|(_, open)| {
+                !open.filename.is_empty() && is_same_file(&open.filename, path)
+            })
+            .map(|(id, _)| *id)
+    }
+
+    /// Runs the commands that open, close, move or go to tabs
+    fn tab_command(&mut self, command: Command) {
+        let count = self.tabs.len() + 1;
+        match command {
+            Command::NewTab => self.new_tab(None),
+            Command::CloseTab => {
+                self.close_tab(false);
+            }
+            Command::NextTab => self.switch_tab((self.tab + 1) % count),
+            Command::PreviousTab => {
+                self.switch_tab((self.tab + count - 1) % count);
+            }
+            Command::MoveTabLeft => self.tab = self.tab.saturating_sub(1),
+            Command::MoveTabRight => self.tab = (self.tab + 1).min(count - 1),
+            _ => {}
+        }
+    }
+
+    /// Opens a tab right after the current one and goes to it. The tab
+    /// shows the file at `path`, or else the current buffer.
+    fn new_tab(&mut self, path: Option<String>) {
+        let layout = Layout::default();
+        let windows = HashMap::from([(layout.focused(), self.copy_window())]);
+        // `tabs` leaves out the current tab, so this is right after it
+        self.tabs.insert(self.tab, TabPage { layout, windows });
+        self.switch_tab(self.tab + 1);
+        if let Some(path) = path {
+            self.open_file(path, false);
+        }
+    }
+
+    /// Goes to tab `index`, putting the current tab away
+    fn switch_tab(&mut self, index: usize) {
+        if index == self.tab || index > self.tabs.len() {
+            return;
+        }
+        let mut tab = self.tabs.remove(self.tab_slot(index));
+        std::mem::swap(&mut self.layout, &mut tab.layout);
+        std::mem::swap(&mut self.windows, &mut tab.windows);
+        let previous = std::mem::replace(&mut self.tab, index);
+        self.tabs.insert(self.tab_slot(previous), tab);
+        self.enter_window();
+    }
+
+    /// Closes the current tab with its panes and goes to the next one.
+    /// Unsaved changes to a buffer no other tab shows are kept unless
+    /// `force` is set. Returns whether it closed.
+    fn close_tab(&mut self, force: bool) -> bool {
+        if self.tabs.is_empty() {
+            self.show_message(String::from("Can not close the last tab"));
+            return false;
+        }
+        let unsaved = self.windows.values().any(|window| {
+            self.is_buffer_modified(window.buffer)
+                && !self.tabs.iter().any(|tab| {
+                    tab.windows.values().any(|w| w.buffer == window.buffer)
+                })
+        });
+        if unsaved && !force {
+            self.show_message(String::from(
+                "No write since last change (add ! to override)",
+            ));
+            return false;
+        }
+        let closing = self.tab;
+        let next = if closing < self.tabs.len() {
+            closing + 1
+        } else {
+            closing - 1
+        };
+        self.switch_tab(next);
+        self.tabs.remove(self.tab_slot(closing));
+        if closing < self.tab {
+            self.tab -= 1;
+        }
+        self.drop_unused_buffers();
+        true
+    }
+
+    /// Where tab `index` is in `tabs`, which leaves out the current one
+    fn tab_slot(&self, index: usize) -> usize {
+        if index < self.tab { index } else { index - 1 }
+    }
+
+    /// A label per tab for the tab bar: its number and the file of its
+    /// focused pane, with `+` when that has unsaved changes
+    fn tab_labels(&self) -> Vec<String> {
+        (0..=self.tabs.len())
+            .map(|index| {
+                let buffer = if index == self.tab {
+                    Some(self.buffer_id)
+                } else {
+                    let tab = &self.tabs[self.tab_slot(index)];
+                    let focused = tab.windows.get(&tab.layout.focused());
+                    focused.map(|window| window.buffer)
+                };
+                let filename = match buffer {
+                    Some(id) if id == self.buffer_id => Some(&self.filename),
+                    Some(id) => self.buffers.get(&id).map(|b| &b.filename),
+                    None => None,
+                };
+                let name = filename
+                    .and_then(|filename| Path::new(filename).file_name())
+                    .map_or_else(
+                        || String::from("[No Name]"),
+                        |name| name.to_string_lossy().into_owned(),
+                    );
+                let modified =
+                    buffer.is_some_and(|id| self.is_buffer_modified(id));
+                let modified = if modified { " +" } else { "" };
+                format!(" {} {name}{modified} ", index + 1)
+            })
+            .collect()
+    }
+
+    /// Goes to the tab clicked in the tab bar, other mouse events are
+    /// ignored
+    fn evaluate_mouse(&mut self, mouse: MouseEvent) {
+        let is_click = mouse.kind == MouseEventKind::Down(MouseButton::Left);
+        if !is_click || mouse.row != 0 || self.tabs.is_empty() {
+            return;
+        }
+        let column = usize::from(mouse.column);
+        if let Some(index) = tab_at(&self.tab_labels(), column) {
+            self.switch_tab(index);
+        }
+    }
+
+    /// Opens the explorer on the directory of the current file, or else
+    /// the working directory, and gives it the focus. From inside the
+    /// explorer it closes it.
+    fn toggle_explorer(&mut self) {
+        if self.explorer_focused {
+            self.explorer = None;
+            self.explorer_focused = false;
+            return;
+        }
+        if let Some(explorer) = &mut self.explorer {
+            explorer.refresh();
+        } else {
+            let file = std::path::absolute(&self.filename).ok();
+            let root = file
+                .as_deref()
+                .and_then(Path::parent)
+                .filter(|dir| dir.is_dir())
+                .map(Path::to_path_buf)
+                .or_else(|| std::env::current_dir().ok());
+            let Some(root) = root else {
+                return;
+            };
+            info!("Opening explorer on {}", root.display());
+            self.explorer = Some(Explorer::new(root));
+        }
+        self.explorer_focused = true;
+        self.results_focused = false;
+        self.hover = None;
+        self.completion = None;
+    }
+
+    /// Keys while the explorer has the focus: moving through the tree,
+    /// opening files and changing them. Keys with Ctrl or Alt run their
+    /// commands as usual.
+    fn evaluate_explorer_key(&mut self, key: &KeyEvent) {
+        let Some(explorer) = &mut self.explorer else {
+            return;
+        };
+        if let Some(prompt) = explorer.prompt_mut() {
+            // Deleting only asks for a yes
+            let confirm = matches!(prompt.action, Action::Delete(_));
+            match key.code {
+                Char('y') if confirm => self.apply_explorer_prompt(),
+                _ if confirm => {
+                    explorer.take_prompt();
+                }
+                Char(c) => prompt.input.push(c),
+                Backspace => {
+                    prompt.input.pop();
+                }
+                Enter => self.apply_explorer_prompt(),
+                Esc => {
+                    explorer.take_prompt();
+                }
+                _ => {}
+            }
+            return;
+        }
+        let modified = key
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if modified || !self.key_sequence.is_empty() {
+            self.evaluate_key(key);
+            return;
+        }
+        let selected = explorer.selected().cloned();
+        let file = selected
+            .as_ref()
+            .filter(|entry| !entry.is_dir)
+            .map(|entry| display_path(&entry.path));
+        // New files go into the selected directory, or next to the file
+        let dir = selected.as_ref().map_or_else(
+            || explorer.root().to_path_buf(),
+            |entry| match entry.path.parent() {
+                Some(parent) if !entry.is_dir => parent.to_path_buf(),
+                _ => entry.path.clone(),
+            },
+        );
+        match (key.code, file) {
+            (Up | Char('k'), _) => explorer.select_previous(),
+            (Down | Char('j'), _) => explorer.select_next(),
+            (Enter | Right | Char('l' | 'o'), Some(file)) => {
+                self.explorer_focused = false;
+                self.open_file(file, false);
+            }
+            (Enter, None) => explorer.toggle(),
+            (Right | Char('l' | 'o'), None) => explorer.expand(),
+            (Left | Char('h'), _) => explorer.collapse(),
+            (Char('s'), Some(file)) => {
+                self.split_window(Split::Horizontal, Some(file));
+            }
+            (Char('v'), Some(file)) => {
+                self.split_window(Split::Vertical, Some(file));
+            }
+            (Char('t'), Some(file)) => {
+                self.explorer_focused = false;
+                self.new_tab(Some(file));
+            }
+            (Char('a'), _) => explorer.ask(Action::Create(dir)),
+            (Char('r' | 'm' | 'd'), _) => {
+                let Some(entry) = selected else {
+                    return;
+                };
+                let action = match key.code {
+                    Char('r') => Action::Rename(entry.path),
+                    Char('m') => Action::Move(entry.path),
+                    _ => Action::Delete(entry.path),
+                };
+                explorer.ask(action);
+            }
+            (Char('u'), _) => explorer.up(),
+            (Char('R'), _) => explorer.refresh(),
+            (Char('q'), _) => self.toggle_explorer(),
+            (Esc | Tab, _) => self.explorer_focused = false,
+            _ => {}
+        }
+    }
+
+    /// Carries out what the explorer asked about, and moves the buffers
+    /// of files it moved along with them
+    fn apply_explorer_prompt(&mut self) {
+        let Some(explorer) = &mut self.explorer else {
+            return;
+        };
+        let Some(prompt) = explorer.take_prompt() else {
+            return;
+        };
+        let message = match explorer.apply(prompt) {
+            Ok(Change::Created(path)) => {
+                format!("Created {}", display_path(&path))
+            }
+            Ok(Change::Moved { from, to }) => {
+                self.move_buffers(&from, &to);
+                format!(
+                    "Moved {} to {}",
+                    display_path(&from),
+                    display_path(&to)
+                )
+            }
+            Ok(Change::Deleted(path)) => {
+                format!("Deleted {}", display_path(&path))
+            }
+            Err(e) => e,
+        };
+        self.show_message(message);
+    }
+
+    /// Points the buffers of the files at or below `from` to where they
+    /// were moved, at or below `to`
+    fn move_buffers(&mut self, from: &Path, to: &Path) {
+        let current = self.filename.clone();
+        let filenames = std::iter::once(&mut self.filename)
+            .chain(self.buffers.values_mut().map(|open| &mut open.filename));
+        for filename in filenames {
+            let Ok(path) = std::path::absolute(&*filename) else {
+                continue;
+            };
+            let Ok(rest) = path.strip_prefix(from) else {
+                continue;
+            };
+            let moved = if rest.as_os_str().is_empty() {
+                to.to_path_buf()
+            } else {
+                to.join(rest)
+            };
+            info!("Buffer {filename} moved to {}", moved.display());
+            self.lsp.close(&path);
+            *filename = display_path(&moved);
+        }
+        if self.filename != current {
+            self.detect_language();
+            self.attach_lsp();
+        }
+    }
+
+    /// Shows `message` in the message bar for a few seconds
+    pub fn show_message(&mut self, message: String) {
+        self.message = Some(message);
+        self.timers.schedule(Timer::MessageExpiry, MESSAGE_DURATION);
+    }
+
+    /// Runs whatever `timer` was scheduled for
+    pub fn fire_timer(&mut self, timer: Timer) {
+        debug!("Timer fired: {timer:?}");
+        self.timers.cancel(timer);
+        match timer {
+            Timer::MessageExpiry => self.message = None,
+            Timer::ConfigCheck => {
+                let changed = self
+                    .config_watcher
+                    .as_mut()
+                    .is_some_and(FileWatcher::changed);
+                if changed {
+                    info!("Configuration changed, reloading");
+                    self.load_config();
+                    if self.message.is_none() {
+                        self.show_message(String::from(
+                            "Configuration reloaded",
+                        ));
+                    }
+                } else {
+                    self.timers
+                        .schedule(Timer::ConfigCheck, CONFIG_CHECK_INTERVAL);
+                }
+            }
+            Timer::LspPoll => self.poll_lsp(),
+            Timer::WalkPoll => self.poll_file_walk(),
+            Timer::SearchPoll => self.poll_search(),
+            Timer::SwapWrite => self.write_swaps(),
+            Timer::Autosave => self.autosave(),
+            Timer::DiskCheck => self.check_disk(),
+        }
+    }
+
+    /// Hands the current file to the language server of its language, if
+    /// one is configured
+    fn attach_lsp(&mut self) {
+        let path = PathBuf::from(&self.filename);
+        if let Some(language) = self.highlighter.language()
+            && !self.filename.is_empty()
+            && let Err(e) = self.lsp.open(&path, language.name())
+        {
+            info!("{e}");
+            self.show_message(e);
+        }
+        let signs = self.lsp.is_attached(&path);
+        self.window_mut().view.set_signs(signs);
+        if self.lsp.is_active() {
+            self.timers.schedule(Timer::LspPoll, LSP_POLL_INTERVAL);
+        }
+    }
+
+    /// Sends the language server the latest changes and acts on what it
+    /// sent back
+    fn poll_lsp(&mut self) {
+        let path = PathBuf::from(&self.filename);
+        self.lsp.sync(&path, &self.current_buffer);
+        for reply in self.lsp.poll() {
+            self.handle_reply(reply);
+        }
+        let signs = self.lsp.is_attached(&path);
+        self.window_mut().view.set_signs(signs);
+        if self.lsp.is_active() {
+            self.timers.schedule(Timer::LspPoll, LSP_POLL_INTERVAL);
+        }
+    }
+
+    /// Runs the commands that need a language server, or can use one
+    fn lsp_command(&mut self, command: Command) {
+        let request = match command {
+            Command::Complete => {
+                self.complete(false);
+                return;
+            }
+            Command::Hover => Request::Hover,
+            Command::GoToDefinition => Request::Definition,
+            Command::FindReferences => Request::References,
+            Command::FormatDocument => Request::Format,
+            Command::RenameSymbol => {
+                // The new name is typed on the command line
+                match self.word_at_caret() {
+                    Some(word) => {
+                        self.open_command_line(format!("rename {word}"));
+                    }
+                    None => {
+                        self.show_message(String::from("Nothing to rename"));
+                    }
+                }
+                return;
+            }
+            _ => return,
+        };
+        self.lsp_request(request);
+    }
+
+    /// Asks the language server `request` about the symbol at the caret
+    fn lsp_request(&mut self, request: Request) {
+        let path = PathBuf::from(&self.filename);
+        let at = self.window().caret.location;
+        if let Err(e) =
+            self.lsp.request(request, &path, &self.current_buffer, at)
+        {
+            self.show_message(e);
+        }
+    }
+
+    fn handle_reply(&mut self, reply: Reply) {
+        match reply {
+            Reply::Hover(text) => self.hover = Some(Popup::new(&text)),
+            Reply::Definition(locations) => match locations.as_slice() {
+                [] => self.show_message(String::from("No definition found")),
+                [location] => self.go_to_file_location(location),
+                _ => self.pick_location("Definitions", locations),
+            },
+            Reply::References(locations) if locations.is_empty() => {
+                self.show_message(String::from("No references found"));
+            }
+            Reply::References(locations) => {
+                self.pick_location("References", locations);
+            }
+            Reply::Rename(files) => self.apply_workspace_edit(&files),
+            Reply::Format {
+                path,
+                revision,
+                edits,
+            } => {
+                if !self.is_current_file(&path)
+                    || revision != self.current_buffer.revision()
+                {
+                    self.show_message(String::from(
+                        "The buffer changed, formatting skipped",
+                    ));
+                } else if edits.is_empty() {
+                    self.show_message(String::from("Already formatted"));
+                } else {
+                    lsp::apply_edits(&mut self.current_buffer, &edits);
+                    self.move_caret(Caret::clamp_to);
+                    self.show_message(String::from("Formatted"));
+                }
+            }
+            Reply::Completion { path, items } => {
+                let Some(start) = self.pending_completion.take() else {
+                    return;
+                };
+                let (word_start, typed) = self.word_before_caret();
+                if self.is_current_file(&path) && word_start == start {
+                    let items = if items.is_empty() {
+                        self.completion_words(&typed)
+                    } else {
+                        items
+                    };
+                    self.show_completion(start, &typed, items);
+                }
+            }
+            Reply::Error(e) => self.show_message(e),
+        }
+    }
+
+    /// Whether `path` is the file of the current buffer
+    fn is_current_file(&self, path: &Path) -> bool {
+        !self.filename.is_empty() && is_same_file(&self.filename, path)
+    }
+
+    /// Moves the caret to `location`, opening its file first if it is
+    /// another one
+    fn go_to_file_location(&mut self, location: &FileLocation) {
+        if !self.is_current_file(&location.path) {
+            self.open_file(display_path(&location.path), false);
+            if !self.is_current_file(&location.path) {
+                return;
+            }
+        }
+        let target = location.start.location(&self.current_buffer.lines);
+        self.jump_to(target);
+    }
+
+    /// Opens a picker to jump to one of `locations`, each shown with its
+    /// line
+    fn pick_location(&mut self, title: &str, locations: Vec<FileLocation>) {
+        let mut files: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        let items = locations
+            .into_iter()
+            .map(|location| {
+                let y = location.start.line;
+                let line = if self.is_current_file(&location.path) {
+                    self.current_buffer.lines.get(y).cloned()
+                } else {
+                    files
+                        .entry(location.path.clone())
+                        .or_insert_with(|| read_lines(&location.path))
+                        .get(y)
+                        .cloned()
+                };
+                let label = format!(
+                    "{}:{}: {}",
+                    display_path(&location.path),
+                    y + 1,
+                    line.unwrap_or_default().trim()
+                );
+                (label, location)
+            })
+            .collect();
+        self.picker = Some(Picker::new(title, items));
+        self.file_walk = None;
+        self.update_preview();
+    }
+
+    /// Opens a picker over the files below the working directory, listed
+    /// on another thread while it is open
+    fn find_file(&mut self) {
+        let root = match std::env::current_dir() {
+            Ok(root) => root,
+            Err(e) => {
+                self.show_message(format!("Could not list files: {e}"));
+                return;
+            }
+        };
+        info!("Finding files below {}", root.display());
+        self.picker = Some(Picker::new("Files", Vec::new()));
+        self.file_walk = Some(FileWalk::start(root));
+        self.hover = None;
+        self.completion = None;
+        self.timers.schedule(Timer::WalkPoll, WALK_POLL_INTERVAL);
+    }
+
+    /// Adds the files listed since the last time to the file finder
+    fn poll_file_walk(&mut self) {
+        let (Some(walk), Some(picker)) =
+            (&mut self.file_walk, &mut self.picker)
+        else {
+            self.file_walk = None;
+            return;
+        };
+        let files: Vec<(String, FileLocation)> = walk
+            .poll()
+            .into_iter()
+            .map(|path| (display_path(&path), FileLocation::file(path)))
+            .collect();
+        let first = picker.candidate_count() == 0 && !files.is_empty();
+        picker.extend(files);
+        let count = picker.candidate_count();
+        if walk.is_done() {
+            picker.set_title(&format!("Files ({count})"));
+            self.file_walk = None;
+        } else {
+            picker.set_title(&format!("Files ({count}…)"));
+            self.timers.schedule(Timer::WalkPoll, WALK_POLL_INTERVAL);
+        }
+        if first {
+            self.update_preview();
+        }
+    }
+
+    /// Shows the file of the selected item of the picker, from a little
+    /// above its place
+    fn update_preview(&mut self) {
+        let Some(picker) = &self.picker else {
+            return;
+        };
+        let preview = match picker.selected() {
+            Some(location) => {
+                let first = location.start.line.saturating_sub(PREVIEW_CONTEXT);
+                if self.is_current_file(&location.path) {
+                    self.current_buffer
+                        .lines
+                        .iter()
+                        .skip(first)
+                        .take(MAX_PREVIEW)
+                        .cloned()
+                        .collect()
+                } else {
+                    preview_lines(&location.path, first, MAX_PREVIEW)
+                }
+            }
+            None => Vec::new(),
+        };
+        if let Some(picker) = &mut self.picker {
+            picker.set_preview(preview);
+        }
+    }
+
+    /// Makes the edits of a rename: undoably in the buffers of open files,
+    /// on disk for the others. Nothing changes if a file fails.
+    fn apply_workspace_edit(
+        &mut self,
+        files: &[(PathBuf, Vec<lsp::protocol::TextEdit>)],
+    ) {
+        // Every file is edited before any is changed, open ones in their
+        // buffer and the others on disk
+        let mut open = Vec::new();
+        let mut written = Vec::new();
+        for (path, edits) in files {
+            let id = self.open_buffer_id(path);
+            let mut buffer = match id {
+                Some(id) => {
+                    let mut buffer = Buffer::new();
+                    buffer.set_lines(&self.buffer_by_id(id).lines);
+                    buffer
+                }
+                None => match Buffer::read_file(&path.to_string_lossy()) {
+                    Ok(buffer) => buffer,
+                    Err(e) => {
+                        debug!("Error renaming in {}: {e}", path.display());
+                        self.show_message(format!(
+                            "Nothing renamed, could not read {}: {e}",
+                            display_path(path)
+                        ));
+                        return;
+                    }
+                },
+            };
+            lsp::apply_edits(&mut buffer, edits);
+            match id {
+                Some(id) => open.push((id, buffer.lines)),
+                None => written.push((path.clone(), buffer.contents())),
+            }
+        }
+        if let Err(e) = self.write_files(&written) {
+            debug!("Error renaming: {e}");
+            self.show_message(format!("Nothing renamed, could not write {e}"));
+            return;
+        }
+        for (id, lines) in &open {
+            if *id == self.buffer_id {
+                self.current_buffer.change_lines(lines);
+            } else if let Some(open) = self.buffers.get_mut(id) {
+                open.buffer.change_lines(lines);
+            }
+        }
+        self.move_caret(Caret::clamp_to);
+        let renamed = files.len();
+        if renamed == 0 {
+            self.show_message(String::from("Nothing to rename"));
+        } else {
+            let files = if renamed == 1 { "file" } else { "files" };
+            self.show_message(format!("Renamed in {renamed} {files}"));
+        }
+    }
+
+    /// The word the caret is on, for commands to start from
+    fn word_at_caret(&self) -> Option<String> {
+        let Location { x, y } = self.window().caret.location;
+        let line: Vec<char> =
+            self.current_buffer.lines.get(y)?.chars().collect();
+        let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+        let start = line[..x.min(line.len())]
+            .iter()
+            .rev()
+            .take_while(|c| is_word(c))
+            .count();
+        let start = x.min(line.len()) - start;
+        let end = line[start..].iter().take_while(|c| is_word(c)).count();
+        (end > 0).then(|| line[start..start + end].iter().collect())
+    }
+
+    /// Where the word before the caret starts, and that part of it
+    fn word_before_caret(&self) -> (Location, String) {
+        let Location { x, y } = self.window().caret.location;
+        let line = self.current_buffer.lines.get(y).map_or("", String::as_str);
+        let before: Vec<char> = line.chars().take(x).collect();
+        let len = before
+            .iter()
+            .rev()
+            .take_while(|c| is_word_char(**c))
+            .count();
+        let start = before.len() - len;
+        (Location { x: start, y }, before[start..].iter().collect())
+    }
+
+    /// The words of all buffers to complete `typed`, the word before the
+    /// caret, with. The word the caret is in is left out, it is what is
+    /// being typed.
+    fn completion_words(&self, typed: &str) -> Vec<CompletionItem> {
+        let Location { x, y } = self.window().caret.location;
+        let line = self.current_buffer.lines.get(y).map_or("", String::as_str);
+        let byte = |x: usize| {
+            line.char_indices().nth(x).map_or(line.len(), |(i, _)| i)
+        };
+        let start = byte(x - typed.chars().count());
+        let end = line[byte(x)..]
+            .find(|c: char| !is_word_char(c))
+            .map_or(line.len(), |len| byte(x) + len);
+        let current = self
+            .current_buffer
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != y)
+            .map(|(_, line)| line.as_str());
+        let others = self
+            .buffers
+            .values()
+            .flat_map(|open| &open.buffer.lines)
+            .map(String::as_str);
+        buffer_words(
+            current.chain([&line[..start], &line[end..]]).chain(others),
+            typed,
+        )
+    }
+
+    /// Opens the completion menu for the word before the caret, with what
+    /// the language server suggests or else the words of the buffer.
+    /// With `quiet` nothing is said when there is nothing to complete.
+    fn complete(&mut self, quiet: bool) {
+        let (start, typed) = self.word_before_caret();
+        let path = PathBuf::from(&self.filename);
+        if self.lsp.is_attached(&path) {
+            let at = self.window().caret.location;
+            let request = Request::Complete;
+            match self.lsp.request(request, &path, &self.current_buffer, at) {
+                Ok(()) => {
+                    self.pending_completion = Some(start);
+                    return;
+                }
+                Err(e) => debug!("Completing with buffer words: {e}"),
+            }
+        }
+        let words = self.completion_words(&typed);
+        self.show_completion(start, &typed, words);
+        if self.completion.is_none() && !quiet {
+            self.show_message(String::from("No completions"));
+        }
+    }
+
+    fn show_completion(
+        &mut self,
+        start: Location,
+        typed: &str,
+        items: Vec<CompletionItem>,
+    ) {
+        let completion = Completion::new(start, typed, items);
+        self.completion = (!completion.is_empty()).then_some(completion);
+    }
+
+    /// Keys the completion menu takes while it is open, returns whether
+    /// `key` was one of them
+    fn evaluate_completion_key(&mut self, key: &KeyEvent) -> bool {
+        let Some(completion) = &mut self.completion else {
+            return false;
+        };
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            Tab | Enter if !control => self.accept_completion(),
+            Esc => self.completion = None,
+            Up => completion.select_previous(),
+            Char('p') if control => completion.select_previous(),
+            Down => completion.select_next(),
+            Char('n') if control => completion.select_next(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Replaces the word before the caret with the selected completion
+    fn accept_completion(&mut self) {
+        let Some(completion) = self.completion.take() else {
+            return;
+        };
+        let Some(item) = completion.selected() else {
+            return;
+        };
+        let start = completion.start();
+        self.current_buffer
+            .delete(start, self.window().caret.location);
+        let end = self.current_buffer.insert_str(&item.insert_text, start);
+        self.window_mut().caret.move_to(end);
+    }
+
+    /// Follows the completion menu after a key: opens it when a word
+    /// was typed far enough, filters it by the word, and closes it once
+    /// the caret left the word
+    fn update_completion(&mut self, key: &KeyEvent, revision: u64) {
+        let typing =
+            self.modal.as_ref().is_none_or(|m| m.mode() == Mode::Insert)
+                && self.palette.is_none()
+                && self.command_line.is_none()
+                && self.picker.is_none();
+        if !typing {
+            self.completion = None;
+            self.pending_completion = None;
+            return;
+        }
+        let (start, typed) = self.word_before_caret();
+        if let Some(completion) = &mut self.completion {
+            if completion.start() == start {
+                completion.set_query(&typed);
+            }
+            if completion.start() != start || completion.is_empty() {
+                self.completion = None;
+            }
+        }
+        if self
+            .pending_completion
+            .is_some_and(|pending| pending != start)
+        {
+            self.pending_completion = None;
+        }
+        let typed_word_char = matches!(key.code, Char(c) if is_word_char(c))
+            && !key
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if typed_word_char
+            && revision != self.current_buffer.revision()
+            && self.completion.is_none()
+            && self.pending_completion.is_none()
+            && typed.chars().count() >= COMPLETION_PREFIX
+        {
+            self.complete(true);
+        }
+    }
+
+    /// Sizes the panes and caret after the terminal
+    fn fit_to_terminal(&mut self) {
+        match self.terminal.size() {
+            Ok(size) => {
+                self.size = size;
+                self.arrange_windows();
+                self.fit_caret();
+            }
+            Err(e) => debug!("Error reading terminal size: {e}"),
+        }
+    }
+
+    /// Sizes the caret of the focused pane after its text area
+    fn fit_caret(&mut self) {
+        let window = self.window_mut();
+        window.caret.size = window.view.text_area_size();
+    }
+
+    pub fn run(&mut self) {
+        info!("--------------------------------------------");
+        info!("Editor is running");
+        Self::set_up_panic_hook(recovery_dir());
+        if let Err(e) = self.terminal.initialize() {
+            debug!("Error initializing terminal: {e}");
+            panic!();
+        }
+
+        self.swap_dir = paths::state_dir().map(|dir| dir.join("swap"));
+        if self.swap_dir.is_some() {
+            self.timers.schedule(Timer::SwapWrite, SWAP_INTERVAL);
+        }
+        match Buffer::read_file(&self.filename) {
+            Ok(buffer) => {
+                self.saved_revision = buffer.revision();
+                self.current_buffer = buffer;
+                self.detect_language();
+                self.attach_lsp();
+                self.watch_file(&self.filename.clone());
+                self.check_swap();
+            }
+            Err(e) => {
+                debug!("Error opening file: {e}");
+                // TODO(dan): for now panic is fine, in the future maybe
+                // open some kind of dashboard
+                panic!();
+            }
+        }
+        // self.buffers.push(self.current_buffer);
+        self.load_state();
+
+        self.fit_to_terminal();
+        if self.message.is_none() {
+            self.show_message(String::from(
+                "HELP: Ctrl-S = save | Ctrl-Q = quit",
+            ));
+        }
+        let outcome =
+            std::panic::catch_unwind(AssertUnwindSafe(|| self.repl()));
+        if let Err(panic) = outcome {
+            self.save_after_panic(&recovery_dir());
+            std::panic::resume_unwind(panic);
+        }
+        self.remove_swaps();
+        self.terminal.terminate().unwrap();
+        info!("Editor finished running");
+        info!("--------------------------------------------");
+    }
+
+    /// Loads the macros, recently used commands and command history kept
+    /// in the data directory, and keeps them there as they change
+    pub fn load_state(&mut self) {
+        self.macros_path = paths::data_dir().map(|dir| dir.join("macros"));
+        self.recent_commands_path =
+            paths::data_dir().map(|dir| dir.join("recent_commands"));
+        self.command_history_path =
+            paths::data_dir().map(|dir| dir.join("command_history"));
+        self.load_recent_commands();
+        self.load_command_history();
+        if let Some(path) = &self.macros_path {
+            match Macros::load(path) {
+                Ok(macros) => self.macros = macros,
+                Err(e) => debug!("Error loading macros: {e}"),
+            }
+        }
+    }
+
+    /// Like `load_state`, without changing what is kept, for replays
+    pub fn read_state(&mut self) {
+        self.load_state();
+        self.macros_path = None;
+        self.recent_commands_path = None;
+        self.command_history_path = None;
+    }
+
+    pub fn repl(&mut self) {
+        info!("Starting read-evaluate-print loop");
+
+        loop {
+            self.render();
+
+            if self.should_quit {
+                info!("Quitting editor");
+                let view = &self.windows[&self.layout.focused()].view;
+                match view.goodbye_message(&mut self.terminal, self.size) {
+                    Ok(()) => {
+                        sleep(Duration::from_secs(1));
+                    }
+                    Err(e) => {
+                        debug!("Error printing goodbye message: {e}");
+                    }
+                }
+                break;
+            }
+
+            let timeout = self.timers.next_timeout(Instant::now());
+            match self.events.next_event(timeout) {
+                Ok(Input::Event(event)) => self.evaluate_event(&event),
+                Ok(Input::Timeout) => {}
+                Ok(Input::Closed) => {
+                    info!("Event source closed");
+                    break;
+                }
+                Err(e) => {
+                    debug!("Error handling event: {e}");
+                }
+            }
+
+            for timer in self.timers.take_due(Instant::now()) {
+                self.fire_timer(timer);
+            }
+        }
+
+        info!("Exiting REPL loop");
+    }
+
+    fn evaluate_event(&mut self, event: &Event) {
+        info!("Evaluating event: {event:?}");
+        if let Resize(x, y) = event {
+            let size = Size {
+                height: usize::from(*y),
+                width: usize::from(*x),
+            };
+            self.size = size;
+            self.arrange_windows();
+            self.fit_caret();
+            return;
+        }
+        if let Mouse(mouse) = event {
+            self.evaluate_mouse(*mouse);
+            return;
+        }
+        if matches!(event, Event::FocusGained | Event::FocusLost) {
+            if *event == Event::FocusLost {
+                self.autosave();
+            }
+            return;
+        }
+        let window = self.window_mut();
+        if window.view.is_new_buffer {
+            // The first event only dismisses the welcome message
+            window.view.is_new_buffer = false;
+            window.caret.location = Location { x: 0, y: 0 };
+            return;
+        }
+        if let Key(key) = event {
+            let was_recording = self.macros.recording().is_some();
+            let before = self.window().caret.location;
+            let revision = self.current_buffer.revision();
+            self.hover = None;
+            if self.palette.is_some() {
+                self.evaluate_palette_key(key);
+            } else if self.command_line.is_some() {
+                self.evaluate_command_line_key(key);
+            } else if self.picker.is_some() {
+                self.evaluate_picker_key(key);
+            } else if self.explorer_focused {
+                self.evaluate_explorer_key(key);
+            } else if self.results_focused {
+                self.evaluate_results_key(key);
+            } else if self.evaluate_completion_key(key) {
+                // The completion menu took it
+            } else if let Some(pending) = self.pending.take() {
+                self.evaluate_pending(pending, key);
+            } else if self.modal.is_some() {
+                self.evaluate_modal_key(key);
+            } else {
+                self.evaluate_key(key);
+            }
+            // Keys that start or stop the recording are not part of it, and
+            // neither are the keys a macro plays back
+            if was_recording && self.replay_depth == 0 {
+                self.macros.record(*key);
+            }
+            self.skip_folds(before);
+            self.update_completion(key, revision);
+            if self.selection().is_none() {
+                self.window_mut().selection_history.clear();
+            }
+            if self.config.autosave > 0 {
+                let idle = Duration::from_secs(self.config.autosave);
+                self.timers.schedule(Timer::Autosave, idle);
+            }
+        }
+    }
+
+    /// Looks `key` up in the keymap together with the keys before it,
+    /// inserting unbound characters into the buffer
+    fn evaluate_key(&mut self, key: &KeyEvent) {
+        if key.code == Esc && !self.key_sequence.is_empty() {
+            self.key_sequence.clear();
+            self.message = None;
+            return;
+        }
+
+        self.key_sequence.push(*key);
+        match self.keymap.lookup(&self.key_sequence) {
+            Lookup::Command(command) => {
+                if self.key_sequence.len() > 1 {
+                    // The keys typed so far are in the message bar
+                    self.message = None;
+                }
+                self.key_sequence.clear();
+                self.run_command(command);
+            }
+            Lookup::Prefix => {
+                let keys = format_keys(&self.key_sequence);
+                self.show_message(format!("{keys} -"));
+            }
+            Lookup::Unbound => {
+                let keys = std::mem::take(&mut self.key_sequence);
+                match keys.as_slice() {
+                    [
+                        KeyEvent {
+                            code: Char(c),
+                            modifiers,
+                            ..
+                        },
+                    ] if !modifiers.intersects(
+                        KeyModifiers::CONTROL | KeyModifiers::ALT,
+                    ) =>
+                    {
+                        self.delete_selection();
+                        self.insert_char(*c);
+                    }
+                    _ => {
+                        info!("Unhandled keys: {keys:?}");
+                        let keys = format_keys(&keys);
+                        self.show_message(format!("{keys} is not bound"));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn run_command(&mut self, command: Command) {
+        info!("Running command {}", command.name());
+        // Typing over a selection replaces it, any other command but
+        // growing or shrinking it ends it
+        if !matches!(
+            command,
+            Command::ExpandSelection | Command::ShrinkSelection
+        ) {
+            let replaces =
+                matches!(command, Command::InsertNewline | Command::InsertTab);
+            if command == Command::DeleteBackward && self.delete_selection() {
+                return;
+            }
+            if replaces {
+                self.delete_selection();
+            }
+            self.window_mut().selection_anchor = None;
+        }
+        match command {
+            Command::MoveLeft => self.shift_caret(Direction::Left),
+            Command::MoveRight => self.shift_caret(Direction::Right),
+            Command::MoveUp => self.shift_caret(Direction::Up),
+            Command::MoveDown => self.shift_caret(Direction::Down),
+            Command::MoveLineStart => {
+                self.window_mut().caret.go_start_of_line();
+            }
+            Command::MoveLineEnd => self.move_caret(Caret::go_end_of_line),
+            Command::PageUp => self.move_caret(Caret::page_up),
+            Command::PageDown => self.move_caret(Caret::page_down),
+            Command::InsertNewline => {
+                let at = self.window().caret.location;
+                self.current_buffer.insert('\n', at);
+                self.window_mut()
+                    .caret
+                    .move_to(Location { x: 0, y: at.y + 1 });
+            }
+            Command::InsertTab => self.insert_tab(),
+            Command::DeleteBackward => self.delete_backward(),
+            Command::Save => {
+                self.save();
+            }
+            Command::SaveAndQuit => {
+                if self.save() {
+                    self.should_quit = true;
+                }
+            }
+            Command::Quit => self.should_quit = true,
+            Command::RecordMacro | Command::PlayMacro => {
+                self.macro_command(command);
+            }
+            Command::CommandPalette => {
+                self.palette =
+                    Some(Palette::new(&self.keymap, &self.recent_commands));
+            }
+            Command::CommandLine => self.open_command_line(String::new()),
+            Command::ShrinkSelection => self.shrink_selection(),
+            Command::ToggleFold if self.unfold() => {}
+            Command::UnfoldAll => self.window_mut().folds.clear(),
+            Command::Hover
+            | Command::GoToDefinition
+            | Command::FindReferences
+            | Command::RenameSymbol
+            | Command::FormatDocument
+            | Command::Complete => self.lsp_command(command),
+            Command::SplitHorizontal
+            | Command::SplitVertical
+            | Command::ClosePane
+            | Command::FocusPaneLeft
+            | Command::FocusPaneDown
+            | Command::FocusPaneUp
+            | Command::FocusPaneRight
+            | Command::FocusNextPane
+            | Command::GrowPane
+            | Command::ShrinkPane
+            | Command::WidenPane
+            | Command::NarrowPane => self.window_command(command),
+            Command::NewTab
+            | Command::CloseTab
+            | Command::NextTab
+            | Command::PreviousTab
+            | Command::MoveTabLeft
+            | Command::MoveTabRight => self.tab_command(command),
+            Command::ToggleExplorer => self.toggle_explorer(),
+            Command::FindFile
+            | Command::SearchInFiles
+            | Command::NextResult
+            | Command::PreviousResult
+            | Command::ReplaceInFiles
+            | Command::UndoReplace => self.search_command(command),
+            Command::ExpandSelection
+            | Command::ParentNode
+            | Command::NextSiblingNode
+            | Command::PreviousSiblingNode
+            | Command::ToggleFold => self.syntax_command(command),
+        }
+    }
+
+    /// Opens the fold on the caret's line, returns whether there was one
+    fn unfold(&mut self) -> bool {
+        let window = self.window_mut();
+        window.folds.remove(window.caret.location.y).is_some()
+    }
+
+    /// Goes back to the selection before the last expansion, down to
+    /// the caret it started from
+    fn shrink_selection(&mut self) {
+        match self.window_mut().selection_history.pop() {
+            Some((start, end)) if start == end => {
+                self.window_mut().selection_anchor = None;
+                if let Some(modal) = &mut self.modal {
+                    modal.escape_visual();
+                }
+                self.window_mut().caret.move_to(start);
+            }
+            Some((start, end)) => self.select(start, end),
+            None => self.show_message(String::from("Nothing to shrink")),
+        }
+    }
+
+    /// Starts recording or playing a macro, the register comes next
+    fn macro_command(&mut self, command: Command) {
+        if command == Command::PlayMacro {
+            self.pending = Some(Pending::PlayMacro { count: 0 });
+            self.show_message(String::from(
+                "Play macro: [count] register (a-z)",
+            ));
+        } else if let Some(register) = self.macros.stop_recording() {
+            self.show_message(format!("Recorded macro @{register}"));
+            self.save_macros();
+        } else {
+            self.pending = Some(Pending::RecordRegister);
+            self.show_message(String::from("Record macro into register (a-z)"));
+        }
+    }
+
+    /// What is selected: the visual selection in modal editing, otherwise
+    /// the text from the selection anchor to the caret
+    fn selection(&self) -> Option<Selection> {
+        let caret = self.window().caret.location;
+        if let Some(modal) = &self.modal {
+            let (start, end) = ordered(modal.anchor()?, caret);
+            // Visual mode takes the character under the caret along
+            return Some(Selection {
+                start,
+                end: Location {
+                    x: end.x + 1,
+                    y: end.y,
+                },
+                lines: modal.mode() == Mode::VisualLine,
+            });
+        }
+        let (start, end) = ordered(self.window().selection_anchor?, caret);
+        (start != end).then_some(Selection {
+            start,
+            end,
+            lines: false,
+        })
+    }
+
+    /// Selects the text from `start` up to `end`, in visual mode with
+    /// modal editing
+    fn select(&mut self, start: Location, end: Location) {
+        let Some(modal) = &mut self.modal else {
+            let window = self.window_mut();
+            window.selection_anchor = Some(start);
+            window.caret.move_to(end);
+            return;
+        };
+        modal.select(start);
+        // The caret is on the last character selected
+        let last = if end.x > 0 {
+            Location {
+                x: end.x - 1,
+                y: end.y,
+            }
+        } else if end.y > start.y {
+            let y = end.y - 1;
+            Location {
+                x: self.current_buffer.line_len(y).saturating_sub(1),
+                y,
+            }
+        } else {
+            end
+        };
+        self.window_mut().caret.move_to(last);
+    }
+
+    /// Deletes the text selected outside of modal editing, returns
+    /// whether there was any
+    fn delete_selection(&mut self) -> bool {
+        let window = self.window_mut();
+        let Some(anchor) = window.selection_anchor.take() else {
+            return false;
+        };
+        let (start, end) = ordered(anchor, window.caret.location);
+        if start == end {
+            return false;
+        }
+        self.current_buffer.delete(start, end);
+        self.window_mut().caret.move_to(start);
+        true
+    }
+
+    /// Runs the commands that need a syntax tree of the buffer
+    #[cfg(feature = "tree-sitter")]
+    fn syntax_command(&mut self, command: Command) {
+        let caret = self.window().caret.location;
+        let (start, end) =
+            self.selection().map_or((caret, caret), |selection| {
+                (selection.start, selection.end)
+            });
+        let buffer = &self.current_buffer;
+        let Some(tree) = self.highlighter.syntax_tree(buffer) else {
+            self.show_message(String::from("No syntax tree for this file"));
+            return;
+        };
+        let forward = command == Command::NextSiblingNode;
+        match command {
+            Command::ExpandSelection => match tree.expand(buffer, start, end) {
+                Some((expanded_start, expanded_end)) => {
+                    self.window_mut().selection_history.push((start, end));
+                    self.select(expanded_start, expanded_end);
+                }
+                None => self.show_message(String::from("Nothing to expand")),
+            },
+            Command::ParentNode => match tree.parent(buffer, caret) {
+                Some(location) => self.jump_to(location),
+                None => self.show_message(String::from("No parent node")),
+            },
+            Command::NextSiblingNode | Command::PreviousSiblingNode => {
+                match tree.sibling(buffer, caret, forward) {
+                    Some(location) => self.jump_to(location),
+                    None => self.show_message(String::from("No sibling node")),
+                }
+            }
+            Command::ToggleFold => match tree.fold(buffer, caret.y) {
+                Some(fold) => {
+                    let window =
+                        focused_window(&mut self.windows, &self.layout);
+                    window.folds.update(&self.current_buffer);
+                    window.folds.add(fold);
+                    if caret.y != fold.first {
+                        self.jump_to(Location {
+                            x: caret.x,
+                            y: fold.first,
+                        });
+                    }
+                }
+                None => self.show_message(String::from("Nothing to fold")),
+            },
+            _ => {}
+        }
+    }
+
+    #[cfg(not(feature = "tree-sitter"))]
+    fn syntax_command(&mut self, command: Command) {
+        info!("No syntax tree for {}", command.name());
+        self.show_message(String::from("Built without tree-sitter support"));
+    }
+
+    /// Moves the caret of the focused pane with `motion`, which sees the
+    /// current buffer
+    fn move_caret(&mut self, motion: fn(&mut Caret, &Buffer)) {
+        let window = focused_window(&mut self.windows, &self.layout);
+        motion(&mut window.caret, &self.current_buffer);
+    }
+
+    /// Moves the caret to `location`, where the mode allows it
+    fn jump_to(&mut self, location: Location) {
+        self.window_mut().caret.move_to(location);
+        self.move_caret(Caret::clamp_to);
+        self.settle_caret();
+    }
+
+    /// Puts the caret where the mode allows it, as after a key
+    fn settle_caret(&mut self) {
+        if let Some(modal) = &self.modal {
+            let window = focused_window(&mut self.windows, &self.layout);
+            modal.settle(&mut window.caret, &self.current_buffer);
+        }
+    }
+
+    /// Keeps the caret out of folded lines: moving down it skips past the
+    /// fold, otherwise it lands on the line the fold starts on
+    fn skip_folds(&mut self, before: Location) {
+        let Location { x, y } = self.window().caret.location;
+        let Some(fold) = self.window().folds.hiding(y) else {
+            return;
+        };
+        let y = if y > before.y
+            && fold.last + 1 < self.current_buffer.lines.len()
+        {
+            fold.last + 1
+        } else {
+            fold.first
+        };
+        self.jump_to(Location { x, y });
+    }
+
+    /// Keys go to the command palette while it is open
+    fn evaluate_palette_key(&mut self, key: &KeyEvent) {
+        let Some(palette) = &mut self.palette else {
+            return;
+        };
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            Esc => self.palette = None,
+            Enter => {
+                let selected = palette.selected();
+                self.palette = None;
+                if let Some(command) = selected {
+                    self.remember_command(command);
+                    self.run_command(command);
+                }
+            }
+            Up => palette.select_previous(),
+            Char('p') if control => palette.select_previous(),
+            Down | Tab => palette.select_next(),
+            Char('n') if control => palette.select_next(),
+            Backspace => palette.backspace(),
+            Char(c) if !control => palette.insert(c),
+            _ => {}
+        }
+    }
+
+    /// Keys go to the picker while it is open
+    fn evaluate_picker_key(&mut self, key: &KeyEvent) {
+        let Some(picker) = &mut self.picker else {
+            return;
+        };
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            Esc => {
+                self.picker = None;
+                self.file_walk = None;
+                return;
+            }
+            Enter => {
+                let selected = picker.selected().cloned();
+                self.picker = None;
+                self.file_walk = None;
+                if let Some(location) = selected {
+                    self.go_to_file_location(&location);
+                }
+                return;
+            }
+            Up => picker.select_previous(),
+            Char('p') if control => picker.select_previous(),
+            Down | Tab => picker.select_next(),
+            Char('n') if control => picker.select_next(),
+            Backspace => picker.backspace(),
+            Char(c) if !control => picker.insert(c),
+            _ => return,
+        }
+        self.update_preview();
+    }
+
+    /// Moves `command` to the top of the recently used commands
+    fn remember_command(&mut self, command: Command) {
+        self.recent_commands.retain(|recent| *recent != command);
+        self.recent_commands.insert(0, command);
+        self.recent_commands.truncate(MAX_RECENT);
+
+        let Some(path) = &self.recent_commands_path else {
+            return;
+        };
+        let names: Vec<&str> =
+            self.recent_commands.iter().map(|c| c.name()).collect();
+        if let Err(e) = write_lines(path, &names) {
+            debug!("Error saving recent commands: {e}");
+        }
+    }
+
+    fn open_command_line(&mut self, input: String) {
+        self.message = None;
+        self.command_line = Some(CommandLine::new(input));
+    }
+
+    /// Keys go to the command line while it is open
+    fn evaluate_command_line_key(&mut self, key: &KeyEvent) {
+        let Some(command_line) = &mut self.command_line else {
+            return;
+        };
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            Esc => self.command_line = None,
+            Enter => {
+                let input = command_line.input().to_string();
+                self.command_line = None;
+                if !input.trim().is_empty() {
+                    self.remember_command_line(&input);
+                    self.run_ex(&input);
+                }
+            }
+            // Like in Vim, backspacing over the `:` closes it
+            Backspace if !command_line.backspace() => self.command_line = None,
+            Up => command_line.history_previous(&self.command_history),
+            Char('p') if control => {
+                command_line.history_previous(&self.command_history);
+            }
+            Down => command_line.history_next(&self.command_history),
+            Char('n') if control => {
+                command_line.history_next(&self.command_history);
+            }
+            Tab if !command_line.complete() => {
+                self.show_message(String::from("No completions"));
+            }
+            Char(c) if !control => command_line.insert(c),
+            _ => {}
+        }
+    }
+
+    /// Runs an ex command line, without the leading `:`
+    pub fn run_ex(&mut self, input: &str) {
+        info!("Running ex command {input:?}");
+        let context = ex::Context {
+            current_line: self.window().caret.location.y,
+            line_count: self.current_buffer.lines.len().max(1),
+        };
+        let ex = match ex::parse(input, context) {
+            Ok(ex) => ex,
+            Err(e) => {
+                self.show_message(e);
+                return;
+            }
+        };
+        match ex {
+            Ex::Write(path) => {
+                if let Some(path) = path {
+                    self.lsp.close(Path::new(&self.filename));
+                    self.filename = path;
+                    self.detect_language();
+                    self.attach_lsp();
+                }
+                self.save();
+            }
+            Ex::Quit { force } if self.has_other_panes() => {
+                self.close_window(force);
+            }
+            Ex::Quit { force } => {
+                if force || !self.is_modified() {
+                    self.should_quit = true;
+                } else {
+                    self.show_message(String::from(
+                        "No write since last change (add ! to override)",
+                    ));
+                }
+            }
+            Ex::WriteQuit if self.has_other_panes() => {
+                if self.save() {
+                    self.close_window(false);
+                }
+            }
+            Ex::WriteQuit => {
+                if self.save() {
+                    self.should_quit = true;
+                }
+            }
+            Ex::Split { split, path } => self.split_window(split, path),
+            Ex::Close { force } => {
+                self.close_window(force);
+            }
+            Ex::NewTab(path) => self.new_tab(path),
+            Ex::CloseTab { force } => {
+                self.close_tab(force);
+            }
+            Ex::NextTab(None) => self.tab_command(Command::NextTab),
+            Ex::NextTab(Some(number))
+                if (1..=self.tabs.len() + 1).contains(&number) =>
+            {
+                self.switch_tab(number - 1);
+            }
+            Ex::NextTab(Some(number)) => {
+                self.show_message(format!("No tab {number}"));
+            }
+            Ex::PreviousTab => self.tab_command(Command::PreviousTab),
+            Ex::MoveTab(position) => {
+                self.tab = position.unwrap_or(usize::MAX).min(self.tabs.len());
+            }
+            Ex::Edit { path, force } => self.open_file(path, force),
+            Ex::Goto(y) => self.go_to_line(y),
+            Ex::Substitute {
+                range,
+                pattern,
+                replacement,
+                global,
+            } => self.substitute(range, &pattern, &replacement, global),
+            Ex::Set(options) => {
+                let mut config = self.config.clone();
+                for option in options {
+                    match option {
+                        SetOption::TabWidth(width) => config.tab_width = width,
+                        SetOption::HardTabs(hard) => config.hard_tabs = hard,
+                        SetOption::LineNumbers(on) => config.line_numbers = on,
+                        SetOption::Modal(on) => config.modal = on,
+                        SetOption::Theme(name) => config.theme = name,
+                    }
+                }
+                self.set_config(config);
+            }
+            Ex::Rename(name) => self.lsp_request(Request::Rename(name)),
+            Ex::Grep(query) => self.search_in_files(query),
+            Ex::NextResult => self.search_command(Command::NextResult),
+            Ex::PreviousResult => {
+                self.search_command(Command::PreviousResult);
+            }
+            Ex::Replace(replacement) => self.preview_replace(replacement),
+            Ex::Recover => self.recover(),
+        }
+    }
+
+    /// Shows the file at `path` in the focused pane, or an empty buffer
+    /// if there is no such file yet. A file open in another pane is
+    /// shared with it. Refuses to drop unsaved changes no other pane
+    /// shows unless `force` is set.
+    fn open_file(&mut self, path: String, force: bool) {
+        let open = self.open_buffer_id(Path::new(&path));
+        if open == Some(self.buffer_id) {
+            let lines = self.current_buffer.lines.len();
+            self.show_message(format!("\"{}\" {lines}L", self.filename));
+            return;
+        }
+        self.autosave();
+        let shown_elsewhere = self.is_shown_elsewhere();
+        if self.is_modified() && !force && !shown_elsewhere {
+            self.show_message(String::from(
+                "No write since last change (add ! to override)",
+            ));
+            return;
+        }
+        let read = open.is_none();
+        let message = if let Some(id) = open {
+            self.switch_buffer(id);
+            format!("\"{path}\" {}L", self.current_buffer.lines.len())
+        } else {
+            let (buffer, message) = match Buffer::read_file(&path) {
+                Ok(buffer) => {
+                    let message = format!("\"{path}\" {}L", buffer.lines.len());
+                    (buffer, message)
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    (Buffer::new(), format!("\"{path}\" [New]"))
+                }
+                Err(e) if e.kind() == ErrorKind::InvalidData => {
+                    info!("Not opening {path}: {e}");
+                    self.show_message(format!(
+                        "Could not open {path}: not UTF-8 text"
+                    ));
+                    return;
+                }
+                Err(e) => {
+                    self.show_message(format!("Could not open {path}: {e}"));
+                    return;
+                }
+            };
+            info!("Opened {path}");
+            self.show_buffer(buffer, path);
+            message
+        };
+        self.drop_unused_buffers();
+        self.reset_pane();
+        self.show_message(message);
+        if read {
+            self.check_swap();
+        }
+    }
+
+    /// Makes `buffer`, of the file at `path`, the current one. The buffer
+    /// it replaces is kept if another pane shows it.
+    fn show_buffer(&mut self, buffer: Buffer, path: String) {
+        let shown_elsewhere = self.is_shown_elsewhere();
+        if !shown_elsewhere {
+            self.lsp.close(Path::new(&self.filename));
+        }
+        let previous = OpenBuffer {
+            saved_revision: std::mem::replace(
+                &mut self.saved_revision,
+                buffer.revision(),
+            ),
+            buffer: std::mem::replace(&mut self.current_buffer, buffer),
+            filename: std::mem::replace(&mut self.filename, path),
+            highlighter: std::mem::replace(
+                &mut self.highlighter,
+                Highlighter::new(None),
+            ),
+        };
+        if shown_elsewhere {
+            self.buffers.insert(self.buffer_id, previous);
+        }
+        self.buffer_id = self.next_buffer_id;
+        self.next_buffer_id += 1;
+        self.window_mut().buffer = self.buffer_id;
+        self.detect_language();
+        self.attach_lsp();
+        self.watch_file(&self.filename.clone());
+    }
+
+    /// Forgets what the focused pane kept of the buffer it showed before,
+    /// and puts the caret at the start of the one it shows now
+    fn reset_pane(&mut self) {
+        let window = self.window_mut();
+        window.folds = Folds::default();
+        window.selection_anchor = None;
+        window.selection_history.clear();
+        window.caret.move_to(Location { x: 0, y: 0 });
+        self.completion = None;
+        self.pending_completion = None;
+    }
+
+    /// Starts highlighting the current buffer anew, in the language its
+    /// file name or first line suggest
+    fn detect_language(&mut self) {
+        let first_line =
+            self.current_buffer.lines.first().map_or("", String::as_str);
+        let language = Language::detect(&self.filename, first_line);
+        if let Some(language) = language {
+            info!("Highlighting {} as {}", self.filename, language.name());
+        }
+        self.highlighter = Highlighter::new(language);
+    }
+
+    /// Moves the caret to the first non-blank of line `y`
+    fn go_to_line(&mut self, y: usize) {
+        let line = self.current_buffer.lines.get(y).map_or("", String::as_str);
+        let x = line.chars().take_while(|c| c.is_whitespace()).count();
+        self.window_mut().caret.move_to(Location { x, y });
+        self.move_caret(Caret::clamp_to);
+    }
+
+    /// Replaces the first match of `pattern`, or every match if `global`,
+    /// on each line in `range`
+    fn substitute(
+        &mut self,
+        range: LineRange,
+        pattern: &Regex,
+        replacement: &str,
+        global: bool,
+    ) {
+        let mut substitutions = 0;
+        let mut changed_lines = 0;
+        let mut last_changed = None;
+        let mut y = range.first;
+        let mut last = range.last;
+        while y <= last && y < self.current_buffer.lines.len() {
+            let line = &self.current_buffer.lines[y];
+            let matches = if global {
+                pattern.find_iter(line).count()
+            } else {
+                usize::from(pattern.is_match(line))
+            };
+            if matches == 0 {
+                y += 1;
+                continue;
+            }
+            let replaced = if global {
+                pattern.replace_all(line, replacement)
+            } else {
+                pattern.replace(line, replacement)
+            }
+            .into_owned();
+            // A replacement with line breaks pushes the rest of the range
+            // further down
+            let taken = self.current_buffer.replace_line(y, &replaced);
+            substitutions += matches;
+            changed_lines += 1;
+            last_changed = Some(y + taken - 1);
+            last += taken - 1;
+            y += taken;
+        }
+
+        match last_changed {
+            Some(y) => {
+                self.go_to_line(y);
+                self.show_message(format!(
+                    "{substitutions} substitutions on {changed_lines} lines"
+                ));
+            }
+            None => self.show_message(format!(
+                "Pattern not found: {}",
+                pattern.as_str()
+            )),
+        }
+    }
+
+    /// Adds `input` to the top of the command history
+    fn remember_command_line(&mut self, input: &str) {
+        self.command_history.retain(|entry| entry != input);
+        self.command_history.insert(0, input.to_string());
+        self.command_history.truncate(MAX_HISTORY);
+
+        let Some(path) = &self.command_history_path else {
+            return;
+        };
+        let entries: Vec<&str> =
+            self.command_history.iter().map(String::as_str).collect();
+        if let Err(e) = write_lines(path, &entries) {
+            debug!("Error saving command history: {e}");
+        }
+    }
+
+    fn load_command_history(&mut self) {
+        let Some(path) = &self.command_history_path else {
+            return;
+        };
+        match std::fs::read_to_string(path) {
+            Ok(entries) => {
+                self.command_history = entries
+                    .lines()
+                    .filter(|entry| !entry.trim().is_empty())
+                    .take(MAX_HISTORY)
+                    .map(String::from)
+                    .collect();
+            }
+            Err(e) => debug!("No command history loaded: {e}"),
+        }
+    }
+
+    fn load_recent_commands(&mut self) {
+        let Some(path) = &self.recent_commands_path else {
+            return;
+        };
+        match std::fs::read_to_string(path) {
+            Ok(names) => {
+                self.recent_commands = names
+                    .lines()
+                    .filter_map(Command::from_name)
+                    .take(MAX_RECENT)
+                    .collect();
+            }
+            Err(e) => debug!("No recent commands loaded: {e}"),
+        }
+    }
+
+    fn shift_caret(&mut self, direction: Direction) {
+        let window = focused_window(&mut self.windows, &self.layout);
+        window.caret.shift(direction, &self.current_buffer);
+    }
+
+    /// Whether the buffer changed since it was read or saved
+    pub fn is_modified(&self) -> bool {
+        self.current_buffer.revision() != self.saved_revision
+    }
+
+    /// Keys in modal editing: insert mode works like modeless editing
+    /// until `Esc`, the other modes go to `Modal`
+    fn evaluate_modal_key(&mut self, key: &KeyEvent) {
+        let Some(modal) = &mut self.modal else {
+            return;
+        };
+        modal.begin_key(key, self.current_buffer.revision());
+
+        if !self.key_sequence.is_empty() {
+            // The rest of a binding longer than one key, like `<C-w>s`
+            self.evaluate_key(key);
+            if self
+                .modal
+                .as_ref()
+                .is_some_and(|m| m.mode() != Mode::Insert)
+            {
+                self.settle_caret();
+            }
+        } else if modal.mode() == Mode::Insert {
+            if key.code == Esc {
+                let window = focused_window(&mut self.windows, &self.layout);
+                modal.escape_insert(&mut window.caret, &self.current_buffer);
+            } else {
+                self.evaluate_key(key);
+            }
+        } else {
+            let window = focused_window(&mut self.windows, &self.layout);
+            let outcome = modal.evaluate_key(
+                key,
+                &mut self.current_buffer,
+                &mut window.caret,
+                &self.config,
+            );
+            match outcome {
+                Outcome::Handled => {}
+                Outcome::Unhandled => {
+                    self.evaluate_key(key);
+                    self.settle_caret();
+                }
+                Outcome::Message(message) => self.show_message(message),
+                Outcome::CommandLine(input) => self.open_command_line(input),
+                Outcome::Repeat(keys) => self.repeat_change(&keys),
+            }
+        }
+
+        if let Some(modal) = &mut self.modal {
+            modal.end_key(self.current_buffer.revision());
+        }
+    }
+
+    /// Plays the keys of the last change again for `.`
+    fn repeat_change(&mut self, keys: &[KeyEvent]) {
+        info!("Repeating {}", format_keys(keys));
+        if let Some(modal) = &mut self.modal {
+            modal.set_repeating(true);
+        }
+        for key in keys {
+            self.evaluate_modal_key(key);
+        }
+        if let Some(modal) = &mut self.modal {
+            modal.set_repeating(false);
+        }
+    }
+
+    fn insert_char(&mut self, c: char) {
+        self.current_buffer.insert(c, self.window().caret.location);
+        self.shift_caret(Direction::Right);
+    }
+
+    /// Writes the buffer to its file, returns whether that worked
+    fn save(&mut self) -> bool {
+        if self.filename.is_empty() {
+            info!("No file name, not saving");
+            self.show_message(String::from("No file name, not saved"));
+            return false;
+        }
+        let lines = &self.current_buffer.lines;
+        if changed_on_disk(&self.file_watcher, &self.filename, lines) {
+            info!("{} changed on disk, asking before saving", self.filename);
+            // Stays until answered
+            self.message = Some(format!(
+                "{} changed on disk since it was read: [r]eload, [o]verwrite, \
+                 di[f]f or Esc",
+                self.filename
+            ));
+            self.timers.cancel(Timer::MessageExpiry);
+            self.pending = Some(Pending::Conflict);
+            return false;
+        }
+        match self.write_buffer() {
+            Ok(()) => {
+                self.show_message(format!("Saved {}", self.filename));
+                true
+            }
+            Err(e) => {
+                debug!("Error saving {}: {e}", self.filename);
+                self.show_message(format!("Could not save: {e}"));
+                false
+            }
+        }
+    }
+
+    /// Writes the current buffer to its file and tells the language
+    /// server, without a message
+    fn write_buffer(&mut self) -> Result<(), Error> {
+        if self.saving == Saving::Write {
+            self.current_buffer.write_file(&self.filename)?;
+        }
+        info!("Saved {}", self.filename);
+        self.saved_revision = self.current_buffer.revision();
+        self.remove_swap(&self.filename.clone());
+        self.watch_file(&self.filename.clone());
+        let path = Path::new(&self.filename);
+        self.lsp.sync(path, &self.current_buffer);
+        self.lsp.save(path);
+        Ok(())
+    }
+
+    /// Writes `files` all or nothing, unless this is a dry run
+    fn write_files(&self, files: &[(PathBuf, String)]) -> Result<(), Error> {
+        if self.saving == Saving::DryRun {
+            return Ok(());
+        }
+        buffer::write_files(files)
+    }
+
+    /// Saves every buffer with unsaved changes that has a file name, if
+    /// autosave is on. Only failures make it to the message bar.
+    fn autosave(&mut self) {
+        if self.config.autosave == 0 {
+            return;
+        }
+        let mut errors = Vec::new();
+        let lines = &self.current_buffer.lines;
+        if !self.is_modified() || self.filename.is_empty() {
+            // Nothing to save
+        } else if changed_on_disk(&self.file_watcher, &self.filename, lines) {
+            errors.push(format!("{}: changed on disk", self.filename));
+        } else if let Err(e) = self.write_buffer() {
+            errors.push(format!("{}: {e}", self.filename));
+        }
+        let mut saved = Vec::new();
+        for open in self.buffers.values_mut() {
+            let revision = open.buffer.revision();
+            if revision == open.saved_revision || open.filename.is_empty() {
+                continue;
+            }
+            let lines = &open.buffer.lines;
+            if changed_on_disk(&self.file_watcher, &open.filename, lines) {
+                errors.push(format!("{}: changed on disk", open.filename));
+                continue;
+            }
+            let written = if self.saving == Saving::DryRun {
+                Ok(())
+            } else {
+                open.buffer.write_file(&open.filename)
+            };
+            match written {
+                Ok(()) => {
+                    info!("Saved {}", open.filename);
+                    open.saved_revision = revision;
+                    saved.push(open.filename.clone());
+                }
+                Err(e) => errors.push(format!("{}: {e}", open.filename)),
+            }
+        }
+        for filename in saved {
+            self.remove_swap(&filename);
+            self.watch_file(&filename);
+        }
+        for error in &errors {
+            debug!("Autosave failed for {error}");
+        }
+        self.autosave_error = errors
+            .first()
+            .map(|error| format!("Autosave failed for {error}"));
+        if let Some(error) = self.autosave_error.clone() {
+            self.show_message(error);
+        }
+    }
+
+    /// Deletes the character before the caret, joining lines at the start
+    /// of one
+    fn delete_backward(&mut self) {
+        let Location { x, y } = self.window().caret.location;
+        // Joining with the previous line puts the caret where the two lines
+        // meet
+        let joined_at =
+            (x == 0 && y > 0).then(|| self.current_buffer.line_len(y - 1));
+        self.current_buffer.backspace(Location { x, y });
+        let caret = &mut self.window_mut().caret;
+        match joined_at {
+            Some(x) => caret.move_to(Location { x, y: y - 1 }),
+            None => caret.location.x = x.saturating_sub(1),
+        }
+    }
+
+    /// Inserts a tab, or spaces up to the next tab stop with soft tabs
+    fn insert_tab(&mut self) {
+        if self.config.hard_tabs {
+            self.current_buffer
+                .insert('\t', self.window().caret.location);
+            self.shift_caret(Direction::Right);
+            return;
+        }
+        let window = self.window();
+        let line = self
+            .current_buffer
+            .lines
+            .get(window.caret.location.y)
+            .map_or("", String::as_str);
+        let column = window.view.display_column(line, window.caret.location.x);
+        let spaces = self.config.tab_width - column % self.config.tab_width;
+        for _ in 0..spaces {
+            self.current_buffer
+                .insert(' ', self.window().caret.location);
+            self.shift_caret(Direction::Right);
+        }
+    }
+
+    /// Feeds `key` to the command in `pending`
+    fn evaluate_pending(&mut self, pending: Pending, key: &KeyEvent) {
+        self.message = None;
+        match pending {
+            Pending::Swap(swap) => return self.evaluate_swap_key(&swap, key),
+            Pending::Conflict => return self.evaluate_conflict_key(key),
+            _ => {}
+        }
+        let register = match key.code {
+            Char(c)
+                if key.modifiers.difference(KeyModifiers::SHIFT).is_empty() =>
+            {
+                c
+            }
+            Esc => return,
+            _ => {
+                self.show_message(String::from("Cancelled"));
+                return;
+            }
+        };
+
+        match pending {
+            Pending::PlayMacro { count } if register.is_ascii_digit() => {
+                let digit = register.to_digit(10).unwrap_or(0) as usize;
+                let count = count.saturating_mul(10).saturating_add(digit);
+                self.pending = Some(Pending::PlayMacro { count });
+                self.show_message(format!(
+                    "Play macro {count} times: register"
+                ));
+            }
+            _ if !Macros::is_register(register) => {
+                self.show_message(format!("Not a register: {register}"));
+            }
+            Pending::RecordRegister => {
+                self.macros.start_recording(register);
+                self.show_message(format!(
+                    "Recording @{register}, Ctrl-R to stop"
+                ));
+            }
+            Pending::PlayMacro { count } => {
+                self.play_macro(register, count.max(1));
+            }
+            Pending::Swap(_) | Pending::Conflict => {}
+        }
+    }
+
+    /// Journals the buffers with unsaved changes to their swap files, and
+    /// removes the swap files of those without any anymore
+    fn write_swaps(&mut self) {
+        let Some(dir) = self.swap_dir.clone() else {
+            return;
+        };
+        let current = (
+            self.filename.as_str(),
+            &self.current_buffer,
+            self.saved_revision,
+        );
+        let others = self.buffers.values().map(|open| {
+            (open.filename.as_str(), &open.buffer, open.saved_revision)
+        });
+        let mut swapped = HashMap::new();
+        let mut error = None;
+        for (filename, buffer, saved_revision) in
+            std::iter::once(current).chain(others)
+        {
+            let revision = buffer.revision();
+            if filename.is_empty() || revision == saved_revision {
+                continue;
+            }
+            let file = absolute(filename);
+            // Failures are not retried before the next change, so the
+            // message does not keep coming back
+            if self.swapped.get(&file) != Some(&revision)
+                && let Err(e) = swap::write(&dir, &file, &buffer.lines)
+            {
+                debug!("Error writing swap file for {filename}: {e}");
+                error = Some(format!(
+                    "Could not write swap file for {filename}: {e}"
+                ));
+            }
+            swapped.insert(file, revision);
+        }
+        for file in self.swapped.keys() {
+            if !swapped.contains_key(file) {
+                swap::remove(&dir, file);
+            }
+        }
+        self.swapped = swapped;
+        if let Some(error) = error {
+            self.show_message(error);
+        }
+        self.timers.schedule(Timer::SwapWrite, SWAP_INTERVAL);
+    }
+
+    /// Removes the swap file of the file at `filename`, its changes are
+    /// saved
+    fn remove_swap(&mut self, filename: &str) {
+        let file = absolute(filename);
+        if self.swapped.remove(&file).is_some()
+            && let Some(dir) = &self.swap_dir
+        {
+            swap::remove(dir, &file);
+        }
+    }
+
+    /// Removes every swap file this editor wrote, as it quits
+    fn remove_swaps(&mut self) {
+        let Some(dir) = &self.swap_dir else {
+            return;
+        };
+        for file in self.swapped.keys() {
+            swap::remove(dir, file);
+        }
+        self.swapped.clear();
+    }
+
+    /// Offers to recover the current file from a swap file another editor
+    /// left behind, returns whether there is one. A swap file holding
+    /// just what the file does is removed instead, unless its editor
+    /// still runs.
+    fn check_swap(&mut self) -> bool {
+        let Some(dir) = &self.swap_dir else {
+            return false;
+        };
+        if self.filename.is_empty() {
+            return false;
+        }
+        let Some(swap) = swap::find(dir, &absolute(&self.filename)) else {
+            return false;
+        };
+        if swap.lines == self.current_buffer.lines && !swap.is_in_use() {
+            info!(
+                "Removing swap file {}, nothing to recover",
+                swap.path.display()
+            );
+            let _ = fs::remove_file(&swap.path);
+            return false;
+        }
+        info!("Found swap file {}", swap.path.display());
+        let in_use = if swap.is_in_use() {
+            format!(", process {} still runs", swap.pid)
+        } else {
+            String::new()
+        };
+        // Stays until answered, and is not hidden behind the welcome
+        // message
+        self.message = Some(format!(
+            "Swap file of {} from {}{in_use}: [r]ecover, [d]elete, di[f]f \
+             or Esc",
+            self.filename,
+            swap.age()
+        ));
+        self.timers.cancel(Timer::MessageExpiry);
+        self.window_mut().view.is_new_buffer = false;
+        self.pending = Some(Pending::Swap(swap));
+        true
+    }
+
+    /// Offers to recover the current file from its swap file again
+    fn recover(&mut self) {
+        if !self.check_swap() {
+            self.show_message(format!("No swap file for {}", self.filename));
+        }
+    }
+
+    /// Opens a tab showing `lines` in a buffer without a file
+    fn show_scratch(&mut self, lines: Vec<String>) {
+        let mut buffer = Buffer::new();
+        for line in lines {
+            buffer.push(line);
+        }
+        self.new_tab(None);
+        self.show_buffer(buffer, String::new());
+        self.reset_pane();
+    }
+
+    /// Watches the file at `filename` for changes other programs make,
+    /// taking it as it is now
+    fn watch_file(&mut self, filename: &str) {
+        if filename.is_empty() {
+            return;
+        }
+        let path = absolute(filename);
+        self.disk_warned.remove(&path);
+        self.file_watcher.watch(path);
+        if !self.timers.is_scheduled(Timer::DiskCheck) {
+            self.timers.schedule(Timer::DiskCheck, DISK_CHECK_INTERVAL);
+        }
+    }
+
+    /// Reloads the buffers without unsaved changes whose file changed on
+    /// disk, and tells about the others once
+    fn check_disk(&mut self) {
+        let open: HashSet<PathBuf> = std::iter::once(&self.filename)
+            .chain(self.buffers.values().map(|open| &open.filename))
+            .filter(|filename| !filename.is_empty())
+            .map(|filename| absolute(filename))
+            .collect();
+        self.file_watcher.retain(|path| open.contains(path));
+        self.disk_warned.retain(|path| open.contains(path));
+        for path in self.file_watcher.changed_files() {
+            self.file_changed(&path);
+        }
+        if !self.file_watcher.is_empty() {
+            self.timers.schedule(Timer::DiskCheck, DISK_CHECK_INTERVAL);
+        }
+    }
+
+    /// Acts on the file at `path`, open in a buffer, having changed on
+    /// disk
+    fn file_changed(&mut self, path: &Path) {
+        let Some(id) = self.open_buffer_id(path) else {
+            return;
+        };
+        let name = display_path(path);
+        let disk = match Buffer::read_file(&path.to_string_lossy()) {
+            Ok(disk) => disk.lines,
+            Err(e) => {
+                if self.disk_warned.insert(path.to_path_buf()) {
+                    info!("{} can not be read anymore: {e}", path.display());
+                    self.show_message(format!(
+                        "{name} can not be read anymore: {e}"
+                    ));
+                }
+                return;
+            }
+        };
+        let lines = if id == self.buffer_id {
+            &self.current_buffer.lines
+        } else {
+            &self.buffers[&id].buffer.lines
+        };
+        if *lines == disk {
+            // Only touched, or changed into what the buffer holds
+            self.file_watcher.watch(path.to_path_buf());
+            self.disk_warned.remove(path);
+        } else if !self.is_buffer_modified(id) {
+            self.reload_buffer(id, path, &disk);
+            self.show_message(format!("Reloaded {name}, it changed on disk"));
+        } else if self.disk_warned.insert(path.to_path_buf()) {
+            info!("{} changed on disk", path.display());
+            self.show_message(format!(
+                "{name} changed on disk, saving asks before overwriting it"
+            ));
+        }
+    }
+
+    /// Replaces the text of buffer `id` with `lines`, what its file at
+    /// `path` holds now. Carets stay where they are as far as the new
+    /// text allows.
+    fn reload_buffer(&mut self, id: usize, path: &Path, lines: &[String]) {
+        info!("Reloading {}", path.display());
+        if id == self.buffer_id {
+            self.current_buffer.set_lines(lines);
+            self.saved_revision = self.current_buffer.revision();
+            self.move_caret(Caret::clamp_to);
+        } else if let Some(open) = self.buffers.get_mut(&id) {
+            open.buffer.set_lines(lines);
+            open.saved_revision = open.buffer.revision();
+        }
+        self.file_watcher.watch(path.to_path_buf());
+        self.disk_warned.remove(path);
+    }
+
+    /// Answers the question what to do about saving the current file,
+    /// which changed on disk since it was read
+    fn evaluate_conflict_key(&mut self, key: &KeyEvent) {
+        let disk = if matches!(key.code, Char('r' | 'f')) {
+            match Buffer::read_file(&self.filename) {
+                Ok(disk) => disk.lines,
+                Err(e) => {
+                    debug!("Error reading {}: {e}", self.filename);
+                    self.show_message(format!(
+                        "Not saved, {} can not be read: {e}",
+                        self.filename
+                    ));
+                    return;
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        match key.code {
+            Char('o') => match self.write_buffer() {
+                Ok(()) => {
+                    self.show_message(format!("Saved {}", self.filename));
+                }
+                Err(e) => {
+                    debug!("Error saving {}: {e}", self.filename);
+                    self.show_message(format!("Could not save: {e}"));
+                }
+            },
+            Char('r') => {
+                let path = absolute(&self.filename);
+                self.reload_buffer(self.buffer_id, &path, &disk);
+                self.show_message(format!(
+                    "Reloaded {}, the changes in the buffer are gone",
+                    self.filename
+                ));
+            }
+            Char('f') => {
+                let diff = diff::with_header(
+                    (&format!("{} on disk", self.filename), &disk),
+                    (
+                        &format!("{} in the buffer", self.filename),
+                        &self.current_buffer.lines,
+                    ),
+                );
+                self.show_scratch(diff);
+                self.show_message(String::from(
+                    "Not saved, saving again asks again",
+                ));
+            }
+            _ => self.show_message(String::from("Not saved")),
+        }
+    }
+
+    /// Answers the offer to recover the current file from `swap`
+    fn evaluate_swap_key(&mut self, swap: &SwapFile, key: &KeyEvent) {
+        match key.code {
+            Char('r') => {
+                info!(
+                    "Recovering {} from {}",
+                    self.filename,
+                    swap.path.display()
+                );
+                self.current_buffer.set_lines(&swap.lines);
+                self.reset_pane();
+                // Journaled again right away, as this editor's own, which
+                // takes over from the one of an editor that is gone
+                self.write_swaps();
+                let file = absolute(&self.filename);
+                let journaled = self.swap_dir.as_ref().is_some_and(|dir| {
+                    swap::swap_path(dir, &file, std::process::id()).exists()
+                });
+                if journaled && !swap.is_in_use() {
+                    let _ = fs::remove_file(&swap.path);
+                }
+                self.show_message(format!(
+                    "Recovered {}, save to keep it",
+                    self.filename
+                ));
+            }
+            Char('d') if swap.is_in_use() => {
+                self.show_message(format!(
+                    "Swap file kept, process {} still runs",
+                    swap.pid
+                ));
+            }
+            Char('d') => match fs::remove_file(&swap.path) {
+                Ok(()) => {
+                    info!("Deleted swap file {}", swap.path.display());
+                    self.show_message(String::from("Swap file deleted"));
+                }
+                Err(e) => self
+                    .show_message(format!("Could not delete swap file: {e}")),
+            },
+            Char('f') => {
+                let diff = diff::with_header(
+                    (&self.filename, &self.current_buffer.lines),
+                    (&swap.path.display().to_string(), &swap.lines),
+                );
+                self.show_scratch(diff);
+                self.show_message(String::from(
+                    "Swap file kept, :recover in the tab of the file offers it \
+                     again",
+                ));
+            }
+            _ => self.show_message(String::from(
+                "Swap file kept, :recover offers it again",
+            )),
+        }
+    }
+
+    /// Plays the macro in `register` back `count` times
+    fn play_macro(&mut self, register: char, count: usize) {
+        let Some(keys) = self.macros.get(register).map(<[KeyEvent]>::to_vec)
+        else {
+            self.show_message(format!("Register {register} is empty"));
+            return;
+        };
+        if self.replay_depth >= MAX_REPLAY_DEPTH {
+            self.show_message(format!("Macro @{register} nested too deep"));
+            return;
+        }
+
+        info!("Playing macro @{register} {count} times");
+        self.replay_depth += 1;
+        'playback: for _ in 0..count {
+            for key in &keys {
+                self.evaluate_event(&Key(*key));
+                if self.should_quit {
+                    break 'playback;
+                }
+            }
+        }
+        self.replay_depth -= 1;
+    }
+
+    fn save_macros(&mut self) {
+        let Some(path) = &self.macros_path else {
+            return;
+        };
+        if let Err(e) = self.macros.save(path) {
+            debug!("Error saving macros: {e}");
+            self.show_message(format!("Could not save macros: {e}"));
+        }
+    }
+
+    /// Writes every buffer with unsaved changes to a new directory in
+    /// `dir`, along with what the panic hook kept of the panic, and tells
+    /// where on the restored terminal
+    fn save_after_panic(&self, dir: &Path) {
+        let report = recovery::take_report()
+            .unwrap_or_else(|| String::from("The panic left no message"));
+        let current = self
+            .is_modified()
+            .then_some((self.filename.as_str(), &self.current_buffer));
+        let others = self
+            .buffers
+            .values()
+            .filter(|open| open.buffer.revision() != open.saved_revision)
+            .map(|open| (open.filename.as_str(), &open.buffer));
+        let buffers: Vec<(Option<PathBuf>, &Buffer)> = current
+            .into_iter()
+            .chain(others)
+            .map(|(filename, buffer)| {
+                ((!filename.is_empty()).then(|| absolute(filename)), buffer)
+            })
+            .collect();
+
+        match recovery::save(dir, &report, &buffers) {
+            Ok((dir, written)) => {
+                info!("Saved {} buffers to {}", buffers.len(), dir.display());
+                if !written.is_empty() {
+                    eprintln!("r-edit crashed, unsaved changes were saved:");
+                }
+                for ((file, _), result) in buffers.iter().zip(written) {
+                    let file = file.as_ref().map_or_else(
+                        || String::from("[No Name]"),
+                        |file| file.display().to_string(),
+                    );
+                    match result {
+                        Ok(path) => eprintln!("  {file} -> {}", path.display()),
+                        Err(e) => eprintln!("  {file} could not be saved: {e}"),
+                    }
+                }
+                eprintln!(
+                    "The panic message and backtrace are in {}",
+                    dir.join("panic.txt").display()
+                );
+            }
+            Err(e) => {
+                eprintln!(
+                    "r-edit crashed and could not save unsaved changes to \
+                     {}: {e}",
+                    dir.display()
+                );
+            }
+        }
+    }
+
+    /// Keeps the report of a panic for `save_after_panic`. Threads working
+    /// beside the editor write theirs to a new directory in `dir` right
+    /// away, as the editor goes on without them and the terminal stays.
+    fn set_up_panic_hook(dir: PathBuf) {
+        let current_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |panic_info| {
+            recovery::record_panic(panic_info);
+            if recovery::is_worker() {
+                let report = recovery::take_report().unwrap_or_default();
+                match recovery::save(&dir, &report, &[]) {
+                    Ok((dir, _)) => {
+                        info!("Worker panicked, see {}", dir.display());
+                    }
+                    Err(e) => debug!("Error saving the panic report: {e}"),
+                }
+                return;
+            }
+            if let Err(e) = CrosstermBackend::restore() {
+                eprintln!("{e}");
+            }
+            current_hook(panic_info);
+        }));
+    }
+}
+
+/// The focused pane of `layout`, which `windows` always has. Takes the
+/// fields rather than the editor, so the others can be borrowed alongside.
+fn focused_window<'a>(
+    windows: &'a mut HashMap<usize, Window>,
+    layout: &Layout,
+) -> &'a mut Window {
+    windows
+        .get_mut(&layout.focused())
+        .expect("every pane has a window")
+}
+
+/// Where buffers and reports go when the editor panics
+fn recovery_dir() -> PathBuf {
+    paths::state_dir()
+        .unwrap_or_else(|| std::env::temp_dir().join("r-edit"))
+        .join("recovery")
+}
+
+/// `path` relative to the working directory, if it is inside it
+fn display_path(path: &Path) -> String {
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok().map(Path::to_path_buf));
+    relative
+        .unwrap_or_else(|| path.to_path_buf())
+        .display()
+        .to_string()
+}
+
+/// The lines of the file at `path`, none if it can not be read
+fn read_lines(path: &Path) -> Vec<String> {
+    std::fs::read_to_string(path)
+        .map(|text| text.lines().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// How autosave stands for the file at `filename`, after the last one
+/// `failed` or not
+fn autosave_state(config: &Config, failed: bool, filename: &str) -> Autosave {
+    if config.autosave == 0 || filename.is_empty() {
+        Autosave::Off
+    } else if failed {
+        Autosave::Failed
+    } else {
+        Autosave::On
+    }
+}
+
+/// Whether the file at `filename` changed on disk since `watcher` last
+/// saw it, into something else than `lines`. A file that is gone did not,
+/// saving it again is fine, one that can not be read anymore did.
+fn changed_on_disk(
+    watcher: &FileWatcher,
+    filename: &str,
+    lines: &[String],
+) -> bool {
+    watcher.has_changed(&absolute(filename))
+        && match Buffer::read_file(filename) {
+            Ok(disk) => disk.lines != lines,
+            Err(e) => e.kind() != ErrorKind::NotFound,
+        }
+}
+
+/// Whether `filename` and `path` name the same file, however they spell
+/// it. Files that do not exist yet are compared by absolute path.
+fn is_same_file(filename: &str, path: &Path) -> bool {
+    let canonical = |path: &Path| {
+        fs::canonicalize(path)
+            .or_else(|_| std::path::absolute(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    };
+    canonical(Path::new(filename)) == canonical(path)
+}
+
+/// The file at `filename` as an absolute path, which names its swap file
+fn absolute(filename: &str) -> PathBuf {
+    std::path::absolute(filename).unwrap_or_else(|_| PathBuf::from(filename))
+}
+
+/// Up to `count` lines of the file at `path` from line `first` on,
+/// without reading more of it than that
+fn preview_lines(path: &Path, first: usize, count: usize) -> Vec<String> {
+    let Ok(file) = std::fs::File::open(path) else {
+        return Vec::new();
+    };
+    std::io::BufReader::new(file)
+        .lines()
+        .skip(first)
+        .take(count)
+        .map_while(Result::ok)
+        .collect()
+}
+
+/// Writes `lines` to `path`, creating its directory if needed
+fn write_lines(path: &Path, lines: &[&str]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyCode;
+
+    /// A headless editor of 20 by 5 on a buffer of `lines`, past the
+    /// welcome message
+    fn editor(lines: &[&str]) -> (Editor, VirtualScreen) {
+        let mut buffer = Buffer::new();
+        for line in lines {
+            buffer.push((*line).to_string());
+        }
+        let size = Size {
+            width: 20,
+            height: 5,
+        };
+        let (mut editor, screen) = Editor::headless(size, buffer);
+        editor.handle_event(&Key(KeyEvent::from(Esc)));
+        (editor, screen)
+    }
+
+    fn press(editor: &mut Editor, code: KeyCode) {
+        editor.handle_event(&Key(KeyEvent::from(code)));
+    }
+
+    fn lines(editor: &Editor) -> Vec<&str> {
+        editor.buffer().lines.iter().map(String::as_str).collect()
+    }
+
+    /// A file named after `name` in the temporary directory holding
+    /// `contents`, last modified a while ago
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("r-edit-test-{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        age(&path);
+        path
+    }
+
+    /// Makes the file at `path` look modified an hour ago, so changing it
+    /// right after tells
+    fn age(path: &Path) {
+        let hour_ago = std::time::SystemTime::now() - Duration::from_hours(1);
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(hour_ago))
+            .unwrap();
+    }
+
+    /// Overwrites the file at `path` with text that is not UTF-8
+    fn write_binary(path: &Path) {
+        fs::write(path, [0xff, 0xfe, 0x00, b'\n']).unwrap();
+    }
+
+    #[test]
+    fn starts_unmodified() {
+        let (_, screen) = editor(&["hello"]);
+        let status = screen.snapshot().lines().nth(3).unwrap().to_string();
+        assert!(status.starts_with("[No Name] "), "{status}");
+        assert!(!status.contains("[+]"), "{status}");
+    }
+
+    #[test]
+    fn opens_another_file_before_any_edit() {
+        let (mut editor, _) = editor(&["hello"]);
+        let path = std::env::temp_dir().join("r-edit-test-not-there.txt");
+        editor.run_ex(&format!("e {}", path.display()));
+        assert_eq!(editor.filename, path.display().to_string());
+        assert!(editor.buffer().lines.is_empty());
+    }
+
+    #[test]
+    fn typing_inserts_at_the_caret() {
+        let (mut editor, screen) = editor(&["world"]);
+        for c in "hi ".chars() {
+            press(&mut editor, Char(c));
+        }
+        assert_eq!(lines(&editor), ["hi world"]);
+        let snapshot = screen.snapshot();
+        assert!(snapshot.starts_with("hi world\n~\n~\n"), "{snapshot}");
+        assert!(snapshot.contains("[No Name] [+]"), "{snapshot}");
+    }
+
+    #[test]
+    fn enter_splits_the_line() {
+        let (mut editor, screen) = editor(&["hello world"]);
+        for _ in 0..5 {
+            press(&mut editor, Right);
+        }
+        press(&mut editor, Enter);
+        assert_eq!(lines(&editor), ["hello", " world"]);
+        assert!(screen.snapshot().starts_with("hello\n world\n~\n"));
+    }
+
+    #[test]
+    fn backspace_at_the_start_of_a_line_joins_it() {
+        let (mut editor, screen) = editor(&["hello", "world", "!"]);
+        press(&mut editor, Down);
+        press(&mut editor, Backspace);
+        assert_eq!(lines(&editor), ["helloworld", "!"]);
+        assert!(screen.snapshot().starts_with("helloworld\n!\n~\n"));
+        // The caret is where the lines met
+        press(&mut editor, Char('_'));
+        assert_eq!(lines(&editor), ["hello_world", "!"]);
+    }
+
+    /// Replaces `foo` with two lines at the start of line 0 and column 4
+    /// of line 1 of the file at `path`
+    fn replace_foo(editor: &mut Editor, path: &Path) {
+        let matches = [(0, 0), (1, 4)].map(|(line, start)| GrepMatch {
+            path: path.to_path_buf(),
+            line,
+            start,
+            end: start + 3,
+            text: String::new(),
+        });
+        let regex = Regex::new("foo").unwrap();
+        let replace = Replace::new(
+            PathBuf::new(),
+            regex,
+            String::from("a\nb"),
+            &matches,
+            |path| editor.file_lines(path),
+        );
+        editor.replace = Some(replace);
+        editor.apply_replace();
+    }
+
+    #[test]
+    fn replacing_in_a_file_that_is_not_open_keeps_its_line_breaks() {
+        let path = temp_file("replace.txt", b"foo\r\nbar foo");
+        let (mut editor, _) = editor(&[]);
+        replace_foo(&mut editor, &path);
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(text, "a\r\nb\r\nbar a\r\nb");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replacing_in_an_open_file_keeps_its_line_breaks() {
+        let path = temp_file("replace-open.txt", b"foo\r\nbar foo\r\n");
+        let (mut editor, _) = editor(&[]);
+        editor.run_ex(&format!("e {}", path.display()));
+        replace_foo(&mut editor, &path);
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(text, "a\r\nb\r\nbar a\r\nb\r\n");
+        assert_eq!(lines(&editor), ["a", "b", "bar a", "b"]);
+        assert!(!editor.is_modified());
+        fs::remove_file(path).unwrap();
+    }
+
+    /// A rename of `foo` to `bar` at the start of line `line`
+    fn rename_foo(line: usize) -> Vec<lsp::protocol::TextEdit> {
+        let at = |character| lsp::protocol::Position {
+            line,
+            character,
+            encoding: lsp::protocol::Encoding::Utf8,
+        };
+        vec![lsp::protocol::TextEdit {
+            start: at(0),
+            end: at(3),
+            new_text: String::from("bar"),
+        }]
+    }
+
+    #[test]
+    fn renaming_edits_open_buffers_undoably_and_writes_other_files() {
+        let path = temp_file("rename.txt", b"x\r\nfoo()\r\n");
+        let (mut editor, _) = editor(&["foo", "y"]);
+        editor.filename = String::from("rename-open.txt");
+        let open = std::env::current_dir().unwrap().join("rename-open.txt");
+        editor.apply_workspace_edit(&[
+            (path.clone(), rename_foo(1)),
+            (open, rename_foo(0)),
+        ]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "x\r\nbar()\r\n");
+        assert_eq!(lines(&editor), ["bar", "y"]);
+        assert_eq!(editor.message.as_deref(), Some("Renamed in 2 files"));
+        assert!(editor.current_buffer.undo());
+        assert_eq!(lines(&editor), ["foo", "y"]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn renaming_changes_nothing_when_a_file_fails() {
+        let path = temp_file("rename-fails.txt", b"foo\n");
+        let missing = std::env::temp_dir().join("r-edit-missing/rename.txt");
+        let (mut editor, _) = editor(&[]);
+        editor.apply_workspace_edit(&[
+            (path.clone(), rename_foo(0)),
+            (missing, rename_foo(0)),
+        ]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "foo\n");
+        assert!(
+            editor
+                .message
+                .as_ref()
+                .unwrap()
+                .starts_with("Nothing renamed")
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replaying_a_recording_ends_on_the_same_frame_without_saving() {
+        let path = temp_file("replay.rs", b"fn main() {}\n");
+        let session = path.with_extension("session");
+        let filename = path.to_string_lossy().to_string();
+        let size = Size {
+            width: 30,
+            height: 6,
+        };
+        let backend = VirtualBackend::new(size);
+        let recorded = backend.screen();
+        let mut editor = Editor::with_terminal(
+            Terminal::new(Box::new(backend)),
+            Buffer::read_file(&filename).unwrap(),
+            filename.clone(),
+        );
+        let typed = "hi ".chars().map(|c| Key(KeyEvent::from(Char(c))));
+        let save = KeyEvent::new(Char('s'), KeyModifiers::CONTROL);
+        // Past the welcome message first
+        let welcome = Key(KeyEvent::from(Esc));
+        let events = [welcome].into_iter().chain(typed).chain([Key(save)]);
+        let events = events.collect();
+        editor.set_event_source(Box::new(ScriptedEvents::new(events)));
+        editor.record_to(&session).unwrap();
+        editor.render();
+        editor.repl();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hi fn main() {}\n");
+
+        fs::write(&path, "fn main() {}\n").unwrap();
+        let (header, entries) = session::read_session(&session).unwrap();
+        let (mut replay, replayed) =
+            Editor::replaying(&header, entries, header.file.clone()).unwrap();
+        replay.render();
+        replay.repl();
+        assert_eq!(replayed.snapshot(), recorded.snapshot());
+        assert!(replayed.snapshot().contains("Saved"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn main() {}\n");
+        fs::remove_file(path).unwrap();
+        fs::remove_file(session).unwrap();
+    }
+
+    /// The labels of the completion menu, empty when it is closed
+    fn completions(editor: &Editor) -> Vec<String> {
+        editor
+            .completion
+            .as_ref()
+            .map_or_else(Vec::new, |completion| {
+                completion.items().map(|item| item.label.clone()).collect()
+            })
+    }
+
+    #[test]
+    fn completion_leaves_out_the_word_being_typed_into() {
+        let (mut editor, _) = editor(&["hello", "abacus"]);
+        press(&mut editor, Char('a'));
+        press(&mut editor, Char('b'));
+        assert_eq!(lines(&editor), ["abhello", "abacus"]);
+        assert_eq!(completions(&editor), ["abacus"]);
+    }
+
+    #[test]
+    fn completion_offers_the_words_of_other_buffers() {
+        let other = temp_file("other-words.txt", b"zebra\n");
+        let (mut editor, _) = editor(&[]);
+        editor.run_ex(&format!("e {}", other.display()));
+        let path = std::env::temp_dir().join("r-edit-test-new-words.txt");
+        editor.run_ex(&format!("sp {}", path.display()));
+        press(&mut editor, Char('z'));
+        press(&mut editor, Char('e'));
+        assert_eq!(completions(&editor), ["zebra"]);
+        fs::remove_file(other).unwrap();
+    }
+
+    #[test]
+    fn opening_a_binary_file_shows_why_it_can_not() {
+        let path = temp_file("open.bin", &[0xff, 0xfe, 0x00]);
+        let (mut editor, _) = editor(&["hello"]);
+        editor.run_ex(&format!("e {}", path.display()));
+        assert_eq!(lines(&editor), ["hello"]);
+        assert!(editor.filename.is_empty());
+        let message = editor.message.clone().unwrap_or_default();
+        assert!(message.ends_with("not UTF-8 text"), "{message}");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_file_turned_binary_on_disk_is_not_reloaded() {
+        let path = temp_file("turned-binary.txt", b"one\n");
+        let (mut editor, _) = editor(&[]);
+        editor.run_ex(&format!("e {}", path.display()));
+        write_binary(&path);
+        editor.file_changed(&path);
+        assert_eq!(lines(&editor), ["one"]);
+        let message = editor.message.clone().unwrap_or_default();
+        assert!(message.contains("can not be read anymore"), "{message}");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn opening_an_open_file_by_another_name_keeps_one_buffer() {
+        let path = temp_file("open-twice.txt", b"one\n");
+        let other = temp_file("open-other.txt", b"two\n");
+        let name = path.file_name().unwrap().to_string_lossy();
+        let dotted = path.parent().unwrap().join(".").join(&*name);
+        let (mut editor, _) = editor(&[]);
+        editor.run_ex(&format!("e {}", path.display()));
+        press(&mut editor, Char('x'));
+        editor.run_ex(&format!("e {}", dotted.display()));
+        assert_eq!(lines(&editor), ["xone"]);
+        assert!(editor.is_modified());
+        assert!(editor.buffers.is_empty());
+
+        editor.run_ex(&format!("sp {}", other.display()));
+        assert_eq!(lines(&editor), ["two"]);
+        editor.run_ex(&format!("e {}", dotted.display()));
+        assert_eq!(lines(&editor), ["xone"]);
+        // Both panes show the one buffer
+        assert!(editor.is_shown_elsewhere());
+        assert!(editor.buffers.is_empty());
+        fs::remove_file(path).unwrap();
+        fs::remove_file(other).unwrap();
+    }
+
+    /// The files in the only report directory in `dir`
+    fn report(dir: &Path) -> Vec<String> {
+        let reports: Vec<PathBuf> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(reports.len(), 1, "{reports:?}");
+        let mut files: Vec<String> = fs::read_dir(&reports[0])
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn a_panic_leaves_a_report_and_the_unsaved_buffers() {
+        let dir = std::env::temp_dir()
+            .join(format!("r-edit-test-{}-recovery", std::process::id()));
+        let (mut editor, _) = editor(&["one"]);
+        press(&mut editor, Char('x'));
+        editor.save_after_panic(&dir);
+        assert_eq!(report(&dir), ["panic.txt", "unnamed-1"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_panic_on_a_worker_thread_leaves_a_report() {
+        let dir = std::env::temp_dir()
+            .join(format!("r-edit-test-{}-worker", std::process::id()));
+        Editor::set_up_panic_hook(dir.clone());
+        let worker = recovery::spawn_worker("test", || panic!("worker failed"));
+        assert!(worker.join().is_err());
+        // Back to the hook of the test harness
+        drop(std::panic::take_hook());
+        assert_eq!(report(&dir), ["panic.txt"]);
+        let reports = fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+        let text = fs::read_to_string(reports.path().join("panic.txt"));
+        assert!(text.unwrap().contains("worker failed"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// An editor journaling to a swap directory of its own, and the swap
+    /// file process `pid` left there of a file holding `one`
+    fn swapped(name: &str, pid: u32) -> (Editor, PathBuf, PathBuf) {
+        let path = temp_file(name, b"one\n");
+        let dir = path.with_extension("swap");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let swap =
+            swap::swap_path(&dir, &absolute(&path.to_string_lossy()), pid);
+        let text = format!(
+            "r-edit swap file\npid {pid}\nfile {}\n\ntwo\n",
+            path.display()
+        );
+        fs::write(&swap, text).unwrap();
+        let (mut editor, _) = editor(&[]);
+        editor.swap_dir = Some(dir);
+        editor.run_ex(&format!("e {}", path.display()));
+        (editor, path, swap)
+    }
+
+    #[test]
+    fn recovering_takes_over_the_swap_file_of_an_editor_that_is_gone() {
+        let (mut editor, path, theirs) = swapped("recover.txt", u32::MAX);
+        assert!(matches!(editor.pending, Some(Pending::Swap(_))));
+        press(&mut editor, Char('r'));
+        assert_eq!(lines(&editor), ["two"]);
+        let dir = editor.swap_dir.clone().unwrap();
+        let file = absolute(&editor.filename);
+        assert!(swap::swap_path(&dir, &file, std::process::id()).exists());
+        assert!(!theirs.exists());
+        editor.remove_swaps();
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn recovering_leaves_the_swap_file_of_an_editor_that_still_runs() {
+        let (mut editor, path, theirs) = swapped("recover-running.txt", 1);
+        let message = editor.message.clone().unwrap_or_default();
+        assert!(message.contains("process 1 still runs"), "{message}");
+        press(&mut editor, Char('d'));
+        assert!(theirs.exists());
+        editor.run_ex("recover");
+        press(&mut editor, Char('r'));
+        assert_eq!(lines(&editor), ["two"]);
+        assert!(theirs.exists());
+        editor.remove_swaps();
+        fs::remove_dir_all(editor.swap_dir.clone().unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn plays_a_recorded_macro_back_count_times() {
+        let (mut editor, _screen) = editor(&[""]);
+        let ctrl = |c| Key(KeyEvent::new(Char(c), KeyModifiers::CONTROL));
+        editor.handle_event(&ctrl('r'));
+        press(&mut editor, Char('a'));
+        press(&mut editor, Char('x'));
+        press(&mut editor, Char('y'));
+        editor.handle_event(&ctrl('r'));
+        assert_eq!(lines(&editor), ["xy"]);
+
+        editor.handle_event(&ctrl('e'));
+        press(&mut editor, Char('2'));
+        press(&mut editor, Char('a'));
+        assert_eq!(lines(&editor), ["xyxyxy"]);
+        assert_eq!(
+            editor.macros.get('a'),
+            Some(
+                [KeyEvent::from(Char('x')), KeyEvent::from(Char('y'))]
+                    .as_slice()
+            )
+        );
+    }
+
+    #[test]
+    fn the_palette_runs_the_chosen_command_and_remembers_it() {
+        let (mut editor, _screen) = editor(&["abc"]);
+        let palette = Key(KeyEvent::new(Char('p'), KeyModifiers::CONTROL));
+        editor.handle_event(&palette);
+        for c in "lend".chars() {
+            press(&mut editor, Char(c));
+        }
+        press(&mut editor, Enter);
+        assert!(editor.palette.is_none());
+        assert_eq!(editor.caret().location, Location { x: 3, y: 0 });
+        assert_eq!(editor.recent_commands, [Command::MoveLineEnd]);
+
+        editor.handle_event(&palette);
+        press(&mut editor, Esc);
+        assert!(editor.palette.is_none());
+        assert_eq!(lines(&editor), ["abc"]);
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    #[test]
+    fn folds_and_selects_by_the_syntax_tree() {
+        let source = "fn main() {\n    let x = 1;\n}\nfn b() {}\n";
+        let path = temp_file("fold.rs", source.as_bytes());
+        let (mut editor, _screen) = editor(&[]);
+        editor.run_ex(&format!("e {}", path.display()));
+        fs::remove_file(&path).unwrap();
+        let alt = |c| Key(KeyEvent::new(Char(c), KeyModifiers::ALT));
+
+        editor.handle_event(&alt('z'));
+        assert_eq!(
+            editor.window().folds.starting_at(0),
+            Some(folds::Fold { first: 0, last: 2 })
+        );
+        press(&mut editor, Down);
+        assert_eq!(editor.caret().location.y, 3);
+        press(&mut editor, Up);
+        editor.handle_event(&alt('z'));
+        assert!(editor.window().folds.is_empty());
+
+        press(&mut editor, Down);
+        for _ in 0..8 {
+            press(&mut editor, Right);
+        }
+        editor.handle_event(&alt('o'));
+        editor.handle_event(&alt('o'));
+        let selection = editor.selection().unwrap();
+        assert_eq!(
+            (selection.start, selection.end),
+            (Location { x: 4, y: 1 }, Location { x: 14, y: 1 })
+        );
+    }
+
+    #[cfg(not(feature = "tree-sitter"))]
+    #[test]
+    fn syntax_commands_say_they_need_tree_sitter() {
+        let (mut editor, _screen) = editor(&["fn main() {}"]);
+        editor.handle_event(&Key(KeyEvent::new(Char('o'), KeyModifiers::ALT)));
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("Built without tree-sitter support")
+        );
+    }
+
+    #[test]
+    fn each_pane_keeps_its_own_caret() {
+        let (mut editor, _screen) = editor(&["one", "two", "three"]);
+        press(&mut editor, Down);
+        editor.run_ex("sp");
+        assert_eq!(editor.windows.len(), 2);
+        assert_eq!(editor.caret().location.y, 1);
+
+        press(&mut editor, Down);
+        press(&mut editor, KeyCode::End);
+        editor.run_command(Command::FocusNextPane);
+        assert_eq!(editor.caret().location, Location { x: 0, y: 1 });
+
+        editor.run_command(Command::FocusNextPane);
+        assert_eq!(editor.caret().location, Location { x: 5, y: 2 });
+        editor.run_ex("close");
+        assert_eq!(editor.windows.len(), 1);
+        assert_eq!(editor.caret().location.y, 1);
+    }
+
+    #[test]
+    fn tabs_keep_their_own_panes() {
+        let (mut editor, screen) = editor(&["one", "two"]);
+        editor.run_ex("sp");
+        press(&mut editor, Down);
+        editor.run_ex("tabnew");
+        assert_eq!((editor.tab, editor.tabs.len()), (1, 1));
+        assert_eq!(editor.layout.pane_count(), 1);
+        assert_eq!(editor.caret().location.y, 1);
+        editor.render();
+        assert!(screen.row(0).starts_with(" 1 [No Name]  2 [No"));
+
+        press(&mut editor, Up);
+        editor.run_ex("tabp");
+        assert_eq!(editor.tab, 0);
+        assert_eq!(editor.layout.pane_count(), 2);
+        assert_eq!(editor.caret().location.y, 1);
+        editor.run_ex("tabn 2");
+        assert_eq!(editor.tab, 1);
+        assert_eq!(editor.caret().location.y, 0);
+        editor.run_ex("tabn 3");
+        assert_eq!(editor.message.as_deref(), Some("No tab 3"));
+    }
+
+    #[test]
+    fn moving_a_tab_reorders_the_tab_bar() {
+        let path = temp_file("moved-tab.txt", b"moved\n");
+        let (mut editor, _screen) = editor(&[]);
+        editor.run_ex(&format!("tabe {}", path.display()));
+        fs::remove_file(&path).unwrap();
+        let name = path.file_name().unwrap().to_string_lossy();
+        let moved = format!(" 2 {name} ");
+        assert_eq!(editor.tab_labels(), [" 1 [No Name] ", moved.as_str()]);
+
+        editor.run_ex("tabm 0");
+        assert_eq!(editor.tab, 0);
+        let moved = format!(" 1 {name} ");
+        assert_eq!(editor.tab_labels(), [moved.as_str(), " 2 [No Name] "]);
+        assert_eq!(lines(&editor), ["moved"]);
+        editor.run_command(Command::MoveTabRight);
+        assert_eq!(editor.tab, 1);
+    }
+
+    #[test]
+    fn closing_a_tab_keeps_changes_no_other_tab_shows() {
+        let (mut editor, _screen) = editor(&["shared"]);
+        editor.run_ex("tabnew");
+        press(&mut editor, Char('x'));
+        editor.run_ex("tabc");
+        assert!(editor.tabs.is_empty());
+        assert_eq!(lines(&editor), ["xshared"]);
+
+        let path = std::env::temp_dir().join("r-edit-test-closed-tab.txt");
+        editor.run_ex(&format!("tabe {}", path.display()));
+        press(&mut editor, Char('y'));
+        editor.run_ex("tabc");
+        assert_eq!(editor.tabs.len(), 1);
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("No write since last change (add ! to override)")
+        );
+        editor.run_ex("tabc!");
+        assert!(editor.tabs.is_empty());
+        assert!(editor.buffers.is_empty());
+        assert_eq!(lines(&editor), ["xshared"]);
+        editor.run_ex("tabc");
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("Can not close the last tab")
+        );
+    }
+
+    /// An editor on the file at `path` with autosave after `seconds`, wide
+    /// enough for the status line to show the whole temporary path
+    fn autosaving(path: &Path, seconds: u64) -> (Editor, VirtualScreen) {
+        let size = Size {
+            width: 120,
+            height: 5,
+        };
+        let (mut editor, screen) = Editor::headless(size, Buffer::new());
+        press(&mut editor, Esc);
+        editor.config.autosave = seconds;
+        editor.run_ex(&format!("e {}", path.display()));
+        (editor, screen)
+    }
+
+    #[test]
+    fn autosaves_when_idle_and_on_focus_loss() {
+        let path = temp_file("autosave.txt", b"one\n");
+        let (mut editor, screen) = autosaving(&path, 30);
+        press(&mut editor, Char('x'));
+        assert!(editor.timers.is_scheduled(Timer::Autosave));
+        editor.render();
+        assert!(screen.row(3).contains("[auto]"), "{}", screen.row(3));
+        editor.fire_timer(Timer::Autosave);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "xone\n");
+        assert!(!editor.is_modified());
+
+        press(&mut editor, Char('y'));
+        editor.handle_event(&Event::FocusLost);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "xyone\n");
+        assert!(!editor.is_modified());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn does_not_autosave_unless_turned_on() {
+        let path = temp_file("no-autosave.txt", b"one\n");
+        let (mut editor, _screen) = autosaving(&path, 0);
+        press(&mut editor, Char('x'));
+        assert!(!editor.timers.is_scheduled(Timer::Autosave));
+        editor.handle_event(&Event::FocusLost);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\n");
+        assert!(editor.is_modified());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn autosave_leaves_a_file_changed_on_disk_alone() {
+        let path = temp_file("autosave-conflict.txt", b"one\n");
+        let (mut editor, screen) = autosaving(&path, 30);
+        press(&mut editor, Char('x'));
+        fs::write(&path, "changed\n").unwrap();
+        editor.handle_event(&Event::FocusLost);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "changed\n");
+        assert!(editor.is_modified());
+        let message = editor.message.clone().unwrap_or_default();
+        assert!(message.ends_with("changed on disk"), "{message}");
+        editor.render();
+        assert!(screen.row(3).contains("[auto failed]"), "{}", screen.row(3));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn substitutes_in_the_buffer() {
+        let (mut editor, _) = editor(&["foo foo", "bar", "foo", "foo"]);
+        editor.run_ex("1,3s/foo/<&>/");
+        assert_eq!(lines(&editor), ["<foo> foo", "bar", "<foo>", "foo"]);
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("2 substitutions on 2 lines")
+        );
+        assert_eq!(editor.caret().location.y, 2);
+
+        editor.run_ex(r"%s/(\S+) (\S+)/\2\n\1/g");
+        assert_eq!(lines(&editor), ["foo", "<foo>", "bar", "<foo>", "foo"]);
+        editor.run_ex("%s/o/0/g");
+        assert_eq!(lines(&editor), ["f00", "<f00>", "bar", "<f00>", "f00"]);
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("8 substitutions on 4 lines")
+        );
+        editor.run_ex("s/x/y/");
+        assert_eq!(editor.message.as_deref(), Some("Pattern not found: x"));
+    }
+
+    #[test]
+    fn reload_refuses_a_file_that_can_not_be_read() {
+        let path = temp_file("conflict.txt", b"one\n");
+        let (mut editor, _) = editor(&[]);
+        editor.run_ex(&format!("e {}", path.display()));
+        press(&mut editor, Char('x'));
+        write_binary(&path);
+        assert!(!editor.save());
+        press(&mut editor, Char('r'));
+        assert_eq!(lines(&editor), ["xone"]);
+        let message = editor.message.clone().unwrap_or_default();
+        assert!(message.contains("can not be read"), "{message}");
+        fs::remove_file(path).unwrap();
     }
 }
@@ -1,58 +1,223 @@
 use super::terminal::Location; // Added this line
+use std::collections::VecDeque;
+use std::fs;
 use std::io::Error;
-use std::io::Write;
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-};
+use std::path::{Path, PathBuf};
+
+/// How many edits `Buffer` remembers for `edits_since`
+const MAX_EDITS: usize = 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// A place in the text counted in bytes, as parsers want it
+pub struct BytePosition {
+    /// Into the whole text, every line ending in `\n`
+    pub offset: usize,
+    /// Into its line
+    pub column: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// One change to a `Buffer`: the text from `start` to `old_end` was
+/// replaced by text now ending at `new_end`
+pub struct Edit {
+    pub start: Location,
+    pub old_end: Location,
+    pub new_end: Location,
+    /// `start`, `old_end` and `new_end` in bytes
+    pub start_byte: BytePosition,
+    pub old_end_byte: BytePosition,
+    pub new_end_byte: BytePosition,
+}
 
 pub struct Buffer {
     pub lines: Vec<String>,
+    /// Bumped by every edit, tells whether something changed in between
+    revision: u64,
+    /// The latest edits with the revision each of them led to
+    edits: VecDeque<(u64, Edit)>,
+    /// Length of the text in bytes, every line ending in `\n`
+    size: usize,
+    /// The lines before the last `replace_lines`, with the revision it
+    /// led to, so `undo` can take it back
+    checkpoint: Option<(u64, Vec<String>)>,
+    /// What ends every line in the file, `\n` or `\r\n`
+    line_ending: &'static str,
+}
+
+/// Byte index of the character at `x` in `line`, or the length of the
+/// line when `x` is past its end
+fn byte_index(line: &str, x: usize) -> usize {
+    line.char_indices()
+        .nth(x)
+        .map_or(line.len(), |(index, _)| index)
 }
 
 impl Buffer {
     pub fn default() -> Buffer {
-        Buffer { lines: Vec::new() }
+        Buffer::new()
     }
     pub fn new() -> Buffer {
-        Buffer { lines: Vec::new() }
+        Buffer {
+            lines: Vec::new(),
+            revision: 0,
+            edits: VecDeque::new(),
+            size: 0,
+            checkpoint: None,
+            line_ending: "\n",
+        }
     }
 
-    pub fn push(&mut self, line: String) {
-        self.lines.push(line);
+    pub fn revision(&self) -> u64 {
+        self.revision
     }
 
-    pub fn pop(&mut self) -> Option<String> {
-        self.lines.pop()
+    /// Length of the text in bytes, every line ending in `\n`
+    pub fn size(&self) -> usize {
+        self.size
     }
 
-    pub fn get_line(&self, index: usize) -> Option<String> {
-        self.lines.get(index).cloned()
+    /// The edits made after `revision`, oldest first. `None` if they are
+    /// not all remembered anymore, or `revision` is not from this buffer.
+    pub fn edits_since(&self, revision: u64) -> Option<Vec<Edit>> {
+        if revision > self.revision {
+            return None;
+        }
+        if revision == self.revision {
+            return Some(Vec::new());
+        }
+        let (oldest, _) = self.edits.front()?;
+        if *oldest > revision + 1 {
+            return None;
+        }
+        Some(
+            self.edits
+                .iter()
+                .filter(|(edit_revision, _)| *edit_revision > revision)
+                .map(|(_, edit)| *edit)
+                .collect(),
+        )
     }
 
-    pub fn read_file(path: &str) -> Result<Buffer, Error> {
-        let mut buffer = Buffer::new();
+    /// Where `location` is in bytes
+    pub fn byte_position(&self, location: Location) -> BytePosition {
+        if location.y >= self.lines.len() {
+            return BytePosition {
+                offset: self.size,
+                column: 0,
+            };
+        }
+        let column = self.byte_column(location);
+        let before: usize =
+            self.lines[..location.y].iter().map(|l| l.len() + 1).sum();
+        BytePosition {
+            offset: before + column,
+            column,
+        }
+    }
 
-        match File::open(path) {
-            Ok(file) => {
-                let reader = BufReader::new(file);
-                for line in reader.lines() {
-                    let line = line.unwrap();
-                    buffer.push(line);
-                }
-            }
-            Err(e) => return Err(e),
+    /// How many bytes into its line `location` is
+    pub fn byte_column(&self, location: Location) -> usize {
+        self.lines
+            .get(location.y)
+            .map_or(0, |line| byte_index(line, location.x))
+    }
+
+    /// The location of byte `column` of line `y`
+    pub fn location_of_byte(&self, y: usize, column: usize) -> Location {
+        let line = self.lines.get(y).map_or("", String::as_str);
+        let x = line
+            .char_indices()
+            .take_while(|(index, _)| *index < column)
+            .count();
+        Location { x, y }
+    }
+
+    /// Logs an edit about to replace the text from `start` to `old_end`
+    /// with `inserted`, which will end at `new_end`
+    fn record(
+        &mut self,
+        start: Location,
+        old_end: Location,
+        new_end: Location,
+        inserted: &str,
+    ) {
+        self.revision += 1;
+        if self.edits.len() == MAX_EDITS {
+            self.edits.pop_front();
         }
+        let start_byte = self.byte_position(start);
+        let old_end_byte = self.byte_position(old_end);
+        let new_end_byte = BytePosition {
+            offset: start_byte.offset + inserted.len(),
+            column: match inserted.rfind('\n') {
+                Some(newline) => inserted.len() - newline - 1,
+                None => start_byte.column + inserted.len(),
+            },
+        };
+        self.size = self.size + new_end_byte.offset - old_end_byte.offset;
+        let edit = Edit {
+            start,
+            old_end,
+            new_end,
+            start_byte,
+            old_end_byte,
+            new_end_byte,
+        };
+        self.edits.push_back((self.revision, edit));
+    }
 
+    /// Length of line `y` in characters, 0 past the end of the buffer
+    pub fn line_len(&self, y: usize) -> usize {
+        self.lines.get(y).map_or(0, |line| line.chars().count())
+    }
+
+    pub fn push(&mut self, line: String) {
+        let end = Location {
+            x: 0,
+            y: self.lines.len(),
+        };
+        let inserted = format!("{line}\n");
+        self.record(end, end, Location { x: 0, y: end.y + 1 }, &inserted);
+        self.lines.push(line);
+    }
+
+    /// Reads the file at `path`, which ends its lines like its first line
+    /// does
+    pub fn read_file(path: &str) -> Result<Buffer, Error> {
+        let mut buffer = Buffer::new();
+        let text = fs::read_to_string(path)?;
+        if text
+            .lines()
+            .next()
+            .is_some_and(|line| text[line.len()..].starts_with("\r\n"))
+        {
+            buffer.line_ending = "\r\n";
+        }
+        for line in text.lines() {
+            buffer.push(line.to_string());
+        }
         Ok(buffer)
     }
 
-    pub fn write_file(&self, path: &str) {
-        let mut file = File::create(path).unwrap();
+    /// What ends every line in the file, `\n` or `\r\n`
+    pub fn line_ending(&self) -> &'static str {
+        self.line_ending
+    }
+
+    /// The text as `write_file` writes it, every line ending like the
+    /// file's did
+    pub fn contents(&self) -> String {
+        let mut text = String::new();
         for line in &self.lines {
-            file.write_all(line.as_bytes()).unwrap();
-            file.write_all(b"\n").unwrap();
+            text.push_str(line);
+            text.push_str(self.line_ending);
         }
+        text
+    }
+
+    /// Writes the buffer to `path` as `write_files` does
+    pub fn write_file(&self, path: &str) -> Result<(), Error> {
+        write_files(&[(PathBuf::from(path), self.contents())])
     }
 
     pub fn insert(&mut self, c: char, at: Location) {
@@ -60,15 +225,30 @@ impl Buffer {
         if at.y > self.lines.len() {
             return; // Invalid y-coordinate
         }
+        let new_end = if c == '\n' {
+            Location { x: 0, y: at.y + 1 }
+        } else {
+            Location { x: at.x + 1, ..at }
+        };
+        let mut inserted = c.to_string();
+        if at.y == self.lines.len() && (c != '\n' || self.lines.is_empty()) {
+            // A new last line comes with its line break
+            inserted.push('\n');
+        }
+        self.record(at, at, new_end, &inserted);
 
         if c == '\n' {
-            if at.y == self.lines.len() {
+            if at.y == self.lines.len() && at.y > 0 {
                 // If at the end of the buffer, add a new empty line
-                self.lines.push("".to_string());
+                self.lines.push(String::new());
             } else {
+                if self.lines.is_empty() {
+                    // Splitting the empty line of an empty buffer
+                    self.lines.push(String::new());
+                }
                 // If in an existing line, split the line
                 let current_line = &mut self.lines[at.y];
-                let x_pos = std::cmp::min(at.x, current_line.len());
+                let x_pos = byte_index(current_line, at.x);
 
                 let rest_of_line =
                     current_line.drain(x_pos..).collect::<String>();
@@ -80,7 +260,7 @@ impl Buffer {
                 self.lines.push(c.to_string());
             } else {
                 let current_line = &mut self.lines[at.y];
-                let x_pos = std::cmp::min(at.x, current_line.len());
+                let x_pos = byte_index(current_line, at.x);
                 current_line.insert(x_pos, c);
             }
         }
@@ -92,14 +272,472 @@ impl Buffer {
         }
 
         if at.x > 0 {
+            let start = Location { x: at.x - 1, ..at };
+            self.record(start, at, start, "");
             let line = &mut self.lines[at.y];
-            if at.x <= line.len() {
-                line.remove(at.x - 1);
+            if at.x <= line.chars().count() {
+                line.remove(byte_index(line, at.x - 1));
             }
         } else if at.y > 0 {
+            let start = Location {
+                x: self.line_len(at.y - 1),
+                y: at.y - 1,
+            };
+            self.record(start, at, start, "");
             let line_to_move = self.lines.remove(at.y);
             let prev_line = &mut self.lines[at.y - 1];
             prev_line.push_str(&line_to_move);
         }
     }
+
+    /// Inserts `text`, which may span lines, at `at` and returns the
+    /// location right after it
+    pub fn insert_str(&mut self, text: &str, at: Location) -> Location {
+        let mut location = at;
+        for c in text.chars() {
+            self.insert(c, location);
+            if c == '\n' {
+                location = Location {
+                    x: 0,
+                    y: location.y + 1,
+                };
+            } else {
+                location.x += 1;
+            }
+        }
+        location
+    }
+
+    /// Removes the text from `start` up to, not including, `end` and
+    /// returns it. An `end` at column 0 of the next line takes the line
+    /// break along.
+    pub fn delete(&mut self, start: Location, end: Location) -> String {
+        if end.y >= self.lines.len() && start.y >= self.lines.len() {
+            return String::new();
+        }
+        let last = self.lines.len() - 1;
+        let end = if end.y > last {
+            Location {
+                x: self.line_len(last),
+                y: last,
+            }
+        } else {
+            end
+        };
+        self.record(start, end, start, "");
+
+        let start_byte = byte_index(&self.lines[start.y], start.x);
+        if start.y == end.y {
+            let line = &mut self.lines[start.y];
+            let end_byte = byte_index(line, end.x).max(start_byte);
+            return line.drain(start_byte..end_byte).collect();
+        }
+
+        let end_byte = byte_index(&self.lines[end.y], end.x);
+        let tail = self.lines[end.y].split_off(end_byte);
+        let mut removed: Vec<String> =
+            self.lines.drain(start.y + 1..=end.y).collect();
+        let first = &mut self.lines[start.y];
+        removed.insert(0, first.split_off(start_byte));
+        first.push_str(&tail);
+        removed.join("\n")
+    }
+
+    /// The text from `start` up to, not including, `end`, lines joined
+    /// with `\n`
+    pub fn text(&self, start: Location, end: Location) -> String {
+        let mut text = String::new();
+        for y in start.y..=end.y.min(self.lines.len().saturating_sub(1)) {
+            let line = &self.lines[y];
+            let from = if y == start.y {
+                byte_index(line, start.x)
+            } else {
+                0
+            };
+            let to = if y == end.y {
+                byte_index(line, end.x).max(from)
+            } else {
+                line.len()
+            };
+            if y > start.y {
+                text.push('\n');
+            }
+            text.push_str(&line[from..to]);
+        }
+        text
+    }
+
+    /// Replaces line `y` with `text`, which may span lines. Returns how
+    /// many lines it takes up now.
+    pub fn replace_line(&mut self, y: usize, text: &str) -> usize {
+        if y >= self.lines.len() {
+            return 0;
+        }
+        let lines: Vec<String> = text.split('\n').map(String::from).collect();
+        let count = lines.len();
+        self.record(
+            Location { x: 0, y },
+            Location {
+                x: self.line_len(y),
+                y,
+            },
+            Location {
+                x: lines.last().map_or(0, |line| line.chars().count()),
+                y: y + count - 1,
+            },
+            text,
+        );
+        self.lines.splice(y..=y, lines);
+        count
+    }
+
+    /// Replaces each line `y` of `changes` with its text, which may span
+    /// lines, as one change that `undo` can take back. The lines are
+    /// numbered as before any of the changes.
+    pub fn replace_lines(&mut self, changes: &[(usize, String)]) {
+        let before = self.lines.clone();
+        let mut changes: Vec<&(usize, String)> = changes.iter().collect();
+        // From the bottom up, so lines that grow do not move the others
+        changes.sort_by(|(a, _), (b, _)| b.cmp(a));
+        for (y, text) in changes {
+            self.replace_line(*y, text);
+        }
+        self.checkpoint = Some((self.revision, before));
+    }
+
+    /// Makes `lines` the text, changing only the lines that differ, as one
+    /// change that `undo` can take back
+    pub fn change_lines(&mut self, lines: &[String]) {
+        if self.lines == lines {
+            return;
+        }
+        let before = self.lines.clone();
+        let same_start = before.iter().zip(lines).take_while(|(a, b)| a == b);
+        let start = same_start.count();
+        let same_end = before[start..]
+            .iter()
+            .rev()
+            .zip(lines[start..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let old_end = before.len() - same_end;
+        if start < old_end {
+            self.delete_lines(start, old_end - 1);
+        }
+        self.insert_lines(start, &lines[start..lines.len() - same_end]);
+        self.checkpoint = Some((self.revision, before));
+    }
+
+    /// Takes back the last `replace_lines`, unless the buffer changed
+    /// since. Returns whether it did.
+    pub fn undo(&mut self) -> bool {
+        match self.checkpoint.take() {
+            Some((revision, lines)) if revision == self.revision => {
+                self.set_lines(&lines);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Replaces every line with `lines`
+    pub fn set_lines(&mut self, lines: &[String]) {
+        if !self.lines.is_empty() {
+            self.delete_lines(0, self.lines.len() - 1);
+        }
+        self.insert_lines(0, lines);
+    }
+
+    /// Removes whole lines `first` through `last` and returns them
+    pub fn delete_lines(&mut self, first: usize, last: usize) -> Vec<String> {
+        if first >= self.lines.len() {
+            return Vec::new();
+        }
+        let last = last.min(self.lines.len() - 1);
+        let start = Location { x: 0, y: first };
+        self.record(start, Location { x: 0, y: last + 1 }, start, "");
+        self.lines.drain(first..=last).collect()
+    }
+
+    /// Inserts whole `lines` before line `y`
+    pub fn insert_lines(&mut self, y: usize, lines: &[String]) {
+        let y = y.min(self.lines.len());
+        let start = Location { x: 0, y };
+        self.record(
+            start,
+            start,
+            Location {
+                x: 0,
+                y: y + lines.len(),
+            },
+            &lines
+                .iter()
+                .flat_map(|line| [line, "\n"])
+                .collect::<String>(),
+        );
+        self.lines.splice(y..y, lines.iter().cloned());
+    }
+}
+
+/// Where `path` is written before it is moved over `path`, or kept while
+/// it is replaced, told apart by `kind`
+fn beside(path: &Path, kind: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.r-edit-{kind}"))
+}
+
+/// `e` saying it is about `path`
+fn about(path: &Path, e: &Error) -> Error {
+    Error::new(e.kind(), format!("{}: {e}", path.display()))
+}
+
+/// Writes each of `files`, a path with its new text, next to its file,
+/// then moves them all over the files. Files are replaced all or none:
+/// nothing is moved unless every one was written, and if moving one fails
+/// the ones moved before are put back. Files that exist keep their
+/// permissions, symbolic links stay and have their target replaced.
+pub fn write_files(files: &[(PathBuf, String)]) -> Result<(), Error> {
+    let targets: Vec<PathBuf> = files
+        .iter()
+        .map(|(path, _)| fs::canonicalize(path).unwrap_or(path.clone()))
+        .collect();
+    let remove = |paths: &mut dyn Iterator<Item = PathBuf>| {
+        for path in paths {
+            let _ = fs::remove_file(path);
+        }
+    };
+    let new = |index: usize| beside(&targets[index], "new");
+    let old = |index: usize| beside(&targets[index], "old");
+    for (index, ((path, text), target)) in
+        files.iter().zip(&targets).enumerate()
+    {
+        let written = fs::write(new(index), text).and_then(|()| {
+            // The file as it is, to put back if another one fails
+            match fs::metadata(target) {
+                Ok(metadata) => {
+                    fs::set_permissions(new(index), metadata.permissions())?;
+                    fs::hard_link(target, old(index))
+                        .or_else(|_| fs::copy(target, old(index)).map(|_| ()))
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(e),
+            }
+        });
+        if let Err(e) = written {
+            remove(&mut (0..=index).flat_map(|i| [new(i), old(i)]));
+            return Err(about(path, &e));
+        }
+    }
+    for (index, (path, _)) in files.iter().enumerate() {
+        if let Err(e) = fs::rename(new(index), &targets[index]) {
+            for (moved, target) in targets.iter().enumerate().take(index) {
+                if old(moved).exists() {
+                    let _ = fs::rename(old(moved), target);
+                } else {
+                    let _ = fs::remove_file(target);
+                }
+            }
+            remove(&mut (0..files.len()).flat_map(|i| [new(i), old(i)]));
+            return Err(about(path, &e));
+        }
+    }
+    remove(&mut (0..files.len()).map(old));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    /// An empty directory of its own in the temporary directory
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("r-edit-buffer-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// The names in `dir`, sorted
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn records_edits_in_characters_and_bytes() {
+        let mut buffer = Buffer::new();
+        buffer.push(String::from("aé"));
+        buffer.push(String::from("b"));
+        let revision = buffer.revision();
+        assert_eq!(buffer.size(), 6);
+
+        buffer.insert('x', Location { x: 2, y: 0 });
+        buffer.insert('\n', Location { x: 1, y: 1 });
+        assert_eq!(buffer.size(), 8);
+        let bytes = |offset, column| BytePosition { offset, column };
+        let at = |x, y| Location { x, y };
+        assert_eq!(
+            buffer.edits_since(revision),
+            Some(vec![
+                Edit {
+                    start: at(2, 0),
+                    old_end: at(2, 0),
+                    new_end: at(3, 0),
+                    start_byte: bytes(3, 3),
+                    old_end_byte: bytes(3, 3),
+                    new_end_byte: bytes(4, 4),
+                },
+                Edit {
+                    start: at(1, 1),
+                    old_end: at(1, 1),
+                    new_end: at(0, 2),
+                    start_byte: bytes(6, 1),
+                    old_end_byte: bytes(6, 1),
+                    new_end_byte: bytes(7, 0),
+                },
+            ])
+        );
+
+        buffer.delete(at(1, 0), at(1, 1));
+        assert_eq!(buffer.lines, ["a", ""]);
+        assert_eq!(buffer.size(), 3);
+        let edits = buffer.edits_since(revision + 2).unwrap();
+        assert_eq!(edits[0].old_end_byte, bytes(6, 1));
+        assert_eq!(edits[0].new_end_byte, bytes(1, 1));
+    }
+
+    #[test]
+    fn forgets_edits_long_past() {
+        let mut buffer = Buffer::new();
+        buffer.push(String::new());
+        let revision = buffer.revision();
+        assert_eq!(buffer.edits_since(revision), Some(Vec::new()));
+        assert_eq!(buffer.edits_since(revision + 1), None);
+
+        for _ in 0..MAX_EDITS {
+            buffer.insert('x', Location { x: 0, y: 0 });
+        }
+        assert_eq!(buffer.edits_since(revision).map(|e| e.len()), Some(1024));
+        buffer.insert('x', Location { x: 0, y: 0 });
+        assert_eq!(buffer.edits_since(revision), None);
+        assert_eq!(
+            buffer.edits_since(revision + 1).map(|e| e.len()),
+            Some(1024)
+        );
+    }
+
+    #[test]
+    fn changes_lines_undoably() {
+        let mut buffer = Buffer::new();
+        let before = ["a", "b", "c", "d"].map(String::from);
+        buffer.set_lines(&before);
+        for after in [vec!["a", "x", "y", "d"], vec!["a", "d"], vec!["x"]] {
+            let after: Vec<String> =
+                after.into_iter().map(String::from).collect();
+            buffer.change_lines(&after);
+            assert_eq!(buffer.lines, after);
+            assert!(buffer.undo());
+            assert_eq!(buffer.lines, before);
+        }
+    }
+
+    #[test]
+    fn keeps_the_line_endings_of_the_file() {
+        let dir = temp_dir("endings");
+        for (name, text) in [("lf", "a\nb\n"), ("crlf", "a\r\nb\r\n")] {
+            let path = dir.join(name).to_string_lossy().to_string();
+            fs::write(&path, text).unwrap();
+            let mut buffer = Buffer::read_file(&path).unwrap();
+            assert_eq!(buffer.lines, ["a", "b"]);
+            buffer.push(String::from("c"));
+            buffer.write_file(&path).unwrap();
+            let ending = buffer.line_ending();
+            let expected = format!("a{ending}b{ending}c{ending}");
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_every_file() {
+        let dir = temp_dir("written");
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        fs::write(&a, "old a").unwrap();
+        let files = [(a.clone(), "new a".into()), (b.clone(), "new b".into())];
+        write_files(&files).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "new a");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "new b");
+        assert_eq!(names(&dir), ["a", "b"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_permissions_and_links() {
+        use std::os::unix::fs::{PermissionsExt, symlink};
+        let dir = temp_dir("kept");
+        let (target, link) = (dir.join("target"), dir.join("link"));
+        fs::write(&target, "old").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o640))
+            .unwrap();
+        symlink(&target, &link).unwrap();
+        write_files(&[(link.clone(), "new".into())]).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_nothing_when_a_file_can_not_be_written() {
+        let dir = temp_dir("not-written");
+        let a = dir.join("a");
+        fs::write(&a, "old a").unwrap();
+        let missing = dir.join("missing").join("b");
+        let files = [(a.clone(), "new a".into()), (missing, "new b".into())];
+        let error = write_files(&files).unwrap_err();
+        assert!(error.to_string().contains("missing"), "{error}");
+        assert_eq!(fs::read_to_string(&a).unwrap(), "old a");
+        assert_eq!(names(&dir), ["a"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn replaces_nothing_when_a_file_can_not_be_replaced() {
+        let dir = temp_dir("not-replaced");
+        let (a, c) = (dir.join("a"), dir.join("c"));
+        fs::write(&a, "old a").unwrap();
+        fs::write(&c, "old c").unwrap();
+        // A directory with something in it, no file can be moved over it
+        let b = dir.join("b");
+        fs::create_dir(&b).unwrap();
+        fs::write(b.join("inside"), "").unwrap();
+        let files = [
+            (a.clone(), "new a".into()),
+            (b, "new b".into()),
+            (c.clone(), "new c".into()),
+        ];
+        assert!(write_files(&files).is_err());
+        assert_eq!(fs::read_to_string(&a).unwrap(), "old a");
+        assert_eq!(fs::read_to_string(&c).unwrap(), "old c");
+        assert_eq!(names(&dir), ["a", "b", "c"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reading_a_file_that_is_not_utf_8_fails() {
+        let path = std::env::temp_dir()
+            .join(format!("r-edit-test-{}-binary.bin", std::process::id()));
+        std::fs::write(&path, [0xff, 0xfe, b'\n']).unwrap();
+        let read = Buffer::read_file(&path.to_string_lossy());
+        std::fs::remove_file(path).unwrap();
+        assert!(read.is_err_and(|e| e.kind() == ErrorKind::InvalidData));
+    }
 }
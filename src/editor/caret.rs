@@ -1,12 +1,15 @@
+use crate::editor::buffer::Buffer;
+use crate::editor::terminal::Location;
 use crate::editor::terminal::Size;
-use crate::editor::terminal::{Location, Terminal};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Caret {
-    // position should probably be responsibility of the terminal
+    /// Where the caret is in the buffer, in characters
     pub location: Location,
+    /// Size of the text area, a page for `page_up` and `page_down`
     pub size: Size,
 }
+#[derive(Debug, Copy, Clone)]
 pub enum Direction {
     Up,
     Down,
@@ -25,7 +28,7 @@ impl Caret {
         }
     }
 
-    pub fn shift(&mut self, direction: Direction) {
+    pub fn shift(&mut self, direction: Direction, buffer: &Buffer) {
         match direction {
             Direction::Up => {
                 log::info!("Shifting up");
@@ -35,7 +38,7 @@ impl Caret {
             }
             Direction::Down => {
                 log::info!("Shifting down");
-                if self.location.y < self.size.height - 1 {
+                if self.location.y + 1 < buffer.lines.len() {
                     self.location.y += 1;
                 }
             }
@@ -47,17 +50,24 @@ impl Caret {
             }
             Direction::Right => {
                 log::info!("Shifting right");
-                if self.location.x < self.size.width - 1 {
-                    self.location.x += 1
+                if self.location.x < buffer.line_len(self.location.y) {
+                    self.location.x += 1;
                 }
             }
         }
-        Terminal::move_caret_to(self.location.into()).unwrap();
+        self.clamp_to(buffer);
     }
 
     pub fn move_to(&mut self, location: Location) {
         self.location = location;
-        Terminal::move_caret_to(self.location.into()).unwrap();
+    }
+
+    /// Keeps the caret on a line of `buffer`, at most right after its
+    /// last character
+    pub fn clamp_to(&mut self, buffer: &Buffer) {
+        let last_line = buffer.lines.len().saturating_sub(1);
+        self.location.y = self.location.y.min(last_line);
+        self.location.x = self.location.x.min(buffer.line_len(self.location.y));
     }
 
     pub fn go_start_of_line(&mut self) {
@@ -67,21 +77,21 @@ impl Caret {
         });
     }
 
-    pub fn go_end_of_line(&mut self) {
+    pub fn go_end_of_line(&mut self, buffer: &Buffer) {
         self.move_to(Location {
-            x: self.size.width - 1,
+            x: buffer.line_len(self.location.y),
             y: self.location.y,
         });
     }
 
-    pub fn page_up(&mut self) {
-        self.move_to(Location { x: 0, y: 0 });
+    pub fn page_up(&mut self, buffer: &Buffer) {
+        self.location.y =
+            self.location.y.saturating_sub(self.size.height.max(1));
+        self.clamp_to(buffer);
     }
 
-    pub fn page_down(&mut self) {
-        self.move_to(Location {
-            x: 0,
-            y: self.size.height - 1,
-        });
+    pub fn page_down(&mut self, buffer: &Buffer) {
+        self.location.y += self.size.height.max(1);
+        self.clamp_to(buffer);
     }
 }
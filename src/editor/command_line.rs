@@ -0,0 +1,196 @@
+use crate::editor::ex::{EX_COMMANDS, SET_OPTIONS};
+use crate::editor::frame::Frame;
+use crate::editor::terminal::Position;
+use crate::editor::view::Overlay;
+use std::fs;
+use std::path::Path;
+
+/// How many command lines are remembered
+pub const MAX_HISTORY: usize = 100;
+
+/// Candidates for the word being completed, cycled through with Tab
+struct Completion {
+    /// The input up to the word being completed
+    prefix: String,
+    candidates: Vec<String>,
+    index: usize,
+}
+
+/// The `:` prompt on the last row, with history and Tab completion
+pub struct CommandLine {
+    input: String,
+    /// Position in the history while browsing it, `None` for new input
+    history_index: Option<usize>,
+    /// What was typed before browsing the history
+    draft: String,
+    completion: Option<Completion>,
+}
+
+/// Lists what `word` could be completed to: ex commands for the first
+/// word, options after `set` and file paths otherwise
+fn complete(before: &str, word: &str) -> Vec<String> {
+    let command = before.trim_start_matches(|c: char| {
+        c.is_ascii_digit() || matches!(c, '%' | ',' | '.' | '$')
+    });
+    let command = command.split_whitespace().next().unwrap_or("");
+    if before.trim().is_empty() || command.is_empty() {
+        return EX_COMMANDS
+            .iter()
+            .filter(|name| name.starts_with(word))
+            .map(|name| (*name).to_string())
+            .collect();
+    }
+    if "set".starts_with(command) && command.len() >= 2 {
+        return SET_OPTIONS
+            .iter()
+            .filter(|name| name.starts_with(word))
+            .map(|name| (*name).to_string())
+            .collect();
+    }
+    complete_path(word)
+}
+
+/// Files and directories starting with `word`, directories ending in `/`
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir, name) = match word.rfind('/') {
+        Some(slash) => (&word[..=slash], &word[slash + 1..]),
+        None => ("", word),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir })
+    else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            // Hidden files only when asked for
+            if !file_name.starts_with(name)
+                || (file_name.starts_with('.') && !name.starts_with('.'))
+            {
+                return None;
+            }
+            let is_dir = Path::new(dir).join(&file_name).is_dir();
+            let slash = if is_dir { "/" } else { "" };
+            Some(format!("{dir}{file_name}{slash}"))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+impl CommandLine {
+    pub fn new(input: String) -> Self {
+        Self {
+            input,
+            history_index: None,
+            draft: String::new(),
+            completion: None,
+        }
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.input.push(c);
+        self.completion = None;
+    }
+
+    /// Deletes the last character, returns false if there was none
+    pub fn backspace(&mut self) -> bool {
+        self.completion = None;
+        self.input.pop().is_some()
+    }
+
+    /// Shows the previous, older, entry of `history`, most recent first
+    pub fn history_previous(&mut self, history: &[String]) {
+        let index = match self.history_index {
+            None if history.is_empty() => return,
+            None => {
+                self.draft = self.input.clone();
+                0
+            }
+            Some(index) => (index + 1).min(history.len() - 1),
+        };
+        self.history_index = Some(index);
+        self.input.clone_from(&history[index]);
+        self.completion = None;
+    }
+
+    /// Shows the next, newer, entry of `history`, and what was typed
+    /// before browsing after the last one
+    pub fn history_next(&mut self, history: &[String]) {
+        match self.history_index {
+            None => return,
+            Some(0) => {
+                self.history_index = None;
+                self.input = std::mem::take(&mut self.draft);
+            }
+            Some(index) => {
+                self.history_index = Some(index - 1);
+                self.input.clone_from(&history[index - 1]);
+            }
+        }
+        self.completion = None;
+    }
+
+    /// Completes the last word of the input, each further call cycles
+    /// to the next candidate. Returns false if nothing matches.
+    pub fn complete(&mut self) -> bool {
+        if let Some(completion) = &mut self.completion {
+            completion.index =
+                (completion.index + 1) % completion.candidates.len();
+            self.input = format!(
+                "{}{}",
+                completion.prefix, completion.candidates[completion.index]
+            );
+            return true;
+        }
+
+        let split = self
+            .input
+            .rfind(char::is_whitespace)
+            .map_or(0, |space| space + 1);
+        // Commands may follow a range without a space, like `%s`
+        let split = if split == 0 {
+            self.input
+                .find(|c: char| c.is_ascii_alphabetic())
+                .unwrap_or(self.input.len())
+        } else {
+            split
+        };
+        let (before, word) = self.input.split_at(split);
+        let candidates = complete(before, word);
+        if candidates.is_empty() {
+            return false;
+        }
+        let prefix = before.to_string();
+        self.input = format!("{prefix}{}", candidates[0]);
+        if candidates.len() > 1 {
+            self.completion = Some(Completion {
+                prefix,
+                candidates,
+                index: 0,
+            });
+        }
+        true
+    }
+}
+
+impl Overlay for CommandLine {
+    /// Draws over the message bar, scrolled to keep the end in view
+    fn draw(&self, frame: &mut Frame) -> Option<Position> {
+        let size = frame.size();
+        let y = size.height.checked_sub(1)?;
+        let text = format!(":{}", self.input);
+        let length = text.chars().count();
+        let skip = (length + 1).saturating_sub(size.width);
+        let visible: String = text.chars().skip(skip).collect();
+        let blank = " ".repeat(size.width);
+        frame.print(Position { x: 0, y }, &blank);
+        let written = frame.print(Position { x: 0, y }, &visible);
+        Some(Position { x: written, y })
+    }
+}
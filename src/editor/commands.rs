@@ -0,0 +1,372 @@
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+/// Every action the editor can take from a key binding
+pub enum Command {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    MoveLineStart,
+    MoveLineEnd,
+    PageUp,
+    PageDown,
+    InsertNewline,
+    InsertTab,
+    DeleteBackward,
+    Save,
+    SaveAndQuit,
+    Quit,
+    RecordMacro,
+    PlayMacro,
+    CommandPalette,
+    CommandLine,
+    ExpandSelection,
+    ShrinkSelection,
+    ParentNode,
+    NextSiblingNode,
+    PreviousSiblingNode,
+    ToggleFold,
+    UnfoldAll,
+    Hover,
+    GoToDefinition,
+    FindReferences,
+    RenameSymbol,
+    FormatDocument,
+    Complete,
+    SplitHorizontal,
+    SplitVertical,
+    ClosePane,
+    FocusPaneLeft,
+    FocusPaneDown,
+    FocusPaneUp,
+    FocusPaneRight,
+    FocusNextPane,
+    GrowPane,
+    ShrinkPane,
+    WidenPane,
+    NarrowPane,
+    NewTab,
+    CloseTab,
+    NextTab,
+    PreviousTab,
+    MoveTabLeft,
+    MoveTabRight,
+    ToggleExplorer,
+    FindFile,
+    SearchInFiles,
+    NextResult,
+    PreviousResult,
+    ReplaceInFiles,
+    UndoReplace,
+}
+
+/// A command together with the name used for it in the configuration and
+/// a short description for users
+pub struct CommandInfo {
+    pub command: Command,
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+/// Every command, in the order they are listed to users
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        command: Command::MoveLeft,
+        name: "move_left",
+        description: "Move the caret left",
+    },
+    CommandInfo {
+        command: Command::MoveRight,
+        name: "move_right",
+        description: "Move the caret right",
+    },
+    CommandInfo {
+        command: Command::MoveUp,
+        name: "move_up",
+        description: "Move the caret up",
+    },
+    CommandInfo {
+        command: Command::MoveDown,
+        name: "move_down",
+        description: "Move the caret down",
+    },
+    CommandInfo {
+        command: Command::MoveLineStart,
+        name: "move_line_start",
+        description: "Move to the start of the line",
+    },
+    CommandInfo {
+        command: Command::MoveLineEnd,
+        name: "move_line_end",
+        description: "Move to the end of the line",
+    },
+    CommandInfo {
+        command: Command::PageUp,
+        name: "page_up",
+        description: "Move up a page",
+    },
+    CommandInfo {
+        command: Command::PageDown,
+        name: "page_down",
+        description: "Move down a page",
+    },
+    CommandInfo {
+        command: Command::InsertNewline,
+        name: "insert_newline",
+        description: "Split the line at the caret",
+    },
+    CommandInfo {
+        command: Command::InsertTab,
+        name: "insert_tab",
+        description: "Insert a tab or spaces up to the next tab stop",
+    },
+    CommandInfo {
+        command: Command::DeleteBackward,
+        name: "delete_backward",
+        description: "Delete the character left of the caret",
+    },
+    CommandInfo {
+        command: Command::Save,
+        name: "save",
+        description: "Save the file",
+    },
+    CommandInfo {
+        command: Command::SaveAndQuit,
+        name: "save_and_quit",
+        description: "Save the file and quit",
+    },
+    CommandInfo {
+        command: Command::Quit,
+        name: "quit",
+        description: "Quit without saving",
+    },
+    CommandInfo {
+        command: Command::RecordMacro,
+        name: "record_macro",
+        description: "Start or stop recording a macro into a register",
+    },
+    CommandInfo {
+        command: Command::PlayMacro,
+        name: "play_macro",
+        description: "Play the macro in a register",
+    },
+    CommandInfo {
+        command: Command::CommandPalette,
+        name: "command_palette",
+        description: "Search and run any command",
+    },
+    CommandInfo {
+        command: Command::CommandLine,
+        name: "command_line",
+        description: "Type an ex command, like :w or :%s/old/new/g",
+    },
+    CommandInfo {
+        command: Command::ExpandSelection,
+        name: "expand_selection",
+        description: "Select the syntax node around the selection",
+    },
+    CommandInfo {
+        command: Command::ShrinkSelection,
+        name: "shrink_selection",
+        description: "Go back to the selection before expanding it",
+    },
+    CommandInfo {
+        command: Command::ParentNode,
+        name: "parent_node",
+        description: "Jump to the start of the enclosing syntax node",
+    },
+    CommandInfo {
+        command: Command::NextSiblingNode,
+        name: "next_sibling_node",
+        description: "Jump to the next syntax node at the same level",
+    },
+    CommandInfo {
+        command: Command::PreviousSiblingNode,
+        name: "previous_sibling_node",
+        description: "Jump to the previous syntax node at the same level",
+    },
+    CommandInfo {
+        command: Command::ToggleFold,
+        name: "toggle_fold",
+        description: "Fold or unfold the syntax node ending on this line",
+    },
+    CommandInfo {
+        command: Command::UnfoldAll,
+        name: "unfold_all",
+        description: "Open every fold",
+    },
+    CommandInfo {
+        command: Command::Hover,
+        name: "hover",
+        description: "Show what the language server knows about the symbol",
+    },
+    CommandInfo {
+        command: Command::GoToDefinition,
+        name: "goto_definition",
+        description: "Jump to where the symbol is defined",
+    },
+    CommandInfo {
+        command: Command::FindReferences,
+        name: "find_references",
+        description: "List every use of the symbol",
+    },
+    CommandInfo {
+        command: Command::RenameSymbol,
+        name: "rename_symbol",
+        description: "Rename the symbol everywhere it is used",
+    },
+    CommandInfo {
+        command: Command::FormatDocument,
+        name: "format_document",
+        description: "Format the buffer with the language server",
+    },
+    CommandInfo {
+        command: Command::Complete,
+        name: "complete",
+        description: "Complete the word at the caret",
+    },
+    CommandInfo {
+        command: Command::SplitHorizontal,
+        name: "split_horizontal",
+        description: "Split the pane into one above the other",
+    },
+    CommandInfo {
+        command: Command::SplitVertical,
+        name: "split_vertical",
+        description: "Split the pane into two side by side",
+    },
+    CommandInfo {
+        command: Command::ClosePane,
+        name: "close_pane",
+        description: "Close the pane",
+    },
+    CommandInfo {
+        command: Command::FocusPaneLeft,
+        name: "focus_pane_left",
+        description: "Move to the pane on the left",
+    },
+    CommandInfo {
+        command: Command::FocusPaneDown,
+        name: "focus_pane_down",
+        description: "Move to the pane below",
+    },
+    CommandInfo {
+        command: Command::FocusPaneUp,
+        name: "focus_pane_up",
+        description: "Move to the pane above",
+    },
+    CommandInfo {
+        command: Command::FocusPaneRight,
+        name: "focus_pane_right",
+        description: "Move to the pane on the right",
+    },
+    CommandInfo {
+        command: Command::FocusNextPane,
+        name: "focus_next_pane",
+        description: "Move to the next pane",
+    },
+    CommandInfo {
+        command: Command::GrowPane,
+        name: "grow_pane",
+        description: "Make the pane taller",
+    },
+    CommandInfo {
+        command: Command::ShrinkPane,
+        name: "shrink_pane",
+        description: "Make the pane shorter",
+    },
+    CommandInfo {
+        command: Command::WidenPane,
+        name: "widen_pane",
+        description: "Make the pane wider",
+    },
+    CommandInfo {
+        command: Command::NarrowPane,
+        name: "narrow_pane",
+        description: "Make the pane narrower",
+    },
+    CommandInfo {
+        command: Command::NewTab,
+        name: "new_tab",
+        description: "Open a tab showing the current buffer",
+    },
+    CommandInfo {
+        command: Command::CloseTab,
+        name: "close_tab",
+        description: "Close the tab and its panes",
+    },
+    CommandInfo {
+        command: Command::NextTab,
+        name: "next_tab",
+        description: "Go to the next tab",
+    },
+    CommandInfo {
+        command: Command::PreviousTab,
+        name: "previous_tab",
+        description: "Go to the previous tab",
+    },
+    CommandInfo {
+        command: Command::MoveTabLeft,
+        name: "move_tab_left",
+        description: "Move the tab one place to the left",
+    },
+    CommandInfo {
+        command: Command::MoveTabRight,
+        name: "move_tab_right",
+        description: "Move the tab one place to the right",
+    },
+    CommandInfo {
+        command: Command::ToggleExplorer,
+        name: "toggle_explorer",
+        description: "Open the file explorer, or close it from inside",
+    },
+    CommandInfo {
+        command: Command::FindFile,
+        name: "find_file",
+        description: "Find a file of the project by its path and open it",
+    },
+    CommandInfo {
+        command: Command::SearchInFiles,
+        name: "search_in_files",
+        description: "Search the files of the project on the command line",
+    },
+    CommandInfo {
+        command: Command::NextResult,
+        name: "next_result",
+        description: "Jump to the next match of the search in files",
+    },
+    CommandInfo {
+        command: Command::PreviousResult,
+        name: "previous_result",
+        description: "Jump to the previous match of the search in files",
+    },
+    CommandInfo {
+        command: Command::ReplaceInFiles,
+        name: "replace_in_files",
+        description: "Replace the matches of the search in files",
+    },
+    CommandInfo {
+        command: Command::UndoReplace,
+        name: "undo_replace",
+        description: "Take back the last replace in files in this buffer",
+    },
+];
+
+impl Command {
+    pub fn info(self) -> &'static CommandInfo {
+        COMMANDS
+            .iter()
+            .find(|info| info.command == self)
+            .expect("every command is listed in COMMANDS")
+    }
+
+    pub fn name(self) -> &'static str {
+        self.info().name
+    }
+
+    pub fn from_name(name: &str) -> Option<Command> {
+        COMMANDS
+            .iter()
+            .find(|info| info.name == name)
+            .map(|info| info.command)
+    }
+}
@@ -0,0 +1,157 @@
+use crate::editor::fuzzy::fuzzy_match;
+use crate::editor::terminal::Location;
+use std::collections::BTreeSet;
+
+/// Shortest word worth suggesting
+const MIN_WORD_LENGTH: usize = 3;
+
+/// Whether `c` can be part of a completed word
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Something to complete the word at the caret with
+pub struct CompletionItem {
+    pub label: String,
+    /// Shown in front of the label, what kind of thing it is
+    pub icon: char,
+    /// Text typed in place of the word
+    pub insert_text: String,
+    /// Matched against what is typed, the label if not given
+    pub filter_text: Option<String>,
+    /// Shown next to the menu for the selected item
+    pub documentation: Option<String>,
+}
+
+impl CompletionItem {
+    /// A word from a buffer
+    pub fn word(word: &str) -> Self {
+        Self {
+            label: word.to_string(),
+            icon: 'w',
+            insert_text: word.to_string(),
+            filter_text: None,
+            documentation: None,
+        }
+    }
+
+    fn filter_text(&self) -> &str {
+        self.filter_text.as_deref().unwrap_or(&self.label)
+    }
+}
+
+/// The words in `lines`, sorted and without duplicates, leaving out
+/// `typed`, the word being completed
+pub fn buffer_words<'a>(
+    lines: impl IntoIterator<Item = &'a str>,
+    typed: &str,
+) -> Vec<CompletionItem> {
+    let mut words = BTreeSet::new();
+    for line in lines {
+        for word in line.split(|c: char| !is_word_char(c)) {
+            if word.chars().count() >= MIN_WORD_LENGTH
+                && word != typed
+                && !word.starts_with(|c: char| c.is_ascii_digit())
+            {
+                words.insert(word);
+            }
+        }
+    }
+    words.into_iter().map(CompletionItem::word).collect()
+}
+
+/// Menu of completions for the word the caret is in, filtered by what is
+/// typed. `View` draws it below the caret. Keys stay with the buffer, the
+/// editor passes the ones the menu wants.
+pub struct Completion {
+    /// Where the word being completed starts
+    start: Location,
+    /// The word typed so far
+    query: String,
+    candidates: Vec<CompletionItem>,
+    /// Indices into `candidates` of the items matching the query
+    items: Vec<usize>,
+    selected: usize,
+}
+
+impl Completion {
+    pub fn new(
+        start: Location,
+        query: &str,
+        candidates: Vec<CompletionItem>,
+    ) -> Self {
+        let mut completion = Self {
+            start,
+            query: String::new(),
+            candidates,
+            items: Vec::new(),
+            selected: 0,
+        };
+        completion.set_query(query);
+        completion
+    }
+
+    pub fn start(&self) -> Location {
+        self.start
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Filters the items by `query`, the word typed so far
+    pub fn set_query(&mut self, query: &str) {
+        self.query = query.to_string();
+        let mut scored: Vec<(i64, usize)> = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                let found = fuzzy_match(&self.query, item.filter_text())?;
+                Some((found.score, index))
+            })
+            .collect();
+        // Stable, so ties keep the order the items came in
+        scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+        self.items = scored.into_iter().map(|(_, index)| index).collect();
+        self.selected = 0;
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.items.is_empty() {
+            self.selected =
+                (self.selected + self.items.len() - 1) % self.items.len();
+        }
+    }
+
+    pub fn selected(&self) -> Option<&CompletionItem> {
+        self.items
+            .get(self.selected)
+            .map(|&index| &self.candidates[index])
+    }
+
+    /// The index of the selected item among the matching ones
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    /// The items matching the query, best first
+    pub fn items(&self) -> impl Iterator<Item = &CompletionItem> {
+        self.items.iter().map(|&index| &self.candidates[index])
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+}
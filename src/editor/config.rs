@@ -0,0 +1,408 @@
+use crate::editor::commands::Command;
+use crate::editor::highlight::Language;
+use crate::editor::keymap::UNBOUND;
+use crate::editor::keys::parse_keys;
+use crate::editor::lsp::ServerConfig;
+use crate::editor::paths;
+use crate::editor::theme::ThemeDefinition;
+use log::LevelFilter;
+use serde::{Deserialize, Deserializer, de};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Name of the per-project configuration, looked up from the directory of
+/// the opened file upwards
+pub const PROJECT_CONFIG: &str = ".r-edit.toml";
+pub const MAX_TAB_WIDTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogConfig {
+    pub level: LevelFilter,
+    pub file: String,
+    pub truncate: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Every setting of the editor, defaults overlaid with the user and
+/// project configuration files
+pub struct Config {
+    pub tab_width: usize,
+    /// Insert `\t` for Tab instead of spaces
+    pub hard_tabs: bool,
+    pub line_numbers: bool,
+    /// Vim-style modal editing instead of the modeless default
+    pub modal: bool,
+    pub theme: String,
+    /// Seconds without input before buffers with unsaved changes are
+    /// saved, 0 turns autosave off
+    pub autosave: u64,
+    /// Themes defined in the configuration, by name
+    pub themes: BTreeMap<String, ThemeDefinition>,
+    /// Key sequences in key notation, e.g. `<C-k><C-c>`, to command names
+    /// or `none` to remove a default binding
+    pub keymap: BTreeMap<String, String>,
+    /// Language servers by the name of the language they serve
+    pub lsp: BTreeMap<String, ServerConfig>,
+    pub log: LogConfig,
+}
+
+impl Config {
+    pub fn default() -> Self {
+        Self {
+            tab_width: 4,
+            hard_tabs: false,
+            line_numbers: false,
+            modal: false,
+            theme: String::from("default"),
+            autosave: 0,
+            themes: BTreeMap::new(),
+            keymap: BTreeMap::new(),
+            lsp: BTreeMap::new(),
+            log: LogConfig {
+                level: LevelFilter::Debug,
+                file: String::from("r-edit.log"),
+                truncate: true,
+            },
+        }
+    }
+
+    /// Loads the defaults overlaid with each of `paths` in order. Missing
+    /// files are skipped, broken ones too but their errors are returned.
+    pub fn load(paths: &[PathBuf]) -> (Self, Vec<ConfigError>) {
+        let mut config = Self::default();
+        let mut errors = Vec::new();
+        for path in paths {
+            match ConfigFile::read(path) {
+                Ok(Some(mut file)) => {
+                    if path.file_name() == Some(PROJECT_CONFIG.as_ref()) {
+                        errors.extend(file.drop_untrusted().map(|key| {
+                            ConfigError {
+                                path: path.clone(),
+                                line: None,
+                                message: format!(
+                                    "{key} is only read from the user \
+                                     configuration"
+                                ),
+                            }
+                        }));
+                    }
+                    file.apply_to(&mut config);
+                }
+                Ok(None) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+        (config, errors)
+    }
+
+    /// The files configuring an editor started in `dir`, least specific
+    /// first: the user configuration, then the nearest project one
+    pub fn paths(dir: &Path) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if let Some(dir) = paths::config_dir() {
+            paths.push(dir.join("config.toml"));
+        }
+        if let Some(project) = dir
+            .ancestors()
+            .map(|dir| dir.join(PROJECT_CONFIG))
+            .find(|path| path.is_file())
+        {
+            paths.push(project);
+        }
+        paths
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A configuration file that could not be used
+pub struct ConfigError {
+    pub path: PathBuf,
+    /// 1-based line the error was found on, if known
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => {
+                write!(f, "{}:{line}: {}", self.path.display(), self.message)
+            }
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+/// A single configuration file, everything optional so files can be
+/// layered on top of each other
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default, deserialize_with = "tab_width")]
+    tab_width: Option<usize>,
+    hard_tabs: Option<bool>,
+    line_numbers: Option<bool>,
+    modal: Option<bool>,
+    theme: Option<String>,
+    autosave: Option<u64>,
+    #[serde(default)]
+    themes: BTreeMap<String, ThemeDefinition>,
+    #[serde(default)]
+    keymap: BTreeMap<KeySequence, CommandName>,
+    #[serde(default)]
+    lsp: BTreeMap<LanguageName, ServerConfig>,
+    #[serde(default)]
+    log: LogFile,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LogFile {
+    #[serde(default, deserialize_with = "level")]
+    level: Option<LevelFilter>,
+    file: Option<String>,
+    truncate: Option<bool>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// A keymap entry's key, checked to be valid key notation
+struct KeySequence(String);
+
+impl<'de> Deserialize<'de> for KeySequence {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let keys = String::deserialize(d)?;
+        match parse_keys(&keys) {
+            Ok(parsed) if !parsed.is_empty() => Ok(Self(keys)),
+            Ok(_) => Err(de::Error::custom("empty key sequence")),
+            Err(e) => Err(de::Error::custom(e)),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A keymap entry's value, checked to name a command
+struct CommandName(String);
+
+impl<'de> Deserialize<'de> for CommandName {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(d)?;
+        if name != UNBOUND && Command::from_name(&name).is_none() {
+            return Err(de::Error::custom(format!("unknown command {name:?}")));
+        }
+        Ok(Self(name))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// A language server entry's key, checked to name a language
+struct LanguageName(String);
+
+impl<'de> Deserialize<'de> for LanguageName {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(d)?;
+        if Language::from_name(&name).is_none() {
+            return Err(de::Error::custom(format!(
+                "unknown language {name:?}"
+            )));
+        }
+        Ok(Self(name))
+    }
+}
+
+fn tab_width<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Option<usize>, D::Error> {
+    let width = usize::deserialize(d)?;
+    if width == 0 || width > MAX_TAB_WIDTH {
+        return Err(de::Error::custom(format!(
+            "tab_width must be between 1 and {MAX_TAB_WIDTH}"
+        )));
+    }
+    Ok(Some(width))
+}
+
+fn level<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Option<LevelFilter>, D::Error> {
+    let level = String::deserialize(d)?;
+    LevelFilter::from_str(&level).map(Some).map_err(|_| {
+        de::Error::custom(format!(
+            "unknown log level {level:?}, expected one of off, error, warn, \
+             info, debug, trace"
+        ))
+    })
+}
+
+impl ConfigFile {
+    /// `Ok(None)` if there is no file at `path`
+    fn read(path: &Path) -> Result<Option<Self>, ConfigError> {
+        let error = |line, message| ConfigError {
+            path: path.to_path_buf(),
+            line,
+            message,
+        };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(error(None, e.to_string())),
+        };
+
+        toml::from_str(&contents).map(Some).map_err(|e| {
+            let line = e.span().map(|span| {
+                contents[..span.start.min(contents.len())]
+                    .matches('\n')
+                    .count()
+                    + 1
+            });
+            error(line, e.message().trim().to_string())
+        })
+    }
+
+    /// Leaves out what a project configuration may not set, as it comes
+    /// with whatever was checked out: the language servers, which are
+    /// programs to run, and the log file, which is written over. Returns
+    /// the keys that were set.
+    fn drop_untrusted(&mut self) -> impl Iterator<Item = &'static str> {
+        let lsp = !std::mem::take(&mut self.lsp).is_empty();
+        let log = std::mem::take(&mut self.log);
+        let log =
+            log.level.is_some() || log.file.is_some() || log.truncate.is_some();
+        [("lsp", lsp), ("log", log)]
+            .into_iter()
+            .filter_map(|(key, set)| set.then_some(key))
+    }
+
+    fn apply_to(self, config: &mut Config) {
+        if let Some(tab_width) = self.tab_width {
+            config.tab_width = tab_width;
+        }
+        if let Some(hard_tabs) = self.hard_tabs {
+            config.hard_tabs = hard_tabs;
+        }
+        if let Some(line_numbers) = self.line_numbers {
+            config.line_numbers = line_numbers;
+        }
+        if let Some(modal) = self.modal {
+            config.modal = modal;
+        }
+        if let Some(theme) = self.theme {
+            config.theme = theme;
+        }
+        if let Some(autosave) = self.autosave {
+            config.autosave = autosave;
+        }
+        config.themes.extend(self.themes);
+        for (KeySequence(keys), CommandName(command)) in self.keymap {
+            config.keymap.insert(keys, command);
+        }
+        for (LanguageName(language), server) in self.lsp {
+            config.lsp.insert(language, server);
+        }
+        if let Some(level) = self.log.level {
+            config.log.level = level;
+        }
+        if let Some(file) = self.log.file {
+            config.log.file = file;
+        }
+        if let Some(truncate) = self.log.truncate {
+            config.log.truncate = truncate;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of its own in the temporary directory holding the
+    /// configuration files `files`, by name
+    fn config_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("r-edit-config-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn project_files_override_user_files() {
+        let dir = config_dir(
+            "override",
+            &[
+                ("config.toml", "tab_width = 2\nmodal = true\n"),
+                (PROJECT_CONFIG, "tab_width = 8\n"),
+            ],
+        );
+        let paths = [dir.join("config.toml"), dir.join(PROJECT_CONFIG)];
+        let (config, errors) = Config::load(&paths);
+        assert_eq!(errors, []);
+        assert_eq!(config.tab_width, 8);
+        assert!(config.modal);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn autosave_is_off_unless_a_file_turns_it_on() {
+        assert_eq!(Config::default().autosave, 0);
+        let dir = config_dir(
+            "autosave",
+            &[("config.toml", "autosave = 30\n"), (PROJECT_CONFIG, "")],
+        );
+        let paths = [dir.join("config.toml"), dir.join(PROJECT_CONFIG)];
+        let (config, errors) = Config::load(&paths);
+        assert_eq!(errors, []);
+        assert_eq!(config.autosave, 30);
+
+        fs::write(dir.join(PROJECT_CONFIG), "autosave = 0\n").unwrap();
+        let (config, errors) = Config::load(&paths);
+        assert_eq!(errors, []);
+        assert_eq!(config.autosave, 0);
+
+        fs::write(dir.join(PROJECT_CONFIG), "autosave = -1\n").unwrap();
+        let (config, errors) = Config::load(&paths);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(config.autosave, 30);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn project_files_can_not_run_servers_or_write_logs() {
+        let project = "tab_width = 8\n\
+                       [log]\nfile = \"/tmp/victim\"\n\
+                       [lsp.rust]\ncommand = \"evil\"\n";
+        let dir = config_dir("untrusted", &[(PROJECT_CONFIG, project)]);
+        let (config, errors) = Config::load(&[dir.join(PROJECT_CONFIG)]);
+        assert_eq!(config.tab_width, 8);
+        assert_eq!(config.log, Config::default().log);
+        assert!(config.lsp.is_empty());
+        let messages: Vec<&str> =
+            errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "lsp is only read from the user configuration",
+                "log is only read from the user configuration"
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn user_files_set_servers_and_logs() {
+        let user = "[log]\nfile = \"here.log\"\n\
+                    [lsp.rust]\ncommand = \"rust-analyzer\"\n";
+        let dir = config_dir("trusted", &[("config.toml", user)]);
+        let (config, errors) = Config::load(&[dir.join("config.toml")]);
+        assert_eq!(errors, []);
+        assert_eq!(config.log.file, "here.log");
+        assert_eq!(config.lsp["rust"].command, "rust-analyzer");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
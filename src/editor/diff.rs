@@ -0,0 +1,136 @@
+/// Unchanged lines shown around each change
+const CONTEXT: usize = 3;
+/// Most line pairs compared to find the lines two texts share between
+/// where they start and end alike. Past that it is all taken as changed,
+/// so large rewrites do not take the memory.
+const MAX_COMPARED: usize = 4_000_000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Line {
+    Same,
+    Removed,
+    Added,
+}
+
+/// The lines in `old` and `new` alike, with those only in one of them, in
+/// order. Holds indexes into the texts.
+fn compare(old: &[String], new: &[String]) -> Vec<(Line, usize)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut lines: Vec<(Line, usize)> =
+        (0..prefix).map(|y| (Line::Same, y)).collect();
+    if a.len().saturating_mul(b.len()) > MAX_COMPARED {
+        lines.extend((0..a.len()).map(|y| (Line::Removed, prefix + y)));
+        lines.extend((0..b.len()).map(|y| (Line::Added, prefix + y)));
+    } else {
+        // Longest common subsequence of what is left, from the end
+        let width = b.len() + 1;
+        let mut common = vec![0_u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                common[i * width + j] = if a[i] == b[j] {
+                    common[(i + 1) * width + j + 1] + 1
+                } else {
+                    common[(i + 1) * width + j].max(common[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                lines.push((Line::Same, prefix + i));
+                i += 1;
+                j += 1;
+            } else if j == b.len()
+                || i < a.len()
+                    && common[(i + 1) * width + j] >= common[i * width + j + 1]
+            {
+                lines.push((Line::Removed, prefix + i));
+                i += 1;
+            } else {
+                lines.push((Line::Added, prefix + j));
+                j += 1;
+            }
+        }
+    }
+    lines.extend((old.len() - suffix..old.len()).map(|y| (Line::Same, y)));
+    lines
+}
+
+/// The changes from `old` to `new` as the hunks of a unified diff, each
+/// an `@@` line over its lines marked with ` `, `-` or `+`. Empty when
+/// the texts are the same.
+pub fn unified(old: &[String], new: &[String]) -> Vec<String> {
+    let lines = compare(old, new);
+    // Where each line is in both texts, 0-based, before it
+    let mut positions = Vec::with_capacity(lines.len());
+    let (mut y_old, mut y_new) = (0, 0);
+    for (line, _) in &lines {
+        positions.push((y_old, y_new));
+        match line {
+            Line::Same => {
+                y_old += 1;
+                y_new += 1;
+            }
+            Line::Removed => y_old += 1,
+            Line::Added => y_new += 1,
+        }
+    }
+
+    // Spans of `lines` to show, the changes with the context around them
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (index, (line, _)) in lines.iter().enumerate() {
+        if *line == Line::Same {
+            continue;
+        }
+        let start = index.saturating_sub(CONTEXT);
+        let end = (index + CONTEXT + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut diff = Vec::new();
+    for (start, end) in hunks {
+        let span = &lines[start..end];
+        let old_count = span.iter().filter(|(l, _)| *l != Line::Added).count();
+        let new_count =
+            span.iter().filter(|(l, _)| *l != Line::Removed).count();
+        let (old_start, new_start) = positions[start];
+        // An empty side is numbered by the line before it, as diff does
+        let first = |at: usize, count: usize| at + usize::from(count > 0);
+        diff.push(format!(
+            "@@ -{},{old_count} +{},{new_count} @@",
+            first(old_start, old_count),
+            first(new_start, new_count)
+        ));
+        for (line, y) in span {
+            diff.push(match line {
+                Line::Same => format!(" {}", old[*y]),
+                Line::Removed => format!("-{}", old[*y]),
+                Line::Added => format!("+{}", new[*y]),
+            });
+        }
+    }
+    diff
+}
+
+/// `unified` under the `---` and `+++` lines naming the texts, each given
+/// with its name
+pub fn with_header(
+    (old_name, old): (&str, &[String]),
+    (new_name, new): (&str, &[String]),
+) -> Vec<String> {
+    let mut diff = vec![format!("--- {old_name}"), format!("+++ {new_name}")];
+    diff.extend(unified(old, new));
+    diff
+}
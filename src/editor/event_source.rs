@@ -0,0 +1,91 @@
+use crossterm::event::{Event, poll, read};
+use std::collections::VecDeque;
+use std::io::Error;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What an `EventSource` produced while the editor was waiting
+pub enum Input {
+    Event(Event),
+    /// Nothing happened before the timeout, time to check the timers
+    Timeout,
+    /// The source will never produce anything again
+    Closed,
+}
+
+/// Where the editor gets its events from
+pub trait EventSource {
+    /// Waits for the next event, at most `timeout` or forever if `None`
+    fn next_event(&mut self, timeout: Option<Duration>)
+    -> Result<Input, Error>;
+}
+
+/// Events read from the real terminal through crossterm
+pub struct TerminalEvents;
+
+impl EventSource for TerminalEvents {
+    fn next_event(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<Input, Error> {
+        if let Some(timeout) = timeout
+            && !poll(timeout)?
+        {
+            return Ok(Input::Timeout);
+        }
+        Ok(Input::Event(read()?))
+    }
+}
+
+/// A fixed list of events played back in order, closed once exhausted
+pub struct ScriptedEvents {
+    events: VecDeque<Event>,
+}
+
+impl ScriptedEvents {
+    pub fn new(events: Vec<Event>) -> Self {
+        Self {
+            events: events.into(),
+        }
+    }
+}
+
+impl EventSource for ScriptedEvents {
+    fn next_event(&mut self, _: Option<Duration>) -> Result<Input, Error> {
+        Ok(self.events.pop_front().map_or(Input::Closed, Input::Event))
+    }
+}
+
+/// Events sent from another thread, e.g. a test harness driving the
+/// editor. Closed once every `Sender` is dropped.
+pub struct ChannelEvents {
+    receiver: Receiver<Event>,
+}
+
+impl ChannelEvents {
+    pub fn new() -> (Sender<Event>, Self) {
+        let (sender, receiver) = channel();
+        (sender, Self { receiver })
+    }
+}
+
+impl EventSource for ChannelEvents {
+    fn next_event(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<Input, Error> {
+        let received = match timeout {
+            Some(timeout) => self.receiver.recv_timeout(timeout),
+            None => self
+                .receiver
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        Ok(match received {
+            Ok(event) => Input::Event(event),
+            Err(RecvTimeoutError::Timeout) => Input::Timeout,
+            Err(RecvTimeoutError::Disconnected) => Input::Closed,
+        })
+    }
+}
@@ -0,0 +1,606 @@
+use crate::editor::config::MAX_TAB_WIDTH;
+use crate::editor::grep::GrepQuery;
+use crate::editor::ignore::Glob;
+use crate::editor::layout::Split;
+use regex::{Regex, RegexBuilder};
+
+/// Every ex command by its full name, for completion
+pub const EX_COMMANDS: &[&str] = &[
+    "close",
+    "cnext",
+    "cprevious",
+    "edit",
+    "grep",
+    "quit",
+    "recover",
+    "rename",
+    "replace",
+    "set",
+    "split",
+    "substitute",
+    "tabclose",
+    "tabedit",
+    "tabmove",
+    "tabnew",
+    "tabnext",
+    "tabprevious",
+    "vsplit",
+    "write",
+    "wq",
+    "xit",
+];
+
+/// Options `:set` knows about, for completion
+pub const SET_OPTIONS: &[&str] = &[
+    "tabstop",
+    "expandtab",
+    "noexpandtab",
+    "number",
+    "nonumber",
+    "modal",
+    "nomodal",
+    "theme=",
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Lines an ex command works on, 0-based and inclusive
+pub struct LineRange {
+    pub first: usize,
+    pub last: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// One `:set` assignment, e.g. `ts=4` or `nonumber`
+pub enum SetOption {
+    TabWidth(usize),
+    HardTabs(bool),
+    LineNumbers(bool),
+    Modal(bool),
+    Theme(String),
+}
+
+#[derive(Debug, Clone)]
+/// A parsed ex command line
+pub enum Ex {
+    /// `:w [file]`, a file name saves under that name from now on
+    Write(Option<String>),
+    /// `:q`, refused with unsaved changes unless `force`
+    Quit {
+        force: bool,
+    },
+    /// `:wq` and `:x`
+    WriteQuit,
+    /// `:e file`, refused with unsaved changes unless `force`
+    Edit {
+        path: String,
+        force: bool,
+    },
+    /// `:N`, a 0-based line
+    Goto(usize),
+    /// `:[range]s/pattern/replacement/[flags]`
+    Substitute {
+        range: LineRange,
+        pattern: Regex,
+        replacement: String,
+        global: bool,
+    },
+    Set(Vec<SetOption>),
+    /// `:rename name`, renames the symbol at the caret with the language
+    /// server
+    Rename(String),
+    /// `:sp [file]` and `:vs [file]`, split the pane, showing `file` in
+    /// the new one if given
+    Split {
+        split: Split,
+        path: Option<String>,
+    },
+    /// `:clo`, closes the pane, refused with unsaved changes no other
+    /// pane shows unless `force`
+    Close {
+        force: bool,
+    },
+    /// `:tabnew [file]` and `:tabe [file]`, open a tab showing `file` or
+    /// else the current buffer
+    NewTab(Option<String>),
+    /// `:tabc`, closes the tab, refused with unsaved changes no other tab
+    /// shows unless `force`
+    CloseTab {
+        force: bool,
+    },
+    /// `:tabn [N]`, goes to the next tab or to tab `N`, counting from 1
+    NextTab(Option<usize>),
+    /// `:tabp`
+    PreviousTab,
+    /// `:tabm [N]`, moves the tab after the `N`th other tab, first for
+    /// 0, or last without `N`
+    MoveTab(Option<usize>),
+    /// `:gr[ep] [-i|-s] [--include=GLOB] [--exclude=GLOB] pattern`,
+    /// searches the files of the project
+    Grep(GrepQuery),
+    /// `:cn`, jumps to the next match of the search in files
+    NextResult,
+    /// `:cp`, jumps to the previous match of the search in files
+    PreviousResult,
+    /// `:repl[ace] text`, previews replacing the matches of the search in
+    /// files with `text`, a Vim replacement as `:s` takes it: `&` and `\0`
+    /// are the match, `\1`..`\9` its groups, `\n` and `\t` a line break
+    /// and a tab. Held converted by `replacement` for the regex crate.
+    Replace(String),
+    /// `:rec[over]`, offers to recover the current file from the swap file
+    /// another editor left behind
+    Recover,
+}
+
+/// What a command line needs to know about the buffer to resolve line
+/// addresses
+#[derive(Debug, Copy, Clone)]
+pub struct Context {
+    /// 0-based line of the caret
+    pub current_line: usize,
+    pub line_count: usize,
+}
+
+/// Parses a single line address, `.`, `$` or a number, at the start of
+/// `input`. Returns the 0-based line and the rest of the input.
+fn address(
+    input: &str,
+    context: Context,
+) -> Result<Option<(usize, &str)>, String> {
+    let last = context.line_count.saturating_sub(1);
+    if let Some(rest) = input.strip_prefix('.') {
+        return Ok(Some((context.current_line, rest)));
+    }
+    if let Some(rest) = input.strip_prefix('$') {
+        return Ok(Some((last, rest)));
+    }
+    let digits = input.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 {
+        return Ok(None);
+    }
+    let line: usize = input[..digits]
+        .parse()
+        .map_err(|_| format!("Invalid line number {}", &input[..digits]))?;
+    Ok(Some((line.saturating_sub(1).min(last), &input[digits..])))
+}
+
+/// Parses the range in front of a command: `%`, `N`, `N,M` or nothing
+fn range(
+    input: &str,
+    context: Context,
+) -> Result<(Option<LineRange>, &str), String> {
+    if let Some(rest) = input.strip_prefix('%') {
+        let range = LineRange {
+            first: 0,
+            last: context.line_count.saturating_sub(1),
+        };
+        return Ok((Some(range), rest));
+    }
+    let Some((first, rest)) = address(input, context)? else {
+        return Ok((None, input));
+    };
+    let Some(rest) = rest.strip_prefix(',') else {
+        return Ok((Some(LineRange { first, last: first }), rest));
+    };
+    let Some((last, rest)) = address(rest, context)? else {
+        return Err(String::from("Missing the end of the range"));
+    };
+    if last < first {
+        return Err(String::from("Backwards range"));
+    }
+    Ok((Some(LineRange { first, last }), rest))
+}
+
+/// Splits `input` at unescaped occurrences of `separator`, at most
+/// `count` parts. A `\` before the separator keeps it literal.
+fn split_escaped(input: &str, separator: char, count: usize) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if parts.len() < count {
+            if c == '\\' {
+                // Escapes stay for the pattern, except for the separator
+                let Some(escaped) = chars.next() else {
+                    break;
+                };
+                if let Some(part) = parts.last_mut() {
+                    if escaped != separator {
+                        part.push(c);
+                    }
+                    part.push(escaped);
+                }
+                continue;
+            }
+            if c == separator {
+                parts.push(String::new());
+                continue;
+            }
+        }
+        if let Some(part) = parts.last_mut() {
+            part.push(c);
+        }
+    }
+    parts
+}
+
+/// Turns a Vim replacement into one for `regex`: `&` and `\0` are the
+/// whole match, `\1`..`\9` groups, `\&` a literal `&`
+fn replacement(vim: &str) -> String {
+    let mut replaced = String::new();
+    let mut chars = vim.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => replaced.push_str("${0}"),
+            '$' => replaced.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => {
+                    replaced.push_str("${");
+                    replaced.push(digit);
+                    replaced.push('}');
+                }
+                Some('n') => replaced.push('\n'),
+                Some('t') => replaced.push('\t'),
+                Some('$') => replaced.push_str("$$"),
+                Some(other) => replaced.push(other),
+                None => replaced.push('\\'),
+            },
+            c => replaced.push(c),
+        }
+    }
+    replaced
+}
+
+fn substitute(range: LineRange, input: &str) -> Result<Ex, String> {
+    let mut chars = input.chars();
+    let separator = match chars.next() {
+        Some(c) if !c.is_alphanumeric() && !c.is_whitespace() && c != '\\' => c,
+        _ => return Err(String::from("Usage: s/pattern/replacement/[gi]")),
+    };
+    let parts = split_escaped(chars.as_str(), separator, 3);
+    let pattern = parts.first().map_or("", String::as_str);
+    if pattern.is_empty() {
+        return Err(String::from("Empty pattern"));
+    }
+    let vim_replacement = parts.get(1).map_or("", String::as_str);
+    let flags = parts.get(2).map_or("", String::as_str);
+
+    let mut global = false;
+    let mut ignore_case = false;
+    for flag in flags.chars() {
+        match flag {
+            'g' => global = true,
+            'i' => ignore_case = true,
+            'I' => ignore_case = false,
+            _ => return Err(format!("Unknown flag {flag}")),
+        }
+    }
+    let pattern = RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {e}"))?;
+
+    Ok(Ex::Substitute {
+        range,
+        pattern,
+        replacement: replacement(vim_replacement),
+        global,
+    })
+}
+
+fn set(arguments: &str) -> Result<Ex, String> {
+    let mut options = Vec::new();
+    for argument in arguments.split_whitespace() {
+        let (name, value) = match argument.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (argument, None),
+        };
+        let option = match (name, value) {
+            ("ts" | "tabstop", Some(value)) => {
+                let width: usize = value
+                    .parse()
+                    .map_err(|_| format!("Invalid number: {value}"))?;
+                if width == 0 || width > MAX_TAB_WIDTH {
+                    return Err(format!(
+                        "tabstop must be between 1 and {MAX_TAB_WIDTH}"
+                    ));
+                }
+                SetOption::TabWidth(width)
+            }
+            ("et" | "expandtab", None) => SetOption::HardTabs(false),
+            ("noet" | "noexpandtab", None) => SetOption::HardTabs(true),
+            ("nu" | "number", None) => SetOption::LineNumbers(true),
+            ("nonu" | "nonumber", None) => SetOption::LineNumbers(false),
+            ("modal", None) => SetOption::Modal(true),
+            ("nomodal", None) => SetOption::Modal(false),
+            ("theme", Some(name)) if !name.is_empty() => {
+                SetOption::Theme(name.to_string())
+            }
+            _ => return Err(format!("Unknown option: {argument}")),
+        };
+        options.push(option);
+    }
+    if options.is_empty() {
+        return Err(String::from("Usage: set option[=value] ..."));
+    }
+    Ok(Ex::Set(options))
+}
+
+/// Parses the optional tab number after `:tabn` and `:tabm`
+fn tab_number(argument: &str) -> Result<Option<usize>, String> {
+    if argument.is_empty() {
+        return Ok(None);
+    }
+    argument
+        .parse()
+        .map(Some)
+        .map_err(|_| format!("Invalid tab number {argument}"))
+}
+
+/// Parses the options and pattern of `:grep`. `-i` ignores case, `-s`
+/// only does when the pattern is all lowercase. `--include=GLOB` and
+/// `--exclude=GLOB` can be given more than once; `--` ends the options.
+fn grep(arguments: &str) -> Result<Ex, String> {
+    const USAGE: &str =
+        "Usage: gr[ep] [-i|-s] [--include=GLOB] [--exclude=GLOB] pattern";
+    let mut rest = arguments.trim_start();
+    let mut ignore_case = false;
+    let mut smart_case = false;
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    while rest.starts_with('-') {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (option, after) = rest.split_at(end);
+        rest = after.trim_start();
+        let glob = |pattern: &str| {
+            Glob::new(pattern).ok_or_else(|| format!("Invalid glob: {pattern}"))
+        };
+        match option {
+            "--" => break,
+            "-i" => ignore_case = true,
+            "-s" => smart_case = true,
+            _ => {
+                if let Some(pattern) = option.strip_prefix("--include=") {
+                    include.push(glob(pattern)?);
+                } else if let Some(pattern) = option.strip_prefix("--exclude=")
+                {
+                    exclude.push(glob(pattern)?);
+                } else {
+                    return Err(format!("Unknown option {option}"));
+                }
+            }
+        }
+    }
+    if rest.is_empty() {
+        return Err(String::from(USAGE));
+    }
+    let ignore_case =
+        ignore_case || (smart_case && !rest.chars().any(char::is_uppercase));
+    let regex = RegexBuilder::new(rest)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {e}"))?;
+    Ok(Ex::Grep(GrepQuery {
+        regex,
+        include,
+        exclude,
+    }))
+}
+
+/// Whether `name` is an abbreviation of `command` at least `shortest`
+/// characters long, like `e` and `ed` for `edit`
+fn abbreviates(name: &str, command: &str, shortest: usize) -> bool {
+    name.len() >= shortest && command.starts_with(name)
+}
+
+/// Parses a command line, without the leading `:`. The line must not be
+/// blank.
+pub fn parse(input: &str, context: Context) -> Result<Ex, String> {
+    let input = input.trim_start();
+    let (range, rest) = range(input, context)?;
+    let rest = rest.trim_start();
+
+    let name_length = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    let (name, arguments) = rest.split_at(name_length);
+    let (force, arguments) = match arguments.strip_prefix('!') {
+        Some(arguments) => (true, arguments),
+        None => (false, arguments),
+    };
+    let argument = arguments.trim();
+    let path = (!argument.is_empty()).then(|| argument.to_string());
+
+    if name.is_empty() && !force {
+        return match range {
+            Some(range) if argument.is_empty() => Ok(Ex::Goto(range.last)),
+            _ => Err(format!("Not an editor command: {input}")),
+        };
+    }
+    if abbreviates(name, "substitute", 1) {
+        let current = LineRange {
+            first: context.current_line,
+            last: context.current_line,
+        };
+        return substitute(range.unwrap_or(current), arguments);
+    }
+    if range.is_some() {
+        return Err(format!("No range allowed for {name}"));
+    }
+
+    match name {
+        _ if abbreviates(name, "write", 1) => Ok(Ex::Write(path)),
+        _ if abbreviates(name, "quit", 1) && path.is_none() => {
+            Ok(Ex::Quit { force })
+        }
+        "wq" | "x" | "xit" if path.is_none() => Ok(Ex::WriteQuit),
+        _ if abbreviates(name, "edit", 1) => match path {
+            Some(path) => Ok(Ex::Edit { path, force }),
+            None => Err(String::from("Usage: e[dit][!] file")),
+        },
+        _ if abbreviates(name, "set", 2) && !force => set(argument),
+        _ if abbreviates(name, "split", 2) && !force => Ok(Ex::Split {
+            split: Split::Horizontal,
+            path,
+        }),
+        _ if abbreviates(name, "vsplit", 2) && !force => Ok(Ex::Split {
+            split: Split::Vertical,
+            path,
+        }),
+        _ if abbreviates(name, "close", 3) && path.is_none() => {
+            Ok(Ex::Close { force })
+        }
+        "tabnew" if !force => Ok(Ex::NewTab(path)),
+        _ if abbreviates(name, "tabedit", 4) && !force => Ok(Ex::NewTab(path)),
+        _ if abbreviates(name, "tabclose", 4) && path.is_none() => {
+            Ok(Ex::CloseTab { force })
+        }
+        _ if abbreviates(name, "tabnext", 4) && !force => {
+            tab_number(argument).map(Ex::NextTab)
+        }
+        _ if abbreviates(name, "tabprevious", 4) && argument.is_empty() => {
+            Ok(Ex::PreviousTab)
+        }
+        _ if abbreviates(name, "tabmove", 4) && !force => {
+            tab_number(argument).map(Ex::MoveTab)
+        }
+        _ if abbreviates(name, "grep", 2) && !force => grep(arguments),
+        _ if abbreviates(name, "cnext", 2) && path.is_none() => {
+            Ok(Ex::NextResult)
+        }
+        _ if abbreviates(name, "cprevious", 2) && path.is_none() => {
+            Ok(Ex::PreviousResult)
+        }
+        _ if abbreviates(name, "replace", 4) && !force => {
+            // Spaces after the first one are part of the replacement
+            let text = arguments.strip_prefix(' ').unwrap_or(arguments);
+            Ok(Ex::Replace(replacement(text)))
+        }
+        _ if abbreviates(name, "recover", 3) && path.is_none() && !force => {
+            Ok(Ex::Recover)
+        }
+        _ if abbreviates(name, "rename", 3) && !force => match path {
+            Some(name) if !name.contains(char::is_whitespace) => {
+                Ok(Ex::Rename(name))
+            }
+            _ => Err(String::from("Usage: ren[ame] name")),
+        },
+        _ => Err(format!("Not an editor command: {input}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A buffer of 10 lines with the caret on the 3rd
+    const CONTEXT: Context = Context {
+        current_line: 2,
+        line_count: 10,
+    };
+
+    /// The parts of the `:s` command `input`
+    fn substitute(input: &str) -> (LineRange, String, String, bool) {
+        match parse(input, CONTEXT) {
+            Ok(Ex::Substitute {
+                range,
+                pattern,
+                replacement,
+                global,
+            }) => (range, pattern.to_string(), replacement, global),
+            other => panic!("Unexpected {other:?}"),
+        }
+    }
+
+    fn lines(first: usize, last: usize) -> LineRange {
+        LineRange { first, last }
+    }
+
+    #[test]
+    fn substitute_takes_ranges() {
+        assert_eq!(substitute("s/a/b/").0, lines(2, 2));
+        assert_eq!(substitute("%s/a/b/").0, lines(0, 9));
+        assert_eq!(substitute("4s/a/b/").0, lines(3, 3));
+        assert_eq!(substitute("2,5s/a/b/").0, lines(1, 4));
+        assert_eq!(substitute(".,$s/a/b/").0, lines(2, 9));
+        assert_eq!(substitute("5,99s/a/b/").0, lines(4, 9));
+        assert_eq!(parse("5,2s/a/b/", CONTEXT).unwrap_err(), "Backwards range");
+        assert_eq!(
+            parse("5,s/a/b/", CONTEXT).unwrap_err(),
+            "Missing the end of the range"
+        );
+    }
+
+    #[test]
+    fn substitute_takes_flags_and_separators() {
+        assert!(!substitute("s/a/b").3);
+        assert!(substitute("s/a/b/g").3);
+        assert_eq!(substitute("s/a/b/gi").1, "a");
+        let Ok(Ex::Substitute { pattern, .. }) = parse("s/a/b/i", CONTEXT)
+        else {
+            panic!("Not a substitution");
+        };
+        assert!(pattern.is_match("A"));
+        assert!(parse("s/a/b/x", CONTEXT).unwrap_err().contains("flag x"));
+        assert_eq!(substitute("s#a/b#c#").1, "a/b");
+        assert_eq!(substitute(r"s/a\/b/c/").1, "a/b");
+        assert_eq!(substitute(r"s/a\.b/c/").1, r"a\.b");
+        assert_eq!(substitute("s/a//").2, "");
+        assert_eq!(parse("s//b/", CONTEXT).unwrap_err(), "Empty pattern");
+        assert!(parse("s a", CONTEXT).unwrap_err().starts_with("Usage"));
+        assert!(parse("s/(/b/", CONTEXT).unwrap_err().starts_with("Invalid"));
+    }
+
+    #[test]
+    fn substitute_converts_vim_replacements() {
+        let cases = [
+            ("plain", "plain"),
+            ("<&>", "<${0}>"),
+            (r"\0", "${0}"),
+            (r"\2-\1", "${2}-${1}"),
+            (r"\&", "&"),
+            ("$1", "$$1"),
+            (r"\$", "$$"),
+            (r"a\nb\tc", "a\nb\tc"),
+            (r"\/", "/"),
+            ("end\\", "end\\"),
+        ];
+        for (vim, regex) in cases {
+            assert_eq!(replacement(vim), regex, "{vim}");
+        }
+        assert_eq!(substitute(r"s/(a)(b)/\2&/").2, "${2}${0}");
+    }
+
+    #[test]
+    fn replace_takes_vim_replacements_too() {
+        let Ok(Ex::Replace(text)) = parse("replace  \\1 & $", CONTEXT) else {
+            panic!("Not a replace");
+        };
+        assert_eq!(text, " ${1} ${0} $$");
+    }
+
+    #[test]
+    fn parses_tab_commands() {
+        let tab = |input| parse(input, CONTEXT);
+        assert!(matches!(tab("tabnew"), Ok(Ex::NewTab(None))));
+        assert!(matches!(
+            tab("tabe notes.txt"),
+            Ok(Ex::NewTab(Some(path))) if path == "notes.txt"
+        ));
+        assert!(matches!(tab("tabc"), Ok(Ex::CloseTab { force: false })));
+        assert!(matches!(tab("tabclose!"), Ok(Ex::CloseTab { force: true })));
+        assert!(matches!(tab("tabn"), Ok(Ex::NextTab(None))));
+        assert!(matches!(tab("tabnext 3"), Ok(Ex::NextTab(Some(3)))));
+        assert!(matches!(tab("tabp"), Ok(Ex::PreviousTab)));
+        assert!(matches!(tab("tabm"), Ok(Ex::MoveTab(None))));
+        assert!(matches!(tab("tabm 0"), Ok(Ex::MoveTab(Some(0)))));
+    }
+
+    #[test]
+    fn refuses_malformed_tab_commands() {
+        let error = |input| parse(input, CONTEXT).unwrap_err();
+        assert_eq!(error("tabn two"), "Invalid tab number two");
+        assert_eq!(error("tabm -1"), "Invalid tab number -1");
+        assert_eq!(error("tab"), "Not an editor command: tab");
+        assert_eq!(error("tabp 2"), "Not an editor command: tabp 2");
+        assert_eq!(error("tabnew!"), "Not an editor command: tabnew!");
+    }
+}
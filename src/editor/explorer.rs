@@ -0,0 +1,441 @@
+use crate::editor::frame::Frame;
+use crate::editor::ignore::Ignore;
+use crate::editor::terminal::{Position, Size};
+use crate::editor::theme::{Element, Theme};
+use crate::editor::view::Overlay;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Columns of the explorer, without the border next to it, at most half
+/// the screen
+pub const EXPLORER_WIDTH: usize = 30;
+/// Columns each level of the tree is indented by
+const INDENT: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A row of the tree, a file or a directory below its parent
+pub struct Entry {
+    pub path: PathBuf,
+    /// How many directories down from the root
+    pub depth: usize,
+    pub is_dir: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A change to the files the explorer asks about first
+pub enum Action {
+    /// Creates a file in the directory, or a directory if the name typed
+    /// ends with `/`
+    Create(PathBuf),
+    /// Gives the file a new name in the same directory
+    Rename(PathBuf),
+    /// Moves the file to a path typed relative to the root
+    Move(PathBuf),
+    Delete(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What an `Action` did, for the editor to follow with its buffers
+pub enum Change {
+    Created(PathBuf),
+    Moved { from: PathBuf, to: PathBuf },
+    Deleted(PathBuf),
+}
+
+/// The explorer asking for a name, or a yes to delete, on the message bar
+pub struct Prompt {
+    pub action: Action,
+    label: String,
+    pub input: String,
+}
+
+impl Overlay for Prompt {
+    /// Draws over the message bar, scrolled to keep the end in view
+    fn draw(&self, frame: &mut Frame) -> Option<Position> {
+        let size = frame.size();
+        let y = size.height.checked_sub(1)?;
+        let text = format!("{}{}", self.label, self.input);
+        let length = text.chars().count();
+        let skip = (length + 1).saturating_sub(size.width);
+        let visible: String = text.chars().skip(skip).collect();
+        frame.print(Position { x: 0, y }, &" ".repeat(size.width));
+        let written = frame.print(Position { x: 0, y }, &visible);
+        Some(Position { x: written, y })
+    }
+}
+
+/// A side panel listing the files below a directory as a tree, leaving
+/// out what `.gitignore` files do
+pub struct Explorer {
+    root: PathBuf,
+    /// Directories showing what is in them
+    expanded: HashSet<PathBuf>,
+    /// The rows shown, directories first and then files, by name
+    entries: Vec<Entry>,
+    selected: usize,
+    /// First row shown
+    scroll: usize,
+    ignore: Ignore,
+    /// The file last revealed for the focused pane
+    followed: Option<PathBuf>,
+    prompt: Option<Prompt>,
+}
+
+impl Explorer {
+    /// An explorer of the directory at `root`, an absolute path
+    pub fn new(root: PathBuf) -> Self {
+        let mut explorer = Self {
+            ignore: Ignore::new(&root),
+            expanded: HashSet::from([root.clone()]),
+            root,
+            entries: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            followed: None,
+            prompt: None,
+        };
+        explorer.refresh();
+        explorer
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn selected(&self) -> Option<&Entry> {
+        self.entries.get(self.selected)
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Selects the row of `path`, if it is shown
+    fn select_path(&mut self, path: &Path) {
+        if let Some(index) = self.entries.iter().position(|e| e.path == path) {
+            self.selected = index;
+        }
+    }
+
+    /// Lists the tree again from the disk, keeping the selected file
+    /// selected if it is still there
+    pub fn refresh(&mut self) {
+        let selected = self.selected().map(|entry| entry.path.clone());
+        self.expanded.retain(|dir| dir.is_dir());
+        let mut entries = Vec::new();
+        let root = self.root.clone();
+        self.list(&root, 0, &mut entries);
+        self.entries = entries;
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+        if let Some(path) = selected {
+            self.select_path(&path);
+        }
+    }
+
+    /// Adds the rows of what is in `dir`, and below it for the directories
+    /// that are expanded
+    fn list(&mut self, dir: &Path, depth: usize, entries: &mut Vec<Entry>) {
+        self.ignore.enter(dir);
+        let Ok(read) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut children: Vec<Entry> = read
+            .filter_map(Result::ok)
+            .map(|child| Entry {
+                is_dir: child.path().is_dir(),
+                path: child.path(),
+                depth,
+            })
+            .filter(|child| !self.ignore.is_ignored(&child.path, child.is_dir))
+            .collect();
+        children.sort_by(|a, b| {
+            b.is_dir.cmp(&a.is_dir).then_with(|| a.path.cmp(&b.path))
+        });
+        for child in children {
+            let expanded = child.is_dir && self.expanded.contains(&child.path);
+            let path = child.path.clone();
+            entries.push(child);
+            if expanded {
+                self.list(&path, depth + 1, entries);
+            }
+        }
+    }
+
+    /// Makes the directory the root is in the root
+    pub fn up(&mut self) {
+        let Some(parent) = self.root.parent() else {
+            return;
+        };
+        self.root = parent.to_path_buf();
+        self.ignore = Ignore::new(&self.root);
+        self.expanded.insert(self.root.clone());
+        self.followed = None;
+        self.refresh();
+    }
+
+    /// Shows what is in the selected directory
+    pub fn expand(&mut self) {
+        if let Some(entry) = self.selected()
+            && entry.is_dir
+            && !self.expanded.contains(&entry.path)
+        {
+            self.expanded.insert(entry.path.clone());
+            self.refresh();
+        }
+    }
+
+    /// Shows what is in the selected directory, or hides it if shown
+    pub fn toggle(&mut self) {
+        let Some(entry) = self.selected() else {
+            return;
+        };
+        if !entry.is_dir {
+            return;
+        }
+        let path = entry.path.clone();
+        if !self.expanded.remove(&path) {
+            self.expanded.insert(path);
+        }
+        self.refresh();
+    }
+
+    /// Hides what is in the selected directory, or else selects the
+    /// directory the selected row is in
+    pub fn collapse(&mut self) {
+        let Some(entry) = self.selected() else {
+            return;
+        };
+        let path = entry.path.clone();
+        if entry.is_dir && self.expanded.remove(&path) {
+            self.refresh();
+        } else if let Some(parent) = path.parent() {
+            let parent = parent.to_path_buf();
+            self.select_path(&parent);
+        }
+    }
+
+    /// Expands the directories down to `path` and selects it, unless it
+    /// is the file revealed last or not below the root
+    pub fn reveal(&mut self, path: &Path) {
+        if self.followed.as_deref() == Some(path)
+            || !path.starts_with(&self.root)
+        {
+            return;
+        }
+        self.followed = Some(path.to_path_buf());
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.root) {
+                break;
+            }
+            self.expanded.insert(dir.to_path_buf());
+        }
+        self.refresh();
+        self.select_path(path);
+    }
+
+    pub fn prompt(&self) -> Option<&Prompt> {
+        self.prompt.as_ref()
+    }
+
+    pub fn prompt_mut(&mut self) -> Option<&mut Prompt> {
+        self.prompt.as_mut()
+    }
+
+    pub fn take_prompt(&mut self) -> Option<Prompt> {
+        self.prompt.take()
+    }
+
+    /// Asks about `action` on the message bar, with a name to start from
+    /// where it needs one
+    pub fn ask(&mut self, action: Action) {
+        let (label, input) = match &action {
+            Action::Create(dir) if *dir == self.root => {
+                (String::from("New file: "), String::new())
+            }
+            Action::Create(dir) => (
+                format!("New file in {}/: ", self.display(dir)),
+                String::new(),
+            ),
+            Action::Rename(path) => {
+                let name = path.file_name().unwrap_or_default();
+                (
+                    String::from("Rename to: "),
+                    name.to_string_lossy().into_owned(),
+                )
+            }
+            Action::Move(path) => {
+                (String::from("Move to: "), self.display(path))
+            }
+            Action::Delete(path) => (
+                format!("Delete {}? (y/n) ", self.display(path)),
+                String::new(),
+            ),
+        };
+        self.prompt = Some(Prompt {
+            action,
+            label,
+            input,
+        });
+    }
+
+    /// `path` relative to the root
+    fn display(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+
+    /// Carries out what `prompt` asked about with the answer typed, then
+    /// lists the tree again with the file it is about selected
+    pub fn apply(&mut self, prompt: Prompt) -> Result<Change, String> {
+        let name = prompt.input.trim();
+        if name.is_empty() && !matches!(prompt.action, Action::Delete(_)) {
+            return Err(String::from("No name given"));
+        }
+        let change = match prompt.action {
+            Action::Create(dir) => {
+                let path = dir.join(name);
+                if path.exists() {
+                    return Err(format!(
+                        "{} already exists",
+                        self.display(&path)
+                    ));
+                }
+                let created = if name.ends_with('/') {
+                    fs::create_dir_all(&path)
+                } else {
+                    path.parent()
+                        .map_or(Ok(()), fs::create_dir_all)
+                        .and_then(|()| fs::File::create_new(&path).map(|_| ()))
+                };
+                created.map_err(|e| format!("Could not create {name}: {e}"))?;
+                self.expanded.insert(dir);
+                Change::Created(path)
+            }
+            Action::Rename(from) => {
+                let to = from.with_file_name(name);
+                self.move_file(from, to)?
+            }
+            Action::Move(from) => {
+                let mut to = self.root.join(name);
+                if to.is_dir() {
+                    to.push(from.file_name().unwrap_or_default());
+                }
+                self.move_file(from, to)?
+            }
+            Action::Delete(path) => {
+                let deleted = if path.is_dir() {
+                    fs::remove_dir_all(&path)
+                } else {
+                    fs::remove_file(&path)
+                };
+                deleted.map_err(|e| {
+                    format!("Could not delete {}: {e}", self.display(&path))
+                })?;
+                Change::Deleted(path)
+            }
+        };
+        self.refresh();
+        match &change {
+            Change::Created(path) | Change::Moved { to: path, .. } => {
+                let path = path.clone();
+                self.reveal(&path);
+            }
+            Change::Deleted(_) => {}
+        }
+        Ok(change)
+    }
+
+    /// Renames `from` to `to`, making the directories `to` is in
+    fn move_file(
+        &mut self,
+        from: PathBuf,
+        to: PathBuf,
+    ) -> Result<Change, String> {
+        if to.exists() {
+            return Err(format!("{} already exists", self.display(&to)));
+        }
+        to.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::rename(&from, &to))
+            .map_err(|e| {
+                format!("Could not move {}: {e}", self.display(&from))
+            })?;
+        if self.expanded.remove(&from) {
+            self.expanded.insert(to.clone());
+        }
+        Ok(Change::Moved { from, to })
+    }
+
+    /// Draws the tree into a frame of `size`, scrolled to the selected
+    /// row, over a status line with the root. The selected row is only
+    /// marked while the explorer has the focus.
+    pub fn draw(&mut self, size: Size, theme: &Theme, focused: bool) -> Frame {
+        let mut frame = Frame::with_style(size, theme.element(Element::Text));
+        let rows = size.height.saturating_sub(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if rows > 0 && self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+        for (y, (index, entry)) in self
+            .entries
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(rows)
+            .enumerate()
+        {
+            let name = entry.path.file_name().unwrap_or_default();
+            let marker = match entry.is_dir {
+                true if self.expanded.contains(&entry.path) => "\u{25be} ",
+                true => "\u{25b8} ",
+                false => "  ",
+            };
+            let slash = if entry.is_dir { "/" } else { "" };
+            let indent = " ".repeat(entry.depth * INDENT);
+            let text =
+                format!("{indent}{marker}{}{slash}", name.to_string_lossy());
+            frame.print(Position { x: 0, y }, &text);
+            if focused && index == self.selected {
+                let selection = theme.element(Element::Selection);
+                frame.patch_style(Position { x: 0, y }, size.width, selection);
+            }
+        }
+        let status = if focused {
+            Element::StatusBar
+        } else {
+            Element::StatusBarInactive
+        };
+        let y = rows;
+        let root = self.root.file_name().unwrap_or(self.root.as_os_str());
+        frame.print(
+            Position { x: 0, y },
+            &format!(" {}/", root.to_string_lossy()),
+        );
+        frame.patch_style(
+            Position { x: 0, y },
+            size.width,
+            theme.element(status),
+        );
+        frame
+    }
+
+    /// Where the caret goes in the frame `draw` made, at the name of the
+    /// selected row
+    pub fn caret(&self) -> Position {
+        let depth = self.selected().map_or(0, |entry| entry.depth);
+        Position {
+            x: depth * INDENT + 2,
+            y: self.selected.saturating_sub(self.scroll),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use crate::editor::buffer::Buffer;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Lines `first` through `last` folded away, only `first` stays visible
+pub struct Fold {
+    pub first: usize,
+    pub last: usize,
+}
+
+impl Fold {
+    /// Lines hidden by the fold
+    pub fn hidden(self) -> usize {
+        self.last - self.first
+    }
+}
+
+#[derive(Clone)]
+/// The folds of a buffer, sorted and never overlapping. Edits inside a
+/// fold open it, edits before it move it along.
+pub struct Folds {
+    folds: Vec<Fold>,
+    /// The buffer revision the folds are up to date with
+    revision: Option<u64>,
+}
+
+impl Folds {
+    pub fn default() -> Self {
+        Self {
+            folds: Vec::new(),
+            revision: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.folds.is_empty()
+    }
+
+    /// Folds `fold` away, replacing the folds inside it
+    pub fn add(&mut self, fold: Fold) {
+        if fold.last <= fold.first || self.hiding(fold.first).is_some() {
+            return;
+        }
+        self.folds
+            .retain(|f| f.last < fold.first || f.first > fold.last);
+        let index = self.folds.partition_point(|f| f.first < fold.first);
+        self.folds.insert(index, fold);
+    }
+
+    /// Opens the fold starting on line `y`, returns it if there was one
+    pub fn remove(&mut self, y: usize) -> Option<Fold> {
+        let index = self.folds.iter().position(|f| f.first == y)?;
+        Some(self.folds.remove(index))
+    }
+
+    pub fn clear(&mut self) {
+        self.folds.clear();
+    }
+
+    /// The fold starting on line `y`
+    pub fn starting_at(&self, y: usize) -> Option<Fold> {
+        self.folds.iter().copied().find(|f| f.first == y)
+    }
+
+    /// The fold line `y` is hidden in
+    pub fn hiding(&self, y: usize) -> Option<Fold> {
+        self.folds
+            .iter()
+            .copied()
+            .find(|f| f.first < y && y <= f.last)
+    }
+
+    /// The first visible line after line `y`
+    pub fn next_visible(&self, y: usize) -> usize {
+        match self.starting_at(y).or_else(|| self.hiding(y)) {
+            Some(fold) => fold.last + 1,
+            None => y + 1,
+        }
+    }
+
+    /// The last visible line before line `y`
+    pub fn previous_visible(&self, y: usize) -> Option<usize> {
+        y.checked_sub(1).map(|previous| self.visible(previous))
+    }
+
+    /// The visible line `y` is on, itself if it is not hidden
+    pub fn visible(&self, y: usize) -> usize {
+        self.hiding(y).map_or(y, |fold| fold.first)
+    }
+
+    /// Keeps the folds in line with the edits made to `buffer`
+    pub fn update(&mut self, buffer: &Buffer) {
+        let edits = self
+            .revision
+            .and_then(|revision| buffer.edits_since(revision));
+        self.revision = Some(buffer.revision());
+        let Some(edits) = edits else {
+            self.folds.clear();
+            return;
+        };
+        for edit in edits {
+            let (start, old_end) = (edit.start.y, edit.old_end.y);
+            // Edits on the line of the fold itself keep it
+            let on_first_line = start == old_end && old_end == edit.new_end.y;
+            self.folds.retain(|f| {
+                f.last < start
+                    || f.first > old_end
+                    || (on_first_line && f.first == start)
+            });
+            for fold in &mut self.folds {
+                if fold.first > old_end {
+                    fold.first = fold.first - old_end + edit.new_end.y;
+                    fold.last = fold.last - old_end + edit.new_end.y;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::terminal::Location;
+
+    fn fold(first: usize, last: usize) -> Fold {
+        Fold { first, last }
+    }
+
+    /// Folds of a buffer of ten lines, up to date with it
+    fn folded(folds: &[Fold]) -> (Folds, Buffer) {
+        let mut buffer = Buffer::new();
+        for y in 0..10 {
+            buffer.push(format!("line {y}"));
+        }
+        let mut result = Folds::default();
+        result.update(&buffer);
+        for &fold in folds {
+            result.add(fold);
+        }
+        (result, buffer)
+    }
+
+    #[test]
+    fn skips_over_hidden_lines() {
+        let (folds, _) = folded(&[fold(2, 4), fold(6, 8)]);
+        assert_eq!(folds.starting_at(2), Some(fold(2, 4)));
+        assert_eq!(folds.hiding(2), None);
+        assert_eq!(folds.hiding(4), Some(fold(2, 4)));
+        assert_eq!(folds.next_visible(1), 2);
+        assert_eq!(folds.next_visible(2), 5);
+        assert_eq!(folds.next_visible(3), 5);
+        assert_eq!(folds.next_visible(5), 6);
+        assert_eq!(folds.previous_visible(5), Some(2));
+        assert_eq!(folds.previous_visible(9), Some(6));
+        assert_eq!(folds.previous_visible(0), None);
+        assert_eq!(folds.visible(7), 6);
+        assert_eq!(folds.visible(5), 5);
+    }
+
+    #[test]
+    fn adding_keeps_the_folds_apart() {
+        let (mut folds, _) = folded(&[fold(2, 4), fold(6, 8)]);
+        folds.add(fold(5, 5));
+        folds.add(fold(3, 9));
+        assert_eq!(folds.folds, [fold(2, 4), fold(6, 8)]);
+
+        folds.add(fold(1, 8));
+        assert_eq!(folds.folds, [fold(1, 8)]);
+        assert_eq!(folds.remove(2), None);
+        assert_eq!(folds.remove(1), Some(fold(1, 8)));
+        assert!(folds.is_empty());
+    }
+
+    #[test]
+    fn edits_move_the_folds_after_them_and_open_the_ones_around_them() {
+        let (mut folds, mut buffer) = folded(&[fold(2, 4), fold(6, 8)]);
+        buffer.insert_lines(0, &[String::from("new")]);
+        folds.update(&buffer);
+        assert_eq!(folds.folds, [fold(3, 5), fold(7, 9)]);
+
+        buffer.insert('x', Location { x: 0, y: 3 });
+        folds.update(&buffer);
+        assert_eq!(folds.folds, [fold(3, 5), fold(7, 9)]);
+
+        buffer.insert('x', Location { x: 0, y: 8 });
+        buffer.delete_lines(0, 0);
+        folds.update(&buffer);
+        assert_eq!(folds.folds, [fold(2, 4)]);
+    }
+
+    #[test]
+    fn edits_it_missed_open_every_fold() {
+        let (mut folds, mut buffer) = folded(&[fold(2, 4)]);
+        for _ in 0..=1024 {
+            buffer.insert('x', Location { x: 0, y: 9 });
+        }
+        folds.update(&buffer);
+        assert!(folds.is_empty());
+
+        let (mut folds, _) = folded(&[fold(2, 4)]);
+        folds.update(&Buffer::new());
+        assert!(folds.is_empty());
+    }
+}
@@ -0,0 +1,340 @@
+use crate::editor::terminal::{Position, Size};
+use crossterm::style::Color;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+///  Colors and attributes of a cell, `None` colors are the terminal's own
+#[allow(clippy::struct_excessive_bools)]
+pub struct Style {
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    pub fn foreground(color: Color) -> Self {
+        Self {
+            foreground: Some(color),
+            ..Self::default()
+        }
+    }
+
+    /// `other` drawn over this style: its colors where it has any, and
+    /// the attributes of both
+    #[must_use]
+    pub fn patch(self, other: Style) -> Self {
+        Self {
+            foreground: other.foreground.or(self.foreground),
+            background: other.background.or(self.background),
+            bold: self.bold || other.bold,
+            italic: self.italic || other.italic,
+            underline: self.underline || other.underline,
+            reverse: self.reverse || other.reverse,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+///  A single character cell on the screen
+pub struct Cell {
+    pub symbol: char,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: ' ',
+            style: Style::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+///  A run of changed cells on a single row sharing one style, ready to be
+///  printed
+pub struct Change {
+    pub position: Position,
+    pub text: String,
+    pub style: Style,
+}
+
+#[derive(Debug, Clone)]
+///  In-memory grid of cells the `View` renders into before anything
+///  reaches the `Terminal`
+pub struct Frame {
+    size: Size,
+    cells: Vec<Cell>,
+    /// Style of blank cells and of printed text
+    style: Style,
+}
+
+impl Frame {
+    pub fn new(size: Size) -> Self {
+        Self::with_style(size, Style::default())
+    }
+
+    /// A frame of blank cells in `style`, text printed on it gets it too
+    pub fn with_style(size: Size, style: Style) -> Self {
+        Self {
+            size,
+            cells: vec![Cell { symbol: ' ', style }; size.width * size.height],
+            style,
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn get(&self, Position { x, y }: Position) -> Option<&Cell> {
+        if x >= self.size.width || y >= self.size.height {
+            return None;
+        }
+        self.cells.get(y * self.size.width + x)
+    }
+
+    pub fn set(&mut self, Position { x, y }: Position, cell: Cell) {
+        if x >= self.size.width || y >= self.size.height {
+            return;
+        }
+        self.cells[y * self.size.width + x] = cell;
+    }
+
+    /// Writes `text` starting at `at`, clipped to the end of the row.
+    /// Returns the number of cells written.
+    pub fn print(&mut self, at: Position, text: &str) -> usize {
+        if at.y >= self.size.height {
+            return 0;
+        }
+        let mut written = 0;
+        for (offset, symbol) in text.chars().enumerate() {
+            let x = at.x + offset;
+            if x >= self.size.width {
+                break;
+            }
+            self.set(
+                Position { x, y: at.y },
+                Cell {
+                    symbol,
+                    style: self.style,
+                },
+            );
+            written += 1;
+        }
+        written
+    }
+
+    /// Applies `style` to `width` cells starting at `at`, clipped to the
+    /// end of the row
+    pub fn set_style(&mut self, at: Position, width: usize, style: Style) {
+        if at.y >= self.size.height {
+            return;
+        }
+        let end = (at.x + width).min(self.size.width);
+        for x in at.x..end {
+            self.cells[at.y * self.size.width + x].style = style;
+        }
+    }
+
+    /// Draws `style` over the style of `width` cells starting at `at`,
+    /// see `Style::patch`
+    pub fn patch_style(&mut self, at: Position, width: usize, style: Style) {
+        if at.y >= self.size.height {
+            return;
+        }
+        let end = (at.x + width).min(self.size.width);
+        for x in at.x..end {
+            let cell = &mut self.cells[at.y * self.size.width + x];
+            cell.style = cell.style.patch(style);
+        }
+    }
+
+    /// Copies the cells of `other` onto this frame with its top left
+    /// corner at `at`, clipped to this frame
+    pub fn blit(&mut self, other: &Frame, at: Position) {
+        for y in 0..other.size.height {
+            for x in 0..other.size.width {
+                if let Some(cell) = other.get(Position { x, y }) {
+                    let position = Position {
+                        x: at.x + x,
+                        y: at.y + y,
+                    };
+                    self.set(position, *cell);
+                }
+            }
+        }
+    }
+
+    /// Returns the row `y` as a string, trailing blanks included.
+    pub fn row(&self, y: usize) -> String {
+        if y >= self.size.height {
+            return String::new();
+        }
+        let start = y * self.size.width;
+        self.cells[start..start + self.size.width]
+            .iter()
+            .map(|cell| cell.symbol)
+            .collect()
+    }
+
+    /// Compares this frame against the `previous` one and returns the
+    /// runs of cells that have to be printed to turn one into the other.
+    /// Frames of different sizes are compared as if `previous` was blank.
+    pub fn diff(&self, previous: &Frame) -> Vec<Change> {
+        let blank = Cell::default();
+        let same_size = previous.size.width == self.size.width
+            && previous.size.height == self.size.height;
+        let mut changes = Vec::new();
+
+        for y in 0..self.size.height {
+            let mut run: Option<Change> = None;
+            for x in 0..self.size.width {
+                let position = Position { x, y };
+                let cell = self.get(position).copied().unwrap_or_default();
+                let old = if same_size {
+                    previous.get(position).copied().unwrap_or_default()
+                } else {
+                    blank
+                };
+
+                if cell == old {
+                    if let Some(change) = run.take() {
+                        changes.push(change);
+                    }
+                    continue;
+                }
+
+                match run.as_mut() {
+                    Some(change) if change.style == cell.style => {
+                        change.text.push(cell.symbol);
+                    }
+                    _ => {
+                        if let Some(change) = run.take() {
+                            changes.push(change);
+                        }
+                        run = Some(Change {
+                            position,
+                            text: cell.symbol.to_string(),
+                            style: cell.style,
+                        });
+                    }
+                }
+            }
+            if let Some(change) = run.take() {
+                changes.push(change);
+            }
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: Size = Size {
+        width: 6,
+        height: 2,
+    };
+
+    fn at(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+
+    fn change(x: usize, y: usize, text: &str, style: Style) -> Change {
+        Change {
+            position: at(x, y),
+            text: text.into(),
+            style,
+        }
+    }
+
+    #[test]
+    fn prints_clipped_to_the_row() {
+        let mut frame = Frame::new(SIZE);
+        assert_eq!(frame.print(at(3, 0), "hello"), 3);
+        assert_eq!(frame.print(at(0, 2), "gone"), 0);
+        assert_eq!(frame.row(0), "   hel");
+        assert_eq!(frame.row(1), "      ");
+    }
+
+    #[test]
+    fn diffs_only_the_changed_runs() {
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        let mut previous = Frame::new(SIZE);
+        previous.print(at(0, 0), "abcdef");
+        previous.print(at(0, 1), "ghijkl");
+        let mut frame = previous.clone();
+        assert!(frame.diff(&previous).is_empty());
+
+        frame.print(at(1, 0), "XY");
+        frame.print(at(4, 0), "Z");
+        frame.print(at(0, 1), "MN");
+        frame.set_style(at(1, 1), 2, bold);
+        assert_eq!(
+            frame.diff(&previous),
+            [
+                change(1, 0, "XY", Style::default()),
+                change(4, 0, "Z", Style::default()),
+                change(0, 1, "M", Style::default()),
+                change(1, 1, "Ni", bold),
+            ]
+        );
+    }
+
+    #[test]
+    fn diffs_frames_of_another_size_against_a_blank_one() {
+        let mut previous = Frame::new(Size {
+            width: 3,
+            height: 1,
+        });
+        previous.print(at(0, 0), "ab");
+        let mut frame = Frame::new(SIZE);
+        frame.print(at(0, 0), "ab");
+        frame.print(at(2, 1), "c");
+        assert_eq!(
+            frame.diff(&previous),
+            [
+                change(0, 0, "ab", Style::default()),
+                change(2, 1, "c", Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn blits_and_patches_clipped_to_the_frame() {
+        let red = Style::foreground(Color::Red);
+        let mut small = Frame::with_style(
+            Size {
+                width: 3,
+                height: 2,
+            },
+            red,
+        );
+        small.print(at(0, 0), "xyz");
+        let mut frame = Frame::new(SIZE);
+        frame.blit(&small, at(4, 1));
+        assert_eq!(frame.row(0), "      ");
+        assert_eq!(frame.row(1), "    xy");
+        assert_eq!(frame.get(at(4, 1)).unwrap().style, red);
+
+        let underline = Style {
+            underline: true,
+            background: Some(Color::Blue),
+            ..Style::default()
+        };
+        frame.patch_style(at(3, 1), 10, underline);
+        let patched = frame.get(at(5, 1)).unwrap().style;
+        assert_eq!(patched.foreground, Some(Color::Red));
+        assert_eq!(patched.background, Some(Color::Blue));
+        assert!(patched.underline);
+        assert_eq!(frame.get(at(3, 1)).unwrap().style, underline);
+    }
+}
@@ -0,0 +1,119 @@
+/// Bonus for a match right after the previous one
+const CONSECUTIVE_BONUS: i64 = 15;
+/// Bonus for a match at the start of a word, e.g. after `_` or `/`
+const WORD_START_BONUS: i64 = 10;
+/// Bonus for matching the very first character
+const FIRST_CHAR_BONUS: i64 = 8;
+/// Penalty for every skipped character between two matches
+const GAP_PENALTY: i64 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// Higher is better
+    pub score: i64,
+    /// Indices of the matched characters in the candidate
+    pub indices: Vec<usize>,
+}
+
+fn is_word_start(previous: Option<char>, current: char) -> bool {
+    match previous {
+        None => true,
+        Some(previous) => {
+            !previous.is_alphanumeric()
+                || (previous.is_lowercase() && current.is_uppercase())
+        }
+    }
+}
+
+/// Matches `query` as a subsequence of `candidate`, ignoring case, and
+/// scores how good the match is. `None` when it does not match at all.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<Match> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut indices = Vec::new();
+    let mut score = 0;
+    let mut start = 0;
+
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        // Prefer the next word start over the next plain occurrence, so
+        // `ms` matches `move_start` at the `s` of `start`
+        let occurrences = (start..candidate.len())
+            .filter(|&i| candidate[i].to_lowercase().eq(wanted.to_lowercase()));
+        let mut first = None;
+        let mut word_start = None;
+        for i in occurrences {
+            first.get_or_insert(i);
+            let previous = i.checked_sub(1).map(|p| candidate[p]);
+            if is_word_start(previous, candidate[i]) {
+                word_start = Some(i);
+                break;
+            }
+        }
+        let index = match (indices.last(), first, word_start) {
+            // Staying consecutive beats jumping to a word start
+            (Some(&last), Some(first), _) if first == last + 1 => first,
+            (_, _, Some(word_start)) => word_start,
+            (_, Some(first), None) => first,
+            (_, None, _) => return None,
+        };
+
+        let previous = index.checked_sub(1).map(|p| candidate[p]);
+        if index == 0 {
+            score += FIRST_CHAR_BONUS;
+        }
+        if is_word_start(previous, candidate[index]) {
+            score += WORD_START_BONUS;
+        }
+        match indices.last() {
+            Some(&last) if index == last + 1 => score += CONSECUTIVE_BONUS,
+            Some(&last) => {
+                score -=
+                    GAP_PENALTY * i64::try_from(index - last - 1).unwrap_or(0);
+            }
+            None => {
+                score -= GAP_PENALTY * i64::try_from(index).unwrap_or(0);
+            }
+        }
+
+        indices.push(index);
+        start = index + 1;
+    }
+
+    Some(Match { score, indices })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(query: &str, candidate: &str) -> Option<Vec<usize>> {
+        fuzzy_match(query, candidate).map(|found| found.indices)
+    }
+
+    fn score(query: &str, candidate: &str) -> i64 {
+        fuzzy_match(query, candidate).unwrap().score
+    }
+
+    #[test]
+    fn matches_a_subsequence_ignoring_case_and_spaces() {
+        assert_eq!(indices("", "save"), Some(vec![]));
+        assert_eq!(indices("SV", "save"), Some(vec![0, 2]));
+        assert_eq!(indices("s v", "save"), Some(vec![0, 2]));
+        assert_eq!(indices("vs", "save"), None);
+        assert_eq!(indices("saves", "save"), None);
+    }
+
+    #[test]
+    fn prefers_word_starts_unless_already_consecutive() {
+        assert_eq!(indices("ms", "move_last_start"), Some(vec![0, 10]));
+        assert_eq!(indices("fb", "fooBar"), Some(vec![0, 3]));
+        assert_eq!(indices("mo", "move_out"), Some(vec![0, 1]));
+    }
+
+    #[test]
+    fn scores_better_matches_higher() {
+        assert!(score("s", "save") > score("s", "has"));
+        assert!(score("ab", "abx") > score("ab", "axb"));
+        assert!(score("ab", "axb") > score("ab", "axxxb"));
+        assert!(score("quit", "quit") > score("quit", "save_and_quit"));
+    }
+}
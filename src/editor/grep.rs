@@ -0,0 +1,293 @@
+use crate::editor::ignore::{Glob, relative};
+use crate::editor::recovery;
+use crate::editor::walk::walk;
+use log::info;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError, channel};
+
+/// Most matches listed, so a pattern matching everything does not fill
+/// the memory
+const MAX_MATCHES: usize = 10_000;
+/// Larger files are left out, they are hardly ever source code
+const MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+/// How much of the start of a file is looked at to tell it is binary
+const BINARY_CHECK: usize = 8 * 1024;
+/// Most characters of a matching line kept to show
+const MAX_TEXT: usize = 200;
+
+#[derive(Debug, Clone)]
+/// What to search the files of the project for, and in which files
+pub struct GrepQuery {
+    pub regex: Regex,
+    /// Only files matching one of these, if any, are searched
+    pub include: Vec<Glob>,
+    /// Files matching one of these are not searched
+    pub exclude: Vec<Glob>,
+}
+
+impl GrepQuery {
+    /// Whether the file at `relative`, from the top of the search, is to
+    /// be searched
+    fn searches(&self, relative: &str) -> bool {
+        let included = self.include.is_empty()
+            || self.include.iter().any(|glob| glob.matches_path(relative));
+        included && !self.exclude.iter().any(|glob| glob.matches_path(relative))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// One place a search in files found
+pub struct GrepMatch {
+    pub path: PathBuf,
+    /// 0-based line
+    pub line: usize,
+    /// Characters into the line where the match starts and ends
+    pub start: usize,
+    pub end: usize,
+    /// The line, trimmed
+    pub text: String,
+}
+
+/// Searches the files below a directory on a thread of its own, leaving
+/// out what `.gitignore` files do. Dropping it stops the thread.
+pub struct Search {
+    receiver: Receiver<Vec<GrepMatch>>,
+    done: bool,
+}
+
+impl Search {
+    pub fn start(root: PathBuf, query: GrepQuery) -> Self {
+        let (sender, receiver) = channel();
+        recovery::spawn_worker("grep", move || {
+            search(&root, &query, &sender);
+        });
+        Self {
+            receiver,
+            done: false,
+        }
+    }
+
+    /// The matches found since the last call, without waiting for more
+    pub fn poll(&mut self) -> Vec<GrepMatch> {
+        let mut matches = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(batch) => matches.extend(batch),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+        matches
+    }
+
+    /// Whether every match has been handed over
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+/// Sends the matches in the files below `root`, those of a file together
+fn search(root: &Path, query: &GrepQuery, sender: &Sender<Vec<GrepMatch>>) {
+    let mut count = 0;
+    walk(root, |path| {
+        let searched = relative(&path, root)
+            .is_some_and(|relative| query.searches(&relative));
+        if !searched {
+            return true;
+        }
+        let matches = search_file(&path, &query.regex, MAX_MATCHES - count);
+        if matches.is_empty() {
+            return true;
+        }
+        count += matches.len();
+        sender.send(matches).is_ok() && count < MAX_MATCHES
+    });
+    info!(
+        "Found {count} matches of {} below {}",
+        query.regex,
+        root.display()
+    );
+}
+
+/// Up to `limit` matches of `regex` in the file at `path`, none for files
+/// that are too large or not text
+fn search_file(path: &Path, regex: &Regex, limit: usize) -> Vec<GrepMatch> {
+    let too_large =
+        fs::metadata(path).map_or(true, |m| m.len() > MAX_FILE_SIZE);
+    if too_large {
+        return Vec::new();
+    }
+    let Ok(bytes) = fs::read(path) else {
+        return Vec::new();
+    };
+    if bytes.iter().take(BINARY_CHECK).any(|&byte| byte == 0) {
+        return Vec::new();
+    }
+    let Ok(text) = String::from_utf8(bytes) else {
+        return Vec::new();
+    };
+    let mut matches = Vec::new();
+    for (y, line) in text.lines().enumerate() {
+        for found in regex.find_iter(line) {
+            let start = line[..found.start()].chars().count();
+            let length = found.as_str().chars().count();
+            matches.push(GrepMatch {
+                path: path.to_path_buf(),
+                line: y,
+                start,
+                end: start + length,
+                text: line
+                    .trim()
+                    .chars()
+                    .take(MAX_TEXT)
+                    .map(|c| if c == '\t' { ' ' } else { c })
+                    .collect(),
+            });
+            if matches.len() == limit {
+                return matches;
+            }
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory in the temporary directory named after `name`, with
+    /// each of `files` holding its bytes
+    fn tree(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let root = std::env::temp_dir()
+            .join(format!("r-edit-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, bytes) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, bytes).unwrap();
+        }
+        root
+    }
+
+    fn query(pattern: &str) -> GrepQuery {
+        GrepQuery {
+            regex: Regex::new(pattern).unwrap(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+
+    /// Every match of `query` below `root`, as file, line and characters
+    fn found(
+        root: &Path,
+        query: &GrepQuery,
+    ) -> Vec<(String, usize, usize, usize)> {
+        let (sender, receiver) = channel();
+        search(root, query, &sender);
+        drop(sender);
+        receiver
+            .iter()
+            .flatten()
+            .map(|found| {
+                let file = relative(&found.path, root).unwrap();
+                (file, found.line, found.start, found.end)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_every_match_in_order() {
+        let root = tree(
+            "grep-search",
+            &[
+                ("b.txt", "foo\n\tfoo foo\n".as_bytes()),
+                ("a/c.txt", "ünïcode foo\n".as_bytes()),
+                ("a.txt", b"nothing\n"),
+            ],
+        );
+        assert_eq!(
+            found(&root, &query("fo+")),
+            [
+                (String::from("b.txt"), 0, 0, 3),
+                (String::from("b.txt"), 1, 1, 4),
+                (String::from("b.txt"), 1, 5, 8),
+                (String::from("a/c.txt"), 0, 8, 11),
+            ]
+        );
+        let (sender, receiver) = channel();
+        search(&root, &query("foo"), &sender);
+        let first = receiver.recv().unwrap();
+        assert_eq!(first[1].text, "foo foo");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn searches_only_included_files_that_are_not_excluded() {
+        let root = tree(
+            "grep-globs",
+            &[("a.rs", b"foo"), ("a.txt", b"foo"), ("gen/b.rs", b"foo")],
+        );
+        let mut query = query("foo");
+        query.include = vec![Glob::new("*.rs").unwrap()];
+        query.exclude = vec![Glob::new("gen").unwrap()];
+        assert_eq!(found(&root, &query), [(String::from("a.rs"), 0, 0, 3)]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn skips_binary_large_and_ignored_files() {
+        let mut large = vec![b'x'; usize::try_from(MAX_FILE_SIZE).unwrap()];
+        large.extend(b"\nfoo\n");
+        let root = tree(
+            "grep-skips",
+            &[
+                (".gitignore", b"ignored.txt\n"),
+                ("ignored.txt", b"foo"),
+                ("binary.bin", b"foo\0"),
+                ("latin1.txt", b"foo \xe9"),
+                ("large.txt", &large),
+                (".git/HEAD", b"foo"),
+                ("text.txt", b"foo"),
+            ],
+        );
+        assert_eq!(
+            found(&root, &query("foo")),
+            [(String::from("text.txt"), 0, 0, 3)]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn stops_at_the_most_matches() {
+        let lines = "foo\n".repeat(MAX_MATCHES * 2 / 3);
+        let root = tree(
+            "grep-limit",
+            &[("a.txt", lines.as_bytes()), ("b.txt", lines.as_bytes())],
+        );
+        let matches = found(&root, &query("foo"));
+        assert_eq!(matches.len(), MAX_MATCHES);
+        assert_eq!(
+            matches.last().unwrap().1,
+            MAX_MATCHES - lines.len() / 4 - 1
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn searches_on_a_thread_of_its_own() {
+        let root = tree("grep-thread", &[("a.txt", b"foo\nfoo\n")]);
+        let mut search = Search::start(root.clone(), query("foo"));
+        let mut matches = Vec::new();
+        while !search.is_done() {
+            matches.extend(search.poll());
+            std::thread::yield_now();
+        }
+        assert_eq!(matches.len(), 2);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,534 @@
+mod languages;
+mod lexer;
+mod markdown;
+
+use crate::editor::buffer::Buffer;
+#[cfg(feature = "tree-sitter")]
+use crate::editor::syntax::SyntaxTree;
+use log::debug;
+use std::ops::Range;
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// What a piece of highlighted text is, themes pick a style for each
+pub enum Scope {
+    Comment,
+    String,
+    Number,
+    Constant,
+    Keyword,
+    Type,
+    Function,
+    Macro,
+    Attribute,
+    /// Rust lifetimes and loop labels
+    Label,
+    /// Shell variables
+    Variable,
+    /// Keys in TOML tables and JSON objects
+    Key,
+    Punctuation,
+    Heading,
+    Emphasis,
+    Strong,
+    Link,
+    /// Inline code and code blocks in Markdown
+    Code,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 18] = [
+        Scope::Comment,
+        Scope::String,
+        Scope::Number,
+        Scope::Constant,
+        Scope::Keyword,
+        Scope::Type,
+        Scope::Function,
+        Scope::Macro,
+        Scope::Attribute,
+        Scope::Label,
+        Scope::Variable,
+        Scope::Key,
+        Scope::Punctuation,
+        Scope::Heading,
+        Scope::Emphasis,
+        Scope::Strong,
+        Scope::Link,
+        Scope::Code,
+    ];
+
+    /// The name themes use for the scope
+    pub fn name(self) -> &'static str {
+        match self {
+            Scope::Comment => "comment",
+            Scope::String => "string",
+            Scope::Number => "number",
+            Scope::Constant => "constant",
+            Scope::Keyword => "keyword",
+            Scope::Type => "type",
+            Scope::Function => "function",
+            Scope::Macro => "macro",
+            Scope::Attribute => "attribute",
+            Scope::Label => "label",
+            Scope::Variable => "variable",
+            Scope::Key => "key",
+            Scope::Punctuation => "punctuation",
+            Scope::Heading => "heading",
+            Scope::Emphasis => "emphasis",
+            Scope::Strong => "strong",
+            Scope::Link => "link",
+            Scope::Code => "code",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scope| scope.name() == name)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Characters `start` up to `end` of a line belong to `scope`
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub scope: Scope,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+/// Where a line starts off: inside a construct left open by the lines
+/// before it, or not
+pub enum State {
+    #[default]
+    Normal,
+    /// Inside a block comment, nested `depth` deep
+    Comment { depth: u32 },
+    /// Inside a string opened by the language's string rule `rule`
+    String { rule: usize },
+    /// Inside a Rust raw string closed by `"` and `hashes` times `#`
+    RawString { hashes: usize },
+    /// Inside a fenced code block in Markdown
+    Fence,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    Toml,
+    Markdown,
+    Json,
+    Shell,
+    Python,
+}
+
+impl Language {
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Rust => "rust",
+            Language::Toml => "toml",
+            Language::Markdown => "markdown",
+            Language::Json => "json",
+            Language::Shell => "shell",
+            Language::Python => "python",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Language::Rust,
+            Language::Toml,
+            Language::Markdown,
+            Language::Json,
+            Language::Shell,
+            Language::Python,
+        ]
+        .into_iter()
+        .find(|language| language.name() == name)
+    }
+
+    /// Guesses the language of a file from its name, and from a `#!` on
+    /// its first line if the name says nothing
+    pub fn detect(path: &str, first_line: &str) -> Option<Self> {
+        let path = Path::new(path);
+        let file_name = path.file_name()?.to_str()?;
+        let extension = path.extension().and_then(|e| e.to_str());
+        let by_name = match (file_name, extension) {
+            ("Cargo.lock", _) | (_, Some("toml")) => Some(Language::Toml),
+            (_, Some("rs")) => Some(Language::Rust),
+            (_, Some("md" | "markdown")) => Some(Language::Markdown),
+            (_, Some("json")) => Some(Language::Json),
+            (".bashrc" | ".bash_profile" | ".profile" | ".zshrc", _)
+            | (_, Some("sh" | "bash" | "zsh")) => Some(Language::Shell),
+            (_, Some("py" | "pyi")) => Some(Language::Python),
+            _ => None,
+        };
+        by_name.or_else(|| Self::from_shebang(first_line))
+    }
+
+    fn from_shebang(line: &str) -> Option<Self> {
+        let command = line.strip_prefix("#!")?;
+        let mut words = command.split_whitespace();
+        let mut program = words.next()?.rsplit('/').next()?;
+        if program == "env" {
+            program = words.find(|word| !word.starts_with('-'))?;
+        }
+        match program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.')
+        {
+            "sh" | "bash" | "zsh" | "dash" | "ksh" => Some(Language::Shell),
+            "python" => Some(Language::Python),
+            _ => None,
+        }
+    }
+
+    /// Splits `line` into spans, starting in `state`. Returns the spans
+    /// and the state the next line starts in.
+    pub fn tokenize(self, line: &str, state: State) -> (Vec<Span>, State) {
+        match self {
+            Language::Markdown => markdown::tokenize(line, state),
+            _ => lexer::tokenize(languages::syntax(self), line, state),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// What the highlighter knows about a line
+struct Line {
+    /// The state the spans were made from
+    start: State,
+    end: State,
+    spans: Vec<Span>,
+    /// False once the line was edited
+    valid: bool,
+}
+
+/// Highlights a buffer lazily and incrementally: only lines that were
+/// edited, or whose starting state changed, are tokenized again. With
+/// tree-sitter, a syntax tree takes over where there is a grammar.
+pub struct Highlighter {
+    language: Option<Language>,
+    lines: Vec<Line>,
+    /// The buffer revision the lines are up to date with
+    revision: Option<u64>,
+    #[cfg(feature = "tree-sitter")]
+    tree: Option<SyntaxTree>,
+}
+
+impl Highlighter {
+    pub fn new(language: Option<Language>) -> Self {
+        Self {
+            language,
+            lines: Vec::new(),
+            revision: None,
+            #[cfg(feature = "tree-sitter")]
+            tree: language.and_then(SyntaxTree::new),
+        }
+    }
+
+    pub fn language(&self) -> Option<Language> {
+        self.language
+    }
+
+    /// The syntax tree of `buffer` brought up to date, if there is a
+    /// grammar for its language
+    #[cfg(feature = "tree-sitter")]
+    pub fn syntax_tree(&mut self, buffer: &Buffer) -> Option<&SyntaxTree> {
+        let tree = self.tree.as_mut()?;
+        tree.update(buffer);
+        Some(tree)
+    }
+
+    /// Catches up with the edits made to `buffer` and highlights `lines`.
+    /// Without a syntax tree every line before them is highlighted too.
+    pub fn update(&mut self, buffer: &Buffer, lines: Range<usize>) {
+        let Some(language) = self.language else {
+            return;
+        };
+        #[cfg(feature = "tree-sitter")]
+        if let Some(tree) = &mut self.tree {
+            tree.update(buffer);
+            let lines = lines.start.min(buffer.lines.len())
+                ..lines.end.min(buffer.lines.len());
+            self.lines.resize_with(buffer.lines.len(), Line::default);
+            let spans = tree.highlight(buffer, lines.clone());
+            for (y, spans) in lines.zip(spans) {
+                self.lines[y].spans = spans;
+            }
+            return;
+        }
+        self.apply_edits(buffer);
+
+        let until = lines.end.min(buffer.lines.len());
+        self.lines.resize_with(buffer.lines.len(), Line::default);
+        let mut tokenized = 0;
+        let mut state = State::Normal;
+        for (y, text) in buffer.lines.iter().enumerate().take(until) {
+            let line = &mut self.lines[y];
+            if !line.valid || line.start != state {
+                let (spans, end) = language.tokenize(text, state);
+                *line = Line {
+                    start: state,
+                    end,
+                    spans,
+                    valid: true,
+                };
+                tokenized += 1;
+            }
+            state = line.end;
+        }
+        if tokenized > 0 {
+            debug!("Highlighted {tokenized} lines");
+        }
+    }
+
+    /// Keeps the cached lines in line with the buffer, dropping the ones
+    /// that were edited
+    fn apply_edits(&mut self, buffer: &Buffer) {
+        let edits = self
+            .revision
+            .and_then(|revision| buffer.edits_since(revision));
+        self.revision = Some(buffer.revision());
+        let Some(edits) = edits else {
+            self.lines.clear();
+            return;
+        };
+        for edit in edits {
+            let y = edit.start.y;
+            if y >= self.lines.len() {
+                continue;
+            }
+            self.lines[y].valid = false;
+            let removed = edit.old_end.y - y;
+            let added = edit.new_end.y - y;
+            let end = (y + 1 + removed).min(self.lines.len());
+            self.lines.splice(
+                y + 1..end,
+                std::iter::repeat_n(Line::default(), added),
+            );
+        }
+    }
+
+    /// The spans of line `y`, empty if it was not highlighted
+    pub fn spans(&self, y: usize) -> &[Span] {
+        self.lines.get(y).map_or(&[], |line| line.spans.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A line's spans as their text and scope name
+    type Scopes = Vec<(String, &'static str)>;
+
+    /// `lines` tokenized one after the other in `language`, and the state
+    /// after the last one
+    fn tokenize(language: Language, lines: &[&str]) -> (Vec<Scopes>, State) {
+        let mut state = State::Normal;
+        let scopes = lines
+            .iter()
+            .map(|line| {
+                let (spans, end) = language.tokenize(line, state);
+                state = end;
+                let chars: Vec<char> = line.chars().collect();
+                spans
+                    .iter()
+                    .map(|span| {
+                        let text = chars[span.start..span.end].iter().collect();
+                        (text, span.scope.name())
+                    })
+                    .collect()
+            })
+            .collect();
+        (scopes, state)
+    }
+
+    /// Spans written as `(text, scope name)` pairs
+    type Expected = &'static [(&'static str, &'static str)];
+
+    fn spans(spans: Expected) -> Scopes {
+        spans
+            .iter()
+            .map(|&(text, scope)| (text.into(), scope))
+            .collect()
+    }
+
+    #[test]
+    fn tokenizes_strings_comments_and_nested_constructs() {
+        let cases: &[(Language, &str, Expected)] = &[
+            (
+                Language::Rust,
+                r#"let s = "a\"b"; // done"#,
+                &[
+                    ("let", "keyword"),
+                    (r#""a\"b""#, "string"),
+                    ("// done", "comment"),
+                ],
+            ),
+            (
+                Language::Rust,
+                "x /* a /* b */ c */ fn f() {}",
+                &[
+                    ("/* a /* b */ c */", "comment"),
+                    ("fn", "keyword"),
+                    ("f", "function"),
+                ],
+            ),
+            (
+                Language::Rust,
+                r#"b"x" #[derive(Debug)] println!("{}", 0x1f)"#,
+                &[
+                    (r#"b"x""#, "string"),
+                    ("#[derive(Debug)]", "attribute"),
+                    ("println!", "macro"),
+                    (r#""{}""#, "string"),
+                    ("0x1f", "number"),
+                ],
+            ),
+            (
+                Language::Rust,
+                "'a' 'life",
+                &[("'a'", "string"), ("'life", "label")],
+            ),
+            (
+                Language::Python,
+                "@dec def f(x=None): pass # c",
+                &[
+                    ("@dec", "attribute"),
+                    ("def", "keyword"),
+                    ("f", "function"),
+                    ("None", "constant"),
+                    ("pass", "keyword"),
+                    ("# c", "comment"),
+                ],
+            ),
+            (
+                Language::Toml,
+                r#"key = "v # not a comment" # c"#,
+                &[
+                    ("key", "key"),
+                    (r#""v # not a comment""#, "string"),
+                    ("# c", "comment"),
+                ],
+            ),
+            (
+                Language::Json,
+                r#"{"key": "value", "n": 1.5, "t": true}"#,
+                &[
+                    (r#""key""#, "key"),
+                    (r#""value""#, "string"),
+                    (r#""n""#, "key"),
+                    ("1.5", "number"),
+                    (r#""t""#, "key"),
+                    ("true", "constant"),
+                ],
+            ),
+            (
+                Language::Shell,
+                r#"if echo "a # b" 'c' # d"#,
+                &[
+                    ("if", "keyword"),
+                    (r#""a # b""#, "string"),
+                    ("'c'", "string"),
+                    ("# d", "comment"),
+                ],
+            ),
+            (
+                Language::Markdown,
+                "Some *em* **strong** `code` [link](url)",
+                &[
+                    ("*em*", "emphasis"),
+                    ("**strong**", "strong"),
+                    ("`code`", "code"),
+                    ("[link](url)", "link"),
+                ],
+            ),
+        ];
+        for (language, line, expected) in cases {
+            let (scopes, state) = tokenize(*language, &[line]);
+            assert_eq!(scopes, [spans(expected)], "{language:?} {line}");
+            assert_eq!(state, State::Normal, "{language:?} {line}");
+        }
+    }
+
+    #[test]
+    fn carries_open_constructs_to_the_next_line() {
+        let cases: &[(Language, &[&str], State, Expected)] = &[
+            (
+                Language::Rust,
+                &["x /* a /* b */ still"],
+                State::Comment { depth: 1 },
+                &[("/* a /* b */ still", "comment")],
+            ),
+            (
+                Language::Rust,
+                &["x /* a /* b */ still", "c */ fn"],
+                State::Normal,
+                &[("c */", "comment"), ("fn", "keyword")],
+            ),
+            (
+                Language::Rust,
+                &[r#"let s = "one"#, r#"two" + 1;"#],
+                State::Normal,
+                &[(r#"two""#, "string"), ("1", "number")],
+            ),
+            (
+                Language::Rust,
+                &[r#"r#"a "quoted""#],
+                State::RawString { hashes: 1 },
+                &[(r#"r#"a "quoted""#, "string")],
+            ),
+            (
+                Language::Rust,
+                &[r#"r#"a "quoted""#, r##"b"# true"##],
+                State::Normal,
+                &[(r##"b"#"##, "string"), ("true", "constant")],
+            ),
+            (
+                Language::Python,
+                &["s = '''one", "two''' # c"],
+                State::Normal,
+                &[("two'''", "string"), ("# c", "comment")],
+            ),
+            (
+                Language::Toml,
+                &[r#"m = """a"#],
+                State::String { rule: 0 },
+                &[("m", "key"), (r#""""a"#, "string")],
+            ),
+            (
+                Language::Markdown,
+                &["```rust", "let x = 1;"],
+                State::Fence,
+                &[("let x = 1;", "code")],
+            ),
+            (
+                Language::Markdown,
+                &["```rust", "let x = 1;", "```", "# Title"],
+                State::Normal,
+                &[("# Title", "heading")],
+            ),
+        ];
+        for (language, lines, end, last) in cases {
+            let (scopes, state) = tokenize(*language, lines);
+            assert_eq!(scopes.last(), Some(&spans(last)), "{lines:?}");
+            assert_eq!(state, *end, "{lines:?}");
+        }
+    }
+
+    #[test]
+    fn highlights_again_what_an_edit_opens_or_closes() {
+        let mut buffer = Buffer::new();
+        buffer.set_lines(&["```".into(), "# Title".into(), "```".into()]);
+        let mut highlighter = Highlighter::new(Some(Language::Markdown));
+        highlighter.update(&buffer, 0..3);
+        assert_eq!(highlighter.spans(1)[0].scope, Scope::Code);
+
+        buffer.replace_line(0, "text");
+        highlighter.update(&buffer, 0..3);
+        assert!(highlighter.spans(0).is_empty());
+        assert_eq!(highlighter.spans(1)[0].scope, Scope::Heading);
+        // Opens a fence now instead of closing one
+        assert_eq!(highlighter.spans(2)[0].scope, Scope::Code);
+        assert_eq!(highlighter.lines[2].end, State::Fence);
+    }
+}
@@ -0,0 +1,136 @@
+use super::Language;
+use super::lexer::{StringRule, Syntax};
+
+const fn string(
+    open: &'static str,
+    close: &'static str,
+    escapes: bool,
+    multiline: bool,
+) -> StringRule {
+    StringRule {
+        open,
+        close,
+        escapes,
+        multiline,
+    }
+}
+
+const PLAIN: Syntax = Syntax {
+    line_comments: &[],
+    block_comment: None,
+    nested_comments: false,
+    strings: &[],
+    string_prefixes: &[],
+    keywords: &[],
+    types: &[],
+    constants: &[],
+    function_keywords: &[],
+    capitalized_types: false,
+    rust: false,
+    variables: false,
+    decorators: false,
+    string_keys: false,
+    tables: false,
+};
+
+const RUST: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    nested_comments: true,
+    strings: &[string("\"", "\"", true, true)],
+    string_prefixes: &["b", "c"],
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn",
+        "else", "enum", "extern", "fn", "for", "if", "impl", "in", "let",
+        "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self",
+        "Self", "static", "struct", "super", "trait", "type", "unsafe", "use",
+        "where", "while", "yield",
+    ],
+    types: &[
+        "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize",
+        "i8", "i16", "i32", "i64", "i128", "isize", "f32", "f64",
+    ],
+    constants: &["true", "false", "None", "Some", "Ok", "Err"],
+    function_keywords: &["fn"],
+    capitalized_types: true,
+    rust: true,
+    ..PLAIN
+};
+
+const TOML: Syntax = Syntax {
+    line_comments: &["#"],
+    strings: &[
+        string("\"\"\"", "\"\"\"", true, true),
+        string("'''", "'''", false, true),
+        string("\"", "\"", true, false),
+        string("'", "'", false, false),
+    ],
+    constants: &["true", "false", "inf", "nan"],
+    tables: true,
+    ..PLAIN
+};
+
+const JSON: Syntax = Syntax {
+    strings: &[string("\"", "\"", true, false)],
+    constants: &["true", "false", "null"],
+    string_keys: true,
+    ..PLAIN
+};
+
+const SHELL: Syntax = Syntax {
+    line_comments: &["#"],
+    strings: &[
+        string("\"", "\"", true, true),
+        string("'", "'", false, true),
+        string("`", "`", true, true),
+    ],
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "case", "esac", "for", "select",
+        "while", "until", "do", "done", "in", "function", "time", "return",
+        "local", "export", "readonly", "declare", "unset", "shift", "exit",
+        "break", "continue", "source",
+    ],
+    constants: &["true", "false"],
+    function_keywords: &["function"],
+    variables: true,
+    ..PLAIN
+};
+
+const PYTHON: Syntax = Syntax {
+    line_comments: &["#"],
+    strings: &[
+        string("\"\"\"", "\"\"\"", true, true),
+        string("'''", "'''", true, true),
+        string("\"", "\"", true, false),
+        string("'", "'", true, false),
+    ],
+    string_prefixes: &["r", "b", "f", "u", "rb", "br", "fr", "rf"],
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue",
+        "def", "del", "elif", "else", "except", "finally", "for", "from",
+        "global", "if", "import", "in", "is", "lambda", "nonlocal", "not",
+        "or", "pass", "raise", "return", "try", "while", "with", "yield",
+        "match", "case", "self",
+    ],
+    types: &[
+        "int", "float", "str", "bytes", "bool", "list", "dict", "set", "tuple",
+        "object",
+    ],
+    constants: &["True", "False", "None"],
+    function_keywords: &["def"],
+    capitalized_types: true,
+    decorators: true,
+    ..PLAIN
+};
+
+/// How the generic lexer reads `language`
+pub fn syntax(language: Language) -> &'static Syntax {
+    match language {
+        Language::Rust => &RUST,
+        Language::Toml => &TOML,
+        Language::Json => &JSON,
+        Language::Shell => &SHELL,
+        Language::Python => &PYTHON,
+        Language::Markdown => &PLAIN,
+    }
+}
@@ -0,0 +1,533 @@
+use super::{Scope, Span, State};
+
+/// A kind of string literal
+pub struct StringRule {
+    pub open: &'static str,
+    pub close: &'static str,
+    /// Whether `\` escapes the next character
+    pub escapes: bool,
+    /// Whether the string may go on past the end of the line
+    pub multiline: bool,
+}
+
+/// Everything the generic lexer needs to know about a language
+#[allow(clippy::struct_excessive_bools)]
+pub struct Syntax {
+    pub line_comments: &'static [&'static str],
+    pub block_comment: Option<(&'static str, &'static str)>,
+    pub nested_comments: bool,
+    /// Tried in order, so longer openers go first
+    pub strings: &'static [StringRule],
+    /// Letters that may come right before a string, like `b"` or `f"`
+    pub string_prefixes: &'static [&'static str],
+    pub keywords: &'static [&'static str],
+    pub types: &'static [&'static str],
+    pub constants: &'static [&'static str],
+    /// Words after which comes the name of a function
+    pub function_keywords: &'static [&'static str],
+    /// Whether capitalized words are types
+    pub capitalized_types: bool,
+    /// Raw strings, lifetimes, characters, attributes and macros
+    pub rust: bool,
+    /// `$name` and `${...}`
+    pub variables: bool,
+    /// `@decorator` at the start of a line
+    pub decorators: bool,
+    /// Strings followed by `:` are keys
+    pub string_keys: bool,
+    /// `[table]` headers and `key =` at the start of lines
+    pub tables: bool,
+}
+
+fn starts_with(chars: &[char], at: usize, pattern: &str) -> bool {
+    let mut rest = chars.get(at..).unwrap_or_default().iter();
+    pattern.chars().all(|p| rest.next() == Some(&p))
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// End of the word starting at `at`
+fn word_end(chars: &[char], at: usize) -> usize {
+    let mut i = at;
+    while chars.get(i).is_some_and(|&c| is_word(c)) {
+        i += 1;
+    }
+    i
+}
+
+/// Index of the first character from `at` on that is not blank
+fn skip_blanks(chars: &[char], at: usize) -> usize {
+    let mut i = at;
+    while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+        i += 1;
+    }
+    i
+}
+
+/// Scans a block comment from `at`, `depth` deep. Returns where it ends
+/// and how deep it still is there.
+fn block_comment(
+    syntax: &Syntax,
+    chars: &[char],
+    at: usize,
+    depth: u32,
+) -> (usize, u32) {
+    let Some((open, close)) = syntax.block_comment else {
+        return (chars.len(), 0);
+    };
+    let mut depth = depth;
+    let mut i = at;
+    while i < chars.len() {
+        if starts_with(chars, i, close) {
+            i += close.chars().count();
+            depth -= 1;
+            if depth == 0 {
+                return (i, 0);
+            }
+        } else if syntax.nested_comments && starts_with(chars, i, open) {
+            i += open.chars().count();
+            depth += 1;
+        } else {
+            i += 1;
+        }
+    }
+    (i, depth)
+}
+
+/// Scans the inside of a string from `at`. Returns where it ends and
+/// whether it was closed.
+fn string(rule: &StringRule, chars: &[char], at: usize) -> (usize, bool) {
+    let mut i = at;
+    while i < chars.len() {
+        if rule.escapes && chars[i] == '\\' {
+            i += 2;
+        } else if starts_with(chars, i, rule.close) {
+            return (i + rule.close.chars().count(), true);
+        } else {
+            i += 1;
+        }
+    }
+    (chars.len(), false)
+}
+
+/// Scans the inside of a raw string from `at`, closed by `"` and
+/// `hashes` times `#`
+fn raw_string(chars: &[char], at: usize, hashes: usize) -> (usize, bool) {
+    let mut i = at;
+    while i < chars.len() {
+        if chars[i] == '"'
+            && (1..=hashes).all(|n| chars.get(i + n) == Some(&'#'))
+        {
+            return (i + 1 + hashes, true);
+        }
+        i += 1;
+    }
+    (chars.len(), false)
+}
+
+/// Continues a construct left open by the line before. Returns where it
+/// ends on this line and the state after it.
+fn resume(
+    syntax: &Syntax,
+    chars: &[char],
+    state: State,
+    spans: &mut Vec<Span>,
+) -> (usize, State) {
+    let (end, state, scope) = match state {
+        State::Normal | State::Fence => return (0, State::Normal),
+        State::Comment { depth } => {
+            let (end, depth) = block_comment(syntax, chars, 0, depth);
+            let state = if depth > 0 {
+                State::Comment { depth }
+            } else {
+                State::Normal
+            };
+            (end, state, Scope::Comment)
+        }
+        State::String { rule } => {
+            let (end, closed) = string(&syntax.strings[rule], chars, 0);
+            let state = if closed {
+                State::Normal
+            } else {
+                State::String { rule }
+            };
+            (end, state, Scope::String)
+        }
+        State::RawString { hashes } => {
+            let (end, closed) = raw_string(chars, 0, hashes);
+            let state = if closed {
+                State::Normal
+            } else {
+                State::RawString { hashes }
+            };
+            (end, state, Scope::String)
+        }
+    };
+    if end > 0 {
+        spans.push(Span {
+            start: 0,
+            end,
+            scope,
+        });
+    }
+    (end, state)
+}
+
+/// Colors a `[table]` header or the key of a `key = value` line. Returns
+/// where the rest of the line starts.
+fn table_line(chars: &[char], at: usize, spans: &mut Vec<Span>) -> usize {
+    let start = skip_blanks(chars, at);
+    if chars.get(start) == Some(&'[') {
+        let end = chars
+            .iter()
+            .rposition(|&c| c == ']')
+            .filter(|&end| end > start)
+            .map_or(chars.len(), |end| end + 1);
+        spans.push(Span {
+            start,
+            end,
+            scope: Scope::Type,
+        });
+        return end;
+    }
+    let mut i = start;
+    let mut quote = None;
+    while let Some(&c) = chars.get(i) {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '=' => break,
+            None if is_word(c) || matches!(c, '-' | '.' | ' ' | '\t') => {}
+            None => return start,
+        }
+        i += 1;
+    }
+    if i == chars.len() {
+        return start;
+    }
+    let mut end = i;
+    while end > start && chars[end - 1].is_whitespace() {
+        end -= 1;
+    }
+    if end > start {
+        spans.push(Span {
+            start,
+            end,
+            scope: Scope::Key,
+        });
+    }
+    i
+}
+
+/// Where a Rust attribute starting at `at`, `#[` or `#![`, ends
+fn attribute(chars: &[char], at: usize) -> Option<usize> {
+    let open = if starts_with(chars, at, "#[") {
+        at + 1
+    } else if starts_with(chars, at, "#![") {
+        at + 2
+    } else {
+        return None;
+    };
+    let mut depth = 0;
+    for (i, &c) in chars.iter().enumerate().skip(open) {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(chars.len())
+}
+
+/// A Rust raw string opener at `at`, `r"`, `r#"` or with a `b` in
+/// front. Returns the number of `#` and where the inside starts.
+fn raw_string_opener(chars: &[char], at: usize) -> Option<(usize, usize)> {
+    let mut i = at;
+    if chars.get(i) == Some(&'b') {
+        i += 1;
+    }
+    if chars.get(i) != Some(&'r') {
+        return None;
+    }
+    i += 1;
+    let hashes = chars[i..].iter().take_while(|&&c| c == '#').count();
+    i += hashes;
+    (chars.get(i) == Some(&'"')).then_some((hashes, i + 1))
+}
+
+/// Where a Rust character literal or lifetime starting at `at` ends, and
+/// which of the two it is
+fn quote(chars: &[char], at: usize) -> (usize, Scope) {
+    match chars.get(at + 1) {
+        Some('\\') => {
+            let end = chars[at + 2..]
+                .iter()
+                .position(|&c| c == '\'')
+                .map_or(chars.len(), |end| at + 2 + end + 1);
+            (end, Scope::String)
+        }
+        Some(_) if chars.get(at + 2) == Some(&'\'') => (at + 3, Scope::String),
+        _ => (word_end(chars, at + 1), Scope::Label),
+    }
+}
+
+/// Where a number starting at `at` ends
+fn number(chars: &[char], at: usize) -> usize {
+    let mut i = at + 1;
+    while let Some(&c) = chars.get(i) {
+        let exponent = matches!(c, '+' | '-')
+            && matches!(chars[i - 1], 'e' | 'E')
+            && !starts_with(chars, at, "0x");
+        let fraction = c == '.'
+            && chars.get(i + 1).is_some_and(char::is_ascii_digit)
+            && !chars[at..i].contains(&'.');
+        if is_word(c) || exponent || fraction {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    i
+}
+
+/// A shell variable starting at `at`, `at` being on the `$`
+fn variable_token(chars: &[char], at: usize) -> Option<Token> {
+    let end = match chars.get(at + 1)? {
+        '{' => chars[at..]
+            .iter()
+            .position(|&c| c == '}')
+            .map_or(chars.len(), |end| at + end + 1),
+        c if is_word(*c) => word_end(chars, at + 1),
+        '#' | '?' | '@' | '$' | '!' | '*' | '-' => at + 2,
+        _ => return None,
+    };
+    Some(Token::new(end, Scope::Variable))
+}
+
+/// A token found in a line
+struct Token {
+    end: usize,
+    scope: Scope,
+    /// The state the next line starts in when the token goes on there
+    open: Option<State>,
+}
+
+impl Token {
+    fn new(end: usize, scope: Scope) -> Self {
+        Self {
+            end,
+            scope,
+            open: None,
+        }
+    }
+}
+
+/// A line or block comment starting at `at`
+fn comment(syntax: &Syntax, chars: &[char], at: usize) -> Option<Token> {
+    // `#` only starts a comment in shell scripts after a blank, think `$#`
+    let after_blank = at == 0 || chars[at - 1].is_whitespace();
+    if syntax
+        .line_comments
+        .iter()
+        .any(|comment| starts_with(chars, at, comment))
+        && (!syntax.variables || after_blank)
+    {
+        return Some(Token::new(chars.len(), Scope::Comment));
+    }
+    let (open, _) = syntax.block_comment?;
+    if !starts_with(chars, at, open) {
+        return None;
+    }
+    let from = at + open.chars().count();
+    let (end, depth) = block_comment(syntax, chars, from, 1);
+    Some(Token {
+        open: (depth > 0).then_some(State::Comment { depth }),
+        ..Token::new(end, Scope::Comment)
+    })
+}
+
+/// An attribute, raw string, character or lifetime in Rust
+fn rust_token(chars: &[char], at: usize) -> Option<Token> {
+    if let Some(end) = attribute(chars, at) {
+        return Some(Token::new(end, Scope::Attribute));
+    }
+    if let Some((hashes, from)) = raw_string_opener(chars, at) {
+        let (end, closed) = raw_string(chars, from, hashes);
+        return Some(Token {
+            open: (!closed).then_some(State::RawString { hashes }),
+            ..Token::new(end, Scope::String)
+        });
+    }
+    let quote_at = match chars[at] {
+        '\'' => at,
+        'b' if chars.get(at + 1) == Some(&'\'') => at + 1,
+        _ => return None,
+    };
+    let (end, scope) = quote(chars, quote_at);
+    Some(Token::new(end, scope))
+}
+
+/// A string starting at `at`, maybe behind a prefix like `b` or `rf`
+fn string_token(syntax: &Syntax, chars: &[char], at: usize) -> Option<Token> {
+    let word: String = chars[at..word_end(chars, at)].iter().collect();
+    let is_prefix = syntax
+        .string_prefixes
+        .iter()
+        .any(|prefix| prefix.eq_ignore_ascii_case(&word));
+    let prefix = if is_prefix { word.chars().count() } else { 0 };
+    let (index, rule) = syntax
+        .strings
+        .iter()
+        .enumerate()
+        .find(|(_, rule)| starts_with(chars, at + prefix, rule.open))?;
+    let from = at + prefix + rule.open.chars().count();
+    let (end, closed) = string(rule, chars, from);
+    let next = skip_blanks(chars, end);
+    let scope = if syntax.string_keys && chars.get(next) == Some(&':') {
+        Scope::Key
+    } else {
+        Scope::String
+    };
+    Some(Token {
+        open: (!closed && rule.multiline)
+            .then_some(State::String { rule: index }),
+        ..Token::new(end, scope)
+    })
+}
+
+/// A number starting at `at`, not in the middle of a word
+fn number_token(chars: &[char], at: usize) -> Option<Token> {
+    let c = chars[at];
+    let digit = c.is_ascii_digit()
+        || (c == '-' && chars.get(at + 1).is_some_and(char::is_ascii_digit));
+    (digit && (at == 0 || !is_word(chars[at - 1])))
+        .then(|| Token::new(number(chars, at), Scope::Number))
+}
+
+/// Classifies the word starting at `at`, `previous` being the word right
+/// before it. Returns where it ends and what it is, if anything special.
+fn word_token(
+    syntax: &Syntax,
+    chars: &[char],
+    at: usize,
+    previous: &str,
+) -> (usize, Option<Scope>) {
+    let end = word_end(chars, at);
+    let word: String = chars[at..end].iter().collect();
+    let word = word.as_str();
+    let next = skip_blanks(chars, end);
+    if syntax.keywords.contains(&word) {
+        (end, Some(Scope::Keyword))
+    } else if syntax.constants.contains(&word) {
+        (end, Some(Scope::Constant))
+    } else if syntax.rust
+        && chars.get(end) == Some(&'!')
+        && chars.get(end + 1) != Some(&'=')
+    {
+        (end + 1, Some(Scope::Macro))
+    } else if syntax.function_keywords.contains(&previous)
+        || chars.get(next) == Some(&'(')
+    {
+        (end, Some(Scope::Function))
+    } else if syntax.types.contains(&word)
+        || (syntax.capitalized_types && chars[at].is_uppercase())
+    {
+        (end, Some(Scope::Type))
+    } else {
+        (end, None)
+    }
+}
+
+/// Colors a `@decorator` at the start of the line. Returns where the
+/// rest of the line starts.
+fn decorator(chars: &[char], at: usize, spans: &mut Vec<Span>) -> usize {
+    let start = skip_blanks(chars, at);
+    if chars.get(start) != Some(&'@') {
+        return at;
+    }
+    let mut end = start + 1;
+    while chars.get(end).is_some_and(|&c| is_word(c) || c == '.') {
+        end += 1;
+    }
+    spans.push(Span {
+        start,
+        end,
+        scope: Scope::Attribute,
+    });
+    end
+}
+
+/// Splits one line of a language described by `syntax` into spans
+pub fn tokenize(
+    syntax: &Syntax,
+    line: &str,
+    state: State,
+) -> (Vec<Span>, State) {
+    let chars: Vec<char> = line.chars().collect();
+    let mut spans = Vec::new();
+    let (mut i, state) = resume(syntax, &chars, state, &mut spans);
+    if state != State::Normal {
+        return (spans, state);
+    }
+    if syntax.tables && i == 0 {
+        i = table_line(&chars, 0, &mut spans);
+    }
+    if syntax.decorators {
+        i = decorator(&chars, i, &mut spans);
+    }
+
+    // The word before, to spot function names after `fn` and the like
+    let mut previous = String::new();
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let token = comment(syntax, &chars, i)
+            .or_else(|| syntax.rust.then(|| rust_token(&chars, i)).flatten())
+            .or_else(|| {
+                let variable = syntax.variables && c == '$';
+                variable.then(|| variable_token(&chars, i)).flatten()
+            })
+            .or_else(|| string_token(syntax, &chars, i))
+            .or_else(|| number_token(&chars, i));
+        if let Some(token) = token {
+            spans.push(Span {
+                start: i,
+                end: token.end,
+                scope: token.scope,
+            });
+            if let Some(open) = token.open {
+                return (spans, open);
+            }
+            i = token.end;
+            previous.clear();
+            continue;
+        }
+        if is_word(c) {
+            let (end, scope) = word_token(syntax, &chars, i, &previous);
+            if let Some(scope) = scope {
+                spans.push(Span {
+                    start: i,
+                    end,
+                    scope,
+                });
+            }
+            previous = chars[i..end].iter().collect();
+            i = end;
+            continue;
+        }
+        previous.clear();
+        i += 1;
+    }
+    (spans, State::Normal)
+}
@@ -0,0 +1,121 @@
+use super::{Scope, Span, State};
+
+fn is_fence(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.starts_with("```") || trimmed.starts_with("~~~")
+}
+
+/// Length of the list or quote marker starting the line, if any
+fn marker(chars: &[char], at: usize) -> usize {
+    let rest = &chars[at..];
+    match rest {
+        ['-' | '*' | '+', ' ', ..] | ['>', ..] => 1,
+        _ => {
+            let digits = rest.iter().take_while(|c| c.is_ascii_digit()).count();
+            match rest.get(digits..digits + 2) {
+                Some(['.' | ')', ' ']) if digits > 0 => digits + 1,
+                _ => 0,
+            }
+        }
+    }
+}
+
+/// Where the run closed by `close` after `at` ends, past the closer
+fn closing(chars: &[char], at: usize, close: &[char]) -> Option<usize> {
+    (at..chars.len())
+        .find(|&i| chars[i..].starts_with(close))
+        .map(|i| i + close.len())
+}
+
+/// Spans for code, emphasis and links inside a line
+fn inline(chars: &[char], at: usize, spans: &mut Vec<Span>) {
+    let mut i = at;
+    while i < chars.len() {
+        let found = match chars[i] {
+            '`' => closing(chars, i + 1, &['`']).map(|end| (end, Scope::Code)),
+            '*' | '_' if chars.get(i + 1) == Some(&chars[i]) => {
+                let close = [chars[i], chars[i]];
+                closing(chars, i + 2, &close).map(|end| (end, Scope::Strong))
+            }
+            '*' | '_'
+                if chars.get(i + 1).is_some_and(|c| !c.is_whitespace())
+                    && (i == 0 || !chars[i - 1].is_alphanumeric()) =>
+            {
+                closing(chars, i + 1, &[chars[i]])
+                    .map(|end| (end, Scope::Emphasis))
+            }
+            '[' => closing(chars, i + 1, &[']', '('])
+                .and_then(|end| closing(chars, end, &[')']))
+                .map(|end| (end, Scope::Link)),
+            '<' if chars[i..].starts_with(&['<', 'h', 't', 't', 'p']) => {
+                closing(chars, i + 1, &['>']).map(|end| (end, Scope::Link))
+            }
+            _ => None,
+        };
+        match found {
+            Some((end, scope)) => {
+                spans.push(Span {
+                    start: i,
+                    end,
+                    scope,
+                });
+                i = end;
+            }
+            None => i += 1,
+        }
+    }
+}
+
+/// Splits one line of Markdown into spans. Lines are simple enough to
+/// not need the generic lexer, only fenced code blocks span lines.
+pub fn tokenize(line: &str, state: State) -> (Vec<Span>, State) {
+    let chars: Vec<char> = line.chars().collect();
+    let whole = |scope| {
+        vec![Span {
+            start: 0,
+            end: chars.len(),
+            scope,
+        }]
+    };
+    if state == State::Fence {
+        let state = if is_fence(line) {
+            State::Normal
+        } else {
+            State::Fence
+        };
+        return (whole(Scope::Code), state);
+    }
+    if is_fence(line) {
+        return (whole(Scope::Code), State::Fence);
+    }
+
+    let trimmed = line.trim_start();
+    let hashes = trimmed.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&hashes)
+        && trimmed[hashes..]
+            .chars()
+            .next()
+            .is_none_or(char::is_whitespace)
+    {
+        return (whole(Scope::Heading), State::Normal);
+    }
+    let rule: String = trimmed.chars().filter(|c| !c.is_whitespace()).collect();
+    if rule.len() >= 3
+        && ["-", "*", "_"].iter().any(|c| rule == c.repeat(rule.len()))
+    {
+        return (whole(Scope::Punctuation), State::Normal);
+    }
+
+    let mut spans = Vec::new();
+    let start = chars.iter().take_while(|c| c.is_whitespace()).count();
+    let length = marker(&chars, start);
+    if length > 0 {
+        spans.push(Span {
+            start,
+            end: start + length,
+            scope: Scope::Punctuation,
+        });
+    }
+    inline(&chars, start + length, &mut spans);
+    (spans, State::Normal)
+}
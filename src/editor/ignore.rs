@@ -0,0 +1,381 @@
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the files listing what git ignores
+const IGNORE_FILE: &str = ".gitignore";
+/// Always left out, whatever the ignore files say
+const GIT_DIR: &str = ".git";
+
+#[derive(Debug, Clone)]
+/// A path pattern written the way ignore files write them, like `*.rs`,
+/// `/target` or `src/**/*.rs`
+pub struct Glob {
+    regex: Regex,
+    /// Patterns with a `/` are matched against the path from where they
+    /// apply, the others against the name only
+    anchored: bool,
+}
+
+impl Glob {
+    /// `None` for an empty pattern or one that can not be read
+    pub fn new(pattern: &str) -> Option<Self> {
+        let anchored = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        if pattern.is_empty() {
+            return None;
+        }
+        let regex = Regex::new(&glob_to_regex(pattern)).ok()?;
+        Some(Self { regex, anchored })
+    }
+
+    /// Whether the glob matches the path `relative` with the file name
+    /// `name`
+    fn is_match(&self, relative: &str, name: &str) -> bool {
+        let target = if self.anchored { relative } else { name };
+        self.regex.is_match(target)
+    }
+
+    /// Whether `relative`, a path with `/` between its parts, or one of
+    /// the directories it is in matches
+    pub fn matches_path(&self, relative: &str) -> bool {
+        let mut end = 0;
+        relative.split('/').any(|name| {
+            end += name.len();
+            let matched = self.is_match(&relative[..end], name);
+            end += 1;
+            matched
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+/// One pattern of an ignore file
+struct Rule {
+    glob: Glob,
+    /// `!pattern`, takes back what the patterns before it ignored
+    negated: bool,
+    /// `pattern/`, only matches directories
+    directories_only: bool,
+}
+
+/// Turns a gitignore glob into a regular expression matching all of a
+/// path: `*` and `?` stop at `/`, `**` does not
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let rest: String = chars.clone().collect();
+                let Some(end) = rest.find(']') else {
+                    regex.push_str(r"\[");
+                    continue;
+                };
+                let class = &rest[..end];
+                // Past the class and its `]`
+                chars.nth(class.chars().count());
+                regex.push('[');
+                let class = match class.strip_prefix('!') {
+                    Some(class) => {
+                        regex.push('^');
+                        class
+                    }
+                    None => class,
+                };
+                for c in class.chars() {
+                    if matches!(c, '[' | '\\' | '&' | '~') {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    regex.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Parses a line of an ignore file, `None` for blank lines, comments and
+/// patterns that can not be read
+fn parse_rule(line: &str) -> Option<Rule> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (negated, pattern) = match line.strip_prefix('!') {
+        Some(pattern) => (true, pattern),
+        None => (false, line),
+    };
+    // `\#` and `\!` start patterns that would be read as something else
+    let pattern = match pattern.strip_prefix('\\') {
+        Some(rest) if rest.starts_with(['#', '!']) => rest,
+        _ => pattern,
+    };
+    let (directories_only, pattern) = match pattern.strip_suffix('/') {
+        Some(pattern) => (true, pattern),
+        None => (false, pattern),
+    };
+    Some(Rule {
+        glob: Glob::new(pattern)?,
+        negated,
+        directories_only,
+    })
+}
+
+/// `path` below `base` with `/` between its parts, as ignore files
+/// write them
+pub fn relative(path: &Path, base: &Path) -> Option<String> {
+    let parts: Vec<String> = path
+        .strip_prefix(base)
+        .ok()?
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().into()),
+            _ => None,
+        })
+        .collect();
+    Some(parts.join("/"))
+}
+
+#[derive(Debug, Clone)]
+/// What the `.gitignore` files of a directory tree leave out of listings
+pub struct Ignore {
+    /// The rules of each directory read so far that has any, outer
+    /// directories first
+    rules: Vec<(PathBuf, Vec<Rule>)>,
+    /// Every directory read so far, so none is read twice
+    entered: HashSet<PathBuf>,
+}
+
+impl Ignore {
+    /// The rules for `dir` and what is below it, from the `.gitignore`
+    /// files of the repository `dir` is in down to `dir` itself. Outside
+    /// a repository only the file in `dir` counts.
+    pub fn new(dir: &Path) -> Self {
+        let repository = dir
+            .ancestors()
+            .find(|ancestor| ancestor.join(GIT_DIR).exists());
+        let mut dirs: Vec<&Path> = match repository {
+            Some(repository) => dir
+                .ancestors()
+                .take_while(|ancestor| ancestor.starts_with(repository))
+                .collect(),
+            None => vec![dir],
+        };
+        dirs.reverse();
+        let mut ignore = Self {
+            rules: Vec::new(),
+            entered: HashSet::new(),
+        };
+        for dir in dirs {
+            ignore.enter(dir);
+        }
+        ignore
+    }
+
+    /// Reads the `.gitignore` of `dir`, for listing what is in it. Its
+    /// parent should have been entered first.
+    pub fn enter(&mut self, dir: &Path) {
+        if !self.entered.insert(dir.to_path_buf()) {
+            return;
+        }
+        let rules: Vec<Rule> = fs::read_to_string(dir.join(IGNORE_FILE))
+            .map(|text| text.lines().filter_map(parse_rule).collect())
+            .unwrap_or_default();
+        if !rules.is_empty() {
+            self.rules.push((dir.to_path_buf(), rules));
+        }
+    }
+
+    /// Whether `path` is left out. Only the path itself is looked at, not
+    /// the directories it is in, as listings do not go into ignored
+    /// directories.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Some(name) = path.file_name() else {
+            return false;
+        };
+        if name == GIT_DIR {
+            return true;
+        }
+        let name = name.to_string_lossy();
+        let mut ignored = false;
+        for (base, rules) in &self.rules {
+            let Some(relative) = relative(path, base) else {
+                continue;
+            };
+            for rule in rules {
+                if rule.directories_only && !is_dir {
+                    continue;
+                }
+                if rule.glob.is_match(&relative, &name) {
+                    ignored = !rule.negated;
+                }
+            }
+        }
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A repository in the temporary directory named after `name`, with
+    /// each of `files` holding its text
+    fn repository(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir()
+            .join(format!("r-edit-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(GIT_DIR)).unwrap();
+        for (path, text) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        root
+    }
+
+    /// The ones of `paths` below `root` that `ignore` leaves out, those
+    /// ending in `/` as directories
+    fn ignored<'a>(
+        ignore: &Ignore,
+        root: &Path,
+        paths: &[&'a str],
+    ) -> Vec<&'a str> {
+        paths
+            .iter()
+            .copied()
+            .filter(|path| {
+                let is_dir = path.ends_with('/');
+                ignore
+                    .is_ignored(&root.join(path.trim_end_matches('/')), is_dir)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn globs_match_names_or_anchored_paths() {
+        let cases = [
+            ("*.rs", "src/main.rs", true),
+            ("*.rs", "src/main.rs.bak", false),
+            ("ma?n.rs", "src/main.rs", true),
+            ("/target", "target/debug", true),
+            ("/target", "src/target", false),
+            ("doc/*.md", "doc/a.md", true),
+            ("doc/*.md", "doc/sub/a.md", false),
+            ("**/gen", "a/b/gen/x.rs", true),
+            ("a/**/b", "a/b", true),
+            ("a/**/b", "a/x/y/b", true),
+            ("a/**/b", "c/a/b", false),
+            ("logs/**", "logs/x/y", true),
+            ("[abc].txt", "b.txt", true),
+            ("[!abc].txt", "b.txt", false),
+            ("[!abc].txt", "d.txt", true),
+            (r"\*.txt", "*.txt", true),
+            (r"\*.txt", "a.txt", false),
+        ];
+        for (pattern, path, matches) in cases {
+            let glob = Glob::new(pattern).unwrap();
+            assert_eq!(glob.matches_path(path), matches, "{pattern} {path}");
+        }
+        assert!(Glob::new("").is_none());
+        assert!(Glob::new("/").is_none());
+    }
+
+    #[test]
+    fn ignore_files_negate_anchor_and_match_directories_only() {
+        let rules =
+            "# comment\n\n*.log\n!keep.log\n/build\nout/\n\\#hash\n\\!bang\n";
+        let root = repository("ignore-rules", &[(IGNORE_FILE, rules)]);
+        let ignore = Ignore::new(&root);
+        let paths = [
+            "a.log",
+            "src/b.log",
+            "keep.log",
+            "build/",
+            "src/build/",
+            "out/",
+            "out",
+            "src/out/",
+            "#hash",
+            "!bang",
+            "# comment",
+            ".git/",
+            "main.rs",
+        ];
+        assert_eq!(
+            ignored(&ignore, &root, &paths),
+            [
+                "a.log",
+                "src/b.log",
+                "build/",
+                "out/",
+                "src/out/",
+                "#hash",
+                "!bang",
+                ".git/"
+            ]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn nested_ignore_files_apply_below_their_directory() {
+        let root = repository(
+            "ignore-nested",
+            &[
+                (IGNORE_FILE, "*.log\n"),
+                ("sub/.gitignore", "*.tmp\n!keep.log\n/only-here\n"),
+            ],
+        );
+        let mut ignore = Ignore::new(&root);
+        ignore.enter(&root.join("sub"));
+        let paths = [
+            "a.log",
+            "a.tmp",
+            "only-here",
+            "sub/a.log",
+            "sub/keep.log",
+            "sub/a.tmp",
+            "sub/deeper/a.tmp",
+            "sub/only-here",
+            "sub/deeper/only-here",
+        ];
+        assert_eq!(
+            ignored(&ignore, &root, &paths),
+            [
+                "a.log",
+                "sub/a.log",
+                "sub/a.tmp",
+                "sub/deeper/a.tmp",
+                "sub/only-here"
+            ]
+        );
+
+        // Starting below the root still reads the files above
+        let below = Ignore::new(&root.join("sub"));
+        assert!(below.is_ignored(&root.join("sub/x.log"), false));
+        assert!(below.is_ignored(&root.join("sub/x.tmp"), false));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
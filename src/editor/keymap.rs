@@ -0,0 +1,205 @@
+use crate::editor::commands::Command;
+use crate::editor::keys::{format_keys, parse_keys};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::{BTreeMap, HashMap};
+
+/// Name to use in the configuration to remove a default binding
+pub const UNBOUND: &str = "none";
+
+/// Bindings every keymap starts from
+const DEFAULT_BINDINGS: &[(&str, Command)] = &[
+    ("<Left>", Command::MoveLeft),
+    ("<Right>", Command::MoveRight),
+    ("<Up>", Command::MoveUp),
+    ("<Down>", Command::MoveDown),
+    ("<Home>", Command::MoveLineStart),
+    ("<End>", Command::MoveLineEnd),
+    ("<PageUp>", Command::PageUp),
+    ("<PageDown>", Command::PageDown),
+    ("<Enter>", Command::InsertNewline),
+    ("<Tab>", Command::InsertTab),
+    ("<BS>", Command::DeleteBackward),
+    ("<C-s>", Command::Save),
+    ("<C-q>", Command::Quit),
+    ("<C-r>", Command::RecordMacro),
+    ("<C-e>", Command::PlayMacro),
+    ("<C-p>", Command::CommandPalette),
+    // Ctrl-Shift-P, for terminals that tell it apart
+    ("<C-P>", Command::CommandPalette),
+    ("<A-;>", Command::CommandLine),
+    ("<A-o>", Command::ExpandSelection),
+    ("<A-i>", Command::ShrinkSelection),
+    ("<A-u>", Command::ParentNode),
+    ("<A-n>", Command::NextSiblingNode),
+    ("<A-p>", Command::PreviousSiblingNode),
+    ("<A-z>", Command::ToggleFold),
+    ("<A-k>", Command::Hover),
+    ("<F12>", Command::GoToDefinition),
+    ("<S-F12>", Command::FindReferences),
+    ("<F2>", Command::RenameSymbol),
+    ("<A-f>", Command::FormatDocument),
+    ("<C-Space>", Command::Complete),
+    ("<C-w>s", Command::SplitHorizontal),
+    ("<C-w>v", Command::SplitVertical),
+    ("<C-w>c", Command::ClosePane),
+    ("<C-w>q", Command::ClosePane),
+    ("<C-w>h", Command::FocusPaneLeft),
+    ("<C-w>j", Command::FocusPaneDown),
+    ("<C-w>k", Command::FocusPaneUp),
+    ("<C-w>l", Command::FocusPaneRight),
+    ("<C-w>w", Command::FocusNextPane),
+    ("<C-w>+", Command::GrowPane),
+    ("<C-w>-", Command::ShrinkPane),
+    ("<C-w>>", Command::WidenPane),
+    ("<C-w><lt>", Command::NarrowPane),
+    ("<C-t>", Command::NewTab),
+    ("<A-w>", Command::CloseTab),
+    ("<C-PageDown>", Command::NextTab),
+    ("<C-PageUp>", Command::PreviousTab),
+    ("<C-S-PageDown>", Command::MoveTabRight),
+    ("<C-S-PageUp>", Command::MoveTabLeft),
+    ("<A-e>", Command::ToggleExplorer),
+    ("<C-o>", Command::FindFile),
+    ("<A-g>", Command::SearchInFiles),
+    ("<F4>", Command::NextResult),
+    ("<S-F4>", Command::PreviousResult),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+/// The part of a key press that matters for bindings. Shift is dropped
+/// for characters, it is already part of the character.
+struct Chord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl From<&KeyEvent> for Chord {
+    fn from(key: &KeyEvent) -> Self {
+        let mut modifiers = key.modifiers;
+        if let KeyCode::Char(_) = key.code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self {
+            code: key.code,
+            modifiers,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// What a sequence of keys means in a `Keymap`
+pub enum Lookup {
+    Command(Command),
+    /// The keys start one or more longer bindings, wait for more
+    Prefix,
+    Unbound,
+}
+
+/// Maps key sequences, like `<C-k><C-c>`, to commands
+pub struct Keymap {
+    bindings: HashMap<Vec<Chord>, Command>,
+}
+
+impl Keymap {
+    pub fn default() -> Self {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+        };
+        for (keys, command) in DEFAULT_BINDINGS {
+            let keys = parse_keys(keys).expect("default bindings parse");
+            keymap.bind(&keys, *command);
+        }
+        keymap
+    }
+
+    fn chords(keys: &[KeyEvent]) -> Vec<Chord> {
+        keys.iter().map(Chord::from).collect()
+    }
+
+    pub fn bind(&mut self, keys: &[KeyEvent], command: Command) {
+        self.bindings.insert(Self::chords(keys), command);
+    }
+
+    pub fn unbind(&mut self, keys: &[KeyEvent]) {
+        self.bindings.remove(&Self::chords(keys));
+    }
+
+    /// Overrides the defaults with bindings from the configuration, keys
+    /// in key notation to command names or `UNBOUND`. Returns a message
+    /// for each binding that could not be applied.
+    pub fn apply_overrides(
+        &mut self,
+        overrides: &BTreeMap<String, String>,
+    ) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (notation, name) in overrides {
+            let keys = match parse_keys(notation) {
+                Ok(keys) if !keys.is_empty() => keys,
+                Ok(_) => {
+                    errors.push(String::from("Empty key binding"));
+                    continue;
+                }
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            if name == UNBOUND {
+                self.unbind(&keys);
+            } else if let Some(command) = Command::from_name(name) {
+                self.bind(&keys, command);
+            } else {
+                errors.push(format!("Unknown command {name} for {notation}"));
+            }
+        }
+        errors
+    }
+
+    pub fn lookup(&self, keys: &[KeyEvent]) -> Lookup {
+        let chords = Self::chords(keys);
+        if let Some(command) = self.bindings.get(&chords) {
+            return Lookup::Command(*command);
+        }
+        let is_prefix = self.bindings.keys().any(|bound| {
+            bound.len() > chords.len() && bound.starts_with(&chords)
+        });
+        if is_prefix {
+            Lookup::Prefix
+        } else {
+            Lookup::Unbound
+        }
+    }
+
+    /// The key sequences bound to `command` in key notation, shortest
+    /// first
+    pub fn keys_for(&self, command: Command) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == command)
+            .map(|(chords, _)| {
+                let keys: Vec<KeyEvent> = chords
+                    .iter()
+                    .map(|chord| KeyEvent::new(chord.code, chord.modifiers))
+                    .collect();
+                format_keys(&keys)
+            })
+            .collect();
+        keys.sort_by(|a, b| a.len().cmp(&b.len()).then(a.cmp(b)));
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ctrl_s_saves_without_quitting() {
+        let keys = parse_keys("<C-s>").unwrap();
+        assert!(matches!(
+            Keymap::default().lookup(&keys),
+            Lookup::Command(Command::Save)
+        ));
+    }
+}
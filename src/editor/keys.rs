@@ -0,0 +1,190 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Names of the keys written as `<Name>` in key notation
+const NAMED_KEYS: [(&str, KeyCode); 17] = [
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Tab", KeyCode::Tab),
+    ("BackTab", KeyCode::BackTab),
+    ("BS", KeyCode::Backspace),
+    ("Del", KeyCode::Delete),
+    ("Ins", KeyCode::Insert),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Space", KeyCode::Char(' ')),
+    ("lt", KeyCode::Char('<')),
+];
+
+/// Formats a key the way Vim writes them: plain characters as is,
+/// everything else between angle brackets, e.g. `<C-s>` or `<Enter>`.
+pub fn format_key(key: &KeyEvent) -> String {
+    let mut prefix = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        prefix.push_str("C-");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        prefix.push_str("A-");
+    }
+    // Shift is part of the character itself for printable keys
+    let is_char = matches!(key.code, KeyCode::Char(_));
+    if key.modifiers.contains(KeyModifiers::SHIFT) && !is_char {
+        prefix.push_str("S-");
+    }
+
+    let name = NAMED_KEYS
+        .iter()
+        .find(|(_, code)| *code == key.code)
+        .map(|(name, _)| (*name).to_string());
+    let name = match (name, key.code) {
+        (Some(name), _) => name,
+        (None, KeyCode::Char(c)) => c.to_string(),
+        (None, KeyCode::F(n)) => format!("F{n}"),
+        (None, code) => format!("{code:?}"),
+    };
+
+    let is_plain = is_char && prefix.is_empty() && name.chars().count() == 1;
+    if is_plain {
+        name
+    } else {
+        format!("<{prefix}{name}>")
+    }
+}
+
+/// Formats a sequence of keys, see `format_key`
+pub fn format_keys(keys: &[KeyEvent]) -> String {
+    keys.iter().map(format_key).collect()
+}
+
+/// Parses a single `<...>` key, without the angle brackets
+fn parse_bracketed(inner: &str) -> Result<KeyEvent, String> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = inner;
+    loop {
+        let modifier = match rest.get(..2) {
+            Some("C-" | "c-") => KeyModifiers::CONTROL,
+            Some("A-" | "a-" | "M-" | "m-") => KeyModifiers::ALT,
+            Some("S-" | "s-") => KeyModifiers::SHIFT,
+            _ => break,
+        };
+        // A lone `-` after the prefix is the key itself, as in `<C-->`
+        if rest.len() == 2 {
+            break;
+        }
+        modifiers |= modifier;
+        rest = &rest[2..];
+    }
+
+    let code = if let Some((_, code)) = NAMED_KEYS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(rest))
+    {
+        *code
+    } else if let Some(n) = rest
+        .strip_prefix(['F', 'f'])
+        .and_then(|n| n.parse::<u8>().ok())
+    {
+        KeyCode::F(n)
+    } else {
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => return Err(format!("Unknown key <{inner}>")),
+        }
+    };
+
+    Ok(KeyEvent::new(code, modifiers))
+}
+
+/// Parses keys written in the notation produced by `format_keys`
+pub fn parse_keys(notation: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut keys = Vec::new();
+    let mut rest = notation;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<'
+            && let Some(end) = rest.find('>')
+            && end > 1
+        {
+            keys.push(parse_bracketed(&rest[1..end])?);
+            rest = &rest[end + 1..];
+            continue;
+        }
+        keys.push(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        rest = &rest[c.len_utf8()..];
+    }
+
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn formats_keys_the_way_vim_writes_them() {
+        let keys = [
+            key(KeyCode::Char('a'), KeyModifiers::NONE),
+            key(KeyCode::Char('A'), KeyModifiers::SHIFT),
+            key(KeyCode::Char('s'), KeyModifiers::CONTROL),
+            key(KeyCode::Char('<'), KeyModifiers::NONE),
+            key(KeyCode::Enter, KeyModifiers::NONE),
+            key(KeyCode::Left, KeyModifiers::SHIFT | KeyModifiers::ALT),
+            key(KeyCode::F(5), KeyModifiers::NONE),
+        ];
+        assert_eq!(format_keys(&keys), "aA<C-s><lt><Enter><A-S-Left><F5>");
+    }
+
+    #[test]
+    fn parses_what_it_formats() {
+        let keys = [
+            key(KeyCode::Char('q'), KeyModifiers::NONE),
+            key(KeyCode::Char(' '), KeyModifiers::NONE),
+            key(KeyCode::Char('<'), KeyModifiers::NONE),
+            key(KeyCode::Char('-'), KeyModifiers::CONTROL),
+            key(KeyCode::Backspace, KeyModifiers::CONTROL),
+            key(KeyCode::F(12), KeyModifiers::ALT),
+            key(KeyCode::Char('é'), KeyModifiers::NONE),
+        ];
+        assert_eq!(parse_keys(&format_keys(&keys)), Ok(keys.to_vec()));
+    }
+
+    #[test]
+    fn parses_names_and_modifiers_in_any_case() {
+        assert_eq!(
+            parse_keys("<c-ENTER><m-x>"),
+            Ok(vec![
+                key(KeyCode::Enter, KeyModifiers::CONTROL),
+                key(KeyCode::Char('x'), KeyModifiers::ALT),
+            ])
+        );
+    }
+
+    #[test]
+    fn takes_unclosed_and_empty_brackets_as_characters() {
+        let chars = |s: &str| {
+            s.chars()
+                .map(|c| key(KeyCode::Char(c), KeyModifiers::NONE))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(parse_keys("<>"), Ok(chars("<>")));
+        assert_eq!(parse_keys("a<b"), Ok(chars("a<b")));
+    }
+
+    #[test]
+    fn refuses_unknown_keys() {
+        assert_eq!(
+            parse_keys("a<Nope>"),
+            Err(String::from("Unknown key <Nope>"))
+        );
+    }
+}
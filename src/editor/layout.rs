@@ -0,0 +1,313 @@
+use crate::editor::caret::Direction;
+
+/// Share of a split's room its first side gets at first, in percent
+const EVEN_SHARE: usize = 50;
+const MIN_SHARE: usize = 10;
+const MAX_SHARE: usize = 90;
+/// How far resizing moves a split, in percent of its room
+const RESIZE_STEP: usize = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// A part of the screen, in cells
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// How a split puts its two sides
+pub enum Split {
+    /// One above the other, each with its own status line
+    Horizontal,
+    /// Side by side, with a border between them
+    Vertical,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Pane(usize),
+    Split {
+        split: Split,
+        /// Percent of the room for `first`
+        share: usize,
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+}
+
+impl Node {
+    fn contains(&self, pane: usize) -> bool {
+        match self {
+            Node::Pane(id) => *id == pane,
+            Node::Split { first, second, .. } => {
+                first.contains(pane) || second.contains(pane)
+            }
+        }
+    }
+
+    /// The panes in order, top to bottom and left to right
+    fn panes(&self, panes: &mut Vec<usize>) {
+        match self {
+            Node::Pane(id) => panes.push(*id),
+            Node::Split { first, second, .. } => {
+                first.panes(panes);
+                second.panes(panes);
+            }
+        }
+    }
+
+    /// Puts the panes into `area`, collecting where they and the borders
+    /// between side by side panes go
+    fn arrange(
+        &self,
+        area: Rect,
+        panes: &mut Vec<(usize, Rect)>,
+        borders: &mut Vec<Rect>,
+    ) {
+        match self {
+            Node::Pane(id) => panes.push((*id, area)),
+            Node::Split {
+                split: Split::Horizontal,
+                share,
+                first,
+                second,
+            } => {
+                let height = divide(area.height, *share);
+                first.arrange(Rect { height, ..area }, panes, borders);
+                let rest = Rect {
+                    y: area.y + height,
+                    height: area.height - height,
+                    ..area
+                };
+                second.arrange(rest, panes, borders);
+            }
+            Node::Split {
+                split: Split::Vertical,
+                share,
+                first,
+                second,
+            } => {
+                // One column for the border
+                let room = area.width.saturating_sub(1);
+                let width = divide(room, *share);
+                first.arrange(Rect { width, ..area }, panes, borders);
+                borders.push(Rect {
+                    x: area.x + width,
+                    width: area.width.min(1),
+                    ..area
+                });
+                let rest = Rect {
+                    x: area.x + width + 1,
+                    width: room - width,
+                    ..area
+                };
+                second.arrange(rest, panes, borders);
+            }
+        }
+    }
+
+    /// Removes `pane`, its sibling takes the place of their split.
+    /// Returns the first pane of the sibling.
+    fn remove(&mut self, pane: usize) -> Option<usize> {
+        let Node::Split { first, second, .. } = self else {
+            return None;
+        };
+        let kept = if **first == Node::Pane(pane) {
+            second
+        } else if **second == Node::Pane(pane) {
+            first
+        } else {
+            return first.remove(pane).or_else(|| second.remove(pane));
+        };
+        let kept = std::mem::replace(&mut **kept, Node::Pane(pane));
+        *self = kept;
+        let mut panes = Vec::new();
+        self.panes(&mut panes);
+        panes.first().copied()
+    }
+
+    /// Moves the closest split of kind `kind` above `pane` by `grow`
+    /// steps in favor of the side `pane` is on. Returns whether there
+    /// was such a split.
+    fn resize(&mut self, pane: usize, kind: Split, grow: bool) -> bool {
+        let Node::Split {
+            split,
+            share,
+            first,
+            second,
+        } = self
+        else {
+            return false;
+        };
+        let in_first = first.contains(pane);
+        if !in_first && !second.contains(pane) {
+            return false;
+        }
+        let inner = if in_first { first } else { second };
+        if inner.resize(pane, kind, grow) {
+            return true;
+        }
+        if *split != kind {
+            return false;
+        }
+        *share = if in_first == grow {
+            *share + RESIZE_STEP
+        } else {
+            share.saturating_sub(RESIZE_STEP)
+        }
+        .clamp(MIN_SHARE, MAX_SHARE);
+        true
+    }
+}
+
+/// The part of `room` that `share` percent of it is, leaving at least
+/// one cell for each side when there is room for that
+fn divide(room: usize, share: usize) -> usize {
+    if room < 2 {
+        return room;
+    }
+    (room * share / 100).clamp(1, room - 1)
+}
+
+/// How the screen is divided into panes: a tree of splits with the panes
+/// as leaves. Panes are known by the ids handed out here, and one of
+/// them has the focus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    root: Node,
+    focused: usize,
+    next_id: usize,
+}
+
+impl Layout {
+    /// A single pane, `0`
+    pub fn default() -> Self {
+        Self {
+            root: Node::Pane(0),
+            focused: 0,
+            next_id: 1,
+        }
+    }
+
+    pub fn focused(&self) -> usize {
+        self.focused
+    }
+
+    pub fn focus(&mut self, pane: usize) {
+        if self.root.contains(pane) {
+            self.focused = pane;
+        }
+    }
+
+    /// Every pane, top to bottom and left to right
+    pub fn panes(&self) -> Vec<usize> {
+        let mut panes = Vec::new();
+        self.root.panes(&mut panes);
+        panes
+    }
+
+    pub fn pane_count(&self) -> usize {
+        self.panes().len()
+    }
+
+    /// Splits the focused pane in two. The new pane, returned, goes above
+    /// or left of it; the focus stays.
+    pub fn split(&mut self, split: Split) -> usize {
+        let pane = self.next_id;
+        self.next_id += 1;
+        let mut node = &mut self.root;
+        while let Node::Split { first, second, .. } = node {
+            node = if first.contains(self.focused) {
+                first
+            } else {
+                second
+            };
+        }
+        let focused = std::mem::replace(node, Node::Pane(pane));
+        *node = Node::Split {
+            split,
+            share: EVEN_SHARE,
+            first: Box::new(Node::Pane(pane)),
+            second: Box::new(focused),
+        };
+        pane
+    }
+
+    /// Removes `pane` and gives its room to the pane or split next to it.
+    /// Returns the pane that should get the focus, `None` for the last
+    /// pane, which stays. The focus is left for the caller to move.
+    pub fn close(&mut self, pane: usize) -> Option<usize> {
+        self.root.remove(pane)
+    }
+
+    /// Where every pane goes in `area`
+    pub fn arrange(&self, area: Rect) -> Vec<(usize, Rect)> {
+        let mut panes = Vec::new();
+        self.root.arrange(area, &mut panes, &mut Vec::new());
+        panes
+    }
+
+    /// The borders between side by side panes in `area`, a column each
+    pub fn borders(&self, area: Rect) -> Vec<Rect> {
+        let mut borders = Vec::new();
+        self.root.arrange(area, &mut Vec::new(), &mut borders);
+        borders
+    }
+
+    /// The pane next to the focused one in `direction`, the closest of
+    /// those beside it and then the one best lined up with it
+    pub fn neighbor(&self, area: Rect, direction: Direction) -> Option<usize> {
+        let panes = self.arrange(area);
+        let (_, from) = panes.iter().find(|(id, _)| *id == self.focused)?;
+        let overlaps = |a: usize, a_len: usize, b: usize, b_len: usize| {
+            a < b + b_len && b < a + a_len
+        };
+        panes
+            .iter()
+            .filter(|(id, _)| *id != self.focused)
+            .filter_map(|(id, to)| {
+                let (gap, offset) = match direction {
+                    Direction::Left if to.x + to.width <= from.x => {
+                        (from.x - to.x - to.width, to.y.abs_diff(from.y))
+                    }
+                    Direction::Right if to.x >= from.x + from.width => {
+                        (to.x - from.x - from.width, to.y.abs_diff(from.y))
+                    }
+                    Direction::Up if to.y + to.height <= from.y => {
+                        (from.y - to.y - to.height, to.x.abs_diff(from.x))
+                    }
+                    Direction::Down if to.y >= from.y + from.height => {
+                        (to.y - from.y - from.height, to.x.abs_diff(from.x))
+                    }
+                    _ => return None,
+                };
+                let beside = match direction {
+                    Direction::Left | Direction::Right => {
+                        overlaps(to.y, to.height, from.y, from.height)
+                    }
+                    Direction::Up | Direction::Down => {
+                        overlaps(to.x, to.width, from.x, from.width)
+                    }
+                };
+                beside.then_some((gap, offset, *id))
+            })
+            .min()
+            .map(|(_, _, id)| id)
+    }
+
+    /// The pane after the focused one, back to the first after the last
+    pub fn next(&self) -> usize {
+        let panes = self.panes();
+        let index = panes.iter().position(|id| *id == self.focused);
+        index.map_or(self.focused, |index| panes[(index + 1) % panes.len()])
+    }
+
+    /// Makes the focused pane taller or wider, or with `grow` false
+    /// smaller, by moving the closest split of kind `split` around it.
+    /// Returns whether there was one.
+    pub fn resize(&mut self, split: Split, grow: bool) -> bool {
+        self.root.resize(self.focused, split, grow)
+    }
+}
@@ -45,8 +45,8 @@ impl log::Log for CustomLogger {
         let log_entry = format!("{} [{}] {}\n", time, record.level(), record.args());
 
         let mut file = self.log_file.lock().unwrap();
-        if let Err(e) = write!(file, "{}", log_entry) {
-            eprintln!("Failed to write to log file: {}", e);
+        if let Err(e) = write!(file, "{log_entry}") {
+            eprintln!("Failed to write to log file: {e}");
         }
     }
 
@@ -0,0 +1,652 @@
+pub mod client;
+pub mod protocol;
+pub mod transport;
+
+use crate::editor::buffer::Buffer;
+use crate::editor::completion::CompletionItem;
+use crate::editor::terminal::Location;
+use client::{Client, Message};
+use log::{debug, info};
+use protocol::{Encoding, FileLocation, TextEdit};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// How to start the language server of a language, e.g.
+/// `{ command = "rust-analyzer" }`
+pub struct ServerConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// How bad a diagnostic is, worst first
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    fn from_number(number: Option<u64>) -> Self {
+        match number {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Information,
+            Some(4) => Severity::Hint,
+            _ => Severity::Error,
+        }
+    }
+
+    /// What the gutter shows for it
+    pub fn sign(self) -> char {
+        match self {
+            Severity::Error => 'E',
+            Severity::Warning => 'W',
+            Severity::Information => 'I',
+            Severity::Hint => 'H',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A problem a server found in the text from `start` to `end`
+pub struct Diagnostic {
+    pub start: Location,
+    pub end: Location,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What can be asked of a server about a place in a document
+pub enum Request {
+    Hover,
+    Definition,
+    References,
+    /// Rename the symbol to the given name
+    Rename(String),
+    Format,
+    /// Complete the word at the position
+    Complete,
+}
+
+impl Request {
+    fn method(&self) -> &'static str {
+        match self {
+            Request::Hover => "textDocument/hover",
+            Request::Definition => "textDocument/definition",
+            Request::References => "textDocument/references",
+            Request::Rename(_) => "textDocument/rename",
+            Request::Format => "textDocument/formatting",
+            Request::Complete => "textDocument/completion",
+        }
+    }
+
+    /// The capability a server needs for the request
+    fn capability(&self) -> &'static str {
+        match self {
+            Request::Hover => "hoverProvider",
+            Request::Definition => "definitionProvider",
+            Request::References => "referencesProvider",
+            Request::Rename(_) => "renameProvider",
+            Request::Format => "documentFormattingProvider",
+            Request::Complete => "completionProvider",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Request::Hover => "hover",
+            Request::Definition => "go to definitions",
+            Request::References => "find references",
+            Request::Rename(_) => "rename",
+            Request::Format => "format",
+            Request::Complete => "complete",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The answer to a request, ready for the editor to act on
+pub enum Reply {
+    Hover(String),
+    Definition(Vec<FileLocation>),
+    References(Vec<FileLocation>),
+    /// Edits to make by file
+    Rename(Vec<(PathBuf, Vec<TextEdit>)>),
+    /// Edits for the document at `path` as it was at buffer `revision`
+    Format {
+        path: PathBuf,
+        revision: u64,
+        edits: Vec<TextEdit>,
+    },
+    /// Completions for the word at the caret in the document at `path`
+    Completion {
+        path: PathBuf,
+        items: Vec<CompletionItem>,
+    },
+    /// Something went wrong, for the message bar
+    Error(String),
+}
+
+/// A document a server was told about
+struct OpenDocument {
+    /// The language, which picks the server
+    language: String,
+    version: u64,
+    /// The text as the server has it, `None` until `didOpen` was sent
+    lines: Option<Vec<String>>,
+    /// The buffer revision `lines` are from
+    revision: u64,
+}
+
+/// A request sent and not answered yet
+struct Pending {
+    request: Request,
+    path: PathBuf,
+    revision: u64,
+}
+
+/// The language servers of the editor, one per configured language,
+/// started when a file of that language is opened
+pub struct Lsp {
+    servers: BTreeMap<String, ServerConfig>,
+    /// Running servers by language
+    clients: HashMap<String, Client>,
+    /// Languages whose server could not start, not tried again until the
+    /// configuration changes
+    failed: HashSet<String>,
+    /// Open documents by absolute path
+    documents: HashMap<PathBuf, OpenDocument>,
+    pending: HashMap<(String, u64), Pending>,
+    diagnostics: HashMap<PathBuf, Vec<Diagnostic>>,
+    /// The workspace servers are started in
+    root: PathBuf,
+}
+
+impl Lsp {
+    pub fn default() -> Self {
+        Self {
+            servers: BTreeMap::new(),
+            clients: HashMap::new(),
+            failed: HashSet::new(),
+            documents: HashMap::new(),
+            pending: HashMap::new(),
+            diagnostics: HashMap::new(),
+            root: std::env::current_dir().unwrap_or_default(),
+        }
+    }
+
+    /// Uses the servers in `servers` from now on. Returns whether they
+    /// changed, in which case all servers were stopped and documents
+    /// have to be opened again.
+    pub fn configure(
+        &mut self,
+        servers: &BTreeMap<String, ServerConfig>,
+    ) -> bool {
+        if self.servers == *servers {
+            return false;
+        }
+        self.shutdown();
+        self.servers = servers.clone();
+        true
+    }
+
+    /// Whether any server is running, so `poll` has something to do
+    pub fn is_active(&self) -> bool {
+        !self.clients.is_empty()
+    }
+
+    /// Whether a server takes care of the file at `path`
+    pub fn is_attached(&self, path: &Path) -> bool {
+        let Some(document) = self.documents.get(&absolute(path)) else {
+            return false;
+        };
+        self.clients.contains_key(&document.language)
+    }
+
+    /// What the server found wrong with the file at `path`
+    pub fn diagnostics(&self, path: &Path) -> &[Diagnostic] {
+        self.diagnostics
+            .get(&absolute(path))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Tells the server of `language` about the file at `path`, starting
+    /// the server first if needed. `sync` sends the text once the server
+    /// is ready. Languages without a server are left alone.
+    pub fn open(&mut self, path: &Path, language: &str) -> Result<(), String> {
+        let Some(config) = self.servers.get(language).cloned() else {
+            return Ok(());
+        };
+        if self.failed.contains(language) {
+            return Ok(());
+        }
+        let path = absolute(path);
+        self.close(&path);
+        if !self.clients.contains_key(language) {
+            match Client::start(language, &config, &self.root) {
+                Ok(client) => {
+                    self.clients.insert(language.to_string(), client);
+                }
+                Err(e) => {
+                    self.failed.insert(language.to_string());
+                    return Err(format!(
+                        "Could not start the {language} language server \
+                         {:?}: {e}",
+                        config.command
+                    ));
+                }
+            }
+        }
+        self.documents.insert(
+            path,
+            OpenDocument {
+                language: language.to_string(),
+                version: 0,
+                lines: None,
+                revision: 0,
+            },
+        );
+        Ok(())
+    }
+
+    /// Tells the server the file at `path` is not edited anymore
+    pub fn close(&mut self, path: &Path) {
+        let path = absolute(path);
+        self.diagnostics.remove(&path);
+        let Some(document) = self.documents.remove(&path) else {
+            return;
+        };
+        if document.lines.is_none() {
+            return;
+        }
+        if let Some(client) = self.clients.get_mut(&document.language) {
+            let params =
+                json!({ "textDocument": { "uri": protocol::uri(&path) } });
+            if let Err(e) = client.notify("textDocument/didClose", &params) {
+                debug!("Error closing {}: {e}", path.display());
+            }
+        }
+    }
+
+    /// Sends the server what changed in `buffer`, the contents of the
+    /// file at `path`, since the last time
+    pub fn sync(&mut self, path: &Path, buffer: &Buffer) {
+        let path = absolute(path);
+        let Some(document) = self.documents.get_mut(&path) else {
+            return;
+        };
+        let Some(client) = self.clients.get_mut(&document.language) else {
+            return;
+        };
+        if !client.is_ready() {
+            return;
+        }
+        let uri = protocol::uri(&path);
+        let sent = match &document.lines {
+            None => client.notify(
+                "textDocument/didOpen",
+                &json!({
+                    "textDocument": {
+                        "uri": uri,
+                        "languageId": document.language,
+                        "version": document.version,
+                        "text": text(&buffer.lines),
+                    }
+                }),
+            ),
+            Some(_) if document.revision == buffer.revision() => return,
+            Some(lines) if *lines == buffer.lines => {
+                document.revision = buffer.revision();
+                return;
+            }
+            Some(lines) => {
+                let change = if client.incremental() {
+                    change(lines, &buffer.lines)
+                } else {
+                    json!({ "text": text(&buffer.lines) })
+                };
+                document.version += 1;
+                client.notify(
+                    "textDocument/didChange",
+                    &json!({
+                        "textDocument": {
+                            "uri": uri,
+                            "version": document.version,
+                        },
+                        "contentChanges": [change],
+                    }),
+                )
+            }
+        };
+        match sent {
+            Ok(()) => {
+                document.lines = Some(buffer.lines.clone());
+                document.revision = buffer.revision();
+            }
+            Err(e) => debug!("Error syncing {}: {e}", path.display()),
+        }
+    }
+
+    /// Tells the server the file at `path` was saved
+    pub fn save(&mut self, path: &Path) {
+        let path = absolute(path);
+        let Some(document) = self.documents.get(&path) else {
+            return;
+        };
+        let Some(client) = self.clients.get_mut(&document.language) else {
+            return;
+        };
+        if document.lines.is_some() {
+            let params =
+                json!({ "textDocument": { "uri": protocol::uri(&path) } });
+            if let Err(e) = client.notify("textDocument/didSave", &params) {
+                debug!("Error saving {}: {e}", path.display());
+            }
+        }
+    }
+
+    /// Sends `request` about `at` in `buffer`, the contents of the file
+    /// at `path`. The answer comes out of `poll` later.
+    pub fn request(
+        &mut self,
+        request: Request,
+        path: &Path,
+        buffer: &Buffer,
+        at: Location,
+    ) -> Result<(), String> {
+        let path = absolute(path);
+        let Some(language) =
+            self.documents.get(&path).map(|d| d.language.clone())
+        else {
+            return Err(String::from("No language server for this file"));
+        };
+        let Some(client) = self.clients.get(&language) else {
+            return Err(String::from("No language server for this file"));
+        };
+        if !client.is_ready() {
+            return Err(format!("The {language} language server is starting"));
+        }
+        let capability = &client.capabilities()[request.capability()];
+        if capability.is_null() || *capability == Value::Bool(false) {
+            return Err(format!(
+                "The {language} language server can not {}",
+                request.description()
+            ));
+        }
+        self.sync(&path, buffer);
+
+        let Some(client) = self.clients.get_mut(&language) else {
+            return Err(String::from("No language server for this file"));
+        };
+        let document = json!({ "uri": protocol::uri(&path) });
+        let position = protocol::position(&buffer.lines, at, client.encoding());
+        let params = match &request {
+            Request::Hover | Request::Definition => json!({
+                "textDocument": document,
+                "position": position,
+            }),
+            Request::References => json!({
+                "textDocument": document,
+                "position": position,
+                "context": { "includeDeclaration": true },
+            }),
+            Request::Complete => json!({
+                "textDocument": document,
+                "position": position,
+                // Invoked, not set off by a trigger character
+                "context": { "triggerKind": 1 },
+            }),
+            Request::Rename(name) => json!({
+                "textDocument": document,
+                "position": position,
+                "newName": name,
+            }),
+            Request::Format => json!({
+                "textDocument": document,
+                "options": { "tabSize": 4, "insertSpaces": true },
+            }),
+        };
+        let id = client
+            .request(request.method(), &params)
+            .map_err(|e| format!("Language server error: {e}"))?;
+        self.pending.insert(
+            (language, id),
+            Pending {
+                request,
+                path,
+                revision: buffer.revision(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Handles what the servers sent since the last call, returns the
+    /// answers to requests
+    pub fn poll(&mut self) -> Vec<Reply> {
+        let mut replies = Vec::new();
+        let mut exited = Vec::new();
+        let languages: Vec<String> = self.clients.keys().cloned().collect();
+        for language in languages {
+            let Some(client) = self.clients.get_mut(&language) else {
+                continue;
+            };
+            let messages = client.receive();
+            let encoding = client.encoding();
+            if !client.is_running() {
+                exited.push(language.clone());
+            }
+            for message in messages {
+                match message {
+                    Message::Response { id, result } => {
+                        let Some(pending) =
+                            self.pending.remove(&(language.clone(), id))
+                        else {
+                            continue;
+                        };
+                        replies.push(match result {
+                            Ok(result) => reply(pending, &result, encoding),
+                            Err(e) => Reply::Error(format!(
+                                "Language server error: {e}"
+                            )),
+                        });
+                    }
+                    Message::Notification { method, params } => {
+                        self.notification(&method, &params, encoding);
+                    }
+                }
+            }
+        }
+        for language in exited {
+            info!("The {language} language server stopped");
+            self.clients.remove(&language);
+            self.failed.insert(language.clone());
+            self.pending.retain(|(pending, _), _| *pending != language);
+            let paths: Vec<PathBuf> = self
+                .documents
+                .iter()
+                .filter(|(_, document)| document.language == language)
+                .map(|(path, _)| path.clone())
+                .collect();
+            for path in paths {
+                self.documents.remove(&path);
+                self.diagnostics.remove(&path);
+            }
+            replies.push(Reply::Error(format!(
+                "The {language} language server stopped"
+            )));
+        }
+        replies
+    }
+
+    fn notification(
+        &mut self,
+        method: &str,
+        params: &Value,
+        encoding: Encoding,
+    ) {
+        match method {
+            "textDocument/publishDiagnostics" => {
+                let Some(path) =
+                    params["uri"].as_str().and_then(protocol::path)
+                else {
+                    return;
+                };
+                let Some(lines) = self
+                    .documents
+                    .get(&path)
+                    .and_then(|document| document.lines.as_ref())
+                else {
+                    return;
+                };
+                let mut diagnostics: Vec<Diagnostic> = params["diagnostics"]
+                    .as_array()
+                    .map(Vec::as_slice)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|diagnostic| {
+                        let (start, end) =
+                            protocol::range(&diagnostic["range"], encoding)?;
+                        Some(Diagnostic {
+                            start: start.location(lines),
+                            end: end.location(lines),
+                            severity: Severity::from_number(
+                                diagnostic["severity"].as_u64(),
+                            ),
+                            message: diagnostic["message"]
+                                .as_str()
+                                .unwrap_or("")
+                                .to_string(),
+                        })
+                    })
+                    .collect();
+                diagnostics.sort_by_key(|d| (d.start.y, d.start.x, d.severity));
+                self.diagnostics.insert(path, diagnostics);
+            }
+            "window/showMessage" | "window/logMessage" => {
+                info!("Language server: {}", params["message"]);
+            }
+            _ => debug!("Unhandled notification {method}"),
+        }
+    }
+
+    /// Stops every server
+    pub fn shutdown(&mut self) {
+        for (_, mut client) in self.clients.drain() {
+            client.shutdown();
+        }
+        self.failed.clear();
+        self.documents.clear();
+        self.pending.clear();
+        self.diagnostics.clear();
+    }
+}
+
+/// `path` made absolute, the way documents are told apart
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The text of a document as the protocol has it, every line ending
+/// in `\n`
+fn text(lines: &[String]) -> String {
+    lines.iter().flat_map(|line| [line, "\n"]).collect()
+}
+
+/// The change turning `old` into `new` as one range of whole lines,
+/// everything between the lines both start and end with
+fn change(old: &[String], new: &[String]) -> Value {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    json!({
+        "range": {
+            "start": { "line": prefix, "character": 0 },
+            "end": { "line": old.len() - suffix, "character": 0 },
+        },
+        "text": text(&new[prefix..new.len() - suffix]),
+    })
+}
+
+fn reply(pending: Pending, result: &Value, encoding: Encoding) -> Reply {
+    match pending.request {
+        Request::Hover => {
+            let text = protocol::hover_text(result);
+            if text.is_empty() {
+                Reply::Error(String::from("Nothing to show here"))
+            } else {
+                Reply::Hover(text)
+            }
+        }
+        Request::Definition => {
+            Reply::Definition(protocol::locations(result, encoding))
+        }
+        Request::References => {
+            Reply::References(protocol::locations(result, encoding))
+        }
+        Request::Rename(_) => {
+            Reply::Rename(protocol::workspace_edit(result, encoding))
+        }
+        Request::Format => Reply::Format {
+            path: pending.path,
+            revision: pending.revision,
+            edits: protocol::text_edits(result, encoding),
+        },
+        Request::Complete => Reply::Completion {
+            path: pending.path,
+            items: protocol::completion_items(result),
+        },
+    }
+}
+
+/// Makes `edits` to `buffer`. They must not overlap and are all meant
+/// for the text before any of them is made, like servers send them.
+pub fn apply_edits(buffer: &mut Buffer, edits: &[TextEdit]) {
+    let mut edits: Vec<(Location, Location, bool, &str)> = edits
+        .iter()
+        .map(|edit| {
+            let start = edit.start.location(&buffer.lines);
+            let end = edit.end.location(&buffer.lines);
+            // The end of the text, past the line break of the last line
+            let to_end = edit.end.line >= buffer.lines.len();
+            (start, end, to_end, edit.new_text.as_str())
+        })
+        .collect();
+    // Back to front, so earlier locations stay where they are
+    edits.sort_by_key(|(start, end, ..)| {
+        std::cmp::Reverse((start.y, start.x, end.y, end.x))
+    });
+    for (start, end, to_end, new_text) in edits {
+        let mut new_text = new_text.to_string();
+        let mut start = start;
+        if start.y >= buffer.lines.len() && !buffer.lines.is_empty() {
+            // Adding lines to the end goes after the last line break
+            let y = buffer.lines.len() - 1;
+            start = Location {
+                x: buffer.line_len(y),
+                y,
+            };
+            new_text.insert(0, '\n');
+        }
+        if to_end || start.y >= buffer.lines.len() {
+            // The buffer has no line break after its last line to keep
+            if new_text.ends_with('\n') {
+                new_text.pop();
+            }
+        }
+        if (end.y, end.x) > (start.y, start.x) {
+            buffer.delete(start, end);
+        }
+        buffer.insert_str(&new_text, start);
+    }
+}
@@ -0,0 +1,342 @@
+use crate::editor::lsp::ServerConfig;
+use crate::editor::lsp::protocol::{Encoding, uri};
+use crate::editor::lsp::transport::{read_message, write_message};
+use crate::editor::recovery;
+use log::{debug, info};
+use serde_json::{Value, json};
+use std::io::{BufReader, Error, ErrorKind};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{Receiver, TryRecvError, channel};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a server gets to shut down before it is killed
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+/// What a server sent that the client does not handle itself
+pub enum Message {
+    /// The answer to request `id`, or the error it failed with
+    Response {
+        id: u64,
+        result: Result<Value, String>,
+    },
+    Notification {
+        method: String,
+        params: Value,
+    },
+}
+
+/// A language server running as a child process, talking JSON-RPC over
+/// its stdin and stdout. Messages are read on a thread of their own and
+/// picked up with `receive`.
+pub struct Client {
+    /// Name of the server for messages, the language it serves
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<Value>,
+    next_id: u64,
+    /// The `initialize` request until the server answered it
+    initializing: Option<u64>,
+    capabilities: Value,
+    encoding: Encoding,
+    /// Set once the server stopped, whatever the reason
+    exited: bool,
+}
+
+impl Client {
+    /// Starts the server of `config` and sends it `initialize` for the
+    /// workspace at `root`
+    pub fn start(
+        name: &str,
+        config: &ServerConfig,
+        root: &Path,
+    ) -> Result<Self, Error> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) =
+            (child.stdin.take(), child.stdout.take())
+        else {
+            return Err(Error::other("no pipes to the language server"));
+        };
+
+        let (sender, messages) = channel();
+        let thread_name = name.to_string();
+        recovery::spawn_worker(&format!("{name} lsp"), move || {
+            let mut reader = BufReader::new(stdout);
+            loop {
+                match read_message(&mut reader) {
+                    Ok(Some(message)) => {
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        debug!("Error reading from {thread_name} server: {e}");
+                        break;
+                    }
+                }
+            }
+        });
+
+        info!("Started {name} language server: {}", config.command);
+        let mut client = Self {
+            name: name.to_string(),
+            child,
+            stdin,
+            messages,
+            next_id: 0,
+            initializing: None,
+            capabilities: Value::Null,
+            encoding: Encoding::Utf16,
+            exited: false,
+        };
+        let root_uri = uri(root);
+        let id = client.request(
+            "initialize",
+            &json!({
+                "processId": std::process::id(),
+                "rootUri": root_uri,
+                "workspaceFolders": [{
+                    "uri": root_uri,
+                    "name": root.file_name().map_or_else(
+                        || root.to_string_lossy(),
+                        |name| name.to_string_lossy(),
+                    ),
+                }],
+                "capabilities": {
+                    "general": { "positionEncodings": ["utf-8", "utf-16"] },
+                    "textDocument": {
+                        "synchronization": { "didSave": true },
+                        "hover": { "contentFormat": ["plaintext", "markdown"] },
+                        "definition": { "linkSupport": true },
+                        "references": {},
+                        "rename": {},
+                        "formatting": {},
+                        "completion": {
+                            "completionItem": {
+                                "snippetSupport": false,
+                                "documentationFormat": ["plaintext", "markdown"],
+                            },
+                        },
+                        "publishDiagnostics": {},
+                    },
+                    "workspace": {
+                        "workspaceEdit": { "documentChanges": true },
+                        "configuration": true,
+                    },
+                },
+            }),
+        )?;
+        client.initializing = Some(id);
+        Ok(client)
+    }
+
+    /// Whether the server answered `initialize` and takes requests
+    pub fn is_ready(&self) -> bool {
+        self.initializing.is_none() && !self.exited
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.exited
+    }
+
+    /// What the server said it can do in its `initialize` result
+    pub fn capabilities(&self) -> &Value {
+        &self.capabilities
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Whether the server takes changes to documents as ranges, instead
+    /// of the whole text every time
+    pub fn incremental(&self) -> bool {
+        let sync = &self.capabilities["textDocumentSync"];
+        let kind = sync.as_u64().or_else(|| sync["change"].as_u64());
+        kind == Some(2)
+    }
+
+    /// Sends request `method`, returns its id to match the response with
+    pub fn request(
+        &mut self,
+        method: &str,
+        params: &Value,
+    ) -> Result<u64, Error> {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        }))?;
+        Ok(id)
+    }
+
+    pub fn notify(
+        &mut self,
+        method: &str,
+        params: &Value,
+    ) -> Result<(), Error> {
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        }))
+    }
+
+    fn send(&mut self, message: &Value) -> Result<(), Error> {
+        if self.exited {
+            return Err(Error::new(
+                ErrorKind::BrokenPipe,
+                format!("the {} language server is not running", self.name),
+            ));
+        }
+        debug!("To {} server: {message}", self.name);
+        let sent = write_message(&mut self.stdin, message);
+        if sent.is_err() {
+            self.exited = true;
+        }
+        sent
+    }
+
+    /// The messages the server sent since the last call. Requests from
+    /// the server are answered right away and the answer to `initialize`
+    /// is handled here.
+    pub fn receive(&mut self) -> Vec<Message> {
+        let mut received = Vec::new();
+        loop {
+            let message = match self.messages.try_recv() {
+                Ok(message) => message,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.exited {
+                        info!("The {} language server exited", self.name);
+                    }
+                    self.exited = true;
+                    break;
+                }
+            };
+            debug!("From {} server: {message}", self.name);
+            if let Some(message) = self.handle(&message) {
+                received.push(message);
+            }
+        }
+        received
+    }
+
+    fn handle(&mut self, message: &Value) -> Option<Message> {
+        let method = message["method"].as_str().map(String::from);
+        match (method, message.get("id")) {
+            (Some(method), Some(id)) => {
+                let result = if method == "workspace/configuration" {
+                    // Nothing configured, one null per item asked for
+                    let items = message["params"]["items"]
+                        .as_array()
+                        .map_or(0, Vec::len);
+                    Value::Array(vec![Value::Null; items])
+                } else {
+                    Value::Null
+                };
+                let response = json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": result,
+                });
+                if let Err(e) = self.send(&response) {
+                    debug!("Error answering {method}: {e}");
+                }
+                None
+            }
+            (Some(method), None) => Some(Message::Notification {
+                method,
+                params: message["params"].clone(),
+            }),
+            (None, Some(id)) => {
+                let id = id.as_u64()?;
+                let result = match message.get("error") {
+                    Some(error) => Err(error["message"]
+                        .as_str()
+                        .unwrap_or("unknown error")
+                        .to_string()),
+                    None => Ok(message["result"].clone()),
+                };
+                if self.initializing == Some(id) {
+                    self.initialized(result);
+                    return None;
+                }
+                Some(Message::Response { id, result })
+            }
+            (None, None) => None,
+        }
+    }
+
+    fn initialized(&mut self, result: Result<Value, String>) {
+        self.initializing = None;
+        match result {
+            Ok(result) => {
+                self.capabilities = result["capabilities"].clone();
+                self.encoding = Encoding::from_capabilities(&self.capabilities);
+                info!("The {} language server is ready", self.name);
+                if let Err(e) = self.notify("initialized", &json!({})) {
+                    debug!("Error sending initialized: {e}");
+                }
+            }
+            Err(e) => {
+                info!("The {} language server failed to start: {e}", self.name);
+                self.exited = true;
+            }
+        }
+    }
+
+    /// Asks the server to shut down and exit, and kills it if it takes
+    /// too long
+    pub fn shutdown(&mut self) {
+        if !self.exited {
+            info!("Shutting down the {} language server", self.name);
+            let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+            if let Ok(id) = self.request("shutdown", &Value::Null) {
+                while let Some(left) =
+                    deadline.checked_duration_since(Instant::now())
+                {
+                    match self.messages.recv_timeout(left) {
+                        Ok(message) if message["id"].as_u64() == Some(id) => {
+                            break;
+                        }
+                        Ok(_) => {}
+                        Err(_) => break,
+                    }
+                }
+            }
+            let _ = self.notify("exit", &Value::Null);
+            self.exited = true;
+            while Instant::now() < deadline {
+                if let Ok(Some(_)) = self.child.try_wait() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        if let Ok(None) = self.child.try_wait() {
+            debug!("Killing the {} language server", self.name);
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
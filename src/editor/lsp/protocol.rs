@@ -0,0 +1,374 @@
+use crate::editor::completion::CompletionItem;
+use crate::editor::terminal::Location;
+use serde_json::{Value, json};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// What the `character` of a position counts
+pub enum Encoding {
+    /// Bytes of UTF-8
+    Utf8,
+    /// UTF-16 code units, the default of the protocol
+    Utf16,
+}
+
+impl Encoding {
+    /// The encoding a server picked in its capabilities
+    pub fn from_capabilities(capabilities: &Value) -> Self {
+        match capabilities["positionEncoding"].as_str() {
+            Some("utf-8") => Encoding::Utf8,
+            _ => Encoding::Utf16,
+        }
+    }
+
+    /// How far into `line` character `x` is
+    pub fn character(self, line: &str, x: usize) -> usize {
+        line.chars()
+            .take(x)
+            .map(|c| match self {
+                Encoding::Utf8 => c.len_utf8(),
+                Encoding::Utf16 => c.len_utf16(),
+            })
+            .sum()
+    }
+
+    /// The character of `line` at `character`, the inverse of `character`
+    pub fn column(self, line: &str, character: usize) -> usize {
+        let mut units = 0;
+        for (x, c) in line.chars().enumerate() {
+            if units >= character {
+                return x;
+            }
+            units += match self {
+                Encoding::Utf8 => c.len_utf8(),
+                Encoding::Utf16 => c.len_utf16(),
+            };
+        }
+        line.chars().count()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// A position as the server sent it, only meaningful next to the text
+/// it was meant for
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+    pub encoding: Encoding,
+}
+
+impl Position {
+    fn parse(value: &Value, encoding: Encoding) -> Option<Self> {
+        let number =
+            |key| value[key].as_u64().and_then(|n| usize::try_from(n).ok());
+        Some(Self {
+            line: number("line")?,
+            character: number("character")?,
+            encoding,
+        })
+    }
+
+    /// Where the position is in `lines`
+    pub fn location(self, lines: &[String]) -> Location {
+        let x = lines
+            .get(self.line)
+            .map_or(0, |line| self.encoding.column(line, self.character));
+        Location { x, y: self.line }
+    }
+}
+
+/// `location` in `lines` as a protocol position
+pub fn position(
+    lines: &[String],
+    location: Location,
+    encoding: Encoding,
+) -> Value {
+    let character = lines
+        .get(location.y)
+        .map_or(0, |line| encoding.character(line, location.x));
+    json!({ "line": location.y, "character": character })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A place in a file the server pointed at, e.g. a definition
+pub struct FileLocation {
+    pub path: PathBuf,
+    pub start: Position,
+    pub end: Position,
+}
+
+impl FileLocation {
+    /// The start of the file at `path`
+    pub fn file(path: PathBuf) -> Self {
+        let start = Position {
+            line: 0,
+            character: 0,
+            encoding: Encoding::Utf8,
+        };
+        Self {
+            path,
+            start,
+            end: start,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Replace the text from `start` to `end` with `new_text`
+pub struct TextEdit {
+    pub start: Position,
+    pub end: Position,
+    pub new_text: String,
+}
+
+/// The start and end of a protocol range
+pub fn range(
+    value: &Value,
+    encoding: Encoding,
+) -> Option<(Position, Position)> {
+    Some((
+        Position::parse(&value["start"], encoding)?,
+        Position::parse(&value["end"], encoding)?,
+    ))
+}
+
+/// Characters a URI can hold as they are
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric()
+        || matches!(byte, b'-' | b'.' | b'_' | b'~' | b'/')
+}
+
+/// The `file://` URI of `path`, which should be absolute
+pub fn uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if is_unreserved(byte) {
+            uri.push(char::from(byte));
+        } else {
+            let _ = write!(uri, "%{byte:02X}");
+        }
+    }
+    uri
+}
+
+/// The path of a `file://` URI
+pub fn path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let decoded = (encoded[i] == b'%')
+            .then(|| encoded.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| {
+                u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+            });
+        if let Some(byte) = decoded {
+            bytes.push(byte);
+            i += 3;
+        } else {
+            bytes.push(encoded[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// The text of a hover result, from any of the shapes the protocol
+/// allows for it
+pub fn hover_text(result: &Value) -> String {
+    fn text(contents: &Value) -> String {
+        match contents {
+            Value::String(text) => text.clone(),
+            Value::Array(items) => items
+                .iter()
+                .map(text)
+                .filter(|text| !text.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            Value::Object(object) => match object.get("language") {
+                Some(language) => format!(
+                    "```{}\n{}\n```",
+                    language.as_str().unwrap_or(""),
+                    object["value"].as_str().unwrap_or("")
+                ),
+                None => object["value"].as_str().unwrap_or("").to_string(),
+            },
+            _ => String::new(),
+        }
+    }
+    text(&result["contents"]).trim().to_string()
+}
+
+/// The locations in a definition or references result: a location, a
+/// list of them or a list of location links
+pub fn locations(result: &Value, encoding: Encoding) -> Vec<FileLocation> {
+    let items = match result {
+        Value::Array(items) => items.as_slice(),
+        Value::Object(_) => std::slice::from_ref(result),
+        _ => &[],
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let uri = item["uri"].as_str().or(item["targetUri"].as_str())?;
+            let range = match item.get("targetSelectionRange") {
+                Some(range) => range,
+                None => &item["range"],
+            };
+            let (start, end) = self::range(range, encoding)?;
+            Some(FileLocation {
+                path: path(uri)?,
+                start,
+                end,
+            })
+        })
+        .collect()
+}
+
+/// The edits of a list of text edits, e.g. a formatting result
+pub fn text_edits(result: &Value, encoding: Encoding) -> Vec<TextEdit> {
+    result
+        .as_array()
+        .map(|edits| {
+            edits
+                .iter()
+                .filter_map(|edit| {
+                    let (start, end) = range(&edit["range"], encoding)?;
+                    Some(TextEdit {
+                        start,
+                        end,
+                        new_text: edit["newText"].as_str()?.to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The edits of a workspace edit by file, from either its `changes` or
+/// its `documentChanges`
+pub fn workspace_edit(
+    result: &Value,
+    encoding: Encoding,
+) -> Vec<(PathBuf, Vec<TextEdit>)> {
+    let mut files = Vec::new();
+    if let Some(changes) = result["changes"].as_object() {
+        for (uri, edits) in changes {
+            if let Some(path) = path(uri) {
+                files.push((path, text_edits(edits, encoding)));
+            }
+        }
+    }
+    if let Some(changes) = result["documentChanges"].as_array() {
+        for change in changes {
+            let uri = change["textDocument"]["uri"].as_str();
+            if let Some(path) = uri.and_then(path) {
+                files.push((path, text_edits(&change["edits"], encoding)));
+            }
+        }
+    }
+    files
+}
+
+/// The letter the menu shows for a kind of completion item
+fn kind_icon(kind: Option<u64>) -> char {
+    match kind {
+        Some(2) => 'm',
+        Some(3) => 'f',
+        Some(4) => 'c',
+        Some(5 | 10) => 'p',
+        Some(6) => 'v',
+        Some(7 | 22) => 'S',
+        Some(8) => 'I',
+        Some(9) => 'M',
+        Some(13) => 'E',
+        Some(14) => 'k',
+        Some(15) => 's',
+        Some(20 | 21) => 'C',
+        Some(25) => 'T',
+        _ => 't',
+    }
+}
+
+/// `text` without the tab stops and placeholders of a snippet, keeping
+/// the placeholders' text: `foo(${1:x})$0` becomes `foo(x)`
+fn strip_snippet(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut chars = text.chars().peekable();
+    // Placeholders open, their `}` is dropped too
+    let mut open = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => stripped.extend(chars.next()),
+            '$' if chars.peek().is_some_and(char::is_ascii_digit) => {
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+            }
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+                if chars.next_if_eq(&':').is_some() {
+                    open += 1;
+                } else {
+                    chars.next_if_eq(&'}');
+                }
+            }
+            '}' if open > 0 => open -= 1,
+            c => stripped.push(c),
+        }
+    }
+    stripped
+}
+
+/// The items of a completion result, either a list of them or a
+/// completion list, in the order the server wants them shown
+pub fn completion_items(result: &Value) -> Vec<CompletionItem> {
+    let items = result
+        .as_array()
+        .or_else(|| result["items"].as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let mut sorted: Vec<(&str, CompletionItem)> = items
+        .iter()
+        .filter_map(|item| {
+            let label = item["label"].as_str()?.to_string();
+            let text = item["textEdit"]["newText"]
+                .as_str()
+                .or_else(|| item["insertText"].as_str())
+                .unwrap_or(&label);
+            let insert_text = if item["insertTextFormat"].as_u64() == Some(2) {
+                strip_snippet(text)
+            } else {
+                text.to_string()
+            };
+            let documentation = [
+                item["detail"].as_str().unwrap_or(""),
+                item["documentation"]
+                    .as_str()
+                    .or_else(|| item["documentation"]["value"].as_str())
+                    .unwrap_or(""),
+            ]
+            .iter()
+            .map(|text| text.trim())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+            let sort_text = item["sortText"].as_str().unwrap_or("");
+            Some((
+                sort_text,
+                CompletionItem {
+                    icon: kind_icon(item["kind"].as_u64()),
+                    insert_text,
+                    filter_text: item["filterText"].as_str().map(String::from),
+                    documentation: (!documentation.is_empty())
+                        .then_some(documentation),
+                    label,
+                },
+            ))
+        })
+        .collect();
+    // Stable, items without a sort text keep their order
+    sorted.sort_by_key(|(sort_text, _)| *sort_text);
+    sorted.into_iter().map(|(_, item)| item).collect()
+}
@@ -0,0 +1,89 @@
+use serde_json::Value;
+use std::io::{BufRead, Error, ErrorKind, Write};
+
+/// Longest message body taken, anything longer is a broken server
+const MAX_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Writes `message` with the `Content-Length` header the protocol wants
+pub fn write_message(
+    writer: &mut impl Write,
+    message: &Value,
+) -> Result<(), Error> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+/// Reads the next message, `None` once the other side closed the stream
+pub fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>, Error> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "message without a Content-Length",
+        ));
+    };
+    if length > MAX_LENGTH {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("message of {length} bytes, at most {MAX_LENGTH} taken"),
+        ));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_what_it_writes() {
+        let mut written = Vec::new();
+        write_message(&mut written, &json!({ "id": 1 })).unwrap();
+        write_message(&mut written, &json!({ "id": 2 })).unwrap();
+        let mut reader = written.as_slice();
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({ "id": 1 }))
+        );
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({ "id": 2 }))
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn refuses_huge_messages() {
+        let mut reader = "Content-Length: 99999999999999\r\n\r\n{}".as_bytes();
+        let error = read_message(&mut reader).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn refuses_messages_without_a_length() {
+        let mut reader = "Content-Type: json\r\n\r\n{}".as_bytes();
+        let error = read_message(&mut reader).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
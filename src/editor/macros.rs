@@ -0,0 +1,186 @@
+use crate::editor::keys::{format_keys, parse_keys};
+use crossterm::event::KeyEvent;
+use log::debug;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+struct Recording {
+    register: char,
+    keys: Vec<KeyEvent>,
+}
+
+/// Keystroke macros stored in named registers `a` to `z`
+pub struct Macros {
+    registers: BTreeMap<char, Vec<KeyEvent>>,
+    recording: Option<Recording>,
+}
+
+impl Macros {
+    pub fn default() -> Self {
+        Self {
+            registers: BTreeMap::new(),
+            recording: None,
+        }
+    }
+
+    pub fn is_register(register: char) -> bool {
+        register.is_ascii_lowercase()
+    }
+
+    /// Starts recording into `register`, dropping any recording in
+    /// progress
+    pub fn start_recording(&mut self, register: char) {
+        debug!("Recording macro into register {register}");
+        self.recording = Some(Recording {
+            register,
+            keys: Vec::new(),
+        });
+    }
+
+    /// Stores the recording in progress in its register and returns the
+    /// register, `None` if nothing was being recorded
+    pub fn stop_recording(&mut self) -> Option<char> {
+        let Recording { register, keys } = self.recording.take()?;
+        debug!("Recorded {} keys into register {register}", keys.len());
+        self.registers.insert(register, keys);
+        Some(register)
+    }
+
+    /// The register being recorded into, if any
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|recording| recording.register)
+    }
+
+    pub fn record(&mut self, key: KeyEvent) {
+        if let Some(recording) = &mut self.recording {
+            recording.keys.push(key);
+        }
+    }
+
+    pub fn get(&self, register: char) -> Option<&[KeyEvent]> {
+        self.registers.get(&register).map(Vec::as_slice)
+    }
+
+    /// Reads macros saved by `save`, one `<register> <keys>` per line.
+    /// A missing file is just an empty set of macros.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let mut macros = Self::default();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(macros),
+            Err(e) => return Err(e),
+        };
+
+        for (index, line) in contents.lines().enumerate() {
+            let mut chars = line.chars();
+            let (Some(register), Some(' ')) = (chars.next(), chars.next())
+            else {
+                continue;
+            };
+            match parse_keys(chars.as_str()) {
+                Ok(keys) if Self::is_register(register) => {
+                    macros.registers.insert(register, keys);
+                }
+                Ok(_) => debug!("Skipping macro for register {register}"),
+                Err(e) => debug!("Skipping macro on line {}: {e}", index + 1),
+            }
+        }
+
+        Ok(macros)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut contents = String::new();
+        for (register, keys) in &self.registers {
+            let _ = writeln!(contents, "{register} {}", format_keys(keys));
+        }
+        fs::write(path, contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyCode;
+
+    fn key(c: char) -> KeyEvent {
+        KeyEvent::from(KeyCode::Char(c))
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir()
+            .join(format!("r-edit-test-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn records_into_a_register_until_stopped() {
+        let mut macros = Macros::default();
+        macros.record(key('x'));
+        assert_eq!(macros.stop_recording(), None);
+
+        macros.start_recording('a');
+        assert_eq!(macros.recording(), Some('a'));
+        macros.record(key('h'));
+        macros.record(key('i'));
+        assert_eq!(macros.get('a'), None);
+        assert_eq!(macros.stop_recording(), Some('a'));
+
+        assert_eq!(macros.recording(), None);
+        assert_eq!(macros.get('a'), Some([key('h'), key('i')].as_slice()));
+    }
+
+    #[test]
+    fn recording_again_replaces_the_register() {
+        let mut macros = Macros::default();
+        macros.start_recording('a');
+        macros.record(key('x'));
+        macros.stop_recording();
+        macros.start_recording('a');
+        macros.stop_recording();
+        assert_eq!(macros.get('a'), Some([].as_slice()));
+    }
+
+    #[test]
+    fn loads_what_it_saves() {
+        let path = temp_path("macros");
+        let mut macros = Macros::default();
+        for (register, c) in [('a', 'x'), ('z', '<')] {
+            macros.start_recording(register);
+            macros.record(key(c));
+            macros.stop_recording();
+        }
+        macros.save(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a x\nz <lt>\n");
+
+        let loaded = Macros::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.get('a'), Some([key('x')].as_slice()));
+        assert_eq!(loaded.get('z'), Some([key('<')].as_slice()));
+    }
+
+    #[test]
+    fn loading_skips_lines_it_can_not_read() {
+        let path = temp_path("bad-macros");
+        fs::write(&path, "a ok\nB upper\nb <Nope>\nc\n\nd x\n").unwrap();
+        let macros = Macros::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(macros.get('a'), Some([key('o'), key('k')].as_slice()));
+        assert_eq!(macros.get('d'), Some([key('x')].as_slice()));
+        for register in ['B', 'b', 'c'] {
+            assert_eq!(macros.get(register), None);
+        }
+    }
+
+    #[test]
+    fn a_missing_file_has_no_macros() {
+        let macros = Macros::load(&temp_path("no-macros")).unwrap();
+        assert!(macros.registers.is_empty());
+    }
+}
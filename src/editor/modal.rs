@@ -0,0 +1,1190 @@
+use crate::editor::buffer::Buffer;
+use crate::editor::caret::Caret;
+use crate::editor::config::Config;
+use crate::editor::keys::format_keys;
+use crate::editor::terminal::Location;
+use crossterm::event::KeyCode::{
+    BackTab, Backspace, Char, Delete, Down, End, Esc, Home, Left, Right, Tab,
+    Up,
+};
+use crossterm::event::{KeyEvent, KeyModifiers};
+use log::info;
+
+/// Most times a put is repeated, and the least any count is cut down to
+const MAX_COUNT: usize = 1_000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+}
+
+impl Mode {
+    /// How the mode is shown in the status bar
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+            Mode::VisualLine => "VISUAL LINE",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    /// `gg`, the first line or the line given as count
+    FirstLine,
+    /// `G`, the last line or the line given as count
+    LastLine,
+    FindForward(char),
+    FindBackward(char),
+    TillForward(char),
+    TillBackward(char),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// How much text a motion covers when an operator uses it
+enum Extent {
+    /// Up to, not including, where the motion ends
+    Exclusive,
+    /// Up to and including where the motion ends
+    Inclusive,
+    /// Every line from the start to the end of the motion
+    Linewise,
+}
+
+impl Motion {
+    fn extent(self) -> Extent {
+        match self {
+            Motion::Up
+            | Motion::Down
+            | Motion::FirstLine
+            | Motion::LastLine => Extent::Linewise,
+            Motion::WordEnd
+            | Motion::LineEnd
+            | Motion::FindForward(_)
+            | Motion::TillForward(_) => Extent::Inclusive,
+            _ => Extent::Exclusive,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// What an operator works on
+enum Target {
+    Motion(Motion),
+    /// The operator typed twice, like `dd`: whole lines
+    Lines,
+    /// The visual selection
+    Selection,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Where `i`, `a`, `I`, `A`, `o` and `O` start inserting
+enum InsertAt {
+    Caret,
+    AfterCaret,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Action {
+    Move(Motion),
+    Operate(Operator, Target),
+    Insert(InsertAt),
+    Put { before: bool },
+    Visual(Mode),
+    Repeat,
+    CommandLine,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Parse {
+    Done(Option<usize>, Action),
+    /// The keys so far start a command, wait for more
+    More,
+    Invalid,
+}
+
+/// Text yanked or deleted, ready to be put back
+struct Register {
+    text: String,
+    linewise: bool,
+}
+
+/// What the editor still has to do after a key went to the modal layer
+pub enum Outcome {
+    Handled,
+    /// Not a modal key, handle it like in modeless editing
+    Unhandled,
+    /// Play these keys again for `.`
+    Repeat(Vec<KeyEvent>),
+    /// Open the command line with this input, e.g. a range
+    CommandLine(String),
+    Message(String),
+}
+
+/// Vim-style modal editing on top of the modeless editor. Insert mode
+/// keys are left to the editor, everything else is parsed here as
+/// `[count] operator [count] motion` and friends.
+pub struct Modal {
+    mode: Mode,
+    /// Keys of the command being typed
+    keys: Vec<KeyEvent>,
+    /// The other end of the visual selection
+    anchor: Location,
+    register: Option<Register>,
+    /// Keys of the change being made, with the buffer revision from
+    /// before it, until the editor is back in normal mode
+    change: Option<(u64, Vec<KeyEvent>)>,
+    /// Keys of the last completed change, for `.`
+    last_change: Vec<KeyEvent>,
+    /// While `.` plays a change back it is not recorded again
+    repeating: bool,
+}
+
+/// Turns a key into the character the grammar uses for it, `None` for
+/// keys that mean nothing in normal mode
+fn token(key: &KeyEvent) -> Option<char> {
+    if key
+        .modifiers
+        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+    {
+        return None;
+    }
+    match key.code {
+        Char(c) => Some(c),
+        Left | Backspace => Some('h'),
+        Right => Some('l'),
+        Up => Some('k'),
+        Down => Some('j'),
+        Home => Some('0'),
+        End => Some('$'),
+        Delete => Some('x'),
+        _ => None,
+    }
+}
+
+/// Reads a count at the start of `tokens`, returning it and the rest.
+/// A lone `0` is a motion, not a count.
+fn count(tokens: &[char]) -> (Option<usize>, &[char]) {
+    let digits = tokens
+        .iter()
+        .enumerate()
+        .take_while(|(i, c)| c.is_ascii_digit() && (*i > 0 || **c != '0'))
+        .count();
+    if digits == 0 {
+        return (None, tokens);
+    }
+    // Too many digits for a number are as many as can be
+    let number = tokens[..digits].iter().fold(0_usize, |number, c| {
+        let digit = c.to_digit(10).unwrap_or(0) as usize;
+        number.saturating_mul(10).saturating_add(digit)
+    });
+    (Some(number), &tokens[digits..])
+}
+
+/// Parses a motion, `Err(true)` when more keys are needed
+fn motion(tokens: &[char]) -> Result<Motion, bool> {
+    let motion = match tokens {
+        [] | ['g' | 'f' | 'F' | 't' | 'T'] => return Err(true),
+        ['h', ..] => Motion::Left,
+        ['l' | ' ', ..] => Motion::Right,
+        ['k', ..] => Motion::Up,
+        ['j', ..] => Motion::Down,
+        ['w', ..] => Motion::WordForward,
+        ['b', ..] => Motion::WordBackward,
+        ['e', ..] => Motion::WordEnd,
+        ['0', ..] => Motion::LineStart,
+        ['^', ..] => Motion::FirstNonBlank,
+        ['$', ..] => Motion::LineEnd,
+        ['G', ..] => Motion::LastLine,
+        ['g', 'g', ..] => Motion::FirstLine,
+        ['f', c, ..] => Motion::FindForward(*c),
+        ['F', c, ..] => Motion::FindBackward(*c),
+        ['t', c, ..] => Motion::TillForward(*c),
+        ['T', c, ..] => Motion::TillBackward(*c),
+        _ => return Err(false),
+    };
+    let length = if matches!(tokens[0], 'g' | 'f' | 'F' | 't' | 'T') {
+        2
+    } else {
+        1
+    };
+    if tokens.len() > length {
+        return Err(false);
+    }
+    Ok(motion)
+}
+
+fn operator(c: char) -> Option<Operator> {
+    match c {
+        'd' => Some(Operator::Delete),
+        'c' => Some(Operator::Change),
+        'y' => Some(Operator::Yank),
+        '>' => Some(Operator::Indent),
+        '<' => Some(Operator::Outdent),
+        _ => None,
+    }
+}
+
+fn multiply(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Parses the keys of a normal or visual mode command
+fn parse(tokens: &[char], mode: Mode) -> Parse {
+    let visual = matches!(mode, Mode::Visual | Mode::VisualLine);
+    let (first_count, rest) = count(tokens);
+    let done = |action| Parse::Done(first_count, action);
+    let Some(&c) = rest.first() else {
+        return Parse::More;
+    };
+
+    if let Some(op) = operator(c) {
+        if visual {
+            return done(Action::Operate(op, Target::Selection));
+        }
+        let (second_count, rest) = count(&rest[1..]);
+        let count = multiply(first_count, second_count);
+        return match rest {
+            [] => Parse::More,
+            [doubled] if *doubled == c => {
+                Parse::Done(count, Action::Operate(op, Target::Lines))
+            }
+            _ => match motion(rest) {
+                Ok(motion) => Parse::Done(
+                    count,
+                    Action::Operate(op, Target::Motion(motion)),
+                ),
+                Err(true) => Parse::More,
+                Err(false) => Parse::Invalid,
+            },
+        };
+    }
+
+    let single = |action| {
+        if rest.len() == 1 {
+            done(action)
+        } else {
+            Parse::Invalid
+        }
+    };
+    let selection = Action::Operate(Operator::Delete, Target::Selection);
+    match c {
+        'x' if visual => single(selection),
+        'x' => single(Action::Operate(
+            Operator::Delete,
+            Target::Motion(Motion::Right),
+        )),
+        'X' => single(Action::Operate(
+            Operator::Delete,
+            Target::Motion(Motion::Left),
+        )),
+        'D' => single(Action::Operate(
+            Operator::Delete,
+            Target::Motion(Motion::LineEnd),
+        )),
+        'C' => single(Action::Operate(
+            Operator::Change,
+            Target::Motion(Motion::LineEnd),
+        )),
+        's' if visual => {
+            single(Action::Operate(Operator::Change, Target::Selection))
+        }
+        's' => single(Action::Operate(
+            Operator::Change,
+            Target::Motion(Motion::Right),
+        )),
+        'S' => single(Action::Operate(Operator::Change, Target::Lines)),
+        'Y' => single(Action::Operate(Operator::Yank, Target::Lines)),
+        'i' if !visual => single(Action::Insert(InsertAt::Caret)),
+        'a' if !visual => single(Action::Insert(InsertAt::AfterCaret)),
+        'I' if !visual => single(Action::Insert(InsertAt::LineStart)),
+        'A' if !visual => single(Action::Insert(InsertAt::LineEnd)),
+        'o' if !visual => single(Action::Insert(InsertAt::LineBelow)),
+        'O' if !visual => single(Action::Insert(InsertAt::LineAbove)),
+        'p' if !visual => single(Action::Put { before: false }),
+        'P' if !visual => single(Action::Put { before: true }),
+        'v' => single(Action::Visual(Mode::Visual)),
+        'V' => single(Action::Visual(Mode::VisualLine)),
+        '.' if !visual => single(Action::Repeat),
+        ':' => single(Action::CommandLine),
+        _ => match motion(rest) {
+            Ok(motion) => done(Action::Move(motion)),
+            Err(true) => Parse::More,
+            Err(false) => Parse::Invalid,
+        },
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Kinds of characters words are made of, a word is a run of one kind
+enum Class {
+    Blank,
+    Word,
+    Punctuation,
+    /// An empty line, which counts as a word of its own
+    EmptyLine,
+}
+
+/// The class of the character at `at`, the end of a line counting as
+/// the blank of its line break
+fn class(buffer: &Buffer, at: Location) -> Class {
+    let Some(line) = buffer.lines.get(at.y) else {
+        return Class::Blank;
+    };
+    if line.is_empty() {
+        return Class::EmptyLine;
+    }
+    match line.chars().nth(at.x) {
+        None => Class::Blank,
+        Some(c) if c.is_whitespace() => Class::Blank,
+        Some(c) if c.is_alphanumeric() || c == '_' => Class::Word,
+        Some(_) => Class::Punctuation,
+    }
+}
+
+/// The location after `at`, stepping over line breaks
+fn next(buffer: &Buffer, at: Location) -> Option<Location> {
+    if at.x < buffer.line_len(at.y) {
+        return Some(Location { x: at.x + 1, ..at });
+    }
+    (at.y + 1 < buffer.lines.len()).then_some(Location { x: 0, y: at.y + 1 })
+}
+
+/// The location before `at`, stepping over line breaks
+fn previous(buffer: &Buffer, at: Location) -> Option<Location> {
+    if at.x > 0 {
+        return Some(Location { x: at.x - 1, ..at });
+    }
+    at.y.checked_sub(1).map(|y| Location {
+        x: buffer.line_len(y),
+        y,
+    })
+}
+
+fn first_non_blank(buffer: &Buffer, y: usize) -> usize {
+    buffer.lines.get(y).map_or(0, |line| {
+        line.chars().take_while(|c| c.is_whitespace()).count()
+    })
+}
+
+/// `w`: the start of the next word
+fn word_forward(buffer: &Buffer, from: Location) -> Location {
+    let start = class(buffer, from);
+    let mut at = from;
+    if start != Class::Blank {
+        while let Some(step) = next(buffer, at) {
+            at = step;
+            if class(buffer, at) != start || start == Class::EmptyLine {
+                break;
+            }
+        }
+        if at == from {
+            return end_of_buffer(buffer);
+        }
+    }
+    while class(buffer, at) == Class::Blank {
+        match next(buffer, at) {
+            Some(step) => at = step,
+            None => return end_of_buffer(buffer),
+        }
+    }
+    at
+}
+
+/// `b`: the start of the previous word
+fn word_backward(buffer: &Buffer, from: Location) -> Location {
+    let Some(mut at) = previous(buffer, from) else {
+        return from;
+    };
+    while class(buffer, at) == Class::Blank {
+        match previous(buffer, at) {
+            Some(step) => at = step,
+            None => return at,
+        }
+    }
+    let word = class(buffer, at);
+    if word == Class::EmptyLine {
+        return at;
+    }
+    while let Some(step) = previous(buffer, at) {
+        if step.y != at.y || class(buffer, step) != word {
+            break;
+        }
+        at = step;
+    }
+    at
+}
+
+/// The last character of the word at `from`
+fn end_of_word(buffer: &Buffer, from: Location) -> Location {
+    let word = class(buffer, from);
+    let mut at = from;
+    while let Some(step) = next(buffer, at) {
+        if step.y != at.y || class(buffer, step) != word {
+            break;
+        }
+        at = step;
+    }
+    at
+}
+
+/// `e`: the end of the next word, skipping blanks and empty lines
+fn word_end(buffer: &Buffer, from: Location) -> Location {
+    let Some(mut at) = next(buffer, from) else {
+        return from;
+    };
+    while matches!(class(buffer, at), Class::Blank | Class::EmptyLine) {
+        match next(buffer, at) {
+            Some(step) => at = step,
+            None => return from,
+        }
+    }
+    end_of_word(buffer, at)
+}
+
+fn end_of_buffer(buffer: &Buffer) -> Location {
+    let y = buffer.lines.len().saturating_sub(1);
+    Location {
+        x: buffer.line_len(y),
+        y,
+    }
+}
+
+/// The largest count taken for `buffer`, larger ones are cut down to it.
+/// No motion takes more steps than the buffer has bytes.
+fn count_limit(buffer: &Buffer) -> usize {
+    buffer.size().max(MAX_COUNT)
+}
+
+/// Where `motion` repeated `count` times takes the caret from `from`,
+/// `None` when it cannot move, like `f` without a match
+fn apply(
+    buffer: &Buffer,
+    from: Location,
+    motion: Motion,
+    count: Option<usize>,
+) -> Option<Location> {
+    let times = count.unwrap_or(1).max(1);
+    let last_line = buffer.lines.len().saturating_sub(1);
+    let repeat = |step: &dyn Fn(Location) -> Location| {
+        (0..times).fold(from, |at, _| step(at))
+    };
+    let chars: Vec<char> = buffer
+        .lines
+        .get(from.y)
+        .map_or_else(Vec::new, |line| line.chars().collect());
+    let find_forward = |c: char| {
+        chars
+            .iter()
+            .enumerate()
+            .skip(from.x + 1)
+            .filter(|(_, found)| **found == c)
+            .nth(times - 1)
+            .map(|(x, _)| x)
+    };
+    let find_backward = |c: char| {
+        chars
+            .iter()
+            .enumerate()
+            .take(from.x)
+            .rev()
+            .filter(|(_, found)| **found == c)
+            .nth(times - 1)
+            .map(|(x, _)| x)
+    };
+    let on_line = |x| Location { x, y: from.y };
+    let to_line = |y: usize| Location {
+        x: first_non_blank(buffer, y),
+        y,
+    };
+
+    let location = match motion {
+        Motion::Left => on_line(from.x.saturating_sub(times)),
+        Motion::Right => on_line(from.x.saturating_add(times).min(chars.len())),
+        Motion::Up => Location {
+            y: from.y.saturating_sub(times),
+            ..from
+        },
+        Motion::Down => Location {
+            y: from.y.saturating_add(times).min(last_line),
+            ..from
+        },
+        Motion::WordForward => repeat(&|at| word_forward(buffer, at)),
+        Motion::WordBackward => repeat(&|at| word_backward(buffer, at)),
+        Motion::WordEnd => repeat(&|at| word_end(buffer, at)),
+        Motion::LineStart => on_line(0),
+        Motion::FirstNonBlank => to_line(from.y),
+        Motion::LineEnd => {
+            let y = from.y.saturating_add(times - 1).min(last_line);
+            Location {
+                x: buffer.line_len(y).saturating_sub(1),
+                y,
+            }
+        }
+        Motion::FirstLine => to_line(
+            count
+                .map_or(0, |line| line.saturating_sub(1))
+                .min(last_line),
+        ),
+        Motion::LastLine => {
+            to_line(count.map_or(last_line, |line| {
+                line.saturating_sub(1).min(last_line)
+            }))
+        }
+        Motion::FindForward(c) => on_line(find_forward(c)?),
+        Motion::FindBackward(c) => on_line(find_backward(c)?),
+        Motion::TillForward(c) => on_line(find_forward(c)?.checked_sub(1)?),
+        Motion::TillBackward(c) => on_line(find_backward(c)? + 1),
+    };
+    Some(location)
+}
+
+/// The location right after the character at `at`, the start of the
+/// next line when `at` is at the end of its line
+fn after(buffer: &Buffer, at: Location) -> Location {
+    if at.x >= buffer.line_len(at.y) && at.y + 1 < buffer.lines.len() {
+        Location { x: 0, y: at.y + 1 }
+    } else {
+        Location { x: at.x + 1, ..at }
+    }
+}
+
+/// The text an operator works on
+enum Range {
+    /// From the first location up to, not including, the second
+    Chars(Location, Location),
+    /// The first through the last line
+    Lines(usize, usize),
+}
+
+/// `a` and `b`, the one first in the text first
+pub fn ordered(a: Location, b: Location) -> (Location, Location) {
+    if (a.y, a.x) <= (b.y, b.x) {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+impl Modal {
+    pub fn default() -> Self {
+        Self {
+            mode: Mode::Normal,
+            keys: Vec::new(),
+            anchor: Location { x: 0, y: 0 },
+            register: None,
+            change: None,
+            last_change: Vec::new(),
+            repeating: false,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The keys of the command typed so far, in key notation
+    pub fn pending_keys(&self) -> String {
+        format_keys(&self.keys)
+    }
+
+    /// Where the visual selection started, while there is one
+    pub fn anchor(&self) -> Option<Location> {
+        matches!(self.mode, Mode::Visual | Mode::VisualLine)
+            .then_some(self.anchor)
+    }
+
+    /// Selects from `anchor` to the caret in visual mode
+    pub fn select(&mut self, anchor: Location) {
+        self.keys.clear();
+        self.mode = Mode::Visual;
+        self.anchor = anchor;
+    }
+
+    /// Leaves visual mode for normal mode
+    pub fn escape_visual(&mut self) {
+        self.keys.clear();
+        self.mode = Mode::Normal;
+    }
+
+    pub fn set_repeating(&mut self, repeating: bool) {
+        self.repeating = repeating;
+    }
+
+    fn is_idle(&self) -> bool {
+        self.mode == Mode::Normal && self.keys.is_empty()
+    }
+
+    /// Call before handling `key`, starts recording a change when the
+    /// key may begin one
+    pub fn begin_key(&mut self, key: &KeyEvent, revision: u64) {
+        if self.repeating {
+            return;
+        }
+        if self.change.is_none() && self.is_idle() {
+            self.change = Some((revision, Vec::new()));
+        }
+        if let Some((_, keys)) = &mut self.change {
+            keys.push(*key);
+        }
+    }
+
+    /// Call after handling a key. Once back in normal mode, the keys
+    /// since the last time are kept for `.` if they changed the buffer.
+    pub fn end_key(&mut self, revision: u64) {
+        if self.repeating || !self.is_idle() {
+            return;
+        }
+        if let Some((before, keys)) = self.change.take()
+            && before != revision
+        {
+            self.last_change = keys;
+        }
+    }
+
+    /// Leaves insert mode, the caret steps back onto the last character
+    /// typed like in Vim
+    pub fn escape_insert(&mut self, caret: &mut Caret, buffer: &Buffer) {
+        self.mode = Mode::Normal;
+        caret.location.x = caret.location.x.saturating_sub(1);
+        Self::clamp_normal(caret, buffer);
+    }
+
+    /// Puts the caret back where the current mode allows it, after it
+    /// was moved by a modeless command
+    pub fn settle(&self, caret: &mut Caret, buffer: &Buffer) {
+        if self.mode == Mode::Normal {
+            Self::clamp_normal(caret, buffer);
+        } else {
+            caret.clamp_to(buffer);
+        }
+    }
+
+    /// In normal mode the caret stays on a character, never past the end
+    /// of the line
+    fn clamp_normal(caret: &mut Caret, buffer: &Buffer) {
+        caret.clamp_to(buffer);
+        let len = buffer.line_len(caret.location.y);
+        caret.location.x = caret.location.x.min(len.saturating_sub(1));
+    }
+
+    /// Handles a key in normal or visual mode
+    pub fn evaluate_key(
+        &mut self,
+        key: &KeyEvent,
+        buffer: &mut Buffer,
+        caret: &mut Caret,
+        config: &Config,
+    ) -> Outcome {
+        if key.code == Esc {
+            self.keys.clear();
+            self.mode = Mode::Normal;
+            Self::clamp_normal(caret, buffer);
+            return Outcome::Handled;
+        }
+        if matches!(key.code, Tab | BackTab) {
+            self.keys.clear();
+            return Outcome::Handled;
+        }
+        if token(key).is_none() {
+            return Outcome::Unhandled;
+        }
+
+        self.keys.push(*key);
+        let tokens: Vec<char> = self.keys.iter().filter_map(token).collect();
+        let (count, action) = match parse(&tokens, self.mode) {
+            Parse::More => return Outcome::Handled,
+            Parse::Invalid => {
+                info!("Invalid modal keys: {}", self.pending_keys());
+                self.keys.clear();
+                return Outcome::Handled;
+            }
+            Parse::Done(count, action) => (count, action),
+        };
+        self.keys.clear();
+        let count = count.map(|count| count.min(count_limit(buffer)));
+        info!("Modal action {action:?} count {count:?}");
+
+        let outcome = self.run(action, count, buffer, caret, config);
+        if action == Action::Repeat {
+            // `.` itself is not a change to repeat
+            self.change = None;
+        }
+        if self.mode != Mode::Insert {
+            self.settle(caret, buffer);
+        }
+        outcome
+    }
+
+    fn run(
+        &mut self,
+        action: Action,
+        count: Option<usize>,
+        buffer: &mut Buffer,
+        caret: &mut Caret,
+        config: &Config,
+    ) -> Outcome {
+        match action {
+            Action::Move(motion) => {
+                let Some(to) = apply(buffer, caret.location, motion, count)
+                else {
+                    return Outcome::Handled;
+                };
+                caret.location = to;
+                if matches!(motion, Motion::Up | Motion::Down) {
+                    caret.clamp_to(buffer);
+                }
+            }
+            Action::Operate(op, target) => {
+                let Some(range) = self.range(buffer, caret, op, target, count)
+                else {
+                    return Outcome::Handled;
+                };
+                self.operate(op, range, buffer, caret, config);
+            }
+            Action::Insert(at) => self.insert(at, buffer, caret),
+            Action::Put { before } => {
+                return self.put(before, count, buffer, caret);
+            }
+            Action::Visual(mode) => {
+                if self.mode == mode {
+                    self.mode = Mode::Normal;
+                } else {
+                    if self.mode == Mode::Normal {
+                        self.anchor = caret.location;
+                    }
+                    self.mode = mode;
+                }
+            }
+            Action::CommandLine => {
+                // Vim fills in the lines the command will work on
+                let y = caret.location.y;
+                let (first, last) = match self.mode {
+                    Mode::Visual | Mode::VisualLine => {
+                        let (start, end) = ordered(self.anchor, caret.location);
+                        (start.y, end.y)
+                    }
+                    _ => (y, y.saturating_add(count.unwrap_or(1).max(1) - 1)),
+                };
+                self.mode = Mode::Normal;
+                let range = if (first, last) == (y, y) && count.is_none() {
+                    String::new()
+                } else {
+                    format!("{},{}", first + 1, last + 1)
+                };
+                return Outcome::CommandLine(range);
+            }
+            Action::Repeat => {
+                if self.last_change.is_empty() {
+                    return Outcome::Message(String::from(
+                        "No change to repeat",
+                    ));
+                }
+                let mut keys = self.last_change.clone();
+                if let Some(count) = count {
+                    // A new count replaces the one the change was made with
+                    let digits = keys
+                        .iter()
+                        .take_while(|key| {
+                            token(key).is_some_and(|c| c.is_ascii_digit())
+                        })
+                        .count();
+                    keys.drain(..digits);
+                    let prefix: Vec<KeyEvent> = count
+                        .to_string()
+                        .chars()
+                        .map(|c| KeyEvent::new(Char(c), KeyModifiers::NONE))
+                        .collect();
+                    keys.splice(0..0, prefix);
+                }
+                return Outcome::Repeat(keys);
+            }
+        }
+        Outcome::Handled
+    }
+
+    /// The text `op` works on when applied to `target`
+    fn range(
+        &self,
+        buffer: &Buffer,
+        caret: &Caret,
+        op: Operator,
+        target: Target,
+        count: Option<usize>,
+    ) -> Option<Range> {
+        let from = caret.location;
+        let lines_or = |range: Range| match (op, range) {
+            // Indenting always works on whole lines
+            (Operator::Indent | Operator::Outdent, Range::Chars(a, b)) => {
+                let last = if b.x == 0 && b.y > a.y { b.y - 1 } else { b.y };
+                Range::Lines(a.y, last)
+            }
+            (_, range) => range,
+        };
+
+        let range = match target {
+            Target::Lines => {
+                let last = from.y.saturating_add(count.unwrap_or(1).max(1) - 1);
+                Range::Lines(
+                    from.y,
+                    last.min(buffer.lines.len().saturating_sub(1)),
+                )
+            }
+            Target::Selection => {
+                let (start, end) = ordered(self.anchor, from);
+                if self.mode == Mode::VisualLine {
+                    Range::Lines(start.y, end.y)
+                } else {
+                    Range::Chars(start, after(buffer, end))
+                }
+            }
+            // Like in Vim, `cw` on a word changes up to its end and leaves
+            // the blank after it alone
+            Target::Motion(Motion::WordForward)
+                if op == Operator::Change
+                    && !matches!(
+                        class(buffer, from),
+                        Class::Blank | Class::EmptyLine
+                    ) =>
+            {
+                let first = end_of_word(buffer, from);
+                let times = count.unwrap_or(1).max(1);
+                let end = (1..times).fold(first, |at, _| word_end(buffer, at));
+                Range::Chars(from, after(buffer, end))
+            }
+            Target::Motion(motion) => {
+                let to = apply(buffer, from, motion, count)?;
+                let (start, end) = ordered(from, to);
+                match motion.extent() {
+                    Extent::Linewise => Range::Lines(start.y, end.y),
+                    Extent::Inclusive => {
+                        Range::Chars(start, after(buffer, end))
+                    }
+                    // An exclusive motion ending at the start of a line,
+                    // like `dw` on the last word, stops at the line break
+                    Extent::Exclusive if end.x == 0 && end.y > start.y => {
+                        let y = end.y - 1;
+                        Range::Chars(
+                            start,
+                            Location {
+                                x: buffer.line_len(y),
+                                y,
+                            },
+                        )
+                    }
+                    Extent::Exclusive => Range::Chars(start, end),
+                }
+            }
+        };
+        Some(lines_or(range))
+    }
+
+    fn operate(
+        &mut self,
+        op: Operator,
+        range: Range,
+        buffer: &mut Buffer,
+        caret: &mut Caret,
+        config: &Config,
+    ) {
+        self.mode = Mode::Normal;
+        match (op, range) {
+            (Operator::Yank, Range::Chars(start, end)) => {
+                self.register = Some(Register {
+                    text: buffer.text(start, end),
+                    linewise: false,
+                });
+                caret.location = start;
+            }
+            (Operator::Yank, Range::Lines(first, last)) => {
+                let lines = buffer.lines.get(first..=last).unwrap_or_default();
+                self.register = Some(Register {
+                    text: lines.join("\n"),
+                    linewise: true,
+                });
+                caret.location.y = first;
+            }
+            // Nothing to delete, like `x` on an empty line: the buffer and
+            // the register stay as they are
+            (Operator::Delete, Range::Chars(start, end)) if start == end => {
+                caret.location = start;
+            }
+            (Operator::Delete | Operator::Change, Range::Chars(start, end)) => {
+                let text = buffer.delete(start, end);
+                self.register = Some(Register {
+                    text,
+                    linewise: false,
+                });
+                caret.location = start;
+                if op == Operator::Change {
+                    self.mode = Mode::Insert;
+                }
+            }
+            (Operator::Delete, Range::Lines(first, last)) => {
+                let lines = buffer.delete_lines(first, last);
+                self.register = Some(Register {
+                    text: lines.join("\n"),
+                    linewise: true,
+                });
+                let y = first.min(buffer.lines.len().saturating_sub(1));
+                caret.location = Location {
+                    x: first_non_blank(buffer, y),
+                    y,
+                };
+            }
+            (Operator::Change, Range::Lines(first, last)) => {
+                let lines = buffer.delete_lines(first, last);
+                self.register = Some(Register {
+                    text: lines.join("\n"),
+                    linewise: true,
+                });
+                buffer.insert_lines(first, &[String::new()]);
+                caret.location = Location { x: 0, y: first };
+                self.mode = Mode::Insert;
+            }
+            (
+                Operator::Indent | Operator::Outdent,
+                Range::Lines(first, last),
+            ) => {
+                for y in first..=last.min(buffer.lines.len().saturating_sub(1))
+                {
+                    if op == Operator::Indent {
+                        Self::indent(buffer, y, config);
+                    } else {
+                        Self::outdent(buffer, y, config);
+                    }
+                }
+                caret.location = Location {
+                    x: first_non_blank(buffer, first),
+                    y: first,
+                };
+            }
+            (Operator::Indent | Operator::Outdent, Range::Chars(..)) => {}
+        }
+    }
+
+    /// Indents line `y` by one level, empty lines stay empty
+    fn indent(buffer: &mut Buffer, y: usize, config: &Config) {
+        if buffer.line_len(y) == 0 {
+            return;
+        }
+        let indent = if config.hard_tabs {
+            String::from("\t")
+        } else {
+            " ".repeat(config.tab_width)
+        };
+        buffer.insert_str(&indent, Location { x: 0, y });
+    }
+
+    /// Removes one level of indentation from line `y`
+    fn outdent(buffer: &mut Buffer, y: usize, config: &Config) {
+        let Some(line) = buffer.lines.get(y) else {
+            return;
+        };
+        let width = if line.starts_with('\t') {
+            1
+        } else {
+            line.chars()
+                .take(config.tab_width)
+                .take_while(|c| *c == ' ')
+                .count()
+        };
+        if width > 0 {
+            buffer.delete(Location { x: 0, y }, Location { x: width, y });
+        }
+    }
+
+    fn insert(&mut self, at: InsertAt, buffer: &mut Buffer, caret: &mut Caret) {
+        let y = caret.location.y;
+        caret.location = match at {
+            InsertAt::Caret => caret.location,
+            InsertAt::AfterCaret => Location {
+                x: (caret.location.x + 1).min(buffer.line_len(y)),
+                y,
+            },
+            InsertAt::LineStart => Location {
+                x: first_non_blank(buffer, y),
+                y,
+            },
+            InsertAt::LineEnd => Location {
+                x: buffer.line_len(y),
+                y,
+            },
+            InsertAt::LineBelow => {
+                let below = (y + 1).min(buffer.lines.len());
+                buffer.insert_lines(below, &[String::new()]);
+                Location { x: 0, y: below }
+            }
+            InsertAt::LineAbove => {
+                buffer.insert_lines(y, &[String::new()]);
+                Location { x: 0, y }
+            }
+        };
+        self.mode = Mode::Insert;
+    }
+
+    /// `p` and `P`: puts the register after or before the caret, lines
+    /// below or above the current one
+    fn put(
+        &mut self,
+        before: bool,
+        count: Option<usize>,
+        buffer: &mut Buffer,
+        caret: &mut Caret,
+    ) -> Outcome {
+        let Some(register) = &self.register else {
+            return Outcome::Message(String::from("Nothing to put"));
+        };
+        let times = count.unwrap_or(1).clamp(1, MAX_COUNT);
+        let y = caret.location.y;
+
+        if register.linewise {
+            let lines: Vec<String> = (0..times)
+                .flat_map(|_| register.text.split('\n').map(String::from))
+                .collect();
+            let at = if before {
+                y
+            } else {
+                (y + 1).min(buffer.lines.len())
+            };
+            buffer.insert_lines(at, &lines);
+            caret.location = Location {
+                x: first_non_blank(buffer, at),
+                y: at,
+            };
+        } else {
+            let text = register.text.repeat(times);
+            let at = if before || buffer.line_len(y) == 0 {
+                caret.location
+            } else {
+                Location {
+                    x: caret.location.x + 1,
+                    y,
+                }
+            };
+            let end = buffer.insert_str(&text, at);
+            caret.location = Location {
+                x: end.x.saturating_sub(1),
+                y: end.y,
+            };
+        }
+        Outcome::Handled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::keys::parse_keys;
+
+    /// Where the caret ends up after typing `keys` in Normal mode
+    fn after_keys(lines: &[&str], keys: &str) -> Location {
+        let mut buffer = Buffer::new();
+        buffer.set_lines(
+            &lines.iter().map(|&l| String::from(l)).collect::<Vec<_>>(),
+        );
+        let mut caret = Caret::default();
+        let mut modal = Modal::default();
+        let config = Config::default();
+        for key in parse_keys(keys).unwrap() {
+            modal.evaluate_key(&key, &mut buffer, &mut caret, &config);
+        }
+        caret.location
+    }
+
+    #[test]
+    fn huge_counts_stop_at_the_end_of_the_buffer() {
+        let lines = ["one two", "three"];
+        assert_eq!(
+            after_keys(&lines, "99999999999999999999l"),
+            Location { x: 6, y: 0 }
+        );
+        assert_eq!(
+            after_keys(&lines, "99999999999999999999j"),
+            Location { x: 0, y: 1 }
+        );
+        assert_eq!(
+            after_keys(&lines, "99999999999999999999$"),
+            Location { x: 4, y: 1 }
+        );
+        assert_eq!(
+            after_keys(&lines, "18446744073709551615j"),
+            Location { x: 0, y: 1 }
+        );
+    }
+
+    #[test]
+    fn huge_word_counts_do_not_take_long() {
+        let location = after_keys(&["one two", "three"], "9999999999w");
+        assert_eq!(location.y, 1);
+    }
+
+    #[test]
+    fn huge_put_counts_are_limited() {
+        let mut buffer = Buffer::new();
+        buffer.set_lines(&[String::from("x")]);
+        let mut caret = Caret::default();
+        let mut modal = Modal::default();
+        let config = Config::default();
+        for key in parse_keys("yl99999999999p").unwrap() {
+            modal.evaluate_key(&key, &mut buffer, &mut caret, &config);
+        }
+        assert_eq!(buffer.lines[0].len(), MAX_COUNT + 1);
+    }
+
+    #[test]
+    fn deleting_nothing_changes_nothing() {
+        let mut buffer = Buffer::new();
+        buffer.set_lines(&[String::from("one"), String::new()]);
+        let mut caret = Caret::default();
+        let mut modal = Modal::default();
+        let config = Config::default();
+        for key in parse_keys("ylj").unwrap() {
+            modal.evaluate_key(&key, &mut buffer, &mut caret, &config);
+        }
+        let revision = buffer.revision();
+        for key in parse_keys("xX").unwrap() {
+            modal.evaluate_key(&key, &mut buffer, &mut caret, &config);
+        }
+        assert_eq!(buffer.revision(), revision);
+        assert_eq!(buffer.lines, ["one", ""]);
+        assert_eq!(modal.register.map(|register| register.text).unwrap(), "o");
+    }
+
+    #[test]
+    fn counts_are_limited() {
+        let buffer = Buffer::new();
+        assert_eq!(count_limit(&buffer), MAX_COUNT);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::editor::commands::{COMMANDS, Command};
+use crate::editor::frame::Frame;
+use crate::editor::fuzzy::fuzzy_match;
+use crate::editor::keymap::Keymap;
+use crate::editor::terminal::Position;
+use crate::editor::view::Overlay;
+
+/// Most commands listed at once, the rest scroll
+const MAX_VISIBLE: usize = 10;
+const MAX_WIDTH: usize = 72;
+/// How many recently run commands are remembered
+pub const MAX_RECENT: usize = 10;
+
+struct Candidate {
+    command: Command,
+    /// Shortest key binding, if any
+    keys: Option<String>,
+}
+
+/// Overlay listing every command, fuzzy filtered by what is typed
+pub struct Palette {
+    query: String,
+    /// Recently used commands first, then the rest in registry order
+    candidates: Vec<Candidate>,
+    /// Indices into `candidates` of the commands matching the query
+    items: Vec<usize>,
+    selected: usize,
+}
+
+impl Palette {
+    pub fn new(keymap: &Keymap, recent: &[Command]) -> Self {
+        let others = COMMANDS
+            .iter()
+            .map(|info| info.command)
+            .filter(|command| !recent.contains(command));
+        let candidates = recent
+            .iter()
+            .copied()
+            .chain(others)
+            .filter(|command| *command != Command::CommandPalette)
+            .map(|command| Candidate {
+                command,
+                keys: keymap.keys_for(command).into_iter().next(),
+            })
+            .collect();
+
+        let mut palette = Self {
+            query: String::new(),
+            candidates,
+            items: Vec::new(),
+            selected: 0,
+        };
+        palette.filter();
+        palette
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.query.push(c);
+        self.filter();
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.filter();
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.items.is_empty() {
+            self.selected =
+                (self.selected + self.items.len() - 1) % self.items.len();
+        }
+    }
+
+    pub fn selected(&self) -> Option<Command> {
+        self.items
+            .get(self.selected)
+            .map(|&candidate| self.candidates[candidate].command)
+    }
+
+    /// Keeps the candidates matching the query, best first. Ties keep the
+    /// candidate order, so recent commands win.
+    fn filter(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(index, candidate)| {
+                let found = fuzzy_match(&self.query, candidate.command.name())?;
+                Some((found.score, index))
+            })
+            .collect();
+        if !self.query.is_empty() {
+            scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+        }
+        self.items = scored.into_iter().map(|(_, item)| item).collect();
+        self.selected = 0;
+    }
+}
+
+impl Overlay for Palette {
+    fn draw(&self, frame: &mut Frame) -> Option<Position> {
+        let size = frame.size();
+        let width = size.width.min(MAX_WIDTH);
+        if width < 4 || size.height < 3 {
+            return None;
+        }
+        let left = (size.width - width) / 2;
+        let inner = width - 2;
+        let visible = self.items.len().min(MAX_VISIBLE).min(size.height - 3);
+        let offset = self.selected.saturating_sub(visible.saturating_sub(1));
+
+        let mut row = 0;
+        let mut line = |frame: &mut Frame, text: String| {
+            frame.print(Position { x: left, y: row }, &text);
+            row += 1;
+        };
+
+        let title = " Commands ";
+        let fill = inner.saturating_sub(title.len() + 1);
+        line(frame, format!("┌─{title}{}┐", "─".repeat(fill)));
+        line(frame, format!("│{:<inner$}│", format!("> {}", self.query)));
+        for (index, &item) in
+            self.items.iter().enumerate().skip(offset).take(visible)
+        {
+            let candidate = &self.candidates[item];
+            let info = candidate.command.info();
+            let marker = if index == self.selected { '▶' } else { ' ' };
+            let keys = candidate.keys.as_deref().unwrap_or("");
+            let text =
+                format!("{marker} {:<18} {}", info.name, info.description);
+            let room = inner.saturating_sub(keys.chars().count() + 1);
+            let text: String = text.chars().take(room).collect();
+            line(frame, format!("│{text:<room$} {keys}│"));
+        }
+        if self.items.is_empty() {
+            line(frame, format!("│{:<inner$}│", "  No matching command"));
+        }
+        line(frame, format!("└{}┘", "─".repeat(inner)));
+
+        let query_width = self.query.chars().count();
+        Some(Position {
+            x: (left + 3 + query_width).min(left + inner),
+            y: 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::terminal::Size;
+
+    fn palette(recent: &[Command], query: &str) -> Palette {
+        let mut palette = Palette::new(&Keymap::default(), recent);
+        for c in query.chars() {
+            palette.insert(c);
+        }
+        palette
+    }
+
+    fn listed(palette: &Palette) -> Vec<Command> {
+        palette
+            .items
+            .iter()
+            .map(|&item| palette.candidates[item].command)
+            .collect()
+    }
+
+    #[test]
+    fn lists_recent_commands_first_and_never_itself() {
+        let listed = listed(&palette(&[Command::Quit, Command::Save], ""));
+        assert_eq!(listed[..2], [Command::Quit, Command::Save]);
+        assert_eq!(listed.len(), COMMANDS.len() - 1);
+        assert!(!listed.contains(&Command::CommandPalette));
+    }
+
+    #[test]
+    fn filters_best_match_first() {
+        let mut palette = palette(&[], "quti");
+        assert!(listed(&palette).is_empty());
+        assert_eq!(palette.selected(), None);
+
+        palette.backspace();
+        palette.backspace();
+        palette.insert('i');
+        palette.insert('t');
+        assert_eq!(palette.query(), "quit");
+        assert_eq!(listed(&palette), [Command::Quit, Command::SaveAndQuit]);
+    }
+
+    #[test]
+    fn selection_wraps_around() {
+        let mut palette = palette(&[], "quit");
+        assert_eq!(palette.selected(), Some(Command::Quit));
+        palette.select_previous();
+        assert_eq!(palette.selected(), Some(Command::SaveAndQuit));
+        palette.select_next();
+        assert_eq!(palette.selected(), Some(Command::Quit));
+    }
+
+    #[test]
+    fn draws_the_query_and_the_matches() {
+        let mut frame = Frame::new(Size {
+            width: 40,
+            height: 6,
+        });
+        let cursor = palette(&[], "qu").draw(&mut frame);
+        assert_eq!(cursor, Some(Position { x: 5, y: 1 }));
+        assert!(frame.row(1).starts_with("│> qu "));
+        assert!(frame.row(2).starts_with("│▶ quit "));
+        assert!(frame.row(3).starts_with("│  save_and_quit "));
+
+        let mut frame = Frame::new(Size {
+            width: 40,
+            height: 6,
+        });
+        palette(&[], "zzz").draw(&mut frame);
+        assert!(frame.row(2).starts_with("│  No matching command"));
+    }
+}
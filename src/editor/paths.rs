@@ -0,0 +1,28 @@
+use std::env;
+use std::path::PathBuf;
+
+/// `$XDG_<var>/r-edit`, falling back to `$HOME/<fallback>/r-edit`
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    let base = env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback))
+        })?;
+    Some(base.join("r-edit"))
+}
+
+/// Where data worth keeping across sessions lives, e.g. macros
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// Where the user's configuration lives
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Where state that is not worth backing up lives, e.g. swap files
+pub fn state_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
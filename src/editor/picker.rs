@@ -0,0 +1,179 @@
+use crate::editor::frame::Frame;
+use crate::editor::fuzzy::fuzzy_match;
+use crate::editor::terminal::Position;
+use crate::editor::view::Overlay;
+
+/// Most items listed at once, the rest scroll
+const MAX_VISIBLE: usize = 10;
+const MAX_WIDTH: usize = 72;
+/// Most lines of the preview shown under the items
+pub const MAX_PREVIEW: usize = 12;
+
+/// Overlay to pick one of a list of items by their labels, fuzzy
+/// filtered by what is typed, like the places a language server found
+/// or the files of the project
+pub struct Picker<T> {
+    title: String,
+    query: String,
+    /// Every item with its label, in the order given
+    candidates: Vec<(String, T)>,
+    /// Indices into `candidates` of the items matching the query
+    items: Vec<usize>,
+    selected: usize,
+    /// Lines shown under the items, like the file of the selected one
+    preview: Vec<String>,
+}
+
+impl<T> Picker<T> {
+    pub fn new(title: &str, candidates: Vec<(String, T)>) -> Self {
+        let mut picker = Self {
+            title: title.to_string(),
+            query: String::new(),
+            candidates,
+            items: Vec::new(),
+            selected: 0,
+            preview: Vec::new(),
+        };
+        picker.filter();
+        picker
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    /// Adds items to pick from, like those found while the picker is
+    /// open. The selected item stays selected.
+    pub fn extend(&mut self, candidates: Vec<(String, T)>) {
+        let selected = self.items.get(self.selected).copied();
+        self.candidates.extend(candidates);
+        self.filter();
+        if let Some(selected) = selected {
+            self.selected = self
+                .items
+                .iter()
+                .position(|&item| item == selected)
+                .unwrap_or(0);
+        }
+    }
+
+    /// How many items there are to pick from, matching the query or not
+    pub fn candidate_count(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn set_preview(&mut self, preview: Vec<String>) {
+        self.preview = preview;
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.query.push(c);
+        self.filter();
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.filter();
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.items.is_empty() {
+            self.selected =
+                (self.selected + self.items.len() - 1) % self.items.len();
+        }
+    }
+
+    pub fn selected(&self) -> Option<&T> {
+        self.items
+            .get(self.selected)
+            .map(|&candidate| &self.candidates[candidate].1)
+    }
+
+    /// Keeps the items matching the query, best first. Ties keep the
+    /// order they were given in.
+    fn filter(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (label, _))| {
+                Some((fuzzy_match(&self.query, label)?.score, index))
+            })
+            .collect();
+        if !self.query.is_empty() {
+            scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+        }
+        self.items = scored.into_iter().map(|(_, item)| item).collect();
+        self.selected = 0;
+    }
+}
+
+impl<T> Overlay for Picker<T> {
+    fn draw(&self, frame: &mut Frame) -> Option<Position> {
+        let size = frame.size();
+        let width = size.width.min(MAX_WIDTH);
+        if width < 4 || size.height < 3 {
+            return None;
+        }
+        let left = (size.width - width) / 2;
+        let inner = width - 2;
+        let visible = self.items.len().min(MAX_VISIBLE).min(size.height - 3);
+        let offset = self.selected.saturating_sub(visible.saturating_sub(1));
+
+        // Lines left for the preview under the title, query, items, its
+        // separator and the bottom border
+        let listed = visible.max(usize::from(self.items.is_empty()));
+        let room = size.height.saturating_sub(listed + 4);
+
+        let mut row = 0;
+        let mut line = |frame: &mut Frame, text: String| {
+            frame.print(Position { x: left, y: row }, &text);
+            row += 1;
+        };
+
+        let title = format!(" {} ", self.title);
+        let fill = inner.saturating_sub(title.chars().count() + 1);
+        line(frame, format!("┌─{title}{}┐", "─".repeat(fill)));
+        line(frame, format!("│{:<inner$}│", format!("> {}", self.query)));
+        for (index, &item) in
+            self.items.iter().enumerate().skip(offset).take(visible)
+        {
+            let marker = if index == self.selected { '▶' } else { ' ' };
+            let text = format!("{marker} {}", self.candidates[item].0);
+            let text: String = text.chars().take(inner).collect();
+            line(frame, format!("│{text:<inner$}│"));
+        }
+        if self.items.is_empty() {
+            line(frame, format!("│{:<inner$}│", "  Nothing matches"));
+        }
+        if !self.preview.is_empty() && room > 0 {
+            line(frame, format!("├{}┤", "─".repeat(inner)));
+            for text in self.preview.iter().take(MAX_PREVIEW.min(room)) {
+                let text: String = text
+                    .replace('\t', "    ")
+                    .chars()
+                    .filter(|c| !c.is_control())
+                    .take(inner)
+                    .collect();
+                line(frame, format!("│{text:<inner$}│"));
+            }
+        }
+        line(frame, format!("└{}┘", "─".repeat(inner)));
+
+        let query_width = self.query.chars().count();
+        Some(Position {
+            x: (left + 3 + query_width).min(left + inner),
+            y: 1,
+        })
+    }
+}
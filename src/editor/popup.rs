@@ -0,0 +1,72 @@
+use crate::editor::frame::Frame;
+use crate::editor::terminal::Position;
+use crate::editor::view::Overlay;
+
+/// Most lines shown, the rest is cut off
+const MAX_LINES: usize = 12;
+const MAX_WIDTH: usize = 72;
+
+/// A box of text next to the caret, like the hover of a language server.
+/// It does not take input, the next key closes it.
+pub struct Popup {
+    lines: Vec<String>,
+    /// The caret's place on the screen, the box goes below or above it
+    at: Position,
+}
+
+impl Popup {
+    pub fn new(text: &str) -> Self {
+        let mut lines: Vec<String> = text.lines().map(String::from).collect();
+        if lines.len() > MAX_LINES {
+            lines.truncate(MAX_LINES);
+            lines.push(String::from("…"));
+        }
+        Self {
+            lines,
+            at: Position { x: 0, y: 0 },
+        }
+    }
+
+    /// Puts the box next to `at`, the caret on the screen
+    pub fn place(&mut self, at: Position) {
+        self.at = at;
+    }
+}
+
+impl Overlay for Popup {
+    fn draw(&self, frame: &mut Frame) -> Option<Position> {
+        let size = frame.size();
+        let longest = self.lines.iter().map(|l| l.chars().count()).max();
+        let inner = longest
+            .unwrap_or(0)
+            .min(MAX_WIDTH)
+            .min(size.width.saturating_sub(2));
+        let width = inner + 2;
+        let height = self.lines.len() + 2;
+        if inner == 0 || size.height < 3 {
+            return None;
+        }
+        // Below the caret if it fits, above it otherwise; the last two
+        // rows are the status and message bars
+        let text_rows = size.height.saturating_sub(2);
+        let y = if self.at.y + 1 + height <= text_rows {
+            self.at.y + 1
+        } else {
+            self.at.y.saturating_sub(height)
+        };
+        let x = self.at.x.min(size.width - width);
+
+        let mut row = y;
+        let mut line = |frame: &mut Frame, text: String| {
+            frame.print(Position { x, y: row }, &text);
+            row += 1;
+        };
+        line(frame, format!("┌{}┐", "─".repeat(inner)));
+        for text in &self.lines {
+            let text: String = text.chars().take(inner).collect();
+            line(frame, format!("│{text:<inner$}│"));
+        }
+        line(frame, format!("└{}┘", "─".repeat(inner)));
+        None
+    }
+}
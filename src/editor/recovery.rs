@@ -0,0 +1,85 @@
+use crate::editor::buffer::Buffer;
+use crate::editor::swap::flat_name;
+use chrono::Local;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread::{self, JoinHandle};
+
+/// What the names of the threads `spawn_worker` starts begin with
+const WORKER: &str = "r-edit ";
+
+thread_local! {
+    /// What the last panic on this thread said, with its backtrace
+    static REPORT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Keeps what `info` says, with a backtrace, for `take_report`. Called
+/// from the panic hook, on the thread that panicked.
+pub fn record_panic(info: &PanicHookInfo) {
+    let report =
+        format!("{info}\n\nBacktrace:\n{}", Backtrace::force_capture());
+    REPORT.with(|last| *last.borrow_mut() = Some(report));
+}
+
+/// What the last panic on this thread said, if the panic hook kept it
+pub fn take_report() -> Option<String> {
+    REPORT.with(|last| last.borrow_mut().take())
+}
+
+/// Runs `task` on a thread of its own, named after `name`, which the
+/// panic hook knows to work beside the editor rather than be it
+///
+/// # Panics
+///
+/// If the thread can not be started, like `thread::spawn`
+pub fn spawn_worker<F>(name: &str, task: F) -> JoinHandle<()>
+where
+    F: FnOnce() + Send + 'static,
+{
+    thread::Builder::new()
+        .name(format!("{WORKER}{name}"))
+        .spawn(task)
+        .expect("failed to spawn thread")
+}
+
+/// Whether the current thread was started by `spawn_worker`
+pub fn is_worker() -> bool {
+    thread::current()
+        .name()
+        .is_some_and(|name| name.starts_with(WORKER))
+}
+
+/// Writes `report` to `panic.txt` in a new directory in `dir`, named
+/// after the time and the process, and each of `buffers` next to it,
+/// named after the absolute path of its file like swap files are.
+/// Returns the directory, and where each buffer went or why it could
+/// not be written. Buffers that fail do not keep the others from being
+/// written.
+pub fn save(
+    dir: &Path,
+    report: &str,
+    buffers: &[(Option<PathBuf>, &Buffer)],
+) -> io::Result<(PathBuf, Vec<io::Result<PathBuf>>)> {
+    let name =
+        format!("{}-{}", Local::now().format("%Y%m%d-%H%M%S"), process::id());
+    let dir = dir.join(name);
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("panic.txt"), report)?;
+    let written = buffers
+        .iter()
+        .enumerate()
+        .map(|(index, (file, buffer))| {
+            let name = file
+                .as_deref()
+                .map_or_else(|| format!("unnamed-{}", index + 1), flat_name);
+            let path = dir.join(name);
+            buffer.write_file(&path.to_string_lossy()).map(|()| path)
+        })
+        .collect();
+    Ok((dir, written))
+}
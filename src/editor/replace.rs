@@ -0,0 +1,385 @@
+use crate::editor::frame::Frame;
+use crate::editor::grep::GrepMatch;
+use crate::editor::terminal::{Position, Size};
+use crate::editor::theme::{Element, Theme};
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// New text for lines, by their number, each may span lines
+pub type LineEdits = Vec<(usize, String)>;
+
+#[derive(Debug, Clone)]
+/// One match of a search in files with what replacing it gives
+pub struct Change {
+    pub found: GrepMatch,
+    /// The line as it is
+    pub old: String,
+    /// The line with only this match replaced
+    pub new: String,
+    /// Whether it is to be made, the user can leave it out
+    pub included: bool,
+}
+
+/// The line `old` with the match of `regex` from character `start` on
+/// replaced, `None` if it does not match there anymore
+fn replace_at(
+    regex: &Regex,
+    replacement: &str,
+    old: &str,
+    start: usize,
+) -> Option<String> {
+    let byte = old.char_indices().nth(start).map_or(old.len(), |(i, _)| i);
+    let captures = regex.captures_at(old, byte)?;
+    let found = captures.get(0)?;
+    if found.start() != byte {
+        return None;
+    }
+    let mut new = old[..byte].to_string();
+    captures.expand(replacement, &mut new);
+    new.push_str(&old[found.end()..]);
+    Some(new)
+}
+
+/// Replacing the matches of a search in files. The changes are previewed
+/// in the results panel, file by file, before any file is touched.
+pub struct Replace {
+    /// Where the search started, paths are shown from there
+    root: PathBuf,
+    regex: Regex,
+    /// The Vim replacement `:replace` was given, `&` for the match and
+    /// `\1`..`\9` for its groups, turned for `Captures::expand`
+    replacement: String,
+    changes: Vec<Change>,
+    selected: usize,
+    /// First row shown
+    scroll: usize,
+}
+
+impl Replace {
+    /// The changes replacing each of `matches`, found with `regex`, gives.
+    /// `lines` reads a file, from its buffer if it is open. Matches that
+    /// are not there anymore are left out.
+    pub fn new(
+        root: PathBuf,
+        regex: Regex,
+        replacement: String,
+        matches: &[GrepMatch],
+        mut lines: impl FnMut(&Path) -> Vec<String>,
+    ) -> Self {
+        let mut files: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        let changes = matches
+            .iter()
+            .filter_map(|found| {
+                let old = files
+                    .entry(found.path.clone())
+                    .or_insert_with(|| lines(&found.path))
+                    .get(found.line)?
+                    .clone();
+                let new = replace_at(&regex, &replacement, &old, found.start)?;
+                Some(Change {
+                    found: found.clone(),
+                    old,
+                    new,
+                    included: true,
+                })
+            })
+            .collect();
+        Self {
+            root,
+            regex,
+            replacement,
+            changes,
+            selected: 0,
+            scroll: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// How many changes are to be made
+    pub fn included_count(&self) -> usize {
+        self.changes.iter().filter(|change| change.included).count()
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.changes.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn selected(&self) -> Option<&Change> {
+        self.changes.get(self.selected)
+    }
+
+    /// Leaves the selected change out, or takes it back in
+    pub fn toggle(&mut self) {
+        if let Some(change) = self.changes.get_mut(self.selected) {
+            change.included = !change.included;
+        }
+    }
+
+    /// Leaves every change in the file of the selected one out, or takes
+    /// them back in if they all are
+    pub fn toggle_file(&mut self) {
+        let Some(path) = self.selected().map(|c| c.found.path.clone()) else {
+            return;
+        };
+        let in_file = |change: &&mut Change| change.found.path == path;
+        let include =
+            self.changes.iter_mut().filter(in_file).all(|c| !c.included);
+        for change in self.changes.iter_mut().filter(in_file) {
+            change.included = include;
+        }
+    }
+
+    /// The included changes by file, each as the new text of the lines it
+    /// changes, numbered as before. Every line is checked against
+    /// `lines` first, so nothing is made of a file that changed since.
+    pub fn edits(
+        &self,
+        mut lines: impl FnMut(&Path) -> Vec<String>,
+    ) -> Result<Vec<(PathBuf, LineEdits)>, String> {
+        let mut files: Vec<(PathBuf, LineEdits)> = Vec::new();
+        let mut current = Vec::new();
+        for change in self.changes.iter().filter(|change| change.included) {
+            let path = &change.found.path;
+            if files.last().is_none_or(|(last, _)| last != path) {
+                current = lines(path);
+                files.push((path.clone(), Vec::new()));
+            }
+            let y = change.found.line;
+            if current.get(y) != Some(&change.old) {
+                return Err(format!(
+                    "{} changed since the search",
+                    self.display(path)
+                ));
+            }
+            let Some((_, edits)) = files.last_mut() else {
+                continue;
+            };
+            // Later matches on the same line go into what the earlier
+            // ones left; they are to the right of them
+            match edits.last_mut() {
+                Some((line, text)) if *line == y => {
+                    let end = change.old.chars().count() - change.found.start;
+                    let start = text.chars().count() - end;
+                    *text =
+                        replace_at(&self.regex, &self.replacement, text, start)
+                            .unwrap_or_else(|| text.clone());
+                }
+                _ => edits.push((y, change.new.clone())),
+            }
+        }
+        Ok(files)
+    }
+
+    /// `path` from where the search started
+    fn display(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+
+    /// Draws the changes into a frame of `size`, each file with its
+    /// changes under it as the line before and after, over a status
+    /// line. Left out changes are dimmed, the selected one is marked
+    /// while the panel has the focus.
+    pub fn draw(&mut self, size: Size, theme: &Theme, focused: bool) -> Frame {
+        let mut frame = Frame::with_style(size, theme.element(Element::Text));
+        // Every row with its text, style and the change it belongs to
+        let mut rows: Vec<(String, Element, Option<usize>)> = Vec::new();
+        for (index, change) in self.changes.iter().enumerate() {
+            let path = &change.found.path;
+            let first =
+                index == 0 || self.changes[index - 1].found.path != *path;
+            if first {
+                rows.push((self.display(path), Element::Text, None));
+            }
+            let mark = if change.included { 'x' } else { ' ' };
+            let place = format!(
+                "  [{mark}] {}:{} ",
+                change.found.line + 1,
+                change.found.start + 1
+            );
+            let pad = " ".repeat(place.chars().count());
+            let (removed, added) = if change.included {
+                (Element::DiffRemoved, Element::DiffAdded)
+            } else {
+                (Element::NonText, Element::NonText)
+            };
+            let old = format!("{place}- {}", change.old.trim());
+            let new = format!("{pad}+ {}", change.new.trim());
+            rows.push((old.replace('\t', " "), removed, Some(index)));
+            rows.push((new.replace('\t', " "), added, Some(index)));
+        }
+
+        let height = size.height.saturating_sub(1);
+        let selected_row = rows
+            .iter()
+            .position(|(_, _, change)| *change == Some(self.selected))
+            .unwrap_or(0);
+        if selected_row < self.scroll {
+            // With the file above it when it is the first of its file
+            self.scroll = selected_row.saturating_sub(1);
+        } else if height > 1 && selected_row + 2 > self.scroll + height {
+            self.scroll = selected_row + 2 - height;
+        }
+        for (y, (text, element, change)) in
+            rows.iter().skip(self.scroll).take(height).enumerate()
+        {
+            let at = Position { x: 0, y };
+            frame.print(at, text);
+            let element = if focused && *change == Some(self.selected) {
+                Element::Selection
+            } else {
+                *element
+            };
+            frame.patch_style(at, size.width, theme.element(element));
+        }
+
+        let status = format!(
+            " Replace {} with {:?}: {} of {} matches",
+            self.regex,
+            self.replacement,
+            self.included_count(),
+            self.changes.len()
+        );
+        let style = if focused {
+            Element::StatusBar
+        } else {
+            Element::StatusBarInactive
+        };
+        let at = Position { x: 0, y: height };
+        frame.print(at, &status);
+        frame.patch_style(at, size.width, theme.element(style));
+        frame
+    }
+
+    /// Where the caret goes in the frame `draw` made, at the start of the
+    /// selected change
+    pub fn caret(&self) -> Position {
+        let files = self
+            .changes
+            .get(..=self.selected)
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .filter(|(index, change)| {
+                *index == 0
+                    || self.changes[index - 1].found.path != change.found.path
+            })
+            .count();
+        let row = files + self.selected * 2;
+        Position {
+            x: 0,
+            y: row.saturating_sub(self.scroll),
+        }
+    }
+}
+
+/// `text` with its lines replaced by `edits`. Every line keeps the line
+/// break it had, `\r\n` or `\n` or none at the end. The lines an edit
+/// adds break like the line, or the file for its last line.
+pub fn edit_text(text: &str, edits: &[(usize, String)]) -> String {
+    let edits: HashMap<usize, &str> =
+        edits.iter().map(|(y, new)| (*y, new.as_str())).collect();
+    let crlf = text.contains("\r\n");
+    let mut edited = String::with_capacity(text.len());
+    for (y, line) in text.split_inclusive('\n').enumerate() {
+        let Some(new) = edits.get(&y) else {
+            edited.push_str(line);
+            continue;
+        };
+        let content = line.strip_suffix('\n').unwrap_or(line);
+        let content = content.strip_suffix('\r').unwrap_or(content);
+        let ending = &line[content.len()..];
+        if ending == "\r\n" || ending.is_empty() && crlf {
+            edited.push_str(&new.replace('\n', "\r\n"));
+        } else {
+            edited.push_str(new);
+        }
+        edited.push_str(ending);
+    }
+    edited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(path: &str, line: usize, start: usize) -> GrepMatch {
+        GrepMatch {
+            path: PathBuf::from(path),
+            line,
+            start,
+            end: start + 3,
+            text: String::new(),
+        }
+    }
+
+    fn lines(text: &[&str]) -> Vec<String> {
+        text.iter().map(|&line| String::from(line)).collect()
+    }
+
+    /// Replacing `foo` with `quux` at `matches` in the file `a`, holding
+    /// `text`
+    fn replace(text: &[&str], matches: &[GrepMatch]) -> Replace {
+        let regex = Regex::new("foo").unwrap();
+        let replacement = String::from("quux");
+        Replace::new(PathBuf::new(), regex, replacement, matches, |_| {
+            lines(text)
+        })
+    }
+
+    #[test]
+    fn matches_on_one_line_make_one_edit() {
+        let text = ["foo foo", "bar foo"];
+        let matches = [found("a", 0, 0), found("a", 0, 4), found("a", 1, 4)];
+        let edits = replace(&text, &matches).edits(|_| lines(&text));
+        let expected =
+            vec![(0, String::from("quux quux")), (1, "bar quux".into())];
+        assert_eq!(edits, Ok(vec![(PathBuf::from("a"), expected)]));
+    }
+
+    #[test]
+    fn left_out_matches_are_not_made() {
+        let text = ["foo foo"];
+        let matches = [found("a", 0, 0), found("a", 0, 4)];
+        let mut replace = replace(&text, &matches);
+        replace.toggle();
+        let edits = replace.edits(|_| lines(&text)).unwrap();
+        assert_eq!(edits[0].1, [(0, String::from("foo quux"))]);
+        replace.toggle_file();
+        assert_eq!(replace.edits(|_| lines(&text)), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn matches_gone_since_the_search_are_left_out() {
+        let replace = replace(&["foo", "bar"], &[found("a", 1, 0)]);
+        assert!(replace.is_empty());
+    }
+
+    #[test]
+    fn files_changed_since_the_search_are_not_edited() {
+        let replace = replace(&["foo"], &[found("a", 0, 0)]);
+        let edits = replace.edits(|_| lines(&["food"]));
+        assert_eq!(edits, Err(String::from("a changed since the search")));
+    }
+
+    #[test]
+    fn edited_text_keeps_its_line_breaks() {
+        let edits = [(1, String::from("x\ny"))];
+        assert_eq!(edit_text("a\r\nfoo\r\nb", &edits), "a\r\nx\r\ny\r\nb");
+        assert_eq!(edit_text("a\r\nfoo", &edits), "a\r\nx\r\ny");
+        assert_eq!(edit_text("a\nfoo", &edits), "a\nx\ny");
+        assert_eq!(edit_text("a\nfoo\n", &edits), "a\nx\ny\n");
+    }
+}
@@ -0,0 +1,181 @@
+use crate::editor::frame::Frame;
+use crate::editor::grep::GrepMatch;
+use crate::editor::terminal::{Position, Size};
+use crate::editor::theme::{Element, Theme};
+use regex::Regex;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// Rows of the results panel with its status line, at most half the
+/// screen
+pub const RESULTS_HEIGHT: usize = 10;
+
+/// Panel under the panes listing what a search in files found, one
+/// `file:line:col: text` row per match, filled in as the search goes
+pub struct Results {
+    /// Where the search started, paths are shown from there
+    root: PathBuf,
+    /// What was searched for
+    regex: Regex,
+    matches: Vec<GrepMatch>,
+    selected: usize,
+    scroll: usize,
+    /// Whether the selected match has been jumped to, so stepping to the
+    /// next one starts with it
+    visited: bool,
+    searching: bool,
+}
+
+impl Results {
+    pub fn new(root: PathBuf, regex: Regex) -> Self {
+        Self {
+            root,
+            regex,
+            matches: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            visited: false,
+            searching: true,
+        }
+    }
+
+    /// Adds matches the search found since the last time
+    pub fn extend(&mut self, matches: Vec<GrepMatch>) {
+        self.matches.extend(matches);
+    }
+
+    /// The search is over, every match is in
+    pub fn finish(&mut self) {
+        self.searching = false;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+
+    pub fn root(&self) -> &PathBuf {
+        &self.root
+    }
+
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    pub fn matches(&self) -> &[GrepMatch] {
+        &self.matches
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// The selected match, to jump to it
+    pub fn visit(&mut self) -> Option<&GrepMatch> {
+        self.visited = true;
+        self.matches.get(self.selected)
+    }
+
+    /// Selects the match after the one last jumped to, or before it when
+    /// not `forward`, going around at the ends. The first step takes the
+    /// selected match itself.
+    pub fn step(&mut self, forward: bool) -> Option<&GrepMatch> {
+        let count = self.matches.len();
+        if count == 0 {
+            return None;
+        }
+        if self.visited {
+            self.selected = if forward {
+                (self.selected + 1) % count
+            } else {
+                (self.selected + count - 1) % count
+            };
+        }
+        self.visit()
+    }
+
+    /// How far along the selected match is, like `3/12`
+    pub fn position(&self) -> String {
+        format!("{}/{}", self.selected + 1, self.matches.len())
+    }
+
+    /// A row of the list
+    fn label(&self, found: &GrepMatch) -> String {
+        let path = found.path.strip_prefix(&self.root).unwrap_or(&found.path);
+        format!(
+            "{}:{}:{}: {}",
+            path.display(),
+            found.line + 1,
+            found.start + 1,
+            found.text
+        )
+    }
+
+    /// Draws the list into a frame of `size`, scrolled to the selected
+    /// match, over a status line with what was searched for. The
+    /// selected row is marked while the panel has the focus.
+    pub fn draw(&mut self, size: Size, theme: &Theme, focused: bool) -> Frame {
+        let mut frame = Frame::with_style(size, theme.element(Element::Text));
+        let rows = size.height.saturating_sub(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if rows > 0 && self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+        for (y, (index, found)) in self
+            .matches
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(rows)
+            .enumerate()
+        {
+            frame.print(Position { x: 0, y }, &self.label(found));
+            if focused && index == self.selected {
+                let selection = theme.element(Element::Selection);
+                frame.patch_style(Position { x: 0, y }, size.width, selection);
+            }
+        }
+        let files: HashSet<&PathBuf> =
+            self.matches.iter().map(|found| &found.path).collect();
+        let progress = if self.searching { ", searching…" } else { "" };
+        let status = format!(
+            " grep {}: {} in {}{progress}",
+            self.regex,
+            count(self.matches.len(), "match", "matches"),
+            count(files.len(), "file", "files"),
+        );
+        let style = if focused {
+            Element::StatusBar
+        } else {
+            Element::StatusBarInactive
+        };
+        let y = rows;
+        frame.print(Position { x: 0, y }, &status);
+        frame.patch_style(
+            Position { x: 0, y },
+            size.width,
+            theme.element(style),
+        );
+        frame
+    }
+
+    /// Where the caret goes in the frame `draw` made, at the start of the
+    /// selected row
+    pub fn caret(&self) -> Position {
+        Position {
+            x: 0,
+            y: self.selected.saturating_sub(self.scroll),
+        }
+    }
+}
+
+/// `n` and the noun for that many, like `1 file` or `3 files`
+fn count(n: usize, one: &str, many: &str) -> String {
+    format!("{n} {}", if n == 1 { one } else { many })
+}
@@ -0,0 +1,210 @@
+use crate::editor::event_source::{EventSource, Input};
+use crate::editor::terminal::Size;
+use crossterm::event::Event;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const SESSION_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// First line of a session file, describing where it was recorded
+pub struct Header {
+    pub version: u32,
+    pub width: u16,
+    pub height: u16,
+    /// The file that was being edited
+    pub file: String,
+}
+
+impl Header {
+    pub fn new(size: Size, file: &str) -> Self {
+        Self {
+            version: SESSION_VERSION,
+            width: u16::try_from(size.width).unwrap_or(u16::MAX),
+            height: u16::try_from(size.height).unwrap_or(u16::MAX),
+            file: file.to_string(),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        Size {
+            height: usize::from(self.height),
+            width: usize::from(self.width),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// One recorded event, one per line after the `Header`
+pub struct Entry {
+    /// Milliseconds since the recording started
+    pub at_ms: u64,
+    pub event: Event,
+}
+
+/// Writes a session file, the header first and then one `Entry` per event
+pub struct SessionWriter {
+    writer: BufWriter<File>,
+    started: Instant,
+}
+
+impl SessionWriter {
+    pub fn create(path: &Path, header: &Header) -> Result<Self, Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, header)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(Self {
+            writer,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn write(&mut self, event: &Event) -> Result<(), Error> {
+        let at_ms = u64::try_from(self.started.elapsed().as_millis())
+            .unwrap_or(u64::MAX);
+        let entry = Entry {
+            at_ms,
+            event: event.clone(),
+        };
+        serde_json::to_writer(&mut self.writer, &entry)?;
+        self.writer.write_all(b"\n")?;
+        // Flushed right away, the session matters most when we crash
+        self.writer.flush()
+    }
+}
+
+/// Passes events from another source through, writing each of them to a
+/// session file along the way
+pub struct RecordingEvents {
+    inner: Box<dyn EventSource>,
+    writer: SessionWriter,
+}
+
+impl RecordingEvents {
+    pub fn new(inner: Box<dyn EventSource>, writer: SessionWriter) -> Self {
+        Self { inner, writer }
+    }
+}
+
+impl EventSource for RecordingEvents {
+    fn next_event(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<Input, Error> {
+        let input = self.inner.next_event(timeout)?;
+        if let Input::Event(event) = &input
+            && let Err(e) = self.writer.write(event)
+        {
+            debug!("Error recording event: {e}");
+        }
+        Ok(input)
+    }
+}
+
+/// Reads a session file written by `SessionWriter`
+pub fn read_session(path: &Path) -> Result<(Header, Vec<Entry>), Error> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header: Header = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => {
+            return Err(Error::new(ErrorKind::InvalidData, "Empty session"));
+        }
+    };
+    if header.version != SESSION_VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Unsupported session version {}", header.version),
+        ));
+    }
+
+    let mut entries = Vec::new();
+    for (index, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line).map_err(|e| {
+            // +2: one for the header, one because lines count from 1
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Line {}: {e}", index + 2),
+            )
+        })?;
+        entries.push(entry);
+    }
+
+    Ok((header, entries))
+}
+
+/// Plays recorded events back. When `paced`, events arrive with the same
+/// delays as when they were recorded, otherwise as fast as asked for.
+/// Once exhausted, events come from `then` if set.
+pub struct ReplayEvents {
+    entries: VecDeque<Entry>,
+    paced: bool,
+    started: Instant,
+    then: Option<Box<dyn EventSource>>,
+}
+
+impl ReplayEvents {
+    /// Starts with a resize to the recorded size, so the editor lays
+    /// itself out exactly as it did while recording
+    pub fn new(header: &Header, entries: Vec<Entry>, paced: bool) -> Self {
+        let mut entries: VecDeque<Entry> = entries.into();
+        entries.push_front(Entry {
+            at_ms: 0,
+            event: Event::Resize(header.width, header.height),
+        });
+        Self {
+            entries,
+            paced,
+            started: Instant::now(),
+            then: None,
+        }
+    }
+
+    /// Continues with events from `source` once the replay is over
+    #[must_use]
+    pub fn then(mut self, source: Box<dyn EventSource>) -> Self {
+        self.then = Some(source);
+        self
+    }
+}
+
+impl EventSource for ReplayEvents {
+    fn next_event(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<Input, Error> {
+        let Some(entry) = self.entries.front() else {
+            return match &mut self.then {
+                Some(source) => source.next_event(timeout),
+                None => Ok(Input::Closed),
+            };
+        };
+
+        if self.paced {
+            let due = self.started + Duration::from_millis(entry.at_ms);
+            let wait = due.saturating_duration_since(Instant::now());
+            if let Some(timeout) = timeout
+                && timeout < wait
+            {
+                sleep(timeout);
+                return Ok(Input::Timeout);
+            }
+            sleep(wait);
+        }
+
+        Ok(self
+            .entries
+            .pop_front()
+            .map_or(Input::Closed, |entry| Input::Event(entry.event)))
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+/// First line of every swap file, tells them from anything else
+const MAGIC: &str = "r-edit swap file";
+
+#[derive(Debug, Clone)]
+/// What another editor journaled of a file it had unsaved changes to, and
+/// left behind, most likely when it crashed
+pub struct SwapFile {
+    /// Where the swap file is
+    pub path: PathBuf,
+    /// The process that wrote it
+    pub pid: u32,
+    /// The text of the buffer when it was last journaled
+    pub lines: Vec<String>,
+    pub modified: Option<SystemTime>,
+}
+
+impl SwapFile {
+    /// Whether the process that wrote it still runs, in which case another
+    /// editor may have the file open right now. Only known on Linux.
+    pub fn is_in_use(&self) -> bool {
+        cfg!(target_os = "linux")
+            && Path::new("/proc").join(self.pid.to_string()).exists()
+    }
+
+    /// How long ago it was last written, like `5 minutes ago`
+    pub fn age(&self) -> String {
+        let Some(elapsed) = self.modified.and_then(|m| m.elapsed().ok()) else {
+            return String::from("some time ago");
+        };
+        let seconds = elapsed.as_secs();
+        let (n, unit) = match seconds {
+            0..60 => return String::from("just now"),
+            60..3600 => (seconds / 60, "minute"),
+            3600..86400 => (seconds / 3600, "hour"),
+            _ => (seconds / 86400, "day"),
+        };
+        format!("{n} {unit}{} ago", if n == 1 { "" } else { "s" })
+    }
+}
+
+/// The absolute path `file` as one file name, its slashes turned into
+/// `%` as Vim names swap files
+pub fn flat_name(file: &Path) -> String {
+    file.to_string_lossy().replace('/', "%")
+}
+
+/// The swap file in `dir` that process `pid` keeps for the file at
+/// `file`, an absolute path. Each editor has its own, so two editing one
+/// file do not write over each other's.
+pub fn swap_path(dir: &Path, file: &Path, pid: u32) -> PathBuf {
+    dir.join(format!("{}.{pid}.swp", flat_name(file)))
+}
+
+/// Journals `lines`, the unsaved text of `file`, to its swap file in
+/// `dir`. The swap file is replaced in one step, so a crash while writing
+/// leaves the one before.
+pub fn write(dir: &Path, file: &Path, lines: &[String]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = swap_path(dir, file, process::id());
+    let mut text = format!(
+        "{MAGIC}\npid {}\nfile {}\n\n",
+        process::id(),
+        file.display()
+    );
+    for line in lines {
+        text.push_str(line);
+        text.push('\n');
+    }
+    let temporary = path.with_extension("swp.new");
+    fs::write(&temporary, text)?;
+    fs::rename(temporary, path)
+}
+
+/// Removes this editor's swap file of `file` in `dir`, if there is one
+pub fn remove(dir: &Path, file: &Path) {
+    let _ = fs::remove_file(swap_path(dir, file, process::id()));
+}
+
+/// The swap file of `file` in `dir` that another editor wrote, if there is
+/// one. Of several, the one written last.
+pub fn find(dir: &Path, file: &Path) -> Option<SwapFile> {
+    let prefix = format!("{}.", flat_name(file));
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let pid = name
+                .to_str()?
+                .strip_prefix(&prefix)?
+                .strip_suffix(".swp")?
+                .parse::<u32>()
+                .ok()?;
+            (pid != process::id()).then(|| read(&entry.path()))?
+        })
+        .max_by_key(|swap| swap.modified)
+}
+
+/// The swap file at `path`, if it is one
+fn read(path: &Path) -> Option<SwapFile> {
+    let text = fs::read_to_string(path).ok()?;
+    let mut lines = text.lines();
+    if lines.next()? != MAGIC {
+        return None;
+    }
+    let pid = lines.next()?.strip_prefix("pid ")?.parse().ok()?;
+    if pid == process::id() {
+        return None;
+    }
+    lines.next()?.strip_prefix("file ")?;
+    if !lines.next()?.is_empty() {
+        return None;
+    }
+    let lines = lines.map(String::from).collect();
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+    Some(SwapFile {
+        path: path.to_path_buf(),
+        pid,
+        lines,
+        modified,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory named after `name` in the temporary directory
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("r-edit-test-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes the swap file process `pid` would keep of `file` in `dir`
+    fn write_as(dir: &Path, file: &Path, pid: u32, line: &str) -> PathBuf {
+        let path = swap_path(dir, file, pid);
+        let text =
+            format!("{MAGIC}\npid {pid}\nfile {}\n\n{line}\n", file.display());
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn names_swap_files_after_the_file_and_the_process() {
+        let dir = Path::new("/swap");
+        let path = swap_path(dir, Path::new("/home/me/notes.txt"), 42);
+        assert_eq!(path, Path::new("/swap/%home%me%notes.txt.42.swp"));
+    }
+
+    #[test]
+    fn editors_keep_swap_files_of_their_own() {
+        let dir = temp_dir("swap-own");
+        let file = Path::new("/home/me/notes.txt");
+        let other = write_as(&dir, file, u32::MAX, "theirs");
+        write(&dir, file, &[String::from("mine")]).unwrap();
+        assert!(swap_path(&dir, file, process::id()).exists());
+
+        let found = find(&dir, file).unwrap();
+        assert_eq!(found.path, other);
+        assert_eq!(found.pid, u32::MAX);
+        assert_eq!(found.lines, ["theirs"]);
+
+        remove(&dir, file);
+        assert!(!swap_path(&dir, file, process::id()).exists());
+        assert!(other.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn finds_no_swap_file_of_its_own_or_of_other_files() {
+        let dir = temp_dir("swap-none");
+        let file = Path::new("/home/me/notes.txt");
+        write(&dir, file, &[String::from("mine")]).unwrap();
+        write_as(&dir, Path::new("/home/me/notes.txt.old"), u32::MAX, "");
+        fs::write(dir.join("%home%me%notes.txt.7.swp"), "not a swap file")
+            .unwrap();
+        assert!(find(&dir, file).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,428 @@
+use crate::editor::buffer::{Buffer, Edit};
+use crate::editor::folds::Fold;
+use crate::editor::highlight::{Language, Scope, Span};
+use crate::editor::terminal::Location;
+use log::debug;
+use std::ops::Range;
+use tree_sitter::{
+    InputEdit, Node, Parser, Point, Query, QueryCursor, StreamingIterator, Tree,
+};
+
+/// The grammar compiled in for `language` and its highlight query
+fn grammar(
+    language: Language,
+) -> Option<(tree_sitter::Language, &'static str)> {
+    let grammar = match language {
+        Language::Rust => (
+            tree_sitter_rust::LANGUAGE.into(),
+            tree_sitter_rust::HIGHLIGHTS_QUERY,
+        ),
+        Language::Toml => (
+            tree_sitter_toml_ng::LANGUAGE.into(),
+            tree_sitter_toml_ng::HIGHLIGHTS_QUERY,
+        ),
+        Language::Json => (
+            tree_sitter_json::LANGUAGE.into(),
+            tree_sitter_json::HIGHLIGHTS_QUERY,
+        ),
+        Language::Shell => (
+            tree_sitter_bash::LANGUAGE.into(),
+            tree_sitter_bash::HIGHLIGHT_QUERY,
+        ),
+        Language::Python => (
+            tree_sitter_python::LANGUAGE.into(),
+            tree_sitter_python::HIGHLIGHTS_QUERY,
+        ),
+        Language::Markdown => return None,
+    };
+    Some(grammar)
+}
+
+/// What a capture name of the highlight queries means, `None` for the
+/// ones left uncolored
+fn scope(capture: &str) -> Option<Scope> {
+    let scope = match capture {
+        "function.macro" => Scope::Macro,
+        "property" | "string.special.key" => Scope::Key,
+        "constant" | "constant.builtin" | "boolean" => Scope::Constant,
+        "variable.builtin" => Scope::Keyword,
+        _ => match capture.split('.').next()? {
+            "comment" => Scope::Comment,
+            "string" | "escape" => Scope::String,
+            "number" => Scope::Number,
+            "keyword" => Scope::Keyword,
+            "type" | "constructor" => Scope::Type,
+            "function" => Scope::Function,
+            "attribute" => Scope::Attribute,
+            "label" => Scope::Label,
+            "punctuation" => Scope::Punctuation,
+            _ => return None,
+        },
+    };
+    Some(scope)
+}
+
+fn input_edit(edit: &Edit) -> InputEdit {
+    InputEdit {
+        start_byte: edit.start_byte.offset,
+        old_end_byte: edit.old_end_byte.offset,
+        new_end_byte: edit.new_end_byte.offset,
+        start_position: Point::new(edit.start.y, edit.start_byte.column),
+        old_end_position: Point::new(edit.old_end.y, edit.old_end_byte.column),
+        new_end_position: Point::new(edit.new_end.y, edit.new_end_byte.column),
+    }
+}
+
+fn point(buffer: &Buffer, location: Location) -> Point {
+    Point::new(location.y, buffer.byte_column(location))
+}
+
+fn location(buffer: &Buffer, point: Point) -> Location {
+    buffer.location_of_byte(point.row, point.column)
+}
+
+/// A tree-sitter parse of a buffer, kept up to date incrementally
+pub struct SyntaxTree {
+    parser: Parser,
+    highlights: Query,
+    tree: Option<Tree>,
+    /// The text the tree was parsed from, every line ending in `\n`
+    text: String,
+    /// The buffer revision the tree was parsed from
+    revision: Option<u64>,
+}
+
+impl SyntaxTree {
+    /// A parser for `language`, if there is a grammar for it
+    pub fn new(language: Language) -> Option<Self> {
+        let (grammar, highlights) = grammar(language)?;
+        let mut parser = Parser::new();
+        if let Err(e) = parser.set_language(&grammar) {
+            debug!("Error loading the {} grammar: {e}", language.name());
+            return None;
+        }
+        let highlights = match Query::new(&grammar, highlights) {
+            Ok(query) => query,
+            Err(e) => {
+                debug!("Error in the {} highlights: {e}", language.name());
+                return None;
+            }
+        };
+        Some(Self {
+            parser,
+            highlights,
+            tree: None,
+            text: String::new(),
+            revision: None,
+        })
+    }
+
+    /// Catches up with the edits made to `buffer`, reparsing only the
+    /// parts they touched
+    pub fn update(&mut self, buffer: &Buffer) {
+        if self.tree.is_some() && self.revision == Some(buffer.revision()) {
+            return;
+        }
+        let edits = self
+            .revision
+            .and_then(|revision| buffer.edits_since(revision));
+        match (&mut self.tree, edits) {
+            (Some(tree), Some(edits)) => {
+                for edit in &edits {
+                    tree.edit(&input_edit(edit));
+                }
+            }
+            _ => self.tree = None,
+        }
+        self.text = buffer
+            .lines
+            .iter()
+            .flat_map(|line| [line.as_str(), "\n"])
+            .collect();
+        self.tree = self.parser.parse(&self.text, self.tree.as_ref());
+        self.revision = Some(buffer.revision());
+        debug!("Parsed revision {}", buffer.revision());
+    }
+
+    fn root(&self) -> Option<Node<'_>> {
+        self.tree.as_ref().map(Tree::root_node)
+    }
+
+    /// Spans for each of `lines`, from the highlight query. Where several
+    /// patterns capture the same node the last one wins.
+    pub fn highlight(
+        &self,
+        buffer: &Buffer,
+        lines: Range<usize>,
+    ) -> Vec<Vec<Span>> {
+        let mut spans = vec![Vec::new(); lines.len()];
+        let Some(root) = self.root() else {
+            return spans;
+        };
+        let mut cursor = QueryCursor::new();
+        cursor.set_point_range(
+            Point::new(lines.start, 0)..Point::new(lines.end, 0),
+        );
+        // Node ranges with the pattern and capture they got
+        let mut nodes: Vec<(Range<usize>, usize, Option<Scope>)> = Vec::new();
+        let names = self.highlights.capture_names();
+        let mut matches =
+            cursor.matches(&self.highlights, root, self.text.as_bytes());
+        while let Some(found) = matches.next() {
+            for capture in found.captures {
+                let range = capture.node.byte_range();
+                let scope = scope(names[capture.index as usize]);
+                match nodes.iter_mut().find(|(r, _, _)| *r == range) {
+                    Some(node) if node.1 <= found.pattern_index => {
+                        *node = (range, found.pattern_index, scope);
+                    }
+                    Some(_) => {}
+                    None => nodes.push((range, found.pattern_index, scope)),
+                }
+            }
+        }
+        // Outer nodes first, so the ones inside them are drawn on top
+        nodes.sort_by_key(|(range, _, _)| {
+            (range.start, std::cmp::Reverse(range.end))
+        });
+
+        let line_starts: Vec<usize> = buffer
+            .lines
+            .iter()
+            .scan(0, |offset, line| {
+                let start = *offset;
+                *offset += line.len() + 1;
+                Some(start)
+            })
+            .collect();
+        for (range, _, scope) in nodes {
+            let Some(scope) = scope else {
+                continue;
+            };
+            let first = line_starts
+                .partition_point(|&s| s <= range.start)
+                .saturating_sub(1);
+            for y in first.max(lines.start)..lines.end {
+                let Some(&line_start) = line_starts.get(y) else {
+                    break;
+                };
+                if line_start >= range.end {
+                    break;
+                }
+                let line_len = buffer.lines[y].len();
+                let from = range.start.saturating_sub(line_start);
+                let to = (range.end - line_start).min(line_len);
+                if to <= from {
+                    continue;
+                }
+                spans[y - lines.start].push(Span {
+                    start: buffer.location_of_byte(y, from).x,
+                    end: buffer.location_of_byte(y, to).x,
+                    scope,
+                });
+            }
+        }
+        spans
+    }
+
+    /// The smallest named node around the text from `start` to `end`,
+    /// larger than it
+    fn enclosing(
+        &self,
+        buffer: &Buffer,
+        start: Location,
+        end: Location,
+    ) -> Option<Node<'_>> {
+        let (start, end) = (point(buffer, start), point(buffer, end));
+        let mut node =
+            self.root()?.named_descendant_for_point_range(start, end)?;
+        while node.start_position() == start && node.end_position() == end {
+            node = node.parent()?;
+        }
+        Some(node)
+    }
+
+    /// The text of the node around the selection from `start` to `end`,
+    /// to select it next
+    pub fn expand(
+        &self,
+        buffer: &Buffer,
+        start: Location,
+        end: Location,
+    ) -> Option<(Location, Location)> {
+        let node = self.enclosing(buffer, start, end)?;
+        Some((
+            location(buffer, node.start_position()),
+            location(buffer, node.end_position()),
+        ))
+    }
+
+    /// The start of the node `at` is in, or of its parent when `at` is
+    /// already at its start
+    pub fn parent(&self, buffer: &Buffer, at: Location) -> Option<Location> {
+        let at_point = point(buffer, at);
+        let mut node = self
+            .root()?
+            .named_descendant_for_point_range(at_point, at_point)?;
+        while node.start_position() == at_point {
+            node = node.parent()?;
+        }
+        Some(location(buffer, node.start_position()))
+    }
+
+    /// The start of the next or previous node next to the one at `at`
+    pub fn sibling(
+        &self,
+        buffer: &Buffer,
+        at: Location,
+        forward: bool,
+    ) -> Option<Location> {
+        let at_point = point(buffer, at);
+        let mut node = self
+            .root()?
+            .named_descendant_for_point_range(at_point, at_point)?;
+        // The largest node starting here, e.g. the whole function from
+        // its first keyword
+        while let Some(parent) = node.parent()
+            && parent.parent().is_some()
+            && parent.start_position() == node.start_position()
+        {
+            node = parent;
+        }
+        let sibling = if forward {
+            node.next_named_sibling()
+        } else {
+            node.prev_named_sibling()
+        }?;
+        Some(location(buffer, sibling.start_position()))
+    }
+
+    /// The lines of the smallest node spanning several lines around the
+    /// end of line `y`, to fold them away
+    pub fn fold(&self, buffer: &Buffer, y: usize) -> Option<Fold> {
+        let line = buffer.lines.get(y)?.trim_end();
+        if line.trim_start().is_empty() {
+            return None;
+        }
+        let at = Point::new(y, line.len() - 1);
+        let mut node = self.root()?.descendant_for_point_range(at, at)?;
+        // Folding the whole file is not much use
+        while node.parent().is_some() {
+            let end = node.end_position();
+            // A node ending with a line break ends on the line before
+            let last = if end.column == 0 {
+                end.row.saturating_sub(1)
+            } else {
+                end.row
+            };
+            if last > node.start_position().row {
+                return Some(Fold {
+                    first: node.start_position().row,
+                    last,
+                });
+            }
+            node = node.parent()?;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: [&str; 4] = ["fn main() {", "    let x = 1;", "}", ""];
+
+    fn parsed(lines: &[&str]) -> (SyntaxTree, Buffer) {
+        let mut buffer = Buffer::new();
+        for line in lines {
+            buffer.push((*line).to_string());
+        }
+        let mut tree = SyntaxTree::new(Language::Rust).unwrap();
+        tree.update(&buffer);
+        (tree, buffer)
+    }
+
+    fn at(x: usize, y: usize) -> Location {
+        Location { x, y }
+    }
+
+    /// The text and scope name of each span on line `y`
+    fn spans(
+        tree: &SyntaxTree,
+        buffer: &Buffer,
+        y: usize,
+    ) -> Vec<(String, &'static str)> {
+        tree.highlight(buffer, y..y + 1)[0]
+            .iter()
+            .map(|span| {
+                let text = buffer.text(at(span.start, y), at(span.end, y));
+                (text, span.scope.name())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn has_no_grammar_for_markdown() {
+        assert!(SyntaxTree::new(Language::Markdown).is_none());
+    }
+
+    #[test]
+    fn highlights_from_the_tree_and_follows_edits() {
+        let (mut tree, mut buffer) = parsed(&SOURCE);
+        assert!(spans(&tree, &buffer, 0).contains(&("fn".into(), "keyword")));
+        let line = |spans: &[(&str, &'static str)]| {
+            spans
+                .iter()
+                .map(|&(text, scope)| (text.to_string(), scope))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            spans(&tree, &buffer, 1),
+            line(&[
+                ("let", "keyword"),
+                ("1", "constant"),
+                (";", "punctuation")
+            ])
+        );
+
+        buffer.insert_str("\"é\"", at(12, 1));
+        buffer.delete(at(15, 1), at(16, 1));
+        tree.update(&buffer);
+        assert_eq!(buffer.lines[1], "    let x = \"é\";");
+        assert_eq!(
+            spans(&tree, &buffer, 1),
+            line(&[
+                ("let", "keyword"),
+                ("\"é\"", "string"),
+                (";", "punctuation")
+            ])
+        );
+    }
+
+    #[test]
+    fn expands_to_the_enclosing_nodes() {
+        let (tree, buffer) = parsed(&SOURCE);
+        let name = tree.expand(&buffer, at(8, 1), at(8, 1));
+        assert_eq!(name, Some((at(8, 1), at(9, 1))));
+        let statement = tree.expand(&buffer, at(8, 1), at(9, 1));
+        assert_eq!(statement, Some((at(4, 1), at(14, 1))));
+        assert_eq!(tree.parent(&buffer, at(8, 1)), Some(at(4, 1)));
+    }
+
+    #[test]
+    fn moves_between_siblings() {
+        let (tree, buffer) = parsed(&["fn a() {}", "fn b() {}"]);
+        assert_eq!(tree.sibling(&buffer, at(0, 0), true), Some(at(0, 1)));
+        assert_eq!(tree.sibling(&buffer, at(0, 1), false), Some(at(0, 0)));
+        assert_eq!(tree.sibling(&buffer, at(0, 1), true), None);
+    }
+
+    #[test]
+    fn folds_the_node_around_the_end_of_a_line() {
+        let (tree, buffer) = parsed(&SOURCE);
+        assert_eq!(tree.fold(&buffer, 0), Some(Fold { first: 0, last: 2 }));
+        assert_eq!(tree.fold(&buffer, 1), Some(Fold { first: 0, last: 2 }));
+        assert_eq!(tree.fold(&buffer, 3), None);
+    }
+}
@@ -1,30 +1,30 @@
+pub mod backend;
+pub mod colors;
+pub mod crossterm_backend;
+pub mod virtual_backend;
+
+use crate::editor::frame::{Frame, Style};
+use backend::Backend;
+use colors::ColorSupport;
 use core::fmt::Display;
-use crossterm::Command;
-use crossterm::cursor::{Hide, MoveTo, Show};
-use crossterm::execute;
-use crossterm::queue;
-use crossterm::style::Print;
-use crossterm::terminal::{
-    Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
-    disable_raw_mode, enable_raw_mode, size,
-};
+use crossterm_backend::CrosstermBackend;
+use log::{debug, info};
 use std::fmt;
-use std::io;
-use std::io::{Error, Write, stdout};
+use std::io::Error;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 ///  Height and Width of a Terminal
 pub struct Size {
     pub height: usize,
     pub width: usize,
 }
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 ///  Represents coordinates on a Screen
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 ///  Represents coordinates on a Document
 pub struct Location {
     pub x: usize,
@@ -37,143 +37,199 @@ impl Display for Location {
     }
 }
 
-impl Into<Position> for Location {
-    fn into(self) -> Position {
+impl From<Location> for Position {
+    fn from(location: Location) -> Self {
         Position {
-            x: self.x,
-            y: self.y,
+            x: location.x,
+            y: location.y,
         }
     }
 }
 
-impl Into<Location> for Position {
-    fn into(self) -> Location {
+impl From<Position> for Location {
+    fn from(position: Position) -> Self {
         Location {
-            x: self.x,
-            y: self.y,
+            x: position.x,
+            y: position.y,
         }
     }
 }
 
-pub struct Terminal;
+/// Draws frames onto a `Backend`, only sending the cells that changed
+/// since the previous frame
+pub struct Terminal {
+    backend: Box<dyn Backend>,
+    /// What is currently on the screen, `None` when unknown
+    previous_frame: Option<Frame>,
+    /// Colors are brought down to what the terminal can show
+    colors: ColorSupport,
+}
 
-impl Drop for Terminal {
-    fn drop(&mut self) {
-        match Terminal::terminate() {
-            Ok(_) => {}
-            Err(e) => {
-                println!("{}", e);
-            }
+impl Terminal {
+    /// A `Terminal` on `backend`, showing colors as they are
+    pub fn new(backend: Box<dyn Backend>) -> Self {
+        Self {
+            backend,
+            previous_frame: None,
+            colors: ColorSupport::TrueColor,
         }
     }
-}
 
-impl Terminal {
+    /// A `Terminal` on the real terminal through crossterm, with as many
+    /// colors as the environment says it has
     pub fn default() -> Self {
-        Self {}
-    }
-    pub fn new() -> Self {
-        Self::initialize();
-        Self {}
-    }
-    pub fn initialize() -> Result<(), Error> {
-        Self::enter_alternate_screen();
-        enable_raw_mode()?;
-        Self::clear_screen()?;
-        Self::move_caret_to(Position { x: 0, y: 0 })?;
-        Self::execute()?;
-        Ok(())
+        let mut terminal = Self::new(Box::new(CrosstermBackend::new()));
+        terminal.set_color_support(ColorSupport::detect());
+        terminal
     }
 
-    pub fn terminate() -> Result<(), Error> {
-        Self::execute()?;
-        disable_raw_mode()?;
-        Self::enter_alternate_screen();
-        Ok(())
+    pub fn color_support(&self) -> ColorSupport {
+        self.colors
     }
 
-    pub fn queue_command(command: impl Command) -> Result<(), Error> {
-        queue!(stdout(), command)?;
-        Ok(())
+    pub fn set_color_support(&mut self, colors: ColorSupport) {
+        info!("Terminal colors: {colors:?}");
+        self.colors = colors;
+        self.previous_frame = None;
     }
 
-    pub fn clear_screen() -> Result<(), Error> {
-        log::info!("Clearing screen");
-        Self::queue_command(Clear(ClearType::All))?;
+    pub fn initialize(&mut self) -> Result<(), Error> {
+        self.backend.initialize()?;
+        self.previous_frame = None;
+        self.backend.clear_screen()?;
+        self.backend.move_caret_to(Position { x: 0, y: 0 })?;
+        self.backend.execute()?;
         Ok(())
     }
 
-    pub fn clear_current_line() -> Result<(), Error> {
-        log::info!("Clearing current line");
-        Self::queue_command(Clear(ClearType::CurrentLine))?;
+    pub fn terminate(&mut self) -> Result<(), Error> {
+        self.backend.execute()?;
+        self.backend.terminate()?;
         Ok(())
     }
 
-    pub fn clear_up() -> Result<(), Error> {
-        Self::queue_command(Clear(ClearType::FromCursorUp))?;
-        Ok(())
-    }
+    /// Sends only the cells of `frame` that changed since the previous
+    /// frame. Without a previous frame, or after a resize, the screen is
+    /// cleared first.
+    pub fn present(&mut self, frame: Frame) -> Result<(), Error> {
+        let changes = match &self.previous_frame {
+            Some(previous) if previous.size() == frame.size() => {
+                frame.diff(previous)
+            }
+            _ => {
+                self.backend.clear_screen()?;
+                frame.diff(&Frame::new(frame.size()))
+            }
+        };
+        debug!("Presenting frame, {} changed runs", changes.len());
+
+        self.backend.hide_caret()?;
+        let mut style = None;
+        for change in &changes {
+            self.backend.move_caret_to(change.position)?;
+            if style != Some(change.style) {
+                self.backend.set_style(self.colors.style(change.style))?;
+                style = Some(change.style);
+            }
+            self.backend.print(&change.text)?;
+        }
+        if style.is_some_and(|style| style != Style::default()) {
+            self.backend.set_style(Style::default())?;
+        }
 
-    pub fn clear_down() -> Result<(), Error> {
-        log::info!("Clearing down");
-        Self::queue_command(Clear(ClearType::FromCursorDown))?;
+        self.previous_frame = Some(frame);
         Ok(())
     }
 
-    pub fn clear_right() -> Result<(), Error> {
-        Self::queue_command(Clear(ClearType::UntilNewLine))?;
-        Ok(())
+    /// Forgets what is on the screen, the next frame is drawn in full
+    pub fn invalidate(&mut self) {
+        self.previous_frame = None;
     }
 
-    pub fn move_caret_to(Position { x, y }: Position) -> Result<(), Error> {
-        Self::queue_command(MoveTo(x as u16, y as u16))?;
-        Ok(())
+    pub fn move_caret_to(&mut self, position: Position) -> Result<(), Error> {
+        self.backend.move_caret_to(position)
     }
 
-    pub fn show_caret() -> Result<(), Error> {
-        Self::queue_command(Show)?;
-        Ok(())
+    pub fn show_caret(&mut self) -> Result<(), Error> {
+        self.backend.show_caret()
     }
 
-    pub fn hide_caret() -> Result<(), Error> {
-        Self::queue_command(Hide)?;
-        Ok(())
+    pub fn hide_caret(&mut self) -> Result<(), Error> {
+        self.backend.hide_caret()
     }
 
     /// Returns the current size of this Terminal.
-    /// Edge Case for systems with `usize` < `u16`:
-    /// * A `Size` representing the terminal size.
-    /// Any coordinate `z` truncated to `usize` if `usize` < `z` < `u16`
-    pub fn size() -> Result<Size, Error> {
-        let (width, height) = size()?;
-        // clippy::as_conversions: see doc above
-        #[allow(clippy::as_conversions)]
-        let width = width as usize;
-        #[allow(clippy::as_conversions)]
-        let height = height as usize;
-        Ok(Size { height, width })
-    }
-
-    pub fn print(string: &str) -> Result<(), Error> {
-        Self::queue_command(Print(string))?;
-        Ok(())
-    }
-
-    pub fn insert(string: &str) -> Result<(), Error> {
-        Self::queue_command(Print(string))?;
-        Ok(())
+    pub fn size(&self) -> Result<Size, Error> {
+        self.backend.size()
     }
 
-    pub fn execute() -> Result<(), Error> {
-        stdout().flush()?;
-        Ok(())
+    pub fn execute(&mut self) -> Result<(), Error> {
+        self.backend.execute()
     }
+}
 
-    pub fn enter_alternate_screen() -> Result<(), Error> {
-        execute!(io::stdout(), EnterAlternateScreen)?;
-        Ok(())
-    }
-    pub fn leave_altenate_screen() {
-        execute!(io::stdout(), LeaveAlternateScreen);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use virtual_backend::{VirtualBackend, VirtualScreen};
+
+    fn terminal() -> (Terminal, VirtualScreen) {
+        let backend = VirtualBackend::new(Size {
+            width: 5,
+            height: 2,
+        });
+        let screen = backend.screen();
+        (Terminal::new(Box::new(backend)), screen)
+    }
+
+    fn frame(rows: [&str; 2]) -> Frame {
+        let mut frame = Frame::new(Size {
+            width: 5,
+            height: 2,
+        });
+        for (y, row) in rows.iter().enumerate() {
+            frame.print(Position { x: 0, y }, row);
+        }
+        frame
+    }
+
+    #[test]
+    fn presents_only_what_changed() {
+        let (mut terminal, screen) = terminal();
+        terminal.present(frame(["hello", "world"])).unwrap();
+        assert_eq!(screen.take_printed(), 10);
+        terminal.present(frame(["hello", "wordy"])).unwrap();
+        assert_eq!(screen.take_printed(), 2);
+        assert_eq!(screen.rows(), ["hello", "wordy"]);
+        terminal.present(frame(["hello", "wordy"])).unwrap();
+        assert_eq!(screen.take_printed(), 0);
+    }
+
+    #[test]
+    fn presents_everything_again_once_invalidated() {
+        let (mut terminal, screen) = terminal();
+        terminal.present(frame(["ab", "cd"])).unwrap();
+        screen.take_printed();
+        terminal.invalidate();
+        terminal.present(frame(["ab", "ce"])).unwrap();
+        assert_eq!(screen.take_printed(), 4);
+        assert_eq!(screen.rows(), ["ab", "ce"]);
+    }
+
+    #[test]
+    fn presents_colors_the_terminal_can_show() {
+        let (mut terminal, screen) = terminal();
+        terminal.set_color_support(ColorSupport::Ansi16);
+        let mut colored = frame(["ab", ""]);
+        let red = Style::foreground(crossterm::style::Color::Rgb {
+            r: 250,
+            g: 10,
+            b: 10,
+        });
+        colored.set_style(Position { x: 0, y: 0 }, 1, red);
+        terminal.present(colored).unwrap();
+        let style = screen.style(Position { x: 0, y: 0 });
+        assert_eq!(style.foreground, Some(crossterm::style::Color::Red));
+        assert_eq!(screen.style(Position { x: 1, y: 0 }), Style::default());
     }
 }
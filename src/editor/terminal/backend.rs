@@ -0,0 +1,22 @@
+use crate::editor::frame::Style;
+use crate::editor::terminal::{Position, Size};
+use std::io::Error;
+
+/// Low level operations a `Terminal` needs from whatever it draws on.
+/// Implemented by `CrosstermBackend` for a real terminal and by
+/// `VirtualBackend` for an in-memory screen.
+pub trait Backend {
+    fn initialize(&mut self) -> Result<(), Error>;
+    fn terminate(&mut self) -> Result<(), Error>;
+    fn clear_screen(&mut self) -> Result<(), Error>;
+    fn move_caret_to(&mut self, position: Position) -> Result<(), Error>;
+    fn show_caret(&mut self) -> Result<(), Error>;
+    fn hide_caret(&mut self) -> Result<(), Error>;
+    /// Sets the style of everything printed from now on
+    fn set_style(&mut self, style: Style) -> Result<(), Error>;
+    /// Prints `string` at the caret, moving the caret past it
+    fn print(&mut self, string: &str) -> Result<(), Error>;
+    fn size(&self) -> Result<Size, Error>;
+    /// Flushes everything queued so far
+    fn execute(&mut self) -> Result<(), Error>;
+}
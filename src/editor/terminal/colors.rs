@@ -0,0 +1,234 @@
+use crate::editor::frame::Style;
+use crossterm::style::Color;
+use std::env;
+
+/// The 16 basic colors in the order of their ANSI numbers
+const BASIC: [Color; 16] = [
+    Color::Black,
+    Color::DarkRed,
+    Color::DarkGreen,
+    Color::DarkYellow,
+    Color::DarkBlue,
+    Color::DarkMagenta,
+    Color::DarkCyan,
+    Color::Grey,
+    Color::DarkGrey,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+];
+
+/// Levels of each channel in the 6x6x6 color cube of the 256 colors
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// How many colors a terminal can show
+pub enum ColorSupport {
+    /// 24-bit RGB colors
+    TrueColor,
+    /// The xterm palette of 256 colors
+    Ansi256,
+    /// Only the 16 basic colors
+    Ansi16,
+}
+
+impl ColorSupport {
+    /// Guesses what the terminal supports from `COLORTERM` and `TERM`
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").ok();
+        let term = env::var("TERM").ok();
+        Self::from_env(colorterm.as_deref(), term.as_deref())
+    }
+
+    /// What `COLORTERM` and `TERM` set to these values say is supported
+    pub fn from_env(colorterm: Option<&str>, term: Option<&str>) -> Self {
+        let term = term.unwrap_or("");
+        if matches!(colorterm, Some("truecolor" | "24bit"))
+            || term.ends_with("-direct")
+        {
+            ColorSupport::TrueColor
+        } else if term.contains("256color") {
+            ColorSupport::Ansi256
+        } else {
+            ColorSupport::Ansi16
+        }
+    }
+
+    /// The color closest to `color` the terminal can show
+    pub fn downsample(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorSupport::Ansi256, Color::Rgb { r, g, b }) => {
+                Color::AnsiValue(ansi256((r, g, b)))
+            }
+            (ColorSupport::Ansi16, Color::Rgb { r, g, b }) => basic((r, g, b)),
+            (ColorSupport::Ansi16, Color::AnsiValue(index)) => {
+                match BASIC.get(usize::from(index)) {
+                    Some(color) => *color,
+                    None => basic(palette(index)),
+                }
+            }
+            _ => color,
+        }
+    }
+
+    /// `style` with both its colors downsampled
+    pub fn style(self, style: Style) -> Style {
+        Style {
+            foreground: style.foreground.map(|color| self.downsample(color)),
+            background: style.background.map(|color| self.downsample(color)),
+            ..style
+        }
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let channel = |a: u8, b: u8| u32::from(a.abs_diff(b)).pow(2);
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+/// The basic color closest in hue: which channels stand out picks one
+/// of the six colors, brightness the light or dark variant. Colors close
+/// to gray become one of the four grays.
+fn basic((r, g, b): (u8, u8, u8)) -> Color {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    if max - min < 40 {
+        return match max {
+            0..64 => Color::Black,
+            64..160 => Color::DarkGrey,
+            160..224 => Color::Grey,
+            _ => Color::White,
+        };
+    }
+    let middle = min + (max - min) / 2;
+    let index = usize::from(r > middle)
+        | usize::from(g > middle) << 1
+        | usize::from(b > middle) << 2;
+    let bright = if max > 191 { 8 } else { 0 };
+    BASIC[index + bright]
+}
+
+/// The index of the closest color of the 256, out of the color cube and
+/// the gray ramp. The first 16 are left out, terminals change them.
+fn ansi256(rgb: (u8, u8, u8)) -> u8 {
+    let level = |value: u8| {
+        (0..CUBE.len())
+            .min_by_key(|&i| CUBE[i].abs_diff(value))
+            .unwrap_or(0)
+    };
+    let (r, g, b) = (level(rgb.0), level(rgb.1), level(rgb.2));
+    let cube = u8::try_from(16 + 36 * r + 6 * g + b).unwrap_or(16);
+
+    let average = (u16::from(rgb.0) + u16::from(rgb.1) + u16::from(rgb.2)) / 3;
+    let step = (average.saturating_sub(3) / 10).min(23);
+    let gray = u8::try_from(232 + step).unwrap_or(232);
+
+    if distance(rgb, palette(gray)) < distance(rgb, palette(cube)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+/// The RGB value of color `index` of the 256, from 16 on
+fn palette(index: u8) -> (u8, u8, u8) {
+    if index >= 232 {
+        let level = 8 + 10 * (index - 232);
+        return (level, level, level);
+    }
+    let i = usize::from(index.saturating_sub(16));
+    (CUBE[i / 36], CUBE[i / 6 % 6], CUBE[i % 6])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color::Rgb { r, g, b }
+    }
+
+    #[test]
+    fn maps_rgb_to_the_closest_of_the_256() {
+        let cases = [
+            ((0, 0, 0), 16),
+            ((255, 0, 0), 196),
+            ((0, 255, 0), 46),
+            ((0, 0, 255), 21),
+            ((255, 255, 255), 231),
+            ((95, 135, 175), 67),
+            ((100, 140, 170), 67),
+            ((8, 8, 8), 232),
+            ((128, 128, 128), 244),
+            ((238, 238, 238), 255),
+            ((30, 30, 40), 235),
+        ];
+        for ((r, g, b), index) in cases {
+            assert_eq!(
+                ColorSupport::Ansi256.downsample(rgb(r, g, b)),
+                Color::AnsiValue(index),
+                "{r} {g} {b}"
+            );
+        }
+    }
+
+    #[test]
+    fn maps_rgb_and_the_256_to_the_basic_16() {
+        let cases = [
+            (rgb(255, 0, 0), Color::Red),
+            (rgb(128, 0, 0), Color::DarkRed),
+            (rgb(0, 0, 255), Color::Blue),
+            (rgb(255, 255, 0), Color::Yellow),
+            (rgb(0, 128, 128), Color::DarkCyan),
+            (rgb(200, 80, 220), Color::Magenta),
+            (rgb(30, 30, 30), Color::Black),
+            (rgb(100, 100, 110), Color::DarkGrey),
+            (rgb(192, 192, 192), Color::Grey),
+            (rgb(250, 250, 250), Color::White),
+            (Color::AnsiValue(3), Color::DarkYellow),
+            (Color::AnsiValue(15), Color::White),
+            (Color::AnsiValue(196), Color::Red),
+            (Color::AnsiValue(244), Color::DarkGrey),
+            (Color::Green, Color::Green),
+        ];
+        for (color, basic) in cases {
+            assert_eq!(
+                ColorSupport::Ansi16.downsample(color),
+                basic,
+                "{color:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_what_the_terminal_can_show() {
+        let colors = [rgb(1, 2, 3), Color::AnsiValue(100), Color::Red];
+        for color in colors {
+            assert_eq!(ColorSupport::TrueColor.downsample(color), color);
+        }
+        assert_eq!(
+            ColorSupport::Ansi256.downsample(Color::AnsiValue(100)),
+            Color::AnsiValue(100)
+        );
+    }
+
+    #[test]
+    fn detects_support_from_the_environment() {
+        let cases = [
+            (Some("truecolor"), Some("xterm"), ColorSupport::TrueColor),
+            (Some("24bit"), None, ColorSupport::TrueColor),
+            (None, Some("xterm-direct"), ColorSupport::TrueColor),
+            (None, Some("xterm-256color"), ColorSupport::Ansi256),
+            (Some("yes"), Some("screen-256color"), ColorSupport::Ansi256),
+            (None, Some("xterm"), ColorSupport::Ansi16),
+            (None, None, ColorSupport::Ansi16),
+        ];
+        for (colorterm, term, support) in cases {
+            assert_eq!(ColorSupport::from_env(colorterm, term), support);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::editor::frame::Style;
+use crate::editor::terminal::backend::Backend;
+use crate::editor::terminal::{Position, Size};
+use crossterm::Command;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    DisableFocusChange, DisableMouseCapture, EnableFocusChange,
+    EnableMouseCapture,
+};
+use crossterm::execute;
+use crossterm::queue;
+use crossterm::style::{
+    Attribute, Print, SetAttribute, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::terminal::{
+    Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
+    disable_raw_mode, enable_raw_mode, size,
+};
+use std::io::{Error, Write, stdout};
+
+/// `Backend` talking to the real terminal on `stdout`
+pub struct CrosstermBackend;
+
+impl CrosstermBackend {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Leaves raw mode and the alternate screen without needing an
+    /// instance, used by the panic hook.
+    pub fn restore() -> Result<(), Error> {
+        disable_raw_mode()?;
+        execute!(
+            stdout(),
+            Show,
+            DisableMouseCapture,
+            DisableFocusChange,
+            LeaveAlternateScreen
+        )?;
+        Ok(())
+    }
+
+    fn queue_command(command: impl Command) -> Result<(), Error> {
+        queue!(stdout(), command)?;
+        Ok(())
+    }
+}
+
+impl Default for CrosstermBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for CrosstermBackend {
+    fn initialize(&mut self) -> Result<(), Error> {
+        // Mouse events are read for clicks on the tab bar, focus changes
+        // for autosave
+        execute!(
+            stdout(),
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            EnableFocusChange
+        )?;
+        enable_raw_mode()?;
+        Ok(())
+    }
+
+    fn terminate(&mut self) -> Result<(), Error> {
+        stdout().flush()?;
+        Self::restore()
+    }
+
+    fn clear_screen(&mut self) -> Result<(), Error> {
+        log::info!("Clearing screen");
+        Self::queue_command(Clear(ClearType::All))
+    }
+
+    /// Coordinates beyond `u16::MAX` are clamped, no terminal is that big.
+    fn move_caret_to(
+        &mut self,
+        Position { x, y }: Position,
+    ) -> Result<(), Error> {
+        let x = u16::try_from(x).unwrap_or(u16::MAX);
+        let y = u16::try_from(y).unwrap_or(u16::MAX);
+        Self::queue_command(MoveTo(x, y))
+    }
+
+    fn show_caret(&mut self) -> Result<(), Error> {
+        Self::queue_command(Show)
+    }
+
+    fn hide_caret(&mut self) -> Result<(), Error> {
+        Self::queue_command(Hide)
+    }
+
+    fn set_style(&mut self, style: Style) -> Result<(), Error> {
+        Self::queue_command(SetAttribute(Attribute::Reset))?;
+        if let Some(color) = style.foreground {
+            Self::queue_command(SetForegroundColor(color))?;
+        }
+        if let Some(color) = style.background {
+            Self::queue_command(SetBackgroundColor(color))?;
+        }
+        let attributes = [
+            (style.bold, Attribute::Bold),
+            (style.italic, Attribute::Italic),
+            (style.underline, Attribute::Underlined),
+            (style.reverse, Attribute::Reverse),
+        ];
+        for (_, attribute) in attributes.iter().filter(|(on, _)| *on) {
+            Self::queue_command(SetAttribute(*attribute))?;
+        }
+        Ok(())
+    }
+
+    fn print(&mut self, string: &str) -> Result<(), Error> {
+        Self::queue_command(Print(string))
+    }
+
+    /// Edge Case for systems with `usize` < `u16`:
+    /// * Any coordinate `z` truncated to `usize` if `usize` < `z` < `u16`
+    fn size(&self) -> Result<Size, Error> {
+        let (width, height) = size()?;
+        // clippy::as_conversions: see doc above
+        #[allow(clippy::as_conversions)]
+        let width = width as usize;
+        #[allow(clippy::as_conversions)]
+        let height = height as usize;
+        Ok(Size { height, width })
+    }
+
+    fn execute(&mut self) -> Result<(), Error> {
+        stdout().flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use crate::editor::frame::{Frame, Style};
+use crate::editor::terminal::backend::Backend;
+use crate::editor::terminal::{Position, Size};
+use std::cell::RefCell;
+use std::io::Error;
+use std::rc::Rc;
+
+#[derive(Debug)]
+struct Screen {
+    frame: Frame,
+    caret: Position,
+    caret_visible: bool,
+    /// Style of what is printed next
+    style: Style,
+    /// Number of cells printed since the last `take_printed`
+    printed: usize,
+}
+
+/// Handle onto the in-memory screen of a `VirtualBackend`.
+/// Clones share the same screen, so a test can keep one around after
+/// handing the backend to a `Terminal`.
+#[derive(Debug, Clone)]
+pub struct VirtualScreen {
+    screen: Rc<RefCell<Screen>>,
+}
+
+impl VirtualScreen {
+    fn new(size: Size) -> Self {
+        Self {
+            screen: Rc::new(RefCell::new(Screen {
+                frame: Frame::new(size),
+                caret: Position { x: 0, y: 0 },
+                caret_visible: true,
+                style: Style::default(),
+                printed: 0,
+            })),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.screen.borrow().frame.size()
+    }
+
+    /// Every row of the screen, trailing blanks trimmed
+    pub fn rows(&self) -> Vec<String> {
+        let screen = self.screen.borrow();
+        (0..screen.frame.size().height)
+            .map(|y| screen.frame.row(y).trim_end().to_string())
+            .collect()
+    }
+
+    pub fn row(&self, y: usize) -> String {
+        self.screen.borrow().frame.row(y).trim_end().to_string()
+    }
+
+    /// The whole screen as one string, handy for snapshot assertions.
+    /// Trailing blank rows are dropped.
+    pub fn snapshot(&self) -> String {
+        let mut rows = self.rows();
+        while rows.last().is_some_and(String::is_empty) {
+            rows.pop();
+        }
+        rows.join("\n")
+    }
+
+    /// The style of the cell at `position`
+    pub fn style(&self, position: Position) -> Style {
+        self.screen
+            .borrow()
+            .frame
+            .get(position)
+            .map(|cell| cell.style)
+            .unwrap_or_default()
+    }
+
+    pub fn caret(&self) -> Position {
+        self.screen.borrow().caret
+    }
+
+    pub fn is_caret_visible(&self) -> bool {
+        self.screen.borrow().caret_visible
+    }
+
+    /// Returns how many cells were printed since the last call, which
+    /// tells how much a render actually sent to the screen
+    pub fn take_printed(&self) -> usize {
+        std::mem::take(&mut self.screen.borrow_mut().printed)
+    }
+
+    /// Changes the size of the screen, discarding its contents like a
+    /// real terminal would after a resize
+    pub fn resize(&self, size: Size) {
+        self.screen.borrow_mut().frame = Frame::new(size);
+    }
+}
+
+/// `Backend` drawing onto an in-memory `VirtualScreen` instead of a real
+/// terminal, used to drive the editor headless
+pub struct VirtualBackend {
+    screen: VirtualScreen,
+}
+
+impl VirtualBackend {
+    pub fn new(size: Size) -> Self {
+        Self {
+            screen: VirtualScreen::new(size),
+        }
+    }
+
+    pub fn screen(&self) -> VirtualScreen {
+        self.screen.clone()
+    }
+}
+
+impl Backend for VirtualBackend {
+    fn initialize(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn terminate(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn clear_screen(&mut self) -> Result<(), Error> {
+        let mut screen = self.screen.screen.borrow_mut();
+        let size = screen.frame.size();
+        screen.frame = Frame::new(size);
+        Ok(())
+    }
+
+    fn move_caret_to(&mut self, position: Position) -> Result<(), Error> {
+        self.screen.screen.borrow_mut().caret = position;
+        Ok(())
+    }
+
+    fn show_caret(&mut self) -> Result<(), Error> {
+        self.screen.screen.borrow_mut().caret_visible = true;
+        Ok(())
+    }
+
+    fn hide_caret(&mut self) -> Result<(), Error> {
+        self.screen.screen.borrow_mut().caret_visible = false;
+        Ok(())
+    }
+
+    fn set_style(&mut self, style: Style) -> Result<(), Error> {
+        self.screen.screen.borrow_mut().style = style;
+        Ok(())
+    }
+
+    fn print(&mut self, string: &str) -> Result<(), Error> {
+        let mut screen = self.screen.screen.borrow_mut();
+        let caret = screen.caret;
+        let written = screen.frame.print(caret, string);
+        let style = screen.style;
+        screen.frame.set_style(caret, written, style);
+        screen.caret.x += written;
+        screen.printed += written;
+        Ok(())
+    }
+
+    fn size(&self) -> Result<Size, Error> {
+        Ok(self.screen.size())
+    }
+
+    fn execute(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
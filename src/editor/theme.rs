@@ -0,0 +1,335 @@
+use crate::editor::frame::Style;
+use crate::editor::highlight::Scope;
+use crossterm::style::Color;
+use serde::{Deserialize, Deserializer, de};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Themes compiled into the editor, by name
+const BUILT_IN: &[(&str, &str)] = &[
+    ("default", include_str!("theme/default.toml")),
+    ("dark", include_str!("theme/dark.toml")),
+    ("light", include_str!("theme/light.toml")),
+];
+
+/// Themes a theme may inherit from in a row, guards against loops
+const MAX_INHERITANCE: usize = 8;
+
+/// Names of the basic colors, as in crossterm
+const COLOR_NAMES: &[(&str, Color)] = &[
+    ("black", Color::Black),
+    ("dark_grey", Color::DarkGrey),
+    ("red", Color::Red),
+    ("dark_red", Color::DarkRed),
+    ("green", Color::Green),
+    ("dark_green", Color::DarkGreen),
+    ("yellow", Color::Yellow),
+    ("dark_yellow", Color::DarkYellow),
+    ("blue", Color::Blue),
+    ("dark_blue", Color::DarkBlue),
+    ("magenta", Color::Magenta),
+    ("dark_magenta", Color::DarkMagenta),
+    ("cyan", Color::Cyan),
+    ("dark_cyan", Color::DarkCyan),
+    ("white", Color::White),
+    ("grey", Color::Grey),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// Parts of the screen themes color, besides highlighted text
+pub enum Element {
+    /// The buffer and everything without a style of its own
+    Text,
+    /// Line numbers
+    Gutter,
+    StatusBar,
+    /// The status lines of panes without the focus
+    StatusBarInactive,
+    /// The column between side by side panes
+    Border,
+    /// The row of tabs at the top, when there is more than one tab
+    TabBar,
+    /// The current tab in the tab bar
+    TabSelected,
+    MessageBar,
+    Selection,
+    SearchMatch,
+    /// The `~` past the end of the buffer and fold markers
+    NonText,
+    /// Signs in the gutter for what language servers found, the text
+    /// itself is underlined
+    DiagnosticError,
+    DiagnosticWarning,
+    DiagnosticInfo,
+    DiagnosticHint,
+    /// The completion menu and the documentation next to it
+    Menu,
+    /// The selected completion
+    MenuSelected,
+    /// Lines as a change leaves them, like in the preview of a replace
+    /// in files
+    DiffAdded,
+    /// Lines as they were before a change
+    DiffRemoved,
+}
+
+impl Element {
+    pub const ALL: [Element; 19] = [
+        Element::Text,
+        Element::Gutter,
+        Element::StatusBar,
+        Element::StatusBarInactive,
+        Element::Border,
+        Element::TabBar,
+        Element::TabSelected,
+        Element::MessageBar,
+        Element::Selection,
+        Element::SearchMatch,
+        Element::NonText,
+        Element::DiagnosticError,
+        Element::DiagnosticWarning,
+        Element::DiagnosticInfo,
+        Element::DiagnosticHint,
+        Element::Menu,
+        Element::MenuSelected,
+        Element::DiffAdded,
+        Element::DiffRemoved,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Element::Text => "text",
+            Element::Gutter => "gutter",
+            Element::StatusBar => "status_bar",
+            Element::StatusBarInactive => "status_bar_inactive",
+            Element::Border => "border",
+            Element::TabBar => "tab_bar",
+            Element::TabSelected => "tab_selected",
+            Element::MessageBar => "message_bar",
+            Element::Selection => "selection",
+            Element::SearchMatch => "search_match",
+            Element::NonText => "non_text",
+            Element::DiagnosticError => "diagnostic_error",
+            Element::DiagnosticWarning => "diagnostic_warning",
+            Element::DiagnosticInfo => "diagnostic_info",
+            Element::DiagnosticHint => "diagnostic_hint",
+            Element::Menu => "menu",
+            Element::MenuSelected => "menu_selected",
+            Element::DiffAdded => "diff_added",
+            Element::DiffRemoved => "diff_removed",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// What an entry of a theme styles
+pub enum Target {
+    Scope(Scope),
+    Element(Element),
+}
+
+impl Target {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Scope::from_name(name).map(Target::Scope).or_else(|| {
+            Element::ALL
+                .into_iter()
+                .find(|element| element.name() == name)
+                .map(Target::Element)
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Target {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(d)?;
+        Self::from_name(&name).ok_or_else(|| {
+            de::Error::custom(format!("unknown scope or element {name:?}"))
+        })
+    }
+}
+
+/// Reads a color from a name like `dark_grey`, `#rrggbb` or a number of
+/// the 256-color palette
+fn color<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Color>, D::Error> {
+    struct ColorVisitor;
+
+    impl de::Visitor<'_> for ColorVisitor {
+        type Value = Color;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a color name, \"#rrggbb\" or a number up to 255")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Color, E> {
+            parse_color(value).ok_or_else(|| {
+                E::custom(format!(
+                    "unknown color {value:?}, expected a name like \
+                     \"dark_grey\", \"#rrggbb\" or a number up to 255"
+                ))
+            })
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<Color, E> {
+            u8::try_from(value).map(Color::AnsiValue).map_err(|_| {
+                E::custom(format!("color {value} is not between 0 and 255"))
+            })
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<Color, E> {
+            self.visit_i64(i64::try_from(value).unwrap_or(i64::MAX))
+        }
+    }
+
+    d.deserialize_any(ColorVisitor).map(Some)
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        });
+    }
+    COLOR_NAMES
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, color)| *color)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+/// How a theme styles one scope or element, e.g.
+/// `{ fg = "#c678dd", bold = true }`
+pub struct StyleDefinition {
+    #[serde(default, deserialize_with = "color")]
+    fg: Option<Color>,
+    #[serde(default, deserialize_with = "color")]
+    bg: Option<Color>,
+    #[serde(default)]
+    bold: bool,
+    #[serde(default)]
+    italic: bool,
+    #[serde(default)]
+    underline: bool,
+    #[serde(default)]
+    reverse: bool,
+}
+
+impl StyleDefinition {
+    fn style(self) -> Style {
+        Style {
+            foreground: self.fg,
+            background: self.bg,
+            bold: self.bold,
+            italic: self.italic,
+            underline: self.underline,
+            reverse: self.reverse,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+/// A theme as written in a configuration file: styles by scope or
+/// element name, on top of the theme it inherits from
+pub struct ThemeDefinition {
+    /// `default` if not given, or the built-in theme of the same name
+    inherits: Option<String>,
+    #[serde(flatten)]
+    styles: BTreeMap<Target, StyleDefinition>,
+}
+
+impl ThemeDefinition {
+    fn built_in(name: &str) -> Option<Self> {
+        let (_, source) = BUILT_IN.iter().find(|(n, _)| *n == name)?;
+        Some(toml::from_str(source).expect("built-in themes parse"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Styles for highlight scopes and the parts of the screen
+pub struct Theme {
+    name: String,
+    styles: HashMap<Target, Style>,
+}
+
+impl Theme {
+    /// The built-in theme using the 16 basic colors
+    pub fn default() -> Self {
+        Self::load("default", &BTreeMap::new())
+            .expect("the default theme is built in")
+    }
+
+    /// The theme called `name`, out of `themes` from the configuration
+    /// and the built-in ones. A configured theme with the name of a
+    /// built-in one changes that one.
+    pub fn load(
+        name: &str,
+        themes: &BTreeMap<String, ThemeDefinition>,
+    ) -> Result<Self, String> {
+        // The theme and the ones it inherits from, most specific first
+        let mut definitions = Vec::new();
+        let mut next = Some(name.to_string());
+        while let Some(current) = next.take() {
+            if definitions.len() > MAX_INHERITANCE {
+                return Err(format!(
+                    "theme {name:?} inherits too deep, do themes inherit \
+                     from each other?"
+                ));
+            }
+            let built_in = ThemeDefinition::built_in(&current);
+            match (themes.get(&current), built_in) {
+                (Some(definition), built_in) => {
+                    definitions.push(definition.clone());
+                    next = match (&definition.inherits, built_in) {
+                        (Some(parent), _) => Some(parent.clone()),
+                        (None, Some(built_in)) => {
+                            definitions.push(built_in);
+                            None
+                        }
+                        (None, None) => Some(String::from("default")),
+                    };
+                }
+                (None, Some(built_in)) => {
+                    next.clone_from(&built_in.inherits);
+                    definitions.push(built_in);
+                }
+                (None, None) => {
+                    return Err(format!("unknown theme {current:?}"));
+                }
+            }
+        }
+
+        let mut styles = HashMap::new();
+        for definition in definitions.into_iter().rev() {
+            for (target, style) in definition.styles {
+                styles.insert(target, style.style());
+            }
+        }
+        Ok(Self {
+            name: name.to_string(),
+            styles,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn scope(&self, scope: Scope) -> Style {
+        self.style(Target::Scope(scope))
+    }
+
+    pub fn element(&self, element: Element) -> Style {
+        self.style(Target::Element(element))
+    }
+
+    fn style(&self, target: Target) -> Style {
+        self.styles.get(&target).copied().unwrap_or_default()
+    }
+}
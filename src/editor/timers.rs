@@ -0,0 +1,74 @@
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Things the editor wants to be woken up for
+pub enum Timer {
+    /// The message in the message bar should disappear
+    MessageExpiry,
+    /// Time to check whether the configuration files changed
+    ConfigCheck,
+    /// Time to sync the buffer with the language server and handle what
+    /// it sent
+    LspPoll,
+    /// Time to pick up the files the file finder listed so far
+    WalkPoll,
+    /// Time to pick up the matches the search in files found so far
+    SearchPoll,
+    /// Time to journal unsaved changes to swap files
+    SwapWrite,
+    /// Time to save buffers with unsaved changes, nothing was typed for
+    /// a while
+    Autosave,
+    /// Time to check whether open files changed on disk
+    DiskCheck,
+}
+
+/// Pending timers, each kind scheduled at most once
+pub struct Timers {
+    pending: Vec<(Instant, Timer)>,
+}
+
+impl Timers {
+    pub fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+        }
+    }
+
+    /// Fires `timer` once `after` has passed, replacing an already
+    /// scheduled timer of the same kind
+    pub fn schedule(&mut self, timer: Timer, after: Duration) {
+        self.cancel(timer);
+        self.pending.push((Instant::now() + after, timer));
+    }
+
+    pub fn cancel(&mut self, timer: Timer) {
+        self.pending.retain(|(_, pending)| *pending != timer);
+    }
+
+    pub fn is_scheduled(&self, timer: Timer) -> bool {
+        self.pending.iter().any(|(_, pending)| *pending == timer)
+    }
+
+    /// How long until the next timer fires, `None` if none is pending
+    pub fn next_timeout(&self, now: Instant) -> Option<Duration> {
+        self.pending
+            .iter()
+            .map(|(deadline, _)| deadline.saturating_duration_since(now))
+            .min()
+    }
+
+    /// Removes and returns every timer due at `now`
+    pub fn take_due(&mut self, now: Instant) -> Vec<Timer> {
+        let mut due = Vec::new();
+        self.pending.retain(|(deadline, timer)| {
+            if *deadline <= now {
+                due.push(*timer);
+                false
+            } else {
+                true
+            }
+        });
+        due
+    }
+}
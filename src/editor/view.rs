@@ -1,226 +1,715 @@
-use crate::editor::Caret;
-use crate::editor::Editor;
 use crate::editor::Size;
 use crate::editor::Terminal;
 use crate::editor::buffer::Buffer;
+use crate::editor::completion::Completion;
+use crate::editor::config::Config;
 use crate::editor::debug;
+use crate::editor::folds::Folds;
+use crate::editor::frame::{Frame, Style};
+use crate::editor::highlight::Highlighter;
 use crate::editor::info;
-use crate::editor::read;
-use crate::editor::terminal::Location;
-use crate::editor::terminal::Position;
+use crate::editor::layout::Rect;
+use crate::editor::lsp::{Diagnostic, Severity};
+use crate::editor::terminal::{Location, Position};
+use crate::editor::theme::{Element, Theme};
 use std::io::Error;
+use std::ops::Range;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Columns of the diagnostic signs, the sign and a space
+const SIGN_WIDTH: usize = 2;
+/// Most completions listed at once, the rest scroll
+const MAX_COMPLETIONS: usize = 8;
+const MAX_LABEL_WIDTH: usize = 40;
+const MAX_DOCUMENTATION_WIDTH: usize = 48;
+const MAX_DOCUMENTATION_LINES: usize = 10;
+
+/// Something drawn over the buffer, like the command palette
+pub trait Overlay {
+    /// Draws onto `frame`, returning where the caret should be if the
+    /// overlay takes the input
+    fn draw(&self, frame: &mut Frame) -> Option<Position>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What the status bar shows about the editor
+pub struct Status {
+    /// The editing mode with modal editing, e.g. `NORMAL`
+    pub mode: Option<&'static str>,
+    pub file: String,
+    pub modified: bool,
+    pub location: Location,
+    /// Keys of a command typed so far
+    pub pending: String,
+    pub autosave: Autosave,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Whether the file of a pane is saved on its own, and how that last went
+pub enum Autosave {
+    Off,
+    On,
+    Failed,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Selected text from `start` up to, not including, `end`. With `lines`
+/// the whole lines from `start` to `end` are selected.
+pub struct Selection {
+    pub start: Location,
+    pub end: Location,
+    pub lines: bool,
+}
 
+/// The buffer to show, with what is drawn over its text
+pub struct Document<'a> {
+    pub buffer: &'a Buffer,
+    pub highlighter: &'a Highlighter,
+    pub folds: &'a Folds,
+    pub selection: Option<Selection>,
+    /// What a language server found, sorted by where it starts
+    pub diagnostics: &'a [Diagnostic],
+}
+
+#[derive(Clone)]
+/// A pane onto a buffer: its text, scrolled to the caret, over a status
+/// line
 pub struct View {
-    is_new_buffer: bool,
+    /// Shows the welcome message until the first event comes in
+    pub is_new_buffer: bool,
+    /// Top left corner of the pane on the screen
+    origin: Position,
     size: Size,
-    pub needs_redraw: bool,
+    /// First line and first screen column of the buffer shown
+    scroll: Location,
+    tab_width: usize,
+    line_numbers: bool,
+    /// A column for diagnostic signs in front of the line numbers
+    signs: bool,
+    theme: Theme,
 }
 
 impl View {
     pub fn default() -> View {
         View {
             is_new_buffer: true,
+            origin: Position { x: 0, y: 0 },
             size: Size {
                 height: 40,
                 width: 80,
             },
-            needs_redraw: false,
+            scroll: Location { x: 0, y: 0 },
+            tab_width: 4,
+            line_numbers: false,
+            signs: false,
+            theme: Theme::default(),
         }
     }
     pub fn new(size: Size) -> View {
         View {
-            is_new_buffer: true,
-            size: size,
-            needs_redraw: false,
+            size,
+            ..View::default()
         }
     }
 
-    /// Render the current state of the editor, called in the main loop
-    pub fn render(
-        view: &mut View,
-        caret: &mut Caret,
-        current_buffer: &Buffer,
+    pub fn apply_config(&mut self, config: &Config) {
+        self.tab_width = config.tab_width;
+        self.line_numbers = config.line_numbers;
+    }
+
+    /// Shows the column for diagnostic signs, while a language server
+    /// looks at the buffer
+    pub fn set_signs(&mut self, signs: bool) {
+        self.signs = signs;
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /// Draws the pane: the buffer with `status` on the line below it,
+    /// dimmed unless the pane has the focus
+    pub fn draw(
+        &self,
+        document: &Document,
+        status: &Status,
+        focused: bool,
+    ) -> Frame {
+        let mut frame = self.draw_buffer(document);
+        self.draw_status_bar(&mut frame, status, focused);
+        frame
+    }
+
+    /// Puts `frame`, the whole screen, on the terminal with `overlay`
+    /// over it. The caret goes where the overlay wants it, or to `caret`.
+    pub fn present(
+        terminal: &mut Terminal,
+        mut frame: Frame,
+        overlay: Option<&dyn Overlay>,
+        caret: Position,
     ) -> Result<(), Error> {
         debug!("Rendering editor");
+        let overlay_caret =
+            overlay.and_then(|overlay| overlay.draw(&mut frame));
+        terminal.present(frame)?;
+        terminal.move_caret_to(overlay_caret.unwrap_or(caret))?;
+        terminal.show_caret()?;
+        terminal.execute()?;
+        Ok(())
+    }
 
-        if view.is_new_buffer {
-            Self::welcome_message(caret)?;
-            read()?;
-            view.is_new_buffer = false;
-            Self::set_size(caret)?;
-            let location: Location = Location { x: 0, y: 0 };
-            caret.location = location;
-            Self::draw_buffer(&current_buffer, caret)?;
+    /// Draws the buffer into a fresh frame, filling the rows past the end
+    /// of the buffer with `~`, and colors it after the highlighter
+    pub fn draw_buffer(&self, document: &Document) -> Frame {
+        let mut frame =
+            Frame::with_style(self.size, self.theme.element(Element::Text));
+        let buffer = document.buffer;
+        let non_text = self.theme.element(Element::NonText);
+        let gutter = self.gutter_width(buffer);
+
+        let mut line_index = self.scroll.y;
+        for y in 0..self.text_area_size().height {
+            let Some(line) = buffer.lines.get(line_index) else {
+                let width = frame.print(Position { x: 0, y }, "~");
+                frame.patch_style(Position { x: 0, y }, width, non_text);
+                continue;
+            };
+            if gutter > 0 {
+                let signs = if self.signs { SIGN_WIDTH } else { 0 };
+                let number = if self.line_numbers {
+                    let width = gutter - signs - 1;
+                    format!("{:>width$} ", line_index + 1)
+                } else {
+                    String::new()
+                };
+                frame.print(Position { x: signs, y }, &number);
+                let gutter_style = self.theme.element(Element::Gutter);
+                frame.patch_style(Position { x: 0, y }, gutter, gutter_style);
+            }
+            let on_line = document
+                .diagnostics
+                .iter()
+                .filter(|d| is_on_line(d, line_index));
+            if let Some(worst) = on_line.clone().map(|d| d.severity).min()
+                && self.signs
+            {
+                let sign = worst.sign().to_string();
+                let width = frame.print(Position { x: 0, y }, &sign);
+                let style = self.theme.element(severity_element(worst));
+                frame.patch_style(Position { x: 0, y }, width, style);
+            }
+            let visible: String =
+                self.expand_tabs(line).chars().skip(self.scroll.x).collect();
+            let written = frame.print(Position { x: gutter, y }, &visible);
+            let style = |span_start, span_end| {
+                let start = self.display_column(line, span_start);
+                let end = self.display_column(line, span_end);
+                let start = start.max(self.scroll.x);
+                let at = Position {
+                    x: gutter + start - self.scroll.x,
+                    y,
+                };
+                (at, end.saturating_sub(start))
+            };
+            for span in document.highlighter.spans(line_index) {
+                let (at, width) = style(span.start, span.end);
+                frame.patch_style(at, width, self.theme.scope(span.scope));
+            }
+            if let Some((start, end)) = document
+                .selection
+                .and_then(|s| selected(s, buffer, line_index))
+            {
+                let (at, width) = style(start, end);
+                let selected = self.theme.element(Element::Selection);
+                frame.patch_style(at, width, selected);
+            }
+            for diagnostic in on_line {
+                let (start, end) = underlined(diagnostic, buffer, line_index);
+                let (at, width) = style(start, end);
+                let underline = Style {
+                    underline: true,
+                    ..Style::default()
+                };
+                frame.patch_style(at, width, underline);
+            }
+            if let Some(fold) = document.folds.starting_at(line_index) {
+                let marker = format!(" \u{22ef} {} lines", fold.hidden());
+                let at = Position {
+                    x: gutter + written,
+                    y,
+                };
+                let width = frame.print(at, &marker);
+                frame.patch_style(at, width, non_text);
+            }
+            line_index = document.folds.next_visible(line_index);
         }
 
-        view.draw_caret(caret)?;
+        frame
+    }
 
-        if view.needs_redraw {
-            view.refresh_screen(caret, current_buffer)?;
-            view.needs_redraw = false;
+    /// Draws the menu of `completion` onto `frame`, the whole screen,
+    /// below screen row `y` of the caret, or above it if there is no
+    /// room, lined up with column `x` where the word starts. The
+    /// documentation of the selected item goes to its right.
+    pub fn draw_completion(
+        &self,
+        frame: &mut Frame,
+        completion: &Completion,
+        y: usize,
+        x: usize,
+    ) {
+        // Everything but the message bar
+        let text_rows = frame.size().height.saturating_sub(1);
+        let screen_width = frame.size().width;
+        let label_width = completion
+            .items()
+            .map(|item| item.label.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(MAX_LABEL_WIDTH);
+        let width = (label_width + 4).min(screen_width);
+        let rows_below = text_rows.saturating_sub(y + 1);
+        let visible = completion.len().min(MAX_COMPLETIONS);
+        let (top, visible) = if visible <= rows_below || rows_below >= y {
+            (y + 1, visible.min(rows_below))
+        } else {
+            (y - visible.min(y), visible.min(y))
+        };
+        if visible == 0 || width < 5 {
+            return;
+        }
+        let left = x.min(screen_width - width);
+        let selected = completion.selected_index();
+        let offset = selected.saturating_sub(visible - 1);
+
+        let menu = self.theme.element(Element::Menu);
+        let menu_selected = self.theme.element(Element::MenuSelected);
+        for (row, (index, item)) in completion
+            .items()
+            .enumerate()
+            .skip(offset)
+            .take(visible)
+            .enumerate()
+        {
+            let label: String = item.label.chars().take(label_width).collect();
+            let text = format!(" {} {label:<label_width$} ", item.icon);
+            let at = Position {
+                x: left,
+                y: top + row,
+            };
+            let written = frame.print(at, &text);
+            let style = if index == selected {
+                menu_selected
+            } else {
+                menu
+            };
+            frame.patch_style(at, written, style);
         }
 
-        Terminal::execute()?;
-
-        Ok(())
+        let Some(documentation) = completion
+            .selected()
+            .and_then(|item| item.documentation.as_ref())
+        else {
+            return;
+        };
+        let doc_left = left + width + 1;
+        let doc_width = screen_width
+            .saturating_sub(doc_left)
+            .min(MAX_DOCUMENTATION_WIDTH);
+        if doc_width < 10 {
+            return;
+        }
+        let lines: Vec<String> = documentation
+            .lines()
+            .flat_map(|line| wrap(line, doc_width - 2))
+            .take(MAX_DOCUMENTATION_LINES.min(text_rows))
+            .collect();
+        let doc_top = top.min(text_rows.saturating_sub(lines.len()));
+        for (row, line) in lines.iter().enumerate() {
+            let inner = doc_width - 2;
+            let at = Position {
+                x: doc_left,
+                y: doc_top + row,
+            };
+            let written = frame.print(at, &format!(" {line:<inner$} "));
+            frame.patch_style(at, written, menu);
+        }
     }
 
-    pub fn draw_caret(self: &mut Self, caret: &mut Caret) -> Result<(), Error> {
-        Terminal::move_caret_to(caret.location.into())?;
-        Ok(())
+    /// Columns taken by diagnostic signs and line numbers, 0 when both
+    /// are off
+    pub fn gutter_width(&self, buffer: &Buffer) -> usize {
+        let signs = if self.signs { SIGN_WIDTH } else { 0 };
+        if !self.line_numbers {
+            return signs;
+        }
+        // One column of padding between the numbers and the text
+        signs + buffer.lines.len().max(1).to_string().len() + 1
     }
 
-    pub fn refresh_screen(
-        self: &mut Self,
-        caret: &mut Caret,
-        current_buffer: &Buffer,
-    ) -> Result<(), Error> {
-        info!("Refreshing screen");
-        debug!("Caret location: {}", caret.location);
-
-        Terminal::hide_caret()?;
-
-        let current_line = caret.location.y;
-        Terminal::move_caret_to(Position {
-            x: 0,
-            y: current_line,
-        })?;
-
-        let string = current_buffer.get_line(current_line);
-        match string {
-            Some(string) => {
-                debug!("Current line: {}", string);
-                Terminal::clear_current_line()?;
-                Terminal::print(string.as_str())?;
-            }
-            None => {
-                debug!("Current line: None");
+    /// Replaces tabs with spaces up to the next tab stop
+    fn expand_tabs(&self, line: &str) -> String {
+        let mut expanded = String::with_capacity(line.len());
+        for c in line.chars() {
+            if c == '\t' {
+                let spaces =
+                    self.tab_width - expanded.chars().count() % self.tab_width;
+                expanded.extend(std::iter::repeat_n(' ', spaces));
+            } else {
+                expanded.push(c);
             }
         }
+        expanded
+    }
 
-        Terminal::move_caret_to(caret.location.into())?;
-        Terminal::show_caret()?;
-        Ok(())
+    /// The screen column of the character at `x` in `line`, tabs expanded.
+    /// Past the end of the line every character counts as one column.
+    pub fn display_column(&self, line: &str, x: usize) -> usize {
+        let mut column = 0;
+        let mut chars = line.chars();
+        for _ in 0..x {
+            match chars.next() {
+                Some('\t') => {
+                    column += self.tab_width - column % self.tab_width;
+                }
+                _ => column += 1,
+            }
+        }
+        column
     }
 
-    pub fn draw_buffer(
+    /// Where `location` in `buffer` ends up on the screen, the pane's
+    /// place on it included
+    pub fn screen_position(
+        &self,
         buffer: &Buffer,
-        caret: &mut Caret,
-    ) -> Result<(), Error> {
-        info!("Drawing buffer");
-
-        let size = Terminal::size()?;
-        let lines: &Vec<String> = &buffer.lines;
-
-        Terminal::clear_screen()?;
-        Terminal::move_caret_to(Position { x: 0, y: 0 })?;
+        folds: &Folds,
+        location: Location,
+    ) -> Position {
+        let line = buffer.lines.get(location.y).map_or("", String::as_str);
+        let column = self.display_column(line, location.x);
+        let x =
+            self.gutter_width(buffer) + column.saturating_sub(self.scroll.x);
+        Position {
+            x: self.origin.x + x,
+            y: self.origin.y + rows_between(folds, self.scroll.y, location.y),
+        }
+    }
 
-        for line in lines {
-            debug!("Line: {}", line);
-            Terminal::print(line.as_str())?;
-            Terminal::print("\r\n")?;
+    /// The lines of the buffer that are on the screen, folded ones
+    /// included
+    pub fn visible_lines(&self, folds: &Folds) -> Range<usize> {
+        let mut end = self.scroll.y;
+        for _ in 0..self.text_area_size().height {
+            end = folds.next_visible(end);
         }
+        self.scroll.y..end
+    }
 
-        if lines.len() < size.height {
-            Self::filling_empty_lines(lines.len(), size)?;
+    /// Scrolls just enough for `location` to be on the screen
+    pub fn scroll_to(
+        &mut self,
+        buffer: &Buffer,
+        folds: &Folds,
+        location: Location,
+    ) {
+        let Size { height, width } = self.text_area_size();
+        let width = width.saturating_sub(self.gutter_width(buffer));
+        let y = folds.visible(location.y);
+        self.scroll.y = folds.visible(self.scroll.y);
+        if y < self.scroll.y {
+            self.scroll.y = y;
+        } else if rows_between(folds, self.scroll.y, y) >= height {
+            // Just enough lines above it to fill the screen
+            self.scroll.y = y;
+            for _ in 1..height.max(1) {
+                match folds.previous_visible(self.scroll.y) {
+                    Some(previous) => self.scroll.y = previous,
+                    None => break,
+                }
+            }
         }
 
-        Terminal::move_caret_to(caret.location.into())?;
-        Ok(())
+        let line = buffer.lines.get(location.y).map_or("", String::as_str);
+        let column = self.display_column(line, location.x);
+        if column < self.scroll.x {
+            self.scroll.x = column;
+        } else if column >= self.scroll.x + width {
+            self.scroll.x = column + 1 - width.max(1);
+        }
     }
 
-    pub fn resize(&mut self, to: Size) {
-        self.size = to;
-        self.needs_redraw = true;
+    /// Draws `status` on the last row of the pane: the mode and file on
+    /// the left, pending keys and the caret location on the right
+    fn draw_status_bar(
+        &self,
+        frame: &mut Frame,
+        status: &Status,
+        focused: bool,
+    ) {
+        let y = self.size.height.saturating_sub(1);
+        let file = if status.file.is_empty() {
+            "[No Name]"
+        } else {
+            status.file.as_str()
+        };
+        let modified = if status.modified { " [+]" } else { "" };
+        let autosave = match status.autosave {
+            Autosave::Off => "",
+            Autosave::On => " [auto]",
+            Autosave::Failed => " [auto failed]",
+        };
+        let left = match status.mode {
+            Some(mode) => format!("-- {mode} -- {file}{modified}{autosave}"),
+            None => format!("{file}{modified}{autosave}"),
+        };
+        let right = format!(
+            "{}  {}:{}",
+            status.pending,
+            status.location.y + 1,
+            status.location.x + 1
+        );
+        let room = self.size.width.saturating_sub(right.chars().count() + 1);
+        let left: String = left.chars().take(room).collect();
+        let width = frame
+            .print(Position { x: 0, y }, &format!("{left:<room$} {right}"));
+        let element = if focused {
+            Element::StatusBar
+        } else {
+            Element::StatusBarInactive
+        };
+        let status_bar = self.theme.element(element);
+        frame.patch_style(Position { x: 0, y }, width, status_bar);
     }
 
-    pub fn set_size(caret: &mut Caret) -> Result<(), Error> {
-        let size = Terminal::size()?;
-        caret.size = size;
-        Ok(())
+    /// Draws `message` on the last row of `frame`, the whole screen
+    pub fn draw_message_bar(&self, frame: &mut Frame, message: Option<&str>) {
+        let Size { width, height } = frame.size();
+        let y = height.saturating_sub(1);
+        if let Some(message) = message {
+            frame.print(Position { x: 0, y }, message);
+        }
+        let message_bar = self.theme.element(Element::MessageBar);
+        frame.patch_style(Position { x: 0, y }, width, message_bar);
     }
 
-    fn filling_empty_lines(
-        current_line: usize,
-        size: Size,
-    ) -> Result<(), Error> {
-        let height = size.height;
+    /// Draws the border in `area` of `frame`, the column between side by
+    /// side panes
+    pub fn draw_border(&self, frame: &mut Frame, area: Rect) {
+        let border = self.theme.element(Element::Border);
+        for y in area.y..area.y + area.height {
+            let at = Position { x: area.x, y };
+            let width = frame.print(at, "\u{2502}");
+            frame.patch_style(at, width, border);
+        }
+    }
 
-        for line in current_line + 1..height {
-            Terminal::print("~")?;
-            if line < height - 1 {
-                Terminal::move_caret_to(Position { x: 0, y: line })?;
+    /// Draws the tab bar on the first row of `frame`, a label per tab
+    pub fn draw_tab_bar(
+        &self,
+        frame: &mut Frame,
+        labels: &[String],
+        current: usize,
+    ) {
+        let tab_bar = self.theme.element(Element::TabBar);
+        let width = frame.size().width;
+        frame.patch_style(Position { x: 0, y: 0 }, width, tab_bar);
+        let selected = self.theme.element(Element::TabSelected);
+        let mut x = 0;
+        for (index, label) in labels.iter().enumerate() {
+            let at = Position { x, y: 0 };
+            let written = frame.print(at, label);
+            if index == current {
+                frame.patch_style(at, written, selected);
             }
+            x += written;
         }
+    }
 
-        Ok(())
+    /// Moves the pane to `area` of the screen
+    pub fn place(&mut self, area: Rect) {
+        self.origin = Position {
+            x: area.x,
+            y: area.y,
+        };
+        self.size = Size {
+            height: area.height,
+            width: area.width,
+        };
     }
 
-    pub fn welcome_message(caret: &mut Caret) -> Result<(), Error> {
-        info!("Displaying welcome message");
-        let Size { width, height } = Terminal::size()?;
+    /// The part of the pane showing the buffer, everything but the status
+    /// line
+    pub fn text_area_size(&self) -> Size {
+        Size {
+            height: self.size.height.saturating_sub(1),
+            width: self.size.width,
+        }
+    }
 
-        Terminal::hide_caret()?;
-        Terminal::clear_screen()?;
+    /// Draws `message` roughly centered on a third of a screen of `size`
+    fn draw_message(&self, message: &str, size: Size) -> Frame {
+        let Size { width, height } = size;
+        let mut frame =
+            Frame::with_style(size, self.theme.element(Element::Text));
 
-        let message = format!("R-EDIT -- v{}", VERSION);
         let row = height / 3;
         let column = width / 2;
-        let msg_len = message.len() as usize;
+        let msg_len = message.chars().count();
 
-        match (column).checked_sub(msg_len / 2) {
-            Some(col) => {
-                Terminal::move_caret_to(Position { x: col, y: row })?;
-            }
-            None => {
-                info!("Underflow");
-                Terminal::move_caret_to(Position { x: 0, y: row })?;
-            }
-        }
+        let col = column.checked_sub(msg_len / 2).unwrap_or_else(|| {
+            info!("Underflow");
+            0
+        });
+        frame.print(Position { x: col, y: row }, message);
 
-        Terminal::print(&message)?;
-        Terminal::print("\r\n\r\n")?;
+        frame
+    }
 
-        Terminal::move_caret_to(Position { x: 0, y: 0 })?;
-        caret.location = Location { x: 0, y: 0 };
-        Terminal::show_caret()?;
-        Terminal::execute()?;
-        Ok(())
+    pub fn welcome_message(
+        &self,
+        terminal: &mut Terminal,
+        size: Size,
+    ) -> Result<(), Error> {
+        info!("Displaying welcome message");
+
+        let frame = self.draw_message(&format!("R-EDIT -- v{VERSION}"), size);
+        Self::show_message(terminal, frame)
     }
 
-    pub fn goodbye_message(caret: &mut Caret) -> Result<(), Error> {
+    pub fn goodbye_message(
+        &self,
+        terminal: &mut Terminal,
+        size: Size,
+    ) -> Result<(), Error> {
         info!("Displaying message");
 
-        Terminal::hide_caret()?;
-        Terminal::clear_screen()?;
+        let frame = self.draw_message("Goodbye.", size);
+        Self::show_message(terminal, frame)
+    }
 
-        let Size { width, height } = Terminal::size()?;
-        let mut message = String::from("Goodbye.");
-        let row = height / 3;
-        let column = width / 2;
-        let msg_len = message.len();
+    fn show_message(
+        terminal: &mut Terminal,
+        frame: Frame,
+    ) -> Result<(), Error> {
+        terminal.present(frame)?;
+        terminal.move_caret_to(Position { x: 0, y: 0 })?;
+        terminal.show_caret()?;
+        terminal.execute()?;
+        Ok(())
+    }
+}
 
-        match (column).checked_sub(msg_len / 2) {
-            Some(col) => {
-                Terminal::move_caret_to(Position { x: col, y: row })?;
-            }
-            None => {
-                info!("Underflow");
-                Terminal::move_caret_to(Position { x: 0, y: row })?;
-            }
+/// How many screen rows there are from line `from` down to line `to`
+fn rows_between(folds: &Folds, from: usize, to: usize) -> usize {
+    let mut rows = 0;
+    let mut y = from;
+    while y < to {
+        y = folds.next_visible(y);
+        if y <= to {
+            rows += 1;
         }
+    }
+    rows
+}
 
-        message.truncate(width as usize);
-        Terminal::print(&message)?;
-        Terminal::print("\r\n\r\n")?;
+/// The characters of line `y` that `selection` covers, the line break
+/// counting as one past the end
+fn selected(
+    selection: Selection,
+    buffer: &Buffer,
+    y: usize,
+) -> Option<(usize, usize)> {
+    let Selection { start, end, lines } = selection;
+    if y < start.y || y > end.y {
+        return None;
+    }
+    let line_end = buffer.line_len(y) + 1;
+    if lines {
+        return Some((0, line_end));
+    }
+    let from = if y == start.y { start.x } else { 0 };
+    let to = if y == end.y { end.x } else { line_end };
+    (to > from).then_some((from, to))
+}
 
-        Terminal::move_caret_to(Position { x: 0, y: 0 })?;
-        caret.location = Location { x: 0, y: 0 };
-        Terminal::show_caret()?;
+/// The tab whose label is at column `x` of the tab bar, see
+/// `View::draw_tab_bar`
+pub fn tab_at(labels: &[String], x: usize) -> Option<usize> {
+    let mut end = 0;
+    labels.iter().position(|label| {
+        end += label.chars().count();
+        x < end
+    })
+}
 
-        Terminal::execute()?;
-        Ok(())
+/// The worst of `diagnostics` on line `y`, for the message bar
+pub fn line_diagnostic(
+    diagnostics: &[Diagnostic],
+    y: usize,
+) -> Option<&Diagnostic> {
+    diagnostics
+        .iter()
+        .filter(|d| is_on_line(d, y))
+        .min_by_key(|d| d.severity)
+}
+
+/// Whether `diagnostic` is about line `y`. Ending at the very start of
+/// a line does not count for that line.
+fn is_on_line(diagnostic: &Diagnostic, y: usize) -> bool {
+    let Diagnostic { start, end, .. } = diagnostic;
+    start.y <= y && (y < end.y || (y == end.y && (end.x > 0 || start.y == y)))
+}
+
+/// The characters of line `y` to underline for `diagnostic`, at least
+/// one so empty ranges show too
+fn underlined(
+    diagnostic: &Diagnostic,
+    buffer: &Buffer,
+    y: usize,
+) -> (usize, usize) {
+    let from = if y == diagnostic.start.y {
+        diagnostic.start.x
+    } else {
+        0
+    };
+    let to = if y == diagnostic.end.y {
+        diagnostic.end.x
+    } else {
+        buffer.line_len(y)
+    };
+    (from, to.max(from + 1))
+}
+
+/// Breaks `line` at spaces into lines of at most `width` characters,
+/// words longer than that are cut
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in line.split(' ') {
+        let word: String = word.chars().take(width).collect();
+        let len = current.chars().count();
+        if len > 0 && len + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&word);
+    }
+    lines.push(current);
+    lines
+}
+
+fn severity_element(severity: Severity) -> Element {
+    match severity {
+        Severity::Error => Element::DiagnosticError,
+        Severity::Warning => Element::DiagnosticWarning,
+        Severity::Information => Element::DiagnosticInfo,
+        Severity::Hint => Element::DiagnosticHint,
     }
 }
@@ -0,0 +1,110 @@
+use crate::editor::ignore::Ignore;
+use crate::editor::recovery;
+use log::info;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError, channel};
+
+/// Files found before they are handed over together
+const BATCH_SIZE: usize = 256;
+/// Most files listed, so huge trees do not fill the memory
+const MAX_FILES: usize = 100_000;
+
+/// Lists the files below a directory on a thread of its own, leaving out
+/// what `.gitignore` files do. Dropping it stops the thread.
+pub struct FileWalk {
+    receiver: Receiver<Vec<PathBuf>>,
+    done: bool,
+}
+
+impl FileWalk {
+    pub fn start(root: PathBuf) -> Self {
+        let (sender, receiver) = channel();
+        recovery::spawn_worker("walk", move || list(&root, &sender));
+        Self {
+            receiver,
+            done: false,
+        }
+    }
+
+    /// The files found since the last call, without waiting for more
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(batch) => files.extend(batch),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+        files
+    }
+
+    /// Whether every file has been handed over
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+/// Sends the files below `root` in batches
+fn list(root: &Path, sender: &Sender<Vec<PathBuf>>) {
+    let mut batch = Vec::new();
+    let mut count = 0;
+    let mut stopped = false;
+    walk(root, |path| {
+        batch.push(path);
+        count += 1;
+        if batch.len() < BATCH_SIZE && count < MAX_FILES {
+            return true;
+        }
+        stopped = sender.send(std::mem::take(&mut batch)).is_err();
+        !stopped && count < MAX_FILES
+    });
+    if stopped {
+        return;
+    }
+    if count == MAX_FILES {
+        info!("Stopped listing {} at {count} files", root.display());
+    }
+    if !batch.is_empty() {
+        let _ = sender.send(batch);
+    }
+    info!("Listed {count} files below {}", root.display());
+}
+
+/// Hands every file below `root` that `.gitignore` files do not leave
+/// out to `visit`, until it returns false. Directories go in name order
+/// and depth first. Symbolic links to directories are not followed, so
+/// loops can not keep it going.
+pub fn walk(root: &Path, mut visit: impl FnMut(PathBuf) -> bool) {
+    let mut ignore = Ignore::new(root);
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        ignore.enter(&dir);
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut entries: Vec<(PathBuf, bool)> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let is_dir = entry.file_type().ok()?.is_dir();
+                Some((entry.path(), is_dir))
+            })
+            .filter(|(path, is_dir)| !ignore.is_ignored(path, *is_dir))
+            .collect();
+        entries.sort();
+        let mut subdirs = Vec::new();
+        for (path, is_dir) in entries {
+            if is_dir {
+                subdirs.push(path);
+            } else if !visit(path) {
+                return;
+            }
+        }
+        // Popped from the end, so the first directory goes next
+        dirs.extend(subdirs.into_iter().rev());
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Notices when any of a set of files is created, changed or removed by
+/// comparing modification times
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl FileWatcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self {
+            files: paths
+                .into_iter()
+                .map(|path| {
+                    let modified = Self::modified(&path);
+                    (path, modified)
+                })
+                .collect(),
+        }
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|meta| meta.modified()).ok()
+    }
+
+    /// Starts watching the file at `path` as it is now. A file already
+    /// watched is taken to be seen as it is now.
+    pub fn watch(&mut self, path: PathBuf) {
+        let modified = Self::modified(&path);
+        match self.files.iter_mut().find(|(watched, _)| *watched == path) {
+            Some((_, seen)) => *seen = modified,
+            None => self.files.push((path, modified)),
+        }
+    }
+
+    /// Stops watching the files `keep` returns false for
+    pub fn retain(&mut self, mut keep: impl FnMut(&Path) -> bool) {
+        self.files.retain(|(path, _)| keep(path));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Whether the file at `path` is watched and changed since it was
+    /// last seen
+    pub fn has_changed(&self, path: &Path) -> bool {
+        self.files.iter().any(|(watched, seen)| {
+            watched == path && Self::modified(watched) != *seen
+        })
+    }
+
+    /// The files that changed since they were last seen, still taken to
+    /// be seen as they were
+    pub fn changed_files(&self) -> Vec<PathBuf> {
+        self.files
+            .iter()
+            .filter(|(path, seen)| Self::modified(path) != *seen)
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Whether any file changed since the last call
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, seen) in &mut self.files {
+            let modified = Self::modified(path);
+            if modified != *seen {
+                *seen = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,54 @@
+use crate::editor::buffer::Buffer;
+use crate::editor::caret::Caret;
+use crate::editor::folds::Folds;
+use crate::editor::highlight::Highlighter;
+use crate::editor::layout::Layout;
+use crate::editor::terminal::Location;
+use crate::editor::view::View;
+use std::collections::HashMap;
+
+/// A pane: the view onto the buffer it shows and where its caret is
+pub struct Window {
+    pub view: View,
+    pub caret: Caret,
+    /// Id of the buffer shown
+    pub buffer: usize,
+    /// Lines of the buffer folded away
+    pub folds: Folds,
+    /// Where the selection started outside of modal editing, it ends at
+    /// the caret
+    pub selection_anchor: Option<Location>,
+    /// The selections `expand_selection` grew out of, latest last
+    pub selection_history: Vec<(Location, Location)>,
+}
+
+impl Window {
+    /// A pane showing buffer `buffer` from its start
+    pub fn new(buffer: usize) -> Self {
+        Self {
+            view: View::default(),
+            caret: Caret::default(),
+            buffer,
+            folds: Folds::default(),
+            selection_anchor: None,
+            selection_history: Vec::new(),
+        }
+    }
+}
+
+/// A buffer only shown in panes without the focus, with what belongs to
+/// it rather than to a pane
+pub struct OpenBuffer {
+    pub buffer: Buffer,
+    pub filename: String,
+    pub saved_revision: u64,
+    pub highlighter: Highlighter,
+}
+
+/// A tab page without the focus, with a layout of its own. All of its
+/// panes are put away here, the focused one too.
+pub struct TabPage {
+    pub layout: Layout,
+    /// Every pane of the tab, by id
+    pub windows: HashMap<usize, Window>,
+}
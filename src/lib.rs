@@ -0,0 +1,11 @@
+#![warn(clippy::all, clippy::pedantic, clippy::print_stdout)]
+// The library only exists for the r-edit binary and its tests, lints about
+// the shape of a public API would just be noise
+#![allow(
+    clippy::must_use_candidate,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::new_without_default,
+    clippy::should_implement_trait
+)]
+pub mod editor;
@@ -1,34 +1,150 @@
 #![warn(clippy::all, clippy::pedantic, clippy::print_stdout)]
-mod editor;
-use editor::Editor;
-use editor::logger::{Config, CustomLogger};
-use log::debug;
 use log::info;
 use log::{self, LevelFilter};
+use r_edit::editor::Editor;
+use r_edit::editor::config::LogConfig;
+use r_edit::editor::event_source::TerminalEvents;
+use r_edit::editor::logger::{self, CustomLogger};
+use r_edit::editor::session::{ReplayEvents, read_session};
+use std::io::{Error, Write, stdout};
+use std::path::{Path, PathBuf};
+use std::process::exit;
 
-fn main() {
+const USAGE: &str = "Usage: r-edit [--record FILE] [FILE]
+       r-edit --replay FILE [--headless] [FILE]";
+
+#[derive(Default)]
+struct Args {
+    file: Option<String>,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    headless: bool,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--record" | "--replay" => {
+                    let Some(path) = args.next() else {
+                        return Err(format!("{arg} needs a file"));
+                    };
+                    if arg == "--record" {
+                        parsed.record = Some(PathBuf::from(path));
+                    } else {
+                        parsed.replay = Some(PathBuf::from(path));
+                    }
+                }
+                "--headless" => parsed.headless = true,
+                _ if arg.starts_with("--") => {
+                    return Err(format!("Unknown option {arg}"));
+                }
+                _ if parsed.file.is_none() => parsed.file = Some(arg),
+                _ => return Err(format!("Unexpected argument {arg}")),
+            }
+        }
+        if parsed.headless && parsed.replay.is_none() {
+            return Err(String::from("--headless only works with --replay"));
+        }
+        if parsed.record.is_some() && parsed.replay.is_some() {
+            return Err(String::from("Cannot --record while replaying"));
+        }
+        Ok(parsed)
+    }
+}
+
+/// Logs according to the configuration. The logger itself lets every
+/// level through so the level can change when the configuration reloads.
+fn set_up_logger(config: &LogConfig) {
     let logger = CustomLogger::new(
-        Config {
-            level_filter: LevelFilter::Debug,
-            truncate: true,
+        logger::Config {
+            level_filter: LevelFilter::Trace,
+            truncate: config.truncate,
         },
-        "r-edit.log",
+        &config.file,
     )
     .unwrap();
     log::set_boxed_logger(Box::new(logger)).unwrap();
-    log::set_max_level(LevelFilter::Debug);
+    log::set_max_level(config.level);
+}
+
+fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            exit(2);
+        }
+    };
+
+    if let Some(session) = &args.replay {
+        if let Err(e) = replay(session, &args) {
+            eprintln!("Could not replay {}: {e}", session.display());
+            exit(1);
+        }
+        return;
+    }
 
     let mut editor: Editor;
-    // collect depletes the iterator
-    let args: Vec<String> = std::env::args().collect();
-    if let Some(first_arg) = args.get(1) {
-        info!("Argument provided: {first_arg} (filename)");
-        let filename = first_arg.clone();
-        editor = Editor::new(filename);
+    if let Some(filename) = &args.file {
+        editor = Editor::new(filename.clone());
     } else {
-        info!("No argument provided");
         editor = Editor::default();
     }
+    editor.load_config();
+    set_up_logger(&editor.config().log);
+    match &args.file {
+        Some(filename) => info!("Argument provided: {filename} (filename)"),
+        None => info!("No argument provided"),
+    }
+
+    if let Some(session) = &args.record
+        && let Err(e) = editor.record_to(session)
+    {
+        eprintln!("Could not record to {}: {e}", session.display());
+        exit(1);
+    }
 
     editor.run();
 }
+
+/// Drives the editor through a recorded session. Headless, the final
+/// screen and buffer are printed once the session is over and no file is
+/// written; otherwise the session plays on the terminal and the editor
+/// stays open afterwards.
+fn replay(session: &Path, args: &Args) -> Result<(), Error> {
+    let (header, entries) = read_session(session)?;
+    let filename = args.file.clone().unwrap_or_else(|| header.file.clone());
+    let count = entries.len();
+
+    if !args.headless {
+        let events = ReplayEvents::new(&header, entries, true)
+            .then(Box::new(TerminalEvents));
+        let mut editor = Editor::new(filename.clone());
+        editor.load_config();
+        set_up_logger(&editor.config().log);
+        info!("Replaying {count} events on {filename}");
+        editor.set_event_source(Box::new(events));
+        editor.run();
+        return Ok(());
+    }
+
+    let (mut editor, screen) = Editor::replaying(&header, entries, filename)?;
+    // The same configuration, macros and history as the recording, or the
+    // keys mean other things
+    editor.load_config();
+    set_up_logger(&editor.config().log);
+    info!("Replaying {count} events on {}", editor.filename());
+    editor.read_state();
+    editor.render();
+    editor.repl();
+
+    let mut out = stdout().lock();
+    writeln!(out, "{}", screen.snapshot())?;
+    writeln!(out, "--- caret {}", editor.caret().location)?;
+    for line in &editor.buffer().lines {
+        writeln!(out, "{line}")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,186 @@
+#![warn(clippy::all, clippy::pedantic, clippy::print_stdout)]
+//! The language server client against the fake server of
+//! `examples/fake-lsp.rs`. This binary is the server as well: started with
+//! `--serve` it serves on stdin and stdout, which is why it runs its tests
+//! itself instead of through the test harness.
+
+#[path = "../examples/fake-lsp.rs"]
+mod fake_lsp;
+
+use r_edit::editor::buffer::Buffer;
+use r_edit::editor::lsp::{self, Lsp, Reply, Request, ServerConfig, Severity};
+use r_edit::editor::terminal::Location;
+use std::collections::BTreeMap;
+use std::io::Error;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Argument that makes this binary the fake server
+const SERVE: &str = "--serve";
+/// Longest wait for the server to answer
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A document open with the fake server
+struct Session {
+    lsp: Lsp,
+    path: PathBuf,
+    buffer: Buffer,
+}
+
+impl Session {
+    /// Starts the fake server and opens a document named after `name`
+    /// holding `lines`. The file does not have to exist.
+    fn start(name: &str, lines: &[&str]) -> Self {
+        let command = std::env::current_exe()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let config = ServerConfig {
+            command,
+            args: vec![String::from(SERVE)],
+        };
+        let mut lsp = Lsp::default();
+        lsp.configure(&BTreeMap::from([(String::from("text"), config)]));
+        let path = std::env::temp_dir()
+            .join(format!("r-edit-lsp-{}-{name}.txt", std::process::id()));
+        lsp.open(&path, "text").unwrap();
+        let mut buffer = Buffer::new();
+        for line in lines {
+            buffer.push((*line).to_string());
+        }
+        Self { lsp, path, buffer }
+    }
+
+    /// Keeps the server up to date until `done` holds
+    fn wait_until(&mut self, done: impl Fn(&Self) -> bool) {
+        let start = Instant::now();
+        while !done(self) {
+            assert!(start.elapsed() < TIMEOUT, "The server did not answer");
+            let replies = self.lsp.poll();
+            assert!(replies.is_empty(), "Unexpected replies {replies:?}");
+            self.lsp.sync(&self.path, &self.buffer);
+            sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// What the server answers to `request` about `at`
+    fn ask(&mut self, request: &Request, at: Location) -> Reply {
+        let start = Instant::now();
+        loop {
+            assert!(start.elapsed() < TIMEOUT, "The server did not start");
+            match self.lsp.request(
+                request.clone(),
+                &self.path,
+                &self.buffer,
+                at,
+            ) {
+                Ok(()) => break,
+                Err(e) if e.ends_with("is starting") => {
+                    self.lsp.poll();
+                    sleep(Duration::from_millis(10));
+                }
+                Err(e) => panic!("{e}"),
+            }
+        }
+        loop {
+            assert!(start.elapsed() < TIMEOUT, "The server did not answer");
+            if let Some(reply) = self.lsp.poll().pop() {
+                return reply;
+            }
+            sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn lines(&self) -> Vec<&str> {
+        self.buffer.lines.iter().map(String::as_str).collect()
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.lsp.shutdown();
+    }
+}
+
+fn reports_diagnostics() {
+    let mut session = Session::start("diagnostics", &["one", "two // TODO"]);
+    session.wait_until(|s| !s.lsp.diagnostics(&s.path).is_empty());
+    let diagnostics = session.lsp.diagnostics(&session.path);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].start, Location { x: 7, y: 1 });
+    assert_eq!(diagnostics[0].end, Location { x: 11, y: 1 });
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+    assert_eq!(diagnostics[0].message, "TODO left in the code");
+}
+
+fn hovers() {
+    let mut session = Session::start("hover", &["fn greet", "greet()"]);
+    let reply = session.ask(&Request::Hover, Location { x: 2, y: 1 });
+    let Reply::Hover(text) = reply else {
+        panic!("Unexpected reply {reply:?}");
+    };
+    assert!(text.contains("`greet`"), "{text}");
+    assert!(text.contains("2 occurrences"), "{text}");
+}
+
+fn finds_definitions() {
+    let mut session = Session::start("definition", &["x", "fn greet", "greet"]);
+    let reply = session.ask(&Request::Definition, Location { x: 0, y: 2 });
+    let Reply::Definition(locations) = reply else {
+        panic!("Unexpected reply {reply:?}");
+    };
+    assert_eq!(locations.len(), 1);
+    assert_eq!(locations[0].path, session.path);
+    let start = locations[0].start.location(&session.buffer.lines);
+    assert_eq!(start, Location { x: 3, y: 1 });
+}
+
+fn renames() {
+    let mut session =
+        Session::start("rename", &["fn greet() {}", "greet(); greeting"]);
+    let request = Request::Rename(String::from("hello"));
+    let reply = session.ask(&request, Location { x: 4, y: 0 });
+    let Reply::Rename(files) = reply else {
+        panic!("Unexpected reply {reply:?}");
+    };
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].0, session.path);
+    lsp::apply_edits(&mut session.buffer, &files[0].1);
+    assert_eq!(session.lines(), ["fn hello() {}", "hello(); greeting"]);
+}
+
+fn formats() {
+    let mut session = Session::start("format", &["one  ", "two", "three\t"]);
+    let reply = session.ask(&Request::Format, Location { x: 0, y: 0 });
+    let Reply::Format {
+        path,
+        revision,
+        edits,
+    } = reply
+    else {
+        panic!("Unexpected reply {reply:?}");
+    };
+    assert_eq!(path, session.path);
+    assert_eq!(revision, session.buffer.revision());
+    lsp::apply_edits(&mut session.buffer, &edits);
+    assert_eq!(session.lines(), ["one", "two", "three"]);
+}
+
+fn main() -> Result<(), Error> {
+    if std::env::args().any(|arg| arg == SERVE) {
+        return fake_lsp::main();
+    }
+    let tests: [(&str, fn()); 5] = [
+        ("reports_diagnostics", reports_diagnostics),
+        ("hovers", hovers),
+        ("finds_definitions", finds_definitions),
+        ("renames", renames),
+        ("formats", formats),
+    ];
+    for (name, test) in tests {
+        test();
+        eprintln!("test {name} ... ok");
+    }
+    Ok(())
+}